@@ -8,7 +8,8 @@ use xmrs::sid::sid_module::SidModule;
 use xmrs::xm::xmmodule::XmModule;
 
 fn save_xm(sid: &SidModule) -> Result<(), EncodeError> {
-    let modules: Vec<Module> = sid.to_modules(false); // for now, simulated instr
+    let mut modules: Vec<Module> = sid.to_modules(false); // for now, simulated instr
+    modules.extend(sid.soundfx_to_modules(false));
     for module in &modules {
         let mut xmmodule: XmModule = XmModule::from_module(&module);
         let xmodule_se = xmmodule.save()?;
@@ -26,10 +27,22 @@ fn main() -> Result<(), EncodeError> {
     println!("(c) 2024 Sébastien Béchet\n");
 
     // println!("{:?}", sid);
-    //TODO: SOUNDFX
     println!("Warning: it's just a game to extract some data. Don't expect anything beautiful.");
     println!("Note: XM is limited to 256 rows");
 
+    if let Some(filename) = std::env::args().nth(1) {
+        let data = std::fs::read(&filename).unwrap();
+        match SidModule::load_all(&data) {
+            Ok(sids) => {
+                for sid in &sids {
+                    save_xm(sid)?;
+                }
+            }
+            Err(e) => println!("{}: {:?}", filename, e),
+        }
+        return Ok(());
+    }
+
     // save_xm(&SidModule::get_sid_commando())?;
     save_xm(&SidModule::get_sid_crazy_comets())?;
     save_xm(&SidModule::get_sid_monty_on_the_run())?;
@@ -44,6 +57,7 @@ fn main() -> Result<(), EncodeError> {
     save_xm(&SidModule::get_sid_sanxion_song_1())?;
     save_xm(&SidModule::get_sid_sanxion_song_2())?;
     save_xm(&SidModule::get_sid_spellbound())?;
+    save_xm(&SidModule::get_sid_thrust())?;
 
     Ok(())
 }
@@ -0,0 +1,682 @@
+use bincode::error::DecodeError;
+
+use crate::envelope::{Envelope, EnvelopePoint};
+use crate::instr_vibrato::{InstrVibrato, Waveform};
+use crate::prelude::*;
+
+use alloc::string::String;
+use alloc::string::ToString;
+use alloc::{vec, vec::Vec};
+
+#[cfg(feature = "micromath")]
+use micromath::F32Ext;
+#[cfg(feature = "libm")]
+use num_traits::float::Float;
+
+const AHX_INSTRUMENT_SIZE: usize = 22;
+const AHX_CHANNELS: usize = 4;
+/// Empty row of a packed HVL track
+const HVL_EMPTY_ROW: u8 = 0x3F;
+
+/// Amiga PAL clock
+const AHX_CLOCK: f32 = 3_546_895.0;
+/// Instruments are baked at this period, the Amiga C-4
+const AHX_BAKE_PERIOD: f32 = 428.0;
+/// Longest baked performance list, in ticks
+const AHX_BAKE_TICKS: usize = 64 * 16;
+/// Noise waveform length
+const AHX_NOISE_LENGTH: usize = 0x280;
+
+/// Performance list waveforms
+const AHX_TRIANGLE: u8 = 1;
+const AHX_SAWTOOTH: u8 = 2;
+const AHX_SQUARE: u8 = 3;
+const AHX_NOISE: u8 = 4;
+
+/// Performance list effects
+const AHX_PLIST_SQUARE: u8 = 0x3;
+const AHX_PLIST_JUMP: u8 = 0x5;
+const AHX_PLIST_VOLUME: u8 = 0xC;
+const AHX_PLIST_SPEED: u8 = 0xF;
+
+fn truncated() -> DecodeError {
+    DecodeError::Other("AHX module is truncated")
+}
+
+#[derive(Default, Clone, Copy, Debug)]
+struct AhxPlistEntry {
+    waveform: u8,
+    /// Note does not follow the played note
+    fixed: bool,
+    /// 0: unchanged
+    note: u8,
+    fx: [u8; 2],
+    fx_param: [u8; 2],
+}
+
+#[derive(Default, Debug)]
+struct AhxInstrument {
+    name: String,
+    /// 0-64
+    volume: u8,
+    /// 4 << wave_length samples by cycle
+    wave_length: u8,
+    attack_frames: u8,
+    attack_volume: u8,
+    decay_frames: u8,
+    decay_volume: u8,
+    sustain_frames: u8,
+    release_frames: u8,
+    release_volume: u8,
+    vibrato_delay: u8,
+    vibrato_depth: u8,
+    vibrato_speed: u8,
+    square_lower_limit: u8,
+    /// Ticks per performance list entry
+    plist_speed: u8,
+    plist: Vec<AhxPlistEntry>,
+}
+
+impl AhxInstrument {
+    fn load(data: &[u8], hvl: bool) -> Result<(Self, &[u8]), DecodeError> {
+        if data.len() < AHX_INSTRUMENT_SIZE {
+            return Err(truncated());
+        }
+        let entry_size = if hvl { 5 } else { 4 };
+        let len = data[21] as usize;
+        let size = AHX_INSTRUMENT_SIZE + len * entry_size;
+        if data.len() < size {
+            return Err(truncated());
+        }
+        let plist = data[AHX_INSTRUMENT_SIZE..size]
+            .chunks_exact(entry_size)
+            .map(|e| {
+                if hvl {
+                    AhxPlistEntry {
+                        waveform: e[1] & 0x07,
+                        fixed: e[2] & 0x40 != 0,
+                        note: e[2] & 0x3F,
+                        fx: [e[0] & 0x0F, (e[1] >> 3) & 0x0F],
+                        fx_param: [e[3], e[4]],
+                    }
+                } else {
+                    // AHX uses 6 and 7 for volume and speed
+                    let fx = |f: u8| match f {
+                        6 => AHX_PLIST_VOLUME,
+                        7 => AHX_PLIST_SPEED,
+                        _ => f,
+                    };
+                    AhxPlistEntry {
+                        waveform: ((e[0] << 1) & 0x06) | (e[1] >> 7),
+                        fixed: e[1] & 0x40 != 0,
+                        note: e[1] & 0x3F,
+                        fx: [fx((e[0] >> 2) & 0x07), fx((e[0] >> 5) & 0x07)],
+                        fx_param: [e[2], e[3]],
+                    }
+                }
+            })
+            .collect();
+        let instr = Self {
+            volume: data[0],
+            wave_length: (data[1] & 0x07).min(5),
+            attack_frames: data[2],
+            attack_volume: data[3],
+            decay_frames: data[4],
+            decay_volume: data[5],
+            sustain_frames: data[6],
+            release_frames: data[7],
+            release_volume: data[8],
+            vibrato_delay: data[13],
+            vibrato_depth: data[14] & 0x0F,
+            vibrato_speed: data[15],
+            square_lower_limit: data[16],
+            plist_speed: data[20],
+            plist,
+            ..Default::default()
+        };
+        Ok((instr, &data[size..]))
+    }
+
+    /// Waveform value at `phase` in 0..1
+    fn wave(waveform: u8, phase: f32, square_pos: u8, noise: &[i8]) -> f32 {
+        match waveform {
+            AHX_TRIANGLE => {
+                if phase < 0.25 {
+                    phase * 4.0
+                } else if phase < 0.75 {
+                    2.0 - phase * 4.0
+                } else {
+                    phase * 4.0 - 4.0
+                }
+            }
+            AHX_SAWTOOTH => phase * 2.0 - 1.0,
+            AHX_SQUARE => {
+                if phase * 128.0 < square_pos as f32 {
+                    1.0
+                } else {
+                    -1.0
+                }
+            }
+            AHX_NOISE => noise[(phase * noise.len() as f32) as usize % noise.len()] as f32 / 128.0,
+            _ => 0.0,
+        }
+    }
+
+    /// Render the performance list at C-4, return the data and the loop
+    ///
+    /// Filter and square modulations, and period slides are not rendered.
+    fn bake(&self, ticks_per_second: f32) -> (Vec<i8>, u32, u32) {
+        let frames_per_tick = AHX_CLOCK / AHX_BAKE_PERIOD / ticks_per_second;
+        let mut noise = vec![0i8; AHX_NOISE_LENGTH];
+        let mut seed: u32 = 0x4148_5821;
+        for n in noise.iter_mut() {
+            seed ^= seed << 13;
+            seed ^= seed >> 17;
+            seed ^= seed << 5;
+            *n = (seed >> 24) as i8;
+        }
+        let cycle = |waveform: u8| {
+            if waveform == AHX_NOISE {
+                AHX_NOISE_LENGTH as f32
+            } else {
+                (4 << self.wave_length) as f32
+            }
+        };
+
+        let mut data: Vec<i8> = vec![];
+        let mut step_start: Vec<Option<usize>> = vec![None; self.plist.len()];
+        let (mut waveform, mut note, mut volume) = (0, 0i16, 64);
+        let mut square_pos = if self.square_lower_limit == 0 {
+            0x20
+        } else {
+            self.square_lower_limit
+        };
+        let mut speed = self.plist_speed.max(1) as usize;
+        let mut phase = 0.0;
+        let mut ticks = 0;
+        let mut step = 0;
+        let mut loop_from = None;
+        while step < self.plist.len() && ticks < AHX_BAKE_TICKS {
+            if let Some(start) = step_start[step] {
+                loop_from = Some(start);
+                break;
+            }
+            step_start[step] = Some(data.len());
+            let entry = &self.plist[step];
+            if entry.waveform != 0 {
+                waveform = entry.waveform;
+            }
+            if entry.note != 0 {
+                // fixed notes are kept relative to C-4
+                note = if entry.fixed {
+                    entry.note as i16 - 37
+                } else {
+                    entry.note as i16 - 1
+                };
+            }
+            let mut next = step + 1;
+            let mut duration = speed;
+            for (&fx, &param) in entry.fx.iter().zip(&entry.fx_param) {
+                match fx {
+                    AHX_PLIST_SQUARE => square_pos = param,
+                    AHX_PLIST_JUMP => {
+                        next = param as usize;
+                        duration = 1;
+                    }
+                    AHX_PLIST_VOLUME if param <= 0x40 => volume = param,
+                    AHX_PLIST_SPEED => {
+                        speed = param.max(1) as usize;
+                        duration = speed;
+                    }
+                    _ => {}
+                }
+            }
+            let step_frames = 2.0f32.powf(note as f32 / 12.0) / cycle(waveform);
+            let end = ((ticks + duration) as f32 * frames_per_tick) as usize;
+            while data.len() < end {
+                let v = Self::wave(waveform, phase, square_pos, &noise) * volume as f32 / 64.0;
+                data.push((v * 127.0) as i8);
+                phase = (phase + step_frames) % 1.0;
+            }
+            ticks += duration;
+            step = next;
+        }
+
+        match loop_from {
+            Some(start) if start < data.len() => {
+                let len = data.len() - start;
+                (data, start as u32, len as u32)
+            }
+            _ => {
+                // last cycle forever
+                let frames = (cycle(waveform) / 2.0f32.powf(note as f32 / 12.0)).max(1.0) as usize;
+                let start = data.len();
+                for i in 0..frames {
+                    let phase = i as f32 / frames as f32;
+                    let v = Self::wave(waveform, phase, square_pos, &noise) * volume as f32 / 64.0;
+                    data.push((v * 127.0) as i8);
+                }
+                (data, start as u32, frames as u32)
+            }
+        }
+    }
+
+    /// ADSR, the release begins after the sustain
+    fn volume_envelope(&self) -> Envelope {
+        let mut point: Vec<EnvelopePoint> = vec![];
+        let mut frame = 0;
+        for (frames, value) in [
+            (0, 0),
+            (self.attack_frames, self.attack_volume),
+            (self.decay_frames, self.decay_volume),
+            (self.sustain_frames, self.decay_volume),
+            (self.release_frames, self.release_volume),
+        ] {
+            frame += frames as usize;
+            let value = value.min(64) as f32 / 64.0;
+            match point.last_mut() {
+                Some(last) if last.frame == frame => last.value = value,
+                _ => point.push(EnvelopePoint { frame, value }),
+            }
+        }
+        Envelope {
+            enabled: true,
+            point,
+            ..Default::default()
+        }
+    }
+
+    fn to_instrument(&self, ticks_per_second: f32) -> Instrument {
+        let (data, loop_start, loop_length) = self.bake(ticks_per_second);
+        let mut sample = Sample {
+            name: self.name.clone(),
+            loop_start,
+            loop_length,
+            volume: self.volume.min(64) as f32 / 64.0,
+            finetune: 0.0,
+            flags: LoopType::Forward,
+            panning: 0.5,
+            relative_note: 0,
+            data: SampleDataType::Depth8(data),
+        };
+        sample.fix_loop();
+
+        let instr_def = InstrDefault {
+            volume_envelope: self.volume_envelope(),
+            vibrato: InstrVibrato {
+                waveform: Waveform::Sine,
+                speed: self.vibrato_speed.min(63) as f32 / 63.0,
+                depth: self.vibrato_depth as f32 / 30.0,
+                sweep: self.vibrato_delay as f32 / 255.0,
+            },
+            sample: vec![sample],
+            ..Default::default()
+        };
+
+        Instrument {
+            name: self.name.clone(),
+            instr_type: InstrumentType::Default(instr_def),
+            ..Default::default()
+        }
+    }
+}
+
+/// AHX (Abyss' Highest eXperience) and HivelyTracker Module
+#[derive(Default, Debug)]
+pub struct AhxModule {
+    hvl: bool,
+    title: String,
+    /// Ticks by 1/50 second
+    speed_multiplier: u8,
+    restart_position: usize,
+    channels: usize,
+    /// HVL stereo separation, 0-4
+    stereo: u8,
+    /// Track and transpose of each channel
+    positions: Vec<Vec<(u8, i8)>>,
+    tracks: Vec<Vec<PatternSlot>>,
+    instruments: Vec<AhxInstrument>,
+}
+
+impl AhxModule {
+    pub fn load(ser_ahx_module: &[u8]) -> Result<AhxModule, DecodeError> {
+        let data = ser_ahx_module;
+        let mut ahx = AhxModule {
+            ..Default::default()
+        };
+
+        // === load header
+
+        if data.len() < 14 {
+            return Err(DecodeError::Other("Not an AHX module?"));
+        }
+        ahx.hvl = match (&data[0..3], data[3]) {
+            (b"THX", 0 | 1) => false,
+            (b"HVL", 0 | 1) => true,
+            _ => return Err(DecodeError::Other("Not an AHX module?")),
+        };
+        let names = u16::from_be_bytes([data[4], data[5]]) as usize;
+        let track0_empty = data[6] & 0x80 != 0;
+        let position_count = (((data[6] & 0x0F) as usize) << 8) | data[7] as usize;
+        ahx.speed_multiplier = if data[3] == 0 && !ahx.hvl {
+            1
+        } else {
+            ((data[6] >> 5) & 0x03) + 1
+        };
+        if ahx.hvl {
+            ahx.channels = (data[8] >> 2) as usize + 4;
+            ahx.restart_position = (((data[8] & 0x03) as usize) << 8) | data[9] as usize;
+        } else {
+            ahx.channels = AHX_CHANNELS;
+            ahx.restart_position = u16::from_be_bytes([data[8], data[9]]) as usize;
+        }
+        let track_length = data[10] as usize;
+        let track_count = data[11] as usize + 1;
+        let instrument_count = data[12] as usize;
+        let subsong_count = data[13] as usize;
+        let mut at = 14;
+        if ahx.hvl {
+            ahx.stereo = *data.get(15).ok_or_else(truncated)?;
+            at = 16;
+        }
+        if track_length == 0 || track_length > 64 || ahx.channels > 16 {
+            return Err(DecodeError::Other("Not an AHX module?"));
+        }
+        // subsongs are positions of the main list
+        at += 2 * subsong_count;
+
+        // === positions
+
+        let s = position_count * ahx.channels * 2;
+        let positions = data.get(at..at + s).ok_or_else(truncated)?;
+        ahx.positions = positions
+            .chunks_exact(ahx.channels * 2)
+            .map(|p| p.chunks_exact(2).map(|c| (c[0], c[1] as i8)).collect())
+            .collect();
+        let mut data = &data[at + s..];
+
+        // === tracks
+
+        for t in 0..track_count {
+            if t == 0 && track0_empty {
+                ahx.tracks.push(vec![PatternSlot::default(); track_length]);
+                continue;
+            }
+            let mut track = vec![];
+            for _ in 0..track_length {
+                let slot;
+                (slot, data) = Self::decode_pattern_slot(data, ahx.hvl)?;
+                track.push(slot);
+            }
+            ahx.tracks.push(track);
+        }
+
+        // === instruments
+
+        for _ in 0..instrument_count {
+            let instr;
+            (instr, data) = AhxInstrument::load(data, ahx.hvl)?;
+            ahx.instruments.push(instr);
+        }
+
+        // === names, the title then instruments
+
+        let mut names = ser_ahx_module
+            .get(names..)
+            .unwrap_or_default()
+            .split(|&c| c == 0)
+            .map(|n| String::from_utf8_lossy(n).trim().to_string());
+        ahx.title = names.next().unwrap_or_default();
+        for (instr, name) in ahx.instruments.iter_mut().zip(names) {
+            instr.name = name;
+        }
+
+        Ok(ahx)
+    }
+
+    fn decode_pattern_slot(data: &[u8], hvl: bool) -> Result<(PatternSlot, &[u8]), DecodeError> {
+        let mut slot = PatternSlot::default();
+        if hvl && data.first() == Some(&HVL_EMPTY_ROW) {
+            return Ok((slot, &data[1..]));
+        }
+        let size = if hvl { 5 } else { 3 };
+        if data.len() < size {
+            return Err(truncated());
+        }
+        let note = data[0] >> 2;
+        if (1..=60).contains(&note) {
+            // AHX C-1 is the Amiga C-0
+            slot.note = Note::try_from(note + 12).unwrap_or(Note::None);
+        }
+        slot.instrument = ((data[0] & 0x03) << 4) | (data[1] >> 4);
+
+        // HVL has two effects, the second one goes in the volume column when possible
+        let first = Self::efx_correction(data[1] & 0x0F, data[2]);
+        let second = if hvl {
+            Self::efx_correction(data[3] >> 4, data[4])
+        } else {
+            None
+        };
+        let (effect, volume) = match (first, second) {
+            (Some(a), Some(b)) => match (Self::to_volume_column(b), Self::to_volume_column(a)) {
+                (Some(v), _) => (Some(a), Some(v)),
+                (None, Some(v)) => (Some(b), Some(v)),
+                // no room for both, keep the first one
+                (None, None) => (Some(a), None),
+            },
+            (Some(a), None) | (None, Some(a)) => (Some(a), None),
+            (None, None) => (None, None),
+        };
+        if let Some((effect_type, effect_parameter)) = effect {
+            slot.effect_type = effect_type;
+            slot.effect_parameter = effect_parameter;
+        }
+        if let Some(volume) = volume {
+            slot.volume = volume;
+        }
+        Ok((slot, &data[size..]))
+    }
+
+    /// Convert AHX effects to XM effects
+    fn efx_correction(effect: u8, param: u8) -> Option<(u8, u8)> {
+        match effect {
+            0x1..=0x3 | 0x5 | 0xA if param != 0 || effect == 0x3 => Some((effect, param)),
+            // HVL panning
+            0x7 => Some((0x08, param)),
+            // decimal position
+            0xB => Some((0x0B, (param >> 4) * 10 + (param & 0x0F))),
+            0xC => match param {
+                0x00..=0x40 => Some((0x0C, param)),
+                0xA0..=0xE0 => Some((0x10, param - 0xA0)),
+                _ => None,
+            },
+            0xD => Some((0x0D, param)),
+            // E4x and EFx are AHX only
+            0xE if !matches!(param >> 4, 0x4 | 0xF) => Some((0x0E, param)),
+            0xF if param != 0 => Some((0x0F, param.min(0x1F))),
+            _ => None,
+        }
+    }
+
+    /// XM volume column for some XM effects
+    fn to_volume_column((effect_type, param): (u8, u8)) -> Option<u8> {
+        match effect_type {
+            0x0C => Some(0x10 + param.min(64)),
+            0x0A if param & 0x0F == 0 => Some(0x70 | (param >> 4)),
+            0x0A if param & 0xF0 == 0 => Some(0x60 | param),
+            0x08 if param % 0x11 == 0 => Some(0xC0 | (param >> 4)),
+            _ => None,
+        }
+    }
+
+    pub fn to_module(&self) -> Module {
+        let mut module = Module {
+            name: self.title.clone(),
+            comment: "XmRs reader".to_string(),
+            frequency_type: FrequencyType::AmigaFrequencies,
+            restart_position: self.restart_position,
+            default_tempo: 6,
+            // 50 ticks per second
+            default_bpm: 125 * self.speed_multiplier as u16,
+            ..Default::default()
+        };
+
+        // one pattern by position, identical positions share it
+        for position in &self.positions {
+            if let Some(p) = self.positions.iter().position(|p| p == position) {
+                if p < module.pattern_order.len() {
+                    let order = module.pattern_order[p];
+                    module.pattern_order.push(order);
+                    continue;
+                }
+            }
+            let rows = self.tracks.first().map_or(0, |t| t.len());
+            let pattern: Pattern = (0..rows)
+                .map(|row| {
+                    position
+                        .iter()
+                        .map(|&(track, transpose)| {
+                            let mut slot = self
+                                .tracks
+                                .get(track as usize)
+                                .map_or(PatternSlot::default(), |t| t[row]);
+                            slot.note = slot.note.transpose(transpose as i16).unwrap_or(Note::None);
+                            slot
+                        })
+                        .collect()
+                })
+                .collect();
+            module.pattern_order.push(module.pattern.len());
+            module.pattern.push(pattern);
+        }
+
+        let ticks_per_second = 50.0 * self.speed_multiplier as f32;
+        module.instrument = self
+            .instruments
+            .iter()
+            .map(|i| i.to_instrument(ticks_per_second))
+            .collect();
+
+        // Amiga voices are left, right, right, left
+        const LEFT: [u8; 5] = [128, 96, 64, 32, 0];
+        const RIGHT: [u8; 5] = [128, 160, 193, 224, 255];
+        let stereo = if self.hvl {
+            self.stereo.min(4) as usize
+        } else {
+            4
+        };
+        let panning: Vec<f32> = (0..self.channels)
+            .map(|c| match c % 4 {
+                0 | 3 => LEFT[stereo] as f32 / 255.0,
+                _ => RIGHT[stereo] as f32 / 255.0,
+            })
+            .collect();
+        module.set_initial_panning(&panning);
+
+        module
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Two identical positions of one track, one square instrument
+    fn thx() -> Vec<u8> {
+        let mut data = b"THX\x01".to_vec();
+        // names offset
+        data.extend_from_slice(&72u16.to_be_bytes());
+        // track 0 is empty, 2 positions, restart, 4 rows, 2 tracks, 1 instrument, no subsong
+        data.extend_from_slice(&[0x80, 2, 0, 0, 4, 1, 1, 0]);
+
+        // track 1 on channels 0 and 3, one octave up on channel 3
+        for _ in 0..2 {
+            data.extend_from_slice(&[1, 0, 0, 0, 0, 0, 1, 12]);
+        }
+
+        // C-3, instrument 1, volume C20
+        data.extend_from_slice(&[25 << 2, 0x1C, 0x20]);
+        data.extend_from_slice(&[0; 9]);
+
+        // volume, wave length, attack, decay, sustain, release
+        let mut instr = vec![64, 2, 1, 64, 1, 32, 2, 3, 0];
+        instr.resize(20, 0);
+        // speed, length
+        instr.extend_from_slice(&[1, 2]);
+        // square, then jump to the first entry
+        instr.extend_from_slice(&[0x01, 0x81, 0, 0]);
+        instr.extend_from_slice(&[AHX_PLIST_JUMP << 2, 0, 0, 0]);
+        data.extend_from_slice(&instr);
+
+        data.extend_from_slice(b"title\0square\0");
+        data
+    }
+
+    #[test]
+    fn load_synthetic_module() {
+        let module = AhxModule::load(&thx()).unwrap().to_module();
+        assert_eq!(module.name, "title");
+        assert_eq!(module.default_bpm, 125);
+        // the first position has its own copy for the initial panning
+        assert_eq!(module.pattern_order, vec![1, 0]);
+        assert_eq!(module.pattern.len(), 2);
+        assert_eq!(module.pattern[1][0][0].volume, 0xC0);
+        assert_eq!(module.pattern[1][1..], module.pattern[0][1..]);
+
+        let pattern = &module.pattern[0];
+        assert_eq!(pattern.len(), 4);
+        assert_eq!(pattern[0].len(), AHX_CHANNELS);
+        let slot = &pattern[0][0];
+        assert_eq!(slot.note, Note::C3);
+        assert_eq!(slot.instrument, 1);
+        assert_eq!((slot.effect_type, slot.effect_parameter), (0x0C, 0x20));
+        assert_eq!(pattern[0][1].note, Note::None);
+        assert_eq!(pattern[0][3].note, Note::C4);
+        assert!(pattern[1..].iter().flatten().all(|s| s.note.is_none()));
+
+        assert_eq!(module.instrument.len(), 1);
+        assert_eq!(module.instrument[0].name, "square");
+        let InstrumentType::Default(instr) = &module.instrument[0].instr_type else {
+            panic!("not a sample instrument");
+        };
+        let points: Vec<(usize, f32)> = instr
+            .volume_envelope
+            .point
+            .iter()
+            .map(|p| (p.frame, p.value))
+            .collect();
+        assert_eq!(
+            points,
+            vec![(0, 0.0), (1, 1.0), (2, 0.5), (4, 0.5), (7, 0.0)]
+        );
+
+        // two ticks, the jump loops on the whole list
+        let sample = &instr.sample[0];
+        let frames = (2.0 * AHX_CLOCK / AHX_BAKE_PERIOD / 50.0) as u32;
+        assert!(matches!(sample.flags, LoopType::Forward));
+        assert_eq!((sample.loop_start, sample.loop_length), (0, frames));
+        let SampleDataType::Depth8(data) = &sample.data else {
+            panic!("not an 8 bits sample");
+        };
+        assert_eq!(data.len(), frames as usize);
+        assert_eq!(data[0], 127);
+        assert!(data.iter().all(|&x| x == 127 || x == -127));
+    }
+
+    #[test]
+    fn reject_broken_modules() {
+        let data = thx();
+        assert!(AhxModule::load(&data[..13]).is_err());
+        // in the middle of the track
+        assert!(AhxModule::load(&data[..35]).is_err());
+        // in the middle of the performance list
+        assert!(AhxModule::load(&data[..70]).is_err());
+
+        let mut bad = data.clone();
+        bad[3] = 2;
+        assert!(AhxModule::load(&bad).is_err());
+        let mut bad = data;
+        bad[10] = 0;
+        assert!(AhxModule::load(&bad).is_err());
+    }
+}
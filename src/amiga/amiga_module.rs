@@ -1,5 +1,6 @@
 use crate::amiga::amiga_sample::AmigaSample;
 use crate::amiga::element::*;
+use crate::amiga::unpack::unpack;
 use bincode::error::DecodeError;
 
 use crate::prelude::*;
@@ -55,6 +56,10 @@ impl AmigaModule {
     }
 
     pub fn load(ser_amiga_module: &[u8]) -> Result<AmigaModule, DecodeError> {
+        // packed modules are loaded as ProTracker modules
+        let unpacked = unpack(ser_amiga_module)?;
+        let ser_amiga_module: &[u8] = &unpacked;
+
         let mut amiga = AmigaModule {
             ..Default::default()
         };
@@ -5,3 +5,4 @@ pub mod serde_helper;
 
 pub mod amiga_module;
 pub mod amiga_sample;
+pub mod unpack;
@@ -0,0 +1,1028 @@
+use alloc::borrow::Cow;
+use alloc::{vec, vec::Vec};
+use bincode::error::DecodeError;
+
+use crate::container::is_mod_tag;
+
+/// ProTracker periods from C-1 to B-3
+const PERIODS: [u16; 36] = [
+    856, 808, 762, 720, 678, 640, 604, 570, 538, 508, 480, 453, //
+    428, 404, 381, 360, 339, 320, 302, 285, 269, 254, 240, 226, //
+    214, 202, 190, 180, 170, 160, 151, 143, 135, 127, 120, 113,
+];
+
+/// ProTracker header size, before patterns
+const PTK_HEADER_SIZE: usize = 0x43C;
+/// ProTracker pattern size, 4 channels
+const PTK_PATTERN_SIZE: usize = 64 * 4 * 4;
+
+/// Bits read from the end of PowerPacker data
+struct BackwardBits<'a> {
+    data: &'a [u8],
+    pos: usize,
+    buffer: u64,
+    left: u32,
+}
+
+impl<'a> BackwardBits<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self {
+            data,
+            pos: data.len(),
+            buffer: 0,
+            left: 0,
+        }
+    }
+
+    fn read(&mut self, bits: u32) -> Result<usize, DecodeError> {
+        while self.left < bits {
+            if self.pos == 0 {
+                return Err(DecodeError::Other("PowerPacker data is truncated"));
+            }
+            self.pos -= 1;
+            self.buffer |= (self.data[self.pos] as u64) << self.left;
+            self.left += 8;
+        }
+        self.left -= bits;
+        let mut value = 0;
+        for _ in 0..bits {
+            value = (value << 1) | (self.buffer & 1) as usize;
+            self.buffer >>= 1;
+        }
+        Ok(value)
+    }
+}
+
+/// Decrunch PowerPacker `PP20` data
+pub fn pp20_decrunch(data: &[u8]) -> Result<Vec<u8>, DecodeError> {
+    if data.len() < 12 || &data[0..4] != b"PP20" {
+        return Err(DecodeError::Other("Not PowerPacker data?"));
+    }
+    let offset_bits = &data[4..8];
+    let trailer = &data[data.len() - 4..];
+    let error = || DecodeError::Other("PowerPacker data is corrupted");
+    if trailer[3] > 32 || offset_bits.iter().any(|&b| b > 15) {
+        return Err(error());
+    }
+    let len = ((trailer[0] as usize) << 16) | ((trailer[1] as usize) << 8) | trailer[2] as usize;
+    let mut bits = BackwardBits::new(&data[8..data.len() - 4]);
+    bits.read(trailer[3] as u32)?;
+
+    // written from the end
+    let mut dest = vec![0u8; len];
+    let mut out = len;
+    while out > 0 {
+        if bits.read(1)? == 0 {
+            let mut literals = 1;
+            loop {
+                let x = bits.read(2)?;
+                literals += x;
+                if x != 3 {
+                    break;
+                }
+            }
+            for _ in 0..literals {
+                out = out.checked_sub(1).ok_or_else(error)?;
+                dest[out] = bits.read(8)? as u8;
+            }
+            if out == 0 {
+                break;
+            }
+        }
+        let x = bits.read(2)?;
+        let mut length = x + 2;
+        let offset = if x == 3 {
+            let offset_len = if bits.read(1)? == 0 {
+                7
+            } else {
+                offset_bits[x] as u32
+            };
+            let offset = bits.read(offset_len)?;
+            loop {
+                let x = bits.read(3)?;
+                length += x;
+                if x != 7 {
+                    break;
+                }
+            }
+            offset
+        } else {
+            bits.read(offset_bits[x] as u32)?
+        };
+        if out + offset >= len {
+            return Err(error());
+        }
+        for _ in 0..length {
+            let byte = dest[out + offset];
+            out = out.checked_sub(1).ok_or_else(error)?;
+            dest[out] = byte;
+        }
+    }
+    Ok(dest)
+}
+
+/// ProRunner 1 to ProTracker: sample and note index instead of the period
+fn prorunner1_to_protracker(data: &[u8]) -> Result<Vec<u8>, DecodeError> {
+    let positions = &data[0x3B8..0x438];
+    let patterns = 1 + *positions.iter().max().unwrap_or(&0) as usize;
+    let end = PTK_HEADER_SIZE + patterns * PTK_PATTERN_SIZE;
+    if data.len() < end {
+        return Err(DecodeError::Other("ProRunner patterns are truncated"));
+    }
+    let mut ptk = data.to_vec();
+    ptk[0x438..PTK_HEADER_SIZE].copy_from_slice(b"M.K.");
+    for slot in ptk[PTK_HEADER_SIZE..end].chunks_exact_mut(4) {
+        let (sample, note, effect) = (slot[0], slot[1] as usize / 2, slot[2] & 0x0F);
+        let period = match note {
+            1..=36 => PERIODS[note - 1],
+            _ => 0,
+        };
+        slot[0] = (sample & 0xF0) | (period >> 8) as u8;
+        slot[1] = period as u8;
+        slot[2] = (sample << 4) | effect;
+    }
+    Ok(ptk)
+}
+
+fn truncated() -> DecodeError {
+    DecodeError::Other("Packed module is truncated")
+}
+
+fn get_bytes(data: &[u8], at: usize, len: usize) -> Result<&[u8], DecodeError> {
+    data.get(at..at.checked_add(len).ok_or_else(truncated)?)
+        .ok_or_else(truncated)
+}
+
+fn get_u8(data: &[u8], at: usize) -> Result<u8, DecodeError> {
+    data.get(at).copied().ok_or_else(truncated)
+}
+
+fn get_u16(data: &[u8], at: usize) -> Result<usize, DecodeError> {
+    let b = get_bytes(data, at, 2)?;
+    Ok(u16::from_be_bytes([b[0], b[1]]) as usize)
+}
+
+fn get_u32(data: &[u8], at: usize) -> Result<usize, DecodeError> {
+    let b = get_bytes(data, at, 4)?;
+    Ok(u32::from_be_bytes([b[0], b[1], b[2], b[3]]) as usize)
+}
+
+/// ProTracker note from a note index, 1 is C-1
+fn protracker_note(note: usize, instrument: u8, effect: u8, param: u8) -> [u8; 4] {
+    let period = match note {
+        1..=36 => PERIODS[note - 1],
+        _ => 0,
+    };
+    [
+        (instrument & 0x10) | (period >> 8) as u8,
+        period as u8,
+        (instrument << 4) | (effect & 0x0F),
+        param,
+    ]
+}
+
+/// ProTracker sample header without the name, lengths in words
+fn sample_header(
+    len: usize,
+    finetune: u8,
+    volume: u8,
+    loop_start: usize,
+    loop_len: usize,
+) -> [u8; 8] {
+    let [l0, l1] = (len as u16).to_be_bytes();
+    let [s0, s1] = (loop_start as u16).to_be_bytes();
+    let [r0, r1] = (loop_len.max(1) as u16).to_be_bytes();
+    [l0, l1, finetune & 0x0F, volume.min(64), s0, s1, r0, r1]
+}
+
+/// Volume slide stored as a signed byte, negative slides down
+fn signed_volume_slide(param: u8) -> u8 {
+    if param > 0x80 {
+        (0u8.wrapping_sub(param)).min(0x0F)
+    } else {
+        param << 4
+    }
+}
+
+/// ProTracker module rebuilt from a packed module
+struct Protracker {
+    title: [u8; 20],
+    samples: Vec<[u8; 8]>,
+    positions: Vec<u8>,
+    /// 64 rows of 4 notes by pattern
+    patterns: Vec<u8>,
+    audio: Vec<u8>,
+}
+
+impl Protracker {
+    fn new(samples: Vec<[u8; 8]>, positions: Vec<u8>) -> Self {
+        Self {
+            title: [0; 20],
+            samples,
+            positions,
+            patterns: vec![],
+            audio: vec![],
+        }
+    }
+
+    /// Patterns needed by the positions
+    fn pattern_count(&self) -> usize {
+        1 + *self.positions.iter().max().unwrap_or(&0) as usize
+    }
+
+    /// Sample data size, in bytes
+    fn audio_size(&self) -> usize {
+        self.samples
+            .iter()
+            .map(|s| u16::from_be_bytes([s[0], s[1]]) as usize * 2)
+            .sum()
+    }
+
+    fn save(self) -> Vec<u8> {
+        let mut ptk = self.title.to_vec();
+        for i in 0..31 {
+            ptk.extend_from_slice(&[0; 22]);
+            ptk.extend_from_slice(
+                &self
+                    .samples
+                    .get(i)
+                    .copied()
+                    .unwrap_or(sample_header(0, 0, 0, 0, 1)),
+            );
+        }
+        let mut positions = [0u8; 128];
+        let len = self.positions.len().min(128);
+        positions[0..len].copy_from_slice(&self.positions[0..len]);
+        ptk.push(len as u8);
+        ptk.push(0x7F);
+        ptk.extend_from_slice(&positions);
+        ptk.extend_from_slice(b"M.K.");
+        // the loader reads the patterns used by the positions
+        let mut patterns = self.patterns;
+        patterns.resize(
+            PTK_PATTERN_SIZE * (1 + *positions.iter().max().unwrap_or(&0) as usize),
+            0,
+        );
+        ptk.extend(patterns);
+        ptk.extend(self.audio);
+        ptk
+    }
+}
+
+/// NoisePacker 2 and 3 header checks, return the version
+///
+/// Header: samples << 4 | 0xC, position list size, unused word and track
+/// data size. NoisePacker 3 samples begin with the finetune and volume.
+fn noisepacker_version(data: &[u8]) -> Option<u8> {
+    let word = |at: usize| get_u16(data, at).ok();
+    let first = word(0)?;
+    let sample_count = (first >> 4) & 0xFF;
+    let position_size = word(2)?;
+    let track_size = word(6)?;
+    if first & 0x0F != 0x0C
+        || !(1..=31).contains(&sample_count)
+        || position_size == 0
+        || position_size % 2 != 0
+        || position_size > 256
+        || track_size == 0
+    {
+        return None;
+    }
+    let positions = 8 + 16 * sample_count + 4;
+    let mut pattern_count = 0;
+    for i in 0..position_size / 2 {
+        let p = word(positions + 2 * i)?;
+        if p % 8 != 0 || p / 8 >= 64 {
+            return None;
+        }
+        pattern_count = pattern_count.max(p / 8 + 1);
+    }
+    let tracks = positions + position_size;
+    let track_data = tracks + 8 * pattern_count;
+    if data.len() < track_data + track_size {
+        return None;
+    }
+    let addresses: Vec<usize> = (0..4 * pattern_count)
+        .map(|i| word(tracks + 2 * i))
+        .collect::<Option<_>>()?;
+    if addresses.iter().any(|&a| a >= track_size) {
+        return None;
+    }
+
+    let samples_ok = |finetune: usize, volume: usize| {
+        (0..sample_count).all(|i| {
+            let s = &data[8 + 16 * i..];
+            s[finetune] <= 0x0F && s[volume] <= 64
+        })
+    };
+    if samples_ok(6, 7) && track_size % 0xC0 == 0 && addresses.iter().all(|a| a % 0xC0 == 0) {
+        Some(2)
+    } else if samples_ok(0, 1) {
+        Some(3)
+    } else {
+        None
+    }
+}
+
+/// NoisePacker effects are ProTracker ones, with signed volume slides
+fn noisepacker_effect(effect: u8, param: u8) -> (u8, u8) {
+    match effect {
+        0x05 | 0x06 => (effect, signed_volume_slide(param)),
+        0x07 => (0x0A, signed_volume_slide(param)),
+        0x08 => (0, 0),
+        0x0B => (0x0B, ((param as u16 + 4) / 2) as u8),
+        _ => (effect, param),
+    }
+}
+
+/// NoisePacker 2 and 3 to ProTracker
+///
+/// Tracks are shared between patterns, the last channel is stored first.
+/// NoisePacker 3 skips empty rows: a byte from 0x80 skips `0x100 - byte` rows.
+fn noisepacker_to_protracker(data: &[u8], version: u8) -> Result<Vec<u8>, DecodeError> {
+    let sample_count = (get_u16(data, 0)? >> 4) & 0xFF;
+    let position_count = get_u16(data, 2)? / 2;
+    let track_size = get_u16(data, 6)?;
+
+    let mut samples = vec![];
+    for i in 0..sample_count {
+        let s = get_bytes(data, 8 + 16 * i, 16)?;
+        let (len, finetune, volume) = if version == 3 {
+            (u16::from_be_bytes([s[6], s[7]]), s[0], s[1])
+        } else {
+            (u16::from_be_bytes([s[4], s[5]]), s[6], s[7])
+        };
+        let loop_len = u16::from_be_bytes([s[12], s[13]]) as usize;
+        // loop start in bytes
+        let loop_start = u16::from_be_bytes([s[14], s[15]]) as usize / 2;
+        samples.push(sample_header(
+            len as usize,
+            finetune,
+            volume,
+            loop_start,
+            loop_len,
+        ));
+    }
+
+    let at = 8 + 16 * sample_count + 4;
+    let positions: Vec<u8> = (0..position_count)
+        .map(|i| get_u16(data, at + 2 * i).map(|p| (p / 8) as u8))
+        .collect::<Result<_, _>>()?;
+    let mut ptk = Protracker::new(samples, positions);
+    let pattern_count = ptk.pattern_count();
+    let tracks = at + 2 * position_count;
+    let track_data = tracks + 8 * pattern_count;
+
+    for p in 0..pattern_count {
+        let mut pattern = [0u8; PTK_PATTERN_SIZE];
+        for channel in 0..4 {
+            let address = get_u16(data, tracks + 8 * p + 2 * (3 - channel))?;
+            let mut at = track_data + address;
+            let mut row = 0;
+            while row < 64 {
+                let c1 = get_u8(data, at)?;
+                if version == 3 && c1 >= 0x80 {
+                    row += 0x100 - c1 as usize;
+                    at += 1;
+                    continue;
+                }
+                let e = get_bytes(data, at, 3)?;
+                at += 3;
+                let instrument = ((e[0] & 0x01) << 4) | (e[1] >> 4);
+                let (effect, param) = noisepacker_effect(e[1] & 0x0F, e[2]);
+                let note = protracker_note((e[0] >> 1) as usize, instrument, effect, param);
+                let x = row * 16 + channel * 4;
+                pattern[x..x + 4].copy_from_slice(&note);
+                row += 1;
+            }
+        }
+        ptk.patterns.extend_from_slice(&pattern);
+    }
+
+    let audio = data.get(track_data + track_size..).unwrap_or_default();
+    ptk.audio = audio[0..ptk.audio_size().min(audio.len())].to_vec();
+    Ok(ptk.save())
+}
+
+/// ProRunner 2 to ProTracker
+///
+/// After `SNT!`, the sample data offset, 31 ProTracker samples without name,
+/// the song length, the restart and 128 positions. Notes are `0x80` when
+/// empty, `0xC0` to repeat the last note of the channel, or
+/// `nnnnnnnE iiiiiEEE pppppppp`.
+fn prorunner2_to_protracker(data: &[u8]) -> Result<Vec<u8>, DecodeError> {
+    let audio = get_u32(data, 4)?;
+    let header = get_bytes(data, 8, 31 * 8 + 2 + 128)?;
+    let samples = header[0..31 * 8]
+        .chunks_exact(8)
+        .map(|s| {
+            let word = |i: usize| u16::from_be_bytes([s[i], s[i + 1]]) as usize;
+            sample_header(word(0), s[2], s[3], word(4), word(6))
+        })
+        .collect();
+    let song_length = (header[248] as usize).clamp(1, 128);
+    let mut ptk = Protracker::new(samples, header[250..250 + song_length].to_vec());
+    let pattern_count = 1 + *header[250..].iter().max().unwrap_or(&0) as usize;
+
+    let mut at = 8 + header.len();
+    let mut last = [[0u8; 4]; 4];
+    for _ in 0..pattern_count {
+        for i in 0..64 * 4 {
+            let note = match get_u8(data, at)? {
+                0x80 => {
+                    at += 1;
+                    [0; 4]
+                }
+                0xC0 => {
+                    at += 1;
+                    last[i % 4]
+                }
+                _ => {
+                    let e = get_bytes(data, at, 3)?;
+                    at += 3;
+                    let effect = ((e[0] & 0x01) << 3) | (e[1] & 0x07);
+                    protracker_note((e[0] >> 1) as usize, e[1] >> 3, effect, e[2])
+                }
+            };
+            last[i % 4] = note;
+            ptk.patterns.extend_from_slice(&note);
+        }
+    }
+    let audio = data.get(audio..).unwrap_or_default();
+    ptk.audio = audio[0..ptk.audio_size().min(audio.len())].to_vec();
+    Ok(ptk.save())
+}
+
+/// The Player 6.1A effects: `8xy` is `Exy`, volume slides are signed
+fn p61a_effect(effect: u8, param: u8) -> (u8, u8) {
+    match effect {
+        0x05 | 0x06 | 0x0A => (effect, signed_volume_slide(param)),
+        0x08 => (0x0E, param),
+        _ => (effect, param),
+    }
+}
+
+/// One track of a The Player 6.1A module
+///
+/// Notes are `x1111111` when empty, `x111eeee pppppppp` for an effect only,
+/// or `xnnnnnni iiiieeee pppppppp`. When `x` is set, a byte follows:
+/// `00nnnnnn` empty rows, `01nnnnnn` repeats of the note, `10nnnnnn oooooooo`
+/// or `11nnnnnn oooooooo oooooooo` plays `n + 1` notes from `o` bytes back.
+fn p61a_track(data: &[u8], mut at: usize) -> Result<[[u8; 4]; 64], DecodeError> {
+    let error = || DecodeError::Other("The Player 6.1A track is corrupted");
+    let mut rows = [[0u8; 4]; 64];
+    let mut row = 0;
+    // notes left to play and where to go back after a jump
+    let mut jump: Option<(usize, usize)> = None;
+    while row < 64 {
+        let c1 = get_u8(data, at)?;
+        at += 1;
+        let note = match c1 & 0x7F {
+            0x7F => [0; 4],
+            c if c & 0x70 == 0x70 => {
+                let (effect, param) = p61a_effect(c & 0x0F, get_u8(data, at)?);
+                at += 1;
+                protracker_note(0, 0, effect, param)
+            }
+            c => {
+                let e = get_bytes(data, at, 2)?;
+                at += 2;
+                let instrument = ((c & 0x01) << 4) | (e[0] >> 4);
+                let (effect, param) = p61a_effect(e[0] & 0x0F, e[1]);
+                protracker_note((c >> 1) as usize, instrument, effect, param)
+            }
+        };
+        // rows with the note, then empty rows
+        let (mut repeat, mut empty) = (0, 0);
+        if c1 & 0x80 != 0 {
+            let info = get_u8(data, at)?;
+            at += 1;
+            match info >> 6 {
+                0 => empty = (info & 0x3F) as usize,
+                1 => repeat = (info & 0x3F) as usize,
+                _ => {
+                    if jump.is_some() {
+                        return Err(error());
+                    }
+                    let offset = if info & 0x40 != 0 {
+                        at += 2;
+                        get_u16(data, at - 2)?
+                    } else {
+                        at += 1;
+                        get_u8(data, at - 1)? as usize
+                    };
+                    jump = Some(((info & 0x3F) as usize + 1, at));
+                    at = at.checked_sub(offset).ok_or_else(error)?;
+                    continue;
+                }
+            }
+        }
+        for _ in 0..=repeat {
+            if let Some(r) = rows.get_mut(row) {
+                *r = note;
+            }
+            row += 1;
+        }
+        row += empty;
+        if let Some((left, back)) = jump {
+            jump = if left > 1 {
+                Some((left - 1, back))
+            } else {
+                at = back;
+                None
+            };
+        }
+    }
+    Ok(rows)
+}
+
+/// The Player 6.1A to ProTracker
+///
+/// After an optional `P61A`: the sample data offset, the pattern count and
+/// the sample count, with delta samples when bit 7 is set. Samples have a
+/// length, a finetune, a volume and a loop start, a negative length uses
+/// the data of an other sample. Then 4 track offsets per pattern, positions
+/// ended by 0xFF and the tracks.
+fn p61a_to_protracker(data: &[u8]) -> Result<Vec<u8>, DecodeError> {
+    let data = data.strip_prefix(b"P61A").unwrap_or(data);
+    let audio_start = get_u16(data, 0)?;
+    let pattern_count = get_u8(data, 2)? as usize;
+    let flags = get_u8(data, 3)?;
+    let sample_count = (flags & 0x3F) as usize;
+    let delta = flags & 0x80 != 0;
+    if flags & 0x40 != 0 {
+        return Err(DecodeError::Other(
+            "The Player 6.1A packed samples are not supported",
+        ));
+    }
+    if pattern_count == 0 || sample_count > 31 {
+        return Err(DecodeError::Other("Not a The Player 6.1A module?"));
+    }
+
+    let mut samples = vec![];
+    // data offset and length of each sample
+    let mut sample_data: Vec<(usize, usize)> = vec![];
+    let mut audio_at = audio_start;
+    for i in 0..sample_count {
+        let s = get_bytes(data, 4 + 6 * i, 6)?;
+        let len = u16::from_be_bytes([s[0], s[1]]);
+        if s[2] & 0x80 != 0 {
+            return Err(DecodeError::Other(
+                "The Player 6.1A packed samples are not supported",
+            ));
+        }
+        let (at, len) = if len & 0x8000 != 0 {
+            *sample_data
+                .get(!len as usize)
+                .ok_or(DecodeError::Other("The Player 6.1A sample is corrupted"))?
+        } else {
+            audio_at += len as usize * 2;
+            (audio_at - len as usize * 2, len as usize)
+        };
+        let loop_start = u16::from_be_bytes([s[4], s[5]]) as usize;
+        samples.push(if loop_start == 0xFFFF || loop_start >= len {
+            sample_header(len, s[2], s[3], 0, 1)
+        } else {
+            sample_header(len, s[2], s[3], loop_start, len - loop_start)
+        });
+        sample_data.push((at, len));
+    }
+
+    let tracks = 4 + 6 * sample_count;
+    let mut at = tracks + 8 * pattern_count;
+    let mut positions = vec![];
+    loop {
+        let p = get_u8(data, at)?;
+        at += 1;
+        if p == 0xFF {
+            break;
+        }
+        if p as usize >= pattern_count || positions.len() == 128 {
+            return Err(DecodeError::Other("Not a The Player 6.1A module?"));
+        }
+        positions.push(p);
+    }
+    let track_data = at;
+
+    let mut ptk = Protracker::new(samples, positions);
+    for p in 0..pattern_count {
+        let mut pattern = [0u8; PTK_PATTERN_SIZE];
+        for channel in 0..4 {
+            let offset = get_u16(data, tracks + 8 * p + 2 * channel)?;
+            for (row, note) in p61a_track(data, track_data + offset)?.iter().enumerate() {
+                let x = row * 16 + channel * 4;
+                pattern[x..x + 4].copy_from_slice(note);
+            }
+        }
+        ptk.patterns.extend_from_slice(&pattern);
+    }
+    for (at, len) in sample_data {
+        let audio = data.get(at..).unwrap_or_default();
+        let audio = &audio[0..(len * 2).min(audio.len())];
+        if delta {
+            let mut value = 0u8;
+            ptk.audio.extend(audio.iter().map(|&d| {
+                value = value.wrapping_add(d);
+                value
+            }));
+        } else {
+            ptk.audio.extend_from_slice(audio);
+        }
+    }
+    Ok(ptk.save())
+}
+
+/// StarTrekker Packer header checks
+///
+/// Title, 31 ProTracker samples without name, the song length times 4, a
+/// zero word, 128 pattern offsets by position and the sample data offset.
+fn is_startrekker_packer(data: &[u8]) -> bool {
+    let Ok(header) = get_bytes(data, 0, 788) else {
+        return false;
+    };
+    let word = |at: usize| u16::from_be_bytes([header[at], header[at + 1]]) as usize;
+    let long = |at: usize| {
+        u32::from_be_bytes([header[at], header[at + 1], header[at + 2], header[at + 3]]) as usize
+    };
+    let song_length = word(268);
+    let samples_ok = header[20..268]
+        .chunks_exact(8)
+        .all(|s| s[2] <= 0x0F && s[3] <= 64);
+    let offsets_ok = (0..song_length / 4).all(|i| {
+        let offset = long(272 + 4 * i);
+        788 + offset < data.len()
+    });
+    samples_ok
+        && song_length != 0
+        && song_length % 4 == 0
+        && song_length <= 512
+        && word(270) == 0
+        && long(272) == 0
+        && offsets_ok
+        && 788 + long(784) <= data.len()
+}
+
+/// StarTrekker Packer to ProTracker
+///
+/// Pattern notes are `0x80` when empty, else a ProTracker note.
+fn startrekker_packer_to_protracker(data: &[u8]) -> Result<Vec<u8>, DecodeError> {
+    let header = get_bytes(data, 0, 788)?;
+    let samples = header[20..268]
+        .chunks_exact(8)
+        .map(|s| {
+            let word = |i: usize| u16::from_be_bytes([s[i], s[i + 1]]) as usize;
+            sample_header(word(0), s[2], s[3], word(4), word(6))
+        })
+        .collect();
+    let song_length = (get_u16(header, 268)? / 4).min(128);
+    let offsets: Vec<usize> = (0..song_length)
+        .map(|i| get_u32(header, 272 + 4 * i))
+        .collect::<Result<_, _>>()?;
+    // patterns are saved in order
+    let mut patterns = offsets.clone();
+    patterns.sort_unstable();
+    patterns.dedup();
+    let positions = offsets
+        .iter()
+        .map(|o| patterns.iter().position(|p| p == o).unwrap_or(0) as u8)
+        .collect();
+
+    let mut ptk = Protracker::new(samples, positions);
+    ptk.title.copy_from_slice(&header[0..20]);
+    for offset in patterns {
+        let mut at = 788 + offset;
+        for _ in 0..64 * 4 {
+            if get_u8(data, at)? == 0x80 {
+                ptk.patterns.extend_from_slice(&[0; 4]);
+                at += 1;
+            } else {
+                ptk.patterns.extend_from_slice(get_bytes(data, at, 4)?);
+                at += 4;
+            }
+        }
+    }
+
+    let audio = data.get(788 + get_u32(header, 784)?..).unwrap_or_default();
+    ptk.audio = audio[0..ptk.audio_size().min(audio.len())].to_vec();
+    Ok(ptk.save())
+}
+
+/// Packed Amiga module formats
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PackedFormat {
+    ProRunner1,
+    ProRunner2,
+    NoisePacker(u8),
+    ThePlayer61A,
+    StarTrekkerPacker,
+}
+
+fn packer(data: &[u8]) -> Option<PackedFormat> {
+    let tag = data.get(0x438..PTK_HEADER_SIZE);
+    if data.starts_with(b"P61A") {
+        Some(PackedFormat::ThePlayer61A)
+    } else if data.starts_with(b"SNT!") {
+        Some(PackedFormat::ProRunner2)
+    } else if tag == Some(b"SNT.") {
+        Some(PackedFormat::ProRunner1)
+    } else if tag.is_some_and(is_mod_tag) {
+        None
+    } else if let Some(version) = noisepacker_version(data) {
+        Some(PackedFormat::NoisePacker(version))
+    } else if is_startrekker_packer(data) {
+        Some(PackedFormat::StarTrekkerPacker)
+    } else {
+        None
+    }
+}
+
+/// True for PowerPacker files and packed modules without a ProTracker tag
+pub fn is_packed(data: &[u8]) -> bool {
+    data.starts_with(b"PP20") || packer(data).is_some()
+}
+
+/// ProTracker data of a packed Amiga module, unchanged data if it is not packed
+///
+/// Supports PowerPacker `PP20` crunched files, ProRunner 1 and 2,
+/// NoisePacker 2 and 3, The Player 6.1A and StarTrekker Packer modules.
+pub fn unpack(data: &[u8]) -> Result<Cow<'_, [u8]>, DecodeError> {
+    let data: Cow<[u8]> = if data.starts_with(b"PP20") {
+        Cow::Owned(pp20_decrunch(data)?)
+    } else {
+        Cow::Borrowed(data)
+    };
+
+    let ptk = match packer(&data) {
+        Some(PackedFormat::ProRunner1) => prorunner1_to_protracker(&data)?,
+        Some(PackedFormat::ProRunner2) => prorunner2_to_protracker(&data)?,
+        Some(PackedFormat::NoisePacker(version)) => noisepacker_to_protracker(&data, version)?,
+        Some(PackedFormat::ThePlayer61A) => p61a_to_protracker(&data)?,
+        Some(PackedFormat::StarTrekkerPacker) => startrekker_packer_to_protracker(&data)?,
+        None => return Ok(data),
+    };
+    Ok(Cow::Owned(ptk))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// PowerPacker data, read from the last byte, first bit is bit 0
+    fn pp20(offset_bits: [u8; 4], skip: u8, values: &[(usize, u32)], len: usize) -> Vec<u8> {
+        let mut bits = vec![0u8; skip as usize];
+        for &(value, width) in values {
+            bits.extend((0..width).rev().map(|i| (value >> i) as u8 & 1));
+        }
+        let mut body = vec![0u8; bits.len().div_ceil(8)];
+        let last = body.len() - 1;
+        for (i, bit) in bits.iter().enumerate() {
+            body[last - i / 8] |= bit << (i % 8);
+        }
+        let mut data = b"PP20".to_vec();
+        data.extend_from_slice(&offset_bits);
+        data.extend(body);
+        data.extend_from_slice(&[(len >> 16) as u8, (len >> 8) as u8, len as u8, skip]);
+        data
+    }
+
+    /// One pattern, one sample of 2 words, the rest is at the caller
+    fn sample() -> [u8; 8] {
+        sample_header(2, 0, 64, 0, 1)
+    }
+
+    fn note(data: &[u8], pattern: usize, row: usize, channel: usize) -> &[u8] {
+        let at = PTK_HEADER_SIZE + pattern * PTK_PATTERN_SIZE + row * 16 + channel * 4;
+        &data[at..at + 4]
+    }
+
+    /// C-2, instrument 1
+    const C2: [u8; 2] = [0x01, 0xAC];
+
+    fn noisepacker(version: u8) -> Vec<u8> {
+        // one sample, one position, track data size
+        let track: Vec<u8> = if version == 3 {
+            vec![26, 0x17, 0xFE, 0x81]
+        } else {
+            let mut track = vec![26, 0x17, 0xFE];
+            track.resize(64 * 3, 0);
+            track
+        };
+        let mut data = vec![0x00, 0x1C, 0, 2, 0, 0];
+        data.extend_from_slice(&(track.len() as u16).to_be_bytes());
+        let mut sample = [0u8; 16];
+        if version == 3 {
+            sample[0..2].copy_from_slice(&[0, 64]);
+            sample[6..8].copy_from_slice(&[0, 2]);
+        } else {
+            sample[4..8].copy_from_slice(&[0, 2, 0, 64]);
+        }
+        sample[12..14].copy_from_slice(&[0, 1]);
+        data.extend_from_slice(&sample);
+        data.extend_from_slice(&[0; 4]);
+        // position, the same track on every channel
+        data.extend_from_slice(&[0; 2 + 8]);
+        data.extend(track);
+        data.extend_from_slice(&[1, 2, 3, 4]);
+        data
+    }
+
+    fn prorunner2() -> Vec<u8> {
+        let mut data = b"SNT!".to_vec();
+        data.extend_from_slice(&644u32.to_be_bytes());
+        data.extend_from_slice(&sample());
+        data.resize(8 + 31 * 8, 0);
+        data.extend_from_slice(&[1, 0]);
+        data.extend_from_slice(&[0; 128]);
+        // C-2, instrument 1, C20, then the note again on row 1
+        data.extend_from_slice(&[27, 0x0C, 0x20, 0x80, 0x80, 0x80, 0xC0]);
+        data.resize(644, 0x80);
+        data.extend_from_slice(&[1, 2, 3, 4]);
+        data
+    }
+
+    fn p61a() -> Vec<u8> {
+        let mut data = b"P61A".to_vec();
+        // one pattern, one delta sample without loop
+        data.extend_from_slice(&[0, 33, 1, 0x81, 0, 2, 0, 64, 0xFF, 0xFF]);
+        data.extend_from_slice(&[0, 0, 0, 6, 0, 0, 0, 0, 0, 0xFF]);
+        // C-2 for 4 rows, 60 empty rows
+        data.extend_from_slice(&[0x9A, 0x1C, 0x30, 0x43, 0xFF, 0x3B]);
+        // C20, played again by a jump 5 bytes back, 62 empty rows
+        data.extend_from_slice(&[0x7C, 0x20, 0xFF, 0x80, 5, 0xFF, 0x3D]);
+        data.extend_from_slice(&[1, 1, 1, 1]);
+        data
+    }
+
+    fn startrekker_packer() -> Vec<u8> {
+        let mut data = b"synthetic".to_vec();
+        data.resize(20, 0);
+        data.extend_from_slice(&sample());
+        data.resize(268, 0);
+        // positions 0, 1, 0
+        data.extend_from_slice(&[0, 12, 0, 0]);
+        for offset in [0u32, 259, 0] {
+            data.extend_from_slice(&offset.to_be_bytes());
+        }
+        data.resize(784, 0);
+        data.extend_from_slice(&515u32.to_be_bytes());
+        data.extend_from_slice(&[0x01, 0xAC, 0x1C, 0x30]);
+        data.resize(788 + 515, 0x80);
+        data.extend_from_slice(&[1, 2, 3, 4]);
+        data
+    }
+
+    #[test]
+    fn decrunch_literals() {
+        // 3 literals, written from the end
+        let data = pp20(
+            [9, 10, 11, 12],
+            5,
+            &[
+                (0, 1),
+                (2, 2),
+                (b'c' as usize, 8),
+                (b'b' as usize, 8),
+                (b'a' as usize, 8),
+            ],
+            3,
+        );
+        assert_eq!(pp20_decrunch(&data).unwrap(), b"abc");
+    }
+
+    #[test]
+    fn decrunch_match() {
+        // 2 literals, then 4 bytes from 1 byte further
+        let data = pp20(
+            [9, 9, 9, 9],
+            0,
+            &[
+                (0, 1),
+                (1, 2),
+                (b'y' as usize, 8),
+                (b'x' as usize, 8),
+                (2, 2),
+                (1, 9),
+            ],
+            6,
+        );
+        assert_eq!(pp20_decrunch(&data).unwrap(), b"xyxyxy");
+    }
+
+    #[test]
+    fn reject_bad_bit_counts() {
+        let data = pp20([9, 9, 9, 9], 33, &[(0, 1)], 1);
+        assert!(pp20_decrunch(&data).is_err());
+        let data = pp20([9, 16, 9, 9], 0, &[(0, 1)], 1);
+        assert!(pp20_decrunch(&data).is_err());
+        assert!(pp20_decrunch(b"PP20\x09\x09\x09\x09\x00\x00\x05\x00").is_err());
+    }
+
+    #[test]
+    fn decrunch_random_data() {
+        let mut seed = 0x1234_5678u32;
+        for _ in 0..2000 {
+            let mut data = b"PP20".to_vec();
+            seed ^= seed << 13;
+            seed ^= seed >> 17;
+            seed ^= seed << 5;
+            let len = 8 + seed as usize % 64;
+            for _ in 0..len {
+                seed ^= seed << 13;
+                seed ^= seed >> 17;
+                seed ^= seed << 5;
+                data.push(seed as u8);
+            }
+            let _ = pp20_decrunch(&data);
+            let _ = unpack(&data);
+        }
+    }
+
+    #[test]
+    fn unpack_noisepacker() {
+        for version in [2, 3] {
+            let data = noisepacker(version);
+            assert_eq!(packer(&data), Some(PackedFormat::NoisePacker(version)));
+            let ptk = unpack(&data).unwrap();
+            assert_eq!(&ptk[0x438..PTK_HEADER_SIZE], b"M.K.");
+            assert_eq!(&ptk[42..50], &sample());
+            for channel in 0..4 {
+                assert_eq!(note(&ptk, 0, 0, channel), &[C2[0], C2[1], 0x1A, 0x02]);
+                assert_eq!(note(&ptk, 0, 1, channel), &[0; 4]);
+            }
+            assert_eq!(ptk.len(), PTK_HEADER_SIZE + PTK_PATTERN_SIZE + 4);
+            assert_eq!(&ptk[ptk.len() - 4..], &[1, 2, 3, 4]);
+        }
+    }
+
+    #[test]
+    fn unpack_prorunner2() {
+        let data = prorunner2();
+        let ptk = unpack(&data).unwrap();
+        assert_eq!(&ptk[42..50], &sample());
+        assert_eq!(note(&ptk, 0, 0, 0), &[C2[0], C2[1], 0x1C, 0x20]);
+        assert_eq!(note(&ptk, 0, 1, 0), &[C2[0], C2[1], 0x1C, 0x20]);
+        assert_eq!(note(&ptk, 0, 0, 1), &[0; 4]);
+        assert_eq!(&ptk[ptk.len() - 4..], &[1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn unpack_p61a() {
+        let data = p61a();
+        let ptk = unpack(&data).unwrap();
+        assert_eq!(&ptk[42..50], &sample());
+        for row in 0..4 {
+            assert_eq!(note(&ptk, 0, row, 0), &[C2[0], C2[1], 0x1C, 0x30]);
+        }
+        assert_eq!(note(&ptk, 0, 4, 0), &[0; 4]);
+        assert_eq!(note(&ptk, 0, 0, 1), &[0, 0, 0x0C, 0x20]);
+        assert_eq!(note(&ptk, 0, 1, 1), &[0, 0, 0x0C, 0x20]);
+        assert_eq!(note(&ptk, 0, 2, 1), &[0; 4]);
+        assert_eq!(ptk.len(), PTK_HEADER_SIZE + PTK_PATTERN_SIZE + 4);
+        assert_eq!(&ptk[ptk.len() - 4..], &[1, 2, 3, 4]);
+
+        // packed samples
+        let mut data = p61a();
+        data[7] |= 0x40;
+        assert!(unpack(&data).is_err());
+        // nested jump
+        let mut data = p61a();
+        data[27] = 0xFF;
+        data[28] = 0x80;
+        data[29] = 0;
+        assert!(unpack(&data).is_err());
+    }
+
+    #[test]
+    fn unpack_startrekker_packer() {
+        let data = startrekker_packer();
+        assert_eq!(packer(&data), Some(PackedFormat::StarTrekkerPacker));
+        let ptk = unpack(&data).unwrap();
+        assert_eq!(&ptk[0..9], b"synthetic");
+        assert_eq!(&ptk[0x3B6..0x3BB], &[3, 0x7F, 0, 1, 0]);
+        assert_eq!(note(&ptk, 0, 0, 0), &[C2[0], C2[1], 0x1C, 0x30]);
+        assert_eq!(note(&ptk, 1, 0, 0), &[0; 4]);
+        assert_eq!(note(&ptk, 1, 63, 3), &[0; 4]);
+        assert_eq!(ptk.len(), PTK_HEADER_SIZE + 2 * PTK_PATTERN_SIZE + 4);
+        assert_eq!(&ptk[ptk.len() - 4..], &[1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn packed_modules_are_detected() {
+        use crate::container::{detect, ModuleFormat};
+        for data in [
+            noisepacker(2),
+            noisepacker(3),
+            prorunner2(),
+            p61a(),
+            startrekker_packer(),
+        ] {
+            assert_eq!(detect(&data), Some(ModuleFormat::Mod));
+            assert!(crate::amiga::amiga_module::AmigaModule::load(&data).is_ok());
+        }
+        let mut data = startrekker_packer();
+        data.resize(PTK_HEADER_SIZE, 0);
+        data[0x438..PTK_HEADER_SIZE].copy_from_slice(b"M.K.");
+        assert!(!is_packed(&data));
+        assert!(!is_packed(&[0; 64]));
+    }
+}
@@ -0,0 +1,409 @@
+use bincode::error::DecodeError;
+
+use crate::prelude::*;
+
+use alloc::string::String;
+use alloc::string::ToString;
+use alloc::{vec, vec::Vec};
+
+const C669_HEADER_SIZE: usize = 0x1F1;
+const C669_SAMPLE_SIZE: usize = 25;
+const C669_CHANNELS: usize = 8;
+const C669_ROWS: usize = 64;
+const C669_PATTERN_SIZE: usize = C669_ROWS * C669_CHANNELS * 3;
+/// End of the order list
+const C669_ORDER_END: u8 = 0xFF;
+/// No pattern break
+const C669_NO_BREAK: u8 = 63;
+
+#[derive(Default, Debug)]
+struct C669Sample {
+    filename: String,
+    len: u32,
+    loop_start: u32,
+    /// 0xFFFFF: no loop
+    loop_end: u32,
+}
+
+impl C669Sample {
+    fn load(data: &[u8]) -> Self {
+        let u32_at =
+            |i: usize| u32::from_le_bytes([data[i], data[i + 1], data[i + 2], data[i + 3]]);
+        Self {
+            filename: String::from_utf8_lossy(&data[0..13])
+                .trim_matches(char::from(0))
+                .trim()
+                .to_string(),
+            len: u32_at(13),
+            loop_start: u32_at(17),
+            loop_end: u32_at(21),
+        }
+    }
+
+    fn is_loop(&self) -> bool {
+        self.loop_end > self.loop_start && self.loop_end <= self.len
+    }
+}
+
+/// Composer 669 and UNIS 669
+#[derive(Default, Debug)]
+pub struct C669Module {
+    /// Three lines of 36 chars
+    message: String,
+    restart_position: u8,
+    positions: Vec<u8>,
+    /// Ticks per row of each pattern
+    tempos: Vec<u8>,
+    /// Last row of each pattern
+    breaks: Vec<u8>,
+    samples: Vec<C669Sample>,
+    audio: Vec<Vec<i8>>,
+    patterns: Vec<Pattern>,
+}
+
+impl C669Module {
+    pub fn load(ser_669_module: &[u8]) -> Result<C669Module, DecodeError> {
+        let mut c669 = C669Module {
+            ..Default::default()
+        };
+
+        // === load header
+
+        let s = C669_HEADER_SIZE;
+        if ser_669_module.len() < s {
+            return Err(DecodeError::Other("Not a 669 module?"));
+        }
+        let header = &ser_669_module[0..s];
+        let data = &ser_669_module[s..];
+
+        // "if" for Composer 669, "JN" for UNIS 669
+        if &header[0..2] != b"if" && &header[0..2] != b"JN" {
+            return Err(DecodeError::Other("Not a 669 module?"));
+        }
+        c669.message = String::from_utf8_lossy(&header[2..110])
+            .trim_matches(char::from(0))
+            .trim()
+            .to_string();
+        let sample_count = header[110] as usize;
+        let pattern_count = header[111] as usize;
+        c669.restart_position = header[112];
+        let orders = &header[113..241];
+        c669.tempos = header[241..369].to_vec();
+        c669.breaks = header[369..497].to_vec();
+
+        if sample_count > 64 || pattern_count > 128 || c669.restart_position >= 128 {
+            return Err(DecodeError::Other("Not a 669 module?"));
+        }
+        for (&order, &tempo) in orders.iter().zip(&c669.tempos) {
+            if (128..0xFE).contains(&order) || (order < 128 && !(1..=15).contains(&tempo)) {
+                return Err(DecodeError::Other("Not a 669 module?"));
+            }
+        }
+
+        c669.positions = orders
+            .iter()
+            .copied()
+            .take_while(|&p| p != C669_ORDER_END)
+            .filter(|&p| (p as usize) < pattern_count)
+            .collect();
+
+        // === samples
+
+        let s = sample_count * C669_SAMPLE_SIZE;
+        if data.len() < s {
+            return Err(DecodeError::Other("Not a 669 module?"));
+        }
+        c669.samples = data[0..s]
+            .chunks_exact(C669_SAMPLE_SIZE)
+            .map(C669Sample::load)
+            .collect();
+        let data = &data[s..];
+
+        // === patterns
+
+        let s = pattern_count * C669_PATTERN_SIZE;
+        if data.len() < s {
+            return Err(DecodeError::Other("669 patterns are truncated"));
+        }
+        for (p, pattern_data) in data[0..s].chunks_exact(C669_PATTERN_SIZE).enumerate() {
+            c669.patterns.push(c669.decode_pattern(p, pattern_data));
+        }
+        let mut data = &data[s..];
+
+        // === audio, unsigned 8 bits one after the other
+
+        for sample in &c669.samples {
+            let len = (sample.len as usize).min(data.len());
+            c669.audio
+                .push(data[0..len].iter().map(|&x| (x ^ 0x80) as i8).collect());
+            data = &data[len..];
+        }
+
+        Ok(c669)
+    }
+
+    fn decode_pattern(&self, p: usize, data: &[u8]) -> Pattern {
+        let mut pattern: Pattern = vec![vec![PatternSlot::default(); C669_CHANNELS]; C669_ROWS];
+        // 669 effects go on every row until a new note
+        let mut effect: [Option<u8>; C669_CHANNELS] = [None; C669_CHANNELS];
+
+        for (slot_data, (row, channel)) in data
+            .chunks_exact(3)
+            .zip((0..C669_ROWS).flat_map(|r| (0..C669_CHANNELS).map(move |c| (r, c))))
+        {
+            let slot = &mut pattern[row][channel];
+            let (note_instr, instr_vol, eff_param) = (slot_data[0], slot_data[1], slot_data[2]);
+
+            // 0xFE: volume only, 0xFF: nothing
+            if note_instr < 0xFE {
+                slot.note = Note::try_from(1 + 24 + (note_instr >> 2)).unwrap_or(Note::None);
+                slot.instrument = 1 + (((note_instr & 0x03) << 4) | (instr_vol >> 4));
+                effect[channel] = None;
+            }
+            if note_instr <= 0xFE {
+                let volume = ((instr_vol & 0x0F) as u16 * 64 + 8) / 15;
+                slot.volume = 0x10 + volume as u8;
+            }
+
+            if eff_param != 0xFF {
+                effect[channel] = Some(eff_param);
+            }
+            // a zero parameter stops the effect, except for frequency adjust
+            if eff_param & 0x0F == 0 && eff_param != 0x30 {
+                effect[channel] = None;
+            }
+            if let Some(eff) = effect[channel] {
+                if Self::efx_correction(eff, slot) {
+                    effect[channel] = None;
+                }
+            }
+        }
+
+        // first row sets the pattern speed
+        let speed = self.tempos.get(p).copied().unwrap_or(4);
+        if let Some(slot) = pattern[0]
+            .iter_mut()
+            .find(|s| s.effect_type == 0 && s.effect_parameter == 0)
+        {
+            slot.effect_type = 0x0F;
+            slot.effect_parameter = speed;
+        }
+
+        // pattern break: rows after it never play
+        let brk = self.breaks.get(p).copied().unwrap_or(C669_NO_BREAK);
+        if brk < C669_NO_BREAK {
+            pattern.truncate(brk as usize + 1);
+        }
+
+        pattern
+    }
+
+    /// Convert a 669 effect to XM, return true when it is played only once
+    fn efx_correction(eff: u8, n: &mut PatternSlot) -> bool {
+        let param = eff & 0x0F;
+        n.effect_parameter = param;
+        match eff >> 4 {
+            0 => n.effect_type = 0x01, // a: slide up
+            1 => n.effect_type = 0x02, // b: slide down
+            2 => n.effect_type = 0x03, // c: slide to note
+            3 => {
+                // d: frequency adjust, as a finetune
+                n.effect_type = 0x0E;
+                n.effect_parameter = 0x50 | param;
+                return true;
+            }
+            4 => {
+                // e: vibrato
+                n.effect_type = 0x04;
+                n.effect_parameter = (param << 4) | param;
+            }
+            5 => {
+                // f: ticks per row
+                n.effect_type = 0x0F;
+                return true;
+            }
+            6 => {
+                // g: UNIS 669 balance slide, 0 left, 1 right
+                n.effect_type = 0x19;
+                match param {
+                    0 => n.effect_parameter = 0x01,
+                    1 => n.effect_parameter = 0x10,
+                    _ => {
+                        n.effect_type = 0;
+                        n.effect_parameter = 0;
+                    }
+                }
+            }
+            7 => {
+                // h: UNIS 669 retrig
+                n.effect_type = 0x0E;
+                n.effect_parameter = 0x90 | param;
+            }
+            _ => {
+                n.effect_parameter = 0;
+                return true;
+            }
+        }
+        false
+    }
+
+    pub fn to_module(&self) -> Module {
+        let mut module = Module {
+            // first line of the message is often the title
+            name: self
+                .message
+                .chars()
+                .take(36)
+                .collect::<String>()
+                .trim()
+                .to_string(),
+            comment: self.message.clone(),
+            frequency_type: FrequencyType::LinearFrequencies,
+            restart_position: self.restart_position as usize,
+            default_tempo: 4,
+            // 669 ticks at about 32 Hz
+            default_bpm: 78,
+            pattern_order: self.positions.iter().map(|&x| x as usize).collect(),
+            pattern: self.patterns.clone(),
+            ..Default::default()
+        };
+
+        for (c669_sample, audio) in self.samples.iter().zip(&self.audio) {
+            let (flags, loop_start, loop_length) = if c669_sample.is_loop() {
+                (
+                    LoopType::Forward,
+                    c669_sample.loop_start,
+                    c669_sample.loop_end - c669_sample.loop_start,
+                )
+            } else {
+                (LoopType::No, 0, 0)
+            };
+            let mut sample = Sample {
+                name: c669_sample.filename.clone(),
+                loop_start,
+                loop_length,
+                volume: 1.0,
+                finetune: 0.0,
+                flags,
+                panning: 0.5,
+                relative_note: 0,
+                data: SampleDataType::Depth8(audio.clone()),
+            };
+            sample.fix_loop();
+
+            // Create InstrDefault
+            let mut instr_def = InstrDefault::default();
+            instr_def.sample.push(sample);
+
+            // Create Instrument
+            let instr = Instrument {
+                name: c669_sample.filename.clone(),
+                instr_type: InstrumentType::Default(instr_def),
+                ..Default::default()
+            };
+
+            module.instrument.push(instr);
+        }
+
+        module
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const C669_SLOTS: usize = C669_ROWS * C669_CHANNELS;
+
+    /// One pattern of 32 rows, sample 1 has 8 frames
+    fn c669() -> Vec<u8> {
+        let mut data = b"ifsynthetic".to_vec();
+        data.resize(110, 0);
+        // samples, patterns, restart position
+        data.extend_from_slice(&[1, 1, 0]);
+        let mut orders = [C669_ORDER_END; 128];
+        orders[0] = 0;
+        data.extend_from_slice(&orders);
+        let mut tempos = [4; 128];
+        tempos[0] = 5;
+        data.extend_from_slice(&tempos);
+        let mut breaks = [C669_NO_BREAK; 128];
+        breaks[0] = 31;
+        data.extend_from_slice(&breaks);
+        data.resize(C669_HEADER_SIZE, 0);
+
+        let mut sample = b"sample.smp".to_vec();
+        sample.resize(13, 0);
+        for value in [8u32, 0, 0xFFFFF] {
+            sample.extend_from_slice(&value.to_le_bytes());
+        }
+        data.extend_from_slice(&sample);
+
+        let mut pattern = [0xFF, 0x00, 0xFF].repeat(C669_SLOTS);
+        // C-4, instrument 1, volume 15, vibrato 2
+        pattern[0..3].copy_from_slice(&[24 << 2, 0x0F, 0x42]);
+        // volume 0 only, on row 2
+        let at = 2 * C669_CHANNELS * 3;
+        pattern[at..at + 3].copy_from_slice(&[0xFE, 0x00, 0xFF]);
+        data.extend_from_slice(&pattern);
+
+        data.extend((0..8).map(|x| 0x80 + x * 16));
+        data
+    }
+
+    #[test]
+    fn load_synthetic_module() {
+        let module = C669Module::load(&c669()).unwrap().to_module();
+        assert_eq!(module.name, "synthetic");
+        assert_eq!(module.pattern_order, vec![0]);
+        assert_eq!(module.pattern.len(), 1);
+
+        let pattern = &module.pattern[0];
+        assert_eq!(pattern.len(), 32);
+        let slot = &pattern[0][0];
+        assert_eq!(slot.note, Note::C4);
+        assert_eq!(slot.instrument, 1);
+        assert_eq!(slot.volume, 0x50);
+        assert_eq!((slot.effect_type, slot.effect_parameter), (0x04, 0x22));
+        // pattern speed on the first free effect
+        assert_eq!(
+            (pattern[0][1].effect_type, pattern[0][1].effect_parameter),
+            (0x0F, 5)
+        );
+        // the vibrato goes on until the next note
+        for row in &pattern[1..] {
+            assert_eq!(row[0].effect_type, 0x04);
+            assert_eq!(row[0].note, Note::None);
+        }
+        assert_eq!(pattern[2][0].volume, 0x10);
+        assert_eq!(pattern[1][0].volume, 0);
+        assert!(pattern
+            .iter()
+            .all(|r| r[2..].iter().all(|s| *s == PatternSlot::default())));
+
+        assert_eq!(module.instrument.len(), 1);
+        let InstrumentType::Default(instr) = &module.instrument[0].instr_type else {
+            panic!("not a sample instrument");
+        };
+        let sample = &instr.sample[0];
+        assert_eq!(sample.name, "sample.smp");
+        assert!(matches!(sample.flags, LoopType::No));
+        let audio: Vec<i8> = (0..8).map(|x| (x * 16) as i8).collect();
+        assert!(matches!(&sample.data, SampleDataType::Depth8(v) if *v == audio));
+    }
+
+    #[test]
+    fn reject_broken_modules() {
+        let data = c669();
+        assert!(C669Module::load(&data[..C669_HEADER_SIZE - 1]).is_err());
+        assert!(C669Module::load(&data[..data.len() - 8 - 1]).is_err());
+
+        let mut bad = data.clone();
+        bad[0] = b'X';
+        assert!(C669Module::load(&bad).is_err());
+        // a played pattern without speed
+        let mut bad = data;
+        bad[241] = 0;
+        assert!(C669Module::load(&bad).is_err());
+    }
+}
@@ -0,0 +1,774 @@
+use alloc::borrow::Cow;
+use alloc::{vec, vec::Vec};
+use bincode::error::DecodeError;
+
+use crate::module::Module;
+
+/// Unreal package tag
+const UMX_TAG: u32 = 0x9E2A83C1;
+const UMX_HEADER_SIZE: usize = 36;
+/// Unreal music data starts a few bytes after the export serial offset
+const UMX_SEARCH: usize = 64;
+
+const MMCMP_HEADER_SIZE: usize = 24;
+const MMCMP_BLOCK_SIZE: usize = 20;
+const MMCMP_SUBBLOCK_SIZE: usize = 8;
+const MMCMP_COMP: u16 = 0x0001;
+const MMCMP_DELTA: u16 = 0x0002;
+const MMCMP_16BIT: u16 = 0x0004;
+const MMCMP_ABS16: u16 = 0x0200;
+
+const MMCMP_8BIT_COMMANDS: [u32; 8] = [0x01, 0x03, 0x07, 0x0F, 0x1E, 0x3C, 0x78, 0xF8];
+const MMCMP_8BIT_FETCH: [u32; 8] = [3, 3, 3, 3, 2, 1, 0, 0];
+const MMCMP_16BIT_COMMANDS: [u32; 16] = [
+    0x01, 0x03, 0x07, 0x0F, 0x1E, 0x3C, 0x78, 0xF0, 0x1F0, 0x3F0, 0x7F0, 0xFF0, 0x1FF0, 0x3FF0,
+    0x7FF0, 0xFFF0,
+];
+const MMCMP_16BIT_FETCH: [u32; 16] = [4, 3, 2, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1];
+
+/// Nested containers, a zipped MMCMP file for example
+const MAX_DEPTH: usize = 4;
+/// Largest unpacked data
+const MAX_SIZE: usize = 0x0800_0000;
+
+/// Module formats known by the container layer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ModuleFormat {
+    Xm,
+    It,
+    S3m,
+    Mod,
+    Stm,
+    C669,
+    Mtm,
+    Ult,
+    Med,
+    Okt,
+    Dbm,
+    Ahx,
+    Midi,
+}
+
+/// ProTracker like tag at 0x438
+pub(crate) fn is_mod_tag(tag: &[u8]) -> bool {
+    match tag {
+        b"M.K." | b"M!K!" | b"M&K!" | b"N.T." | b"FLT4" | b"FLT8" | b"EXO4" | b"EXO8" | b"CD61"
+        | b"CD81" | b"OKTA" | b"OCTA" | b"TDZ1" | b"TDZ2" | b"TDZ3" | b"FEST" | b"NSMS"
+        | b"LARD" | b"PATT" | b"SNT." => true,
+        [d, b'C', b'H', b'N'] => d.is_ascii_digit(),
+        [d1, d2, b'C', b'H'] | [d1, d2, b'C', b'N'] => d1.is_ascii_digit() && d2.is_ascii_digit(),
+        _ => false,
+    }
+}
+
+/// Module format from the file signature
+pub fn detect(data: &[u8]) -> Option<ModuleFormat> {
+    let at = |offset: usize, magic: &[u8]| data.get(offset..offset + magic.len()) == Some(magic);
+
+    if at(0, b"Extended Module: ") {
+        Some(ModuleFormat::Xm)
+    } else if at(0, b"IMPM") {
+        Some(ModuleFormat::It)
+    } else if at(0x2C, b"SCRM") {
+        Some(ModuleFormat::S3m)
+    } else if at(0, b"PP20")
+        || at(0, b"P61A")
+        || at(0, b"SNT!")
+        || data.get(0x438..0x43C).is_some_and(is_mod_tag)
+    {
+        Some(ModuleFormat::Mod)
+    } else if at(0, b"MTM\x10") {
+        Some(ModuleFormat::Mtm)
+    } else if at(0, b"MAS_UTrack_V00") {
+        Some(ModuleFormat::Ult)
+    } else if at(0, b"MMD") && data.get(3).is_some_and(|v| (b'0'..=b'3').contains(v)) {
+        Some(ModuleFormat::Med)
+    } else if at(0, b"OKTASONG") {
+        Some(ModuleFormat::Okt)
+    } else if at(0, b"DBM0") {
+        Some(ModuleFormat::Dbm)
+    } else if (at(0, b"THX") || at(0, b"HVL")) && data.get(3).is_some_and(|&v| v < 2) {
+        Some(ModuleFormat::Ahx)
+    } else if at(0, b"MThd") {
+        Some(ModuleFormat::Midi)
+    } else if at(28, &[0x1A, 2]) {
+        Some(ModuleFormat::Stm)
+    } else if (at(0, b"if") || at(0, b"JN")) && data.len() > 0x1F1 {
+        Some(ModuleFormat::C669)
+    } else if is_packed_mod(data) {
+        Some(ModuleFormat::Mod)
+    } else {
+        None
+    }
+}
+
+/// Amiga module packed without a signature
+fn is_packed_mod(data: &[u8]) -> bool {
+    #[cfg(feature = "import_amiga")]
+    return crate::amiga::unpack::is_packed(data);
+    #[cfg(not(feature = "import_amiga"))]
+    {
+        let _ = data;
+        false
+    }
+}
+
+/// Module data from a container, unchanged data if it is not wrapped
+///
+/// Supports Unreal packages (`.umx`) and MMCMP compressed files, and with
+/// the `std` feature gzip (`.mdz`, `.s3z`, `.xmz`, `.itz`) and zip files.
+pub fn unwrap(data: &[u8]) -> Result<Cow<'_, [u8]>, DecodeError> {
+    let mut data = Cow::Borrowed(data);
+    for _ in 0..MAX_DEPTH {
+        let inner = if data.starts_with(&UMX_TAG.to_le_bytes()) {
+            umx_extract(&data)?.to_vec()
+        } else if data.starts_with(b"ziRCONia") {
+            mmcmp_unpack(&data)?
+        } else {
+            #[cfg(feature = "std")]
+            if data.starts_with(&[0x1F, 0x8B]) {
+                gzip_extract(&data)?
+            } else if data.starts_with(b"PK\x03\x04") {
+                zip_extract(&data)?
+            } else {
+                return Ok(data);
+            }
+            #[cfg(not(feature = "std"))]
+            return Ok(data);
+        };
+        data = Cow::Owned(inner);
+    }
+    Err(DecodeError::Other("Too many nested containers"))
+}
+
+/// Load any known module, wrapped or not
+pub fn import(data: &[u8]) -> Result<Module, DecodeError> {
+    let data = unwrap(data)?;
+    let data: &[u8] = &data;
+    match detect(data) {
+        #[cfg(feature = "import_xm")]
+        Some(ModuleFormat::Xm) => Ok(crate::xm::xmmodule::XmModule::load(data)?.to_module()),
+        #[cfg(feature = "import_s3m")]
+        Some(ModuleFormat::S3m) => Ok(crate::s3m::s3m_module::S3mModule::load(data)?.to_module()),
+        #[cfg(feature = "import_amiga")]
+        Some(ModuleFormat::Mod) => {
+            Ok(crate::amiga::amiga_module::AmigaModule::load(data)?.to_module())
+        }
+        #[cfg(feature = "import_stm")]
+        Some(ModuleFormat::Stm) => Ok(crate::stm::stm_module::StmModule::load(data)?.to_module()),
+        #[cfg(feature = "import_669")]
+        Some(ModuleFormat::C669) => {
+            Ok(crate::c669::c669_module::C669Module::load(data)?.to_module())
+        }
+        #[cfg(feature = "import_mtm")]
+        Some(ModuleFormat::Mtm) => Ok(crate::mtm::mtm_module::MtmModule::load(data)?.to_module()),
+        #[cfg(feature = "import_ult")]
+        Some(ModuleFormat::Ult) => Ok(crate::ult::ult_module::UltModule::load(data)?.to_module()),
+        #[cfg(feature = "import_med")]
+        Some(ModuleFormat::Med) => Ok(crate::med::med_module::MedModule::load(data)?.to_module()),
+        #[cfg(feature = "import_okt")]
+        Some(ModuleFormat::Okt) => Ok(crate::okt::okt_module::OktModule::load(data)?.to_module()),
+        #[cfg(feature = "import_dbm")]
+        Some(ModuleFormat::Dbm) => Ok(crate::dbm::dbm_module::DbmModule::load(data)?.to_module()),
+        #[cfg(feature = "import_ahx")]
+        Some(ModuleFormat::Ahx) => Ok(crate::ahx::ahx_module::AhxModule::load(data)?.to_module()),
+        #[cfg(feature = "import_midi")]
+        Some(ModuleFormat::Midi) => Ok(crate::midi::midi_import::MidiFile::load(data)?
+            .to_module(&crate::midi::midi_import::MidiImportOptions::default())),
+        Some(ModuleFormat::It) => Err(DecodeError::Other("IT modules are not supported")),
+        #[allow(unreachable_patterns)]
+        Some(_) => Err(DecodeError::Other("This module format is not enabled")),
+        None => Err(DecodeError::Other("Unknown module format")),
+    }
+}
+
+// === Unreal packages
+
+fn get_u32(data: &[u8], at: usize) -> Result<u32, DecodeError> {
+    data.get(at..at + 4)
+        .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .ok_or(DecodeError::Other("Container is truncated"))
+}
+
+fn get_u16(data: &[u8], at: usize) -> Result<u16, DecodeError> {
+    data.get(at..at + 2)
+        .map(|b| u16::from_le_bytes([b[0], b[1]]))
+        .ok_or(DecodeError::Other("Container is truncated"))
+}
+
+/// Unreal compact index: value and size
+fn umx_index(data: &[u8], at: usize) -> Result<(i32, usize), DecodeError> {
+    let error = || DecodeError::Other("UMX index is truncated");
+    let first = *data.get(at).ok_or_else(error)?;
+    let mut value = (first & 0x3F) as i32;
+    let mut size = 1;
+    if first & 0x40 != 0 {
+        let mut shift = 6;
+        loop {
+            let b = *data.get(at + size).ok_or_else(error)?;
+            value |= ((b & 0x7F) as i32) << shift;
+            size += 1;
+            shift += 7;
+            if b & 0x80 == 0 || size == 5 {
+                break;
+            }
+        }
+    }
+    if first & 0x80 != 0 {
+        value = value
+            .checked_neg()
+            .ok_or(DecodeError::Other("UMX index is corrupted"))?;
+    }
+    Ok((value, size))
+}
+
+/// Names of an Unreal package
+fn umx_names(
+    data: &[u8],
+    version: u16,
+    count: usize,
+    at: usize,
+) -> Result<Vec<&[u8]>, DecodeError> {
+    let mut names = vec![];
+    let mut at = at;
+    for _ in 0..count {
+        let name = if version < 64 {
+            let rest = data
+                .get(at..)
+                .ok_or(DecodeError::Other("UMX names are truncated"))?;
+            let len = rest.iter().position(|&c| c == 0).unwrap_or(rest.len());
+            at += len + 1;
+            &rest[..len]
+        } else {
+            let (len, size) = umx_index(data, at)?;
+            let len = len.max(0) as usize;
+            let name = data
+                .get(at + size..at + size + len)
+                .ok_or(DecodeError::Other("UMX names are truncated"))?;
+            at += size + len;
+            name.strip_suffix(&[0]).unwrap_or(name)
+        };
+        // flags
+        at += 4;
+        names.push(name);
+    }
+    Ok(names)
+}
+
+/// Music data of an Unreal package
+fn umx_extract(data: &[u8]) -> Result<&[u8], DecodeError> {
+    if data.len() < UMX_HEADER_SIZE || get_u32(data, 0)? != UMX_TAG {
+        return Err(DecodeError::Other("Not an UMX package?"));
+    }
+    let version = get_u16(data, 4)?;
+    let name_count = get_u32(data, 12)? as usize;
+    let name_offset = get_u32(data, 16)? as usize;
+    let export_count = get_u32(data, 20)? as usize;
+    let export_offset = get_u32(data, 24)? as usize;
+    let import_count = get_u32(data, 28)? as usize;
+    let import_offset = get_u32(data, 32)? as usize;
+
+    let names = umx_names(data, version, name_count, name_offset)?;
+    let name = |index: i32| names.get(index.max(0) as usize).copied().unwrap_or(&[]);
+
+    // imported classes
+    let mut imports = vec![];
+    let mut at = import_offset;
+    for _ in 0..import_count {
+        let (_class_package, s1) = umx_index(data, at)?;
+        let (_class_name, s2) = umx_index(data, at + s1)?;
+        at += s1 + s2 + 4;
+        let (object_name, s3) = umx_index(data, at)?;
+        at += s3;
+        imports.push(name(object_name));
+    }
+
+    let mut at = export_offset;
+    for _ in 0..export_count {
+        let (class, s1) = umx_index(data, at)?;
+        let (_super, s2) = umx_index(data, at + s1)?;
+        at += s1 + s2 + 4;
+        let (_object_name, s3) = umx_index(data, at)?;
+        at += s3 + 4;
+        let (serial_size, s4) = umx_index(data, at)?;
+        at += s4;
+        let serial_offset = if serial_size > 0 {
+            let (offset, s5) = umx_index(data, at)?;
+            at += s5;
+            offset
+        } else {
+            0
+        };
+
+        let is_music = class < 0 && {
+            let import = class
+                .checked_neg()
+                .ok_or(DecodeError::Other("UMX class is corrupted"))?
+                - 1;
+            imports
+                .get(import as usize)
+                .is_some_and(|&n| n.eq_ignore_ascii_case(b"Music"))
+        };
+        if !is_music || serial_size <= 0 || serial_offset < 0 {
+            continue;
+        }
+        let start = serial_offset as usize;
+        let serial = data
+            .get(start..(start + serial_size as usize).min(data.len()))
+            .ok_or(DecodeError::Other("UMX music is truncated"))?;
+
+        // the module is preceded by a few properties and its size
+        for at in 0..UMX_SEARCH.min(serial.len()) {
+            let Ok((len, size)) = umx_index(serial, at) else {
+                break;
+            };
+            if len <= 0 {
+                continue;
+            }
+            if let Some(music) = serial.get(at + size..at + size + len as usize) {
+                if detect(music).is_some() {
+                    return Ok(music);
+                }
+            }
+        }
+    }
+    Err(DecodeError::Other("No music found in the UMX package"))
+}
+
+// === MMCMP
+
+/// Bits read from the start, least significant first, zeroes after the end
+struct MmcmpBits<'a> {
+    data: &'a [u8],
+    pos: usize,
+    buffer: u32,
+    count: u32,
+}
+
+impl MmcmpBits<'_> {
+    fn read(&mut self, bits: u32) -> u32 {
+        if bits == 0 {
+            return 0;
+        }
+        while self.count < 24 {
+            let byte = self.data.get(self.pos).copied().unwrap_or(0);
+            self.pos += 1;
+            self.buffer |= (byte as u32) << self.count;
+            self.count += 8;
+        }
+        let value = self.buffer & ((1 << bits) - 1);
+        self.buffer >>= bits;
+        self.count -= bits;
+        value
+    }
+}
+
+/// Unpack a MMCMP (ziRCONia) compressed file
+fn mmcmp_unpack(data: &[u8]) -> Result<Vec<u8>, DecodeError> {
+    let error = || DecodeError::Other("MMCMP data is corrupted");
+    if data.len() < MMCMP_HEADER_SIZE || &data[0..8] != b"ziRCONia" || get_u16(data, 8)? != 14 {
+        return Err(DecodeError::Other("Not a MMCMP file?"));
+    }
+    let block_count = get_u16(data, 12)? as usize;
+    let size = get_u32(data, 14)? as usize;
+    let table = get_u32(data, 18)? as usize;
+    if block_count == 0 || !(16..=MAX_SIZE).contains(&size) {
+        return Err(error());
+    }
+
+    let mut dest = vec![0u8; size];
+    for b in 0..block_count {
+        let at = get_u32(data, table + b * 4)? as usize;
+        let packed_size = get_u32(data, at + 4)? as usize;
+        let subblock_count = get_u16(data, at + 12)? as usize;
+        let flags = get_u16(data, at + 14)?;
+        let table_entries = get_u16(data, at + 16)? as usize;
+        let mut bits = get_u16(data, at + 18)? as usize;
+
+        let mut subblocks = vec![];
+        for s in 0..subblock_count {
+            let sub = at + MMCMP_BLOCK_SIZE + s * MMCMP_SUBBLOCK_SIZE;
+            let position = get_u32(data, sub)? as usize;
+            let len = get_u32(data, sub + 4)? as usize;
+            if position.checked_add(len).is_none_or(|end| end > size) {
+                return Err(error());
+            }
+            subblocks.push((position, len));
+        }
+        let start = at + MMCMP_BLOCK_SIZE + subblock_count * MMCMP_SUBBLOCK_SIZE;
+        let block = data.get(start..).ok_or_else(error)?;
+
+        if flags & MMCMP_COMP == 0 {
+            // stored
+            let mut src = block;
+            for &(position, len) in &subblocks {
+                let chunk = src.get(..len).ok_or_else(error)?;
+                dest[position..position + len].copy_from_slice(chunk);
+                src = &src[len..];
+            }
+            continue;
+        }
+
+        let block = block.get(..packed_size.min(block.len())).unwrap_or(block);
+        let mut reader = MmcmpBits {
+            data: block.get(table_entries..).unwrap_or(&[]),
+            pos: 0,
+            buffer: 0,
+            count: 0,
+        };
+        let mut subblocks = subblocks.into_iter();
+        let Some((mut position, mut len)) = subblocks.next() else {
+            continue;
+        };
+
+        if flags & MMCMP_16BIT != 0 {
+            if bits >= 16 {
+                return Err(error());
+            }
+            let mut old: u32 = 0;
+            loop {
+                let mut value = 0x10000;
+                let d = reader.read(bits as u32 + 1);
+                if d >= MMCMP_16BIT_COMMANDS[bits] {
+                    let fetch = MMCMP_16BIT_FETCH[bits];
+                    let new_bits = reader.read(fetch) + ((d - MMCMP_16BIT_COMMANDS[bits]) << fetch);
+                    if new_bits as usize != bits {
+                        bits = new_bits as usize & 0x0F;
+                    } else {
+                        let d = reader.read(4);
+                        if d == 0x0F {
+                            if reader.read(1) != 0 {
+                                break;
+                            }
+                            value = 0xFFFF;
+                        } else {
+                            value = 0xFFF0 + d;
+                        }
+                    }
+                } else {
+                    value = d;
+                }
+                if value < 0x10000 {
+                    let mut v = if value & 1 != 0 {
+                        ((value + 1) >> 1).wrapping_neg()
+                    } else {
+                        value >> 1
+                    };
+                    if flags & MMCMP_DELTA != 0 {
+                        v = v.wrapping_add(old);
+                        old = v;
+                    } else if flags & MMCMP_ABS16 == 0 {
+                        v ^= 0x8000;
+                    }
+                    if len >= 2 {
+                        dest[position..position + 2].copy_from_slice(&(v as u16).to_le_bytes());
+                        position += 2;
+                        len -= 2;
+                    }
+                }
+                if len < 2 {
+                    match subblocks.next() {
+                        Some(next) => (position, len) = next,
+                        None => break,
+                    }
+                }
+                if reader.pos > block.len() + 4 {
+                    return Err(error());
+                }
+            }
+        } else {
+            if bits >= 8 {
+                return Err(error());
+            }
+            let translation = block.get(..table_entries).ok_or_else(error)?;
+            let mut old: u8 = 0;
+            loop {
+                let mut value = 0x100;
+                let d = reader.read(bits as u32 + 1);
+                if d >= MMCMP_8BIT_COMMANDS[bits] {
+                    let fetch = MMCMP_8BIT_FETCH[bits];
+                    let new_bits = reader.read(fetch) + ((d - MMCMP_8BIT_COMMANDS[bits]) << fetch);
+                    if new_bits as usize != bits {
+                        bits = new_bits as usize & 0x07;
+                    } else {
+                        let d = reader.read(3);
+                        if d == 7 {
+                            if reader.read(1) != 0 {
+                                break;
+                            }
+                            value = 0xFF;
+                        } else {
+                            value = 0xF8 + d;
+                        }
+                    }
+                } else {
+                    value = d;
+                }
+                if value < 0x100 {
+                    let mut v = translation.get(value as usize).copied().unwrap_or(0);
+                    if flags & MMCMP_DELTA != 0 {
+                        v = v.wrapping_add(old);
+                        old = v;
+                    }
+                    if len > 0 {
+                        dest[position] = v;
+                        position += 1;
+                        len -= 1;
+                    }
+                }
+                if len == 0 {
+                    match subblocks.next() {
+                        Some(next) => (position, len) = next,
+                        None => break,
+                    }
+                }
+                if reader.pos > block.len() + 4 {
+                    return Err(error());
+                }
+            }
+        }
+    }
+    Ok(dest)
+}
+
+// === gzip and zip
+
+#[cfg(feature = "std")]
+fn gzip_extract(data: &[u8]) -> Result<Vec<u8>, DecodeError> {
+    const FHCRC: u8 = 0x02;
+    const FEXTRA: u8 = 0x04;
+    const FNAME: u8 = 0x08;
+    const FCOMMENT: u8 = 0x10;
+
+    let error = || DecodeError::Other("gzip file is truncated");
+    if data.len() < 18 || data[0..2] != [0x1F, 0x8B] || data[2] != 8 {
+        return Err(DecodeError::Other("Not a gzip file?"));
+    }
+    let flags = data[3];
+    let mut at = 10;
+    if flags & FEXTRA != 0 {
+        at += 2 + get_u16(data, at)? as usize;
+    }
+    for flag in [FNAME, FCOMMENT] {
+        if flags & flag != 0 {
+            let rest = data.get(at..).ok_or_else(error)?;
+            at += 1 + rest.iter().position(|&c| c == 0).ok_or_else(error)?;
+        }
+    }
+    if flags & FHCRC != 0 {
+        at += 2;
+    }
+    let (inflated, _) = crate::inflate::inflate(data.get(at..).ok_or_else(error)?, MAX_SIZE)?;
+    Ok(inflated)
+}
+
+/// Signature of a container handled by `unwrap`
+#[cfg(feature = "std")]
+fn is_container(data: &[u8]) -> bool {
+    data.starts_with(&UMX_TAG.to_le_bytes())
+        || data.starts_with(b"ziRCONia")
+        || data.starts_with(&[0x1F, 0x8B])
+        || data.starts_with(b"PK\x03\x04")
+}
+
+/// First zip entry which is a module or a container
+#[cfg(feature = "std")]
+fn zip_extract(data: &[u8]) -> Result<Vec<u8>, DecodeError> {
+    const LOCAL_HEADER_SIZE: usize = 30;
+    const DATA_DESCRIPTOR: u16 = 0x0008;
+
+    let mut at = 0;
+    while data.get(at..at + 4) == Some(b"PK\x03\x04") {
+        let flags = get_u16(data, at + 6)?;
+        let method = get_u16(data, at + 8)?;
+        let packed_size = get_u32(data, at + 18)? as usize;
+        let size = get_u32(data, at + 22)? as usize;
+        let name_len = get_u16(data, at + 26)? as usize;
+        let extra_len = get_u16(data, at + 28)? as usize;
+        let start = at + LOCAL_HEADER_SIZE + name_len + extra_len;
+        let body = data
+            .get(start..)
+            .ok_or(DecodeError::Other("zip file is truncated"))?;
+
+        let (entry, used) = match method {
+            0 if flags & DATA_DESCRIPTOR == 0 => {
+                let stored = body
+                    .get(..size)
+                    .ok_or(DecodeError::Other("zip file is truncated"))?;
+                (stored.to_vec(), size)
+            }
+            8 => crate::inflate::inflate(body, MAX_SIZE)?,
+            _ => {
+                return Err(DecodeError::Other(
+                    "zip compression method is not supported",
+                ))
+            }
+        };
+        if detect(&entry).is_some() || is_container(&entry) {
+            return Ok(entry);
+        }
+
+        at = start
+            + if flags & DATA_DESCRIPTOR != 0 {
+                // optional signature, crc and sizes
+                let descriptor = start + used;
+                used + if data.get(descriptor..descriptor + 4) == Some(b"PK\x07\x08") {
+                    16
+                } else {
+                    12
+                }
+            } else {
+                packed_size
+            };
+    }
+    Err(DecodeError::Other("No module found in the zip file"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const XM: &[u8] = b"Extended Module: test";
+
+    fn mmcmp(payload: &[u8]) -> Vec<u8> {
+        let mut data = b"ziRCONia".to_vec();
+        data.extend_from_slice(&[14, 0, 0, 0, 1, 0]);
+        data.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+        data.extend_from_slice(&24u32.to_le_bytes());
+        data.extend_from_slice(&[0; 2]);
+        // one stored block at 28, with one subblock
+        data.extend_from_slice(&28u32.to_le_bytes());
+        data.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+        data.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+        data.extend_from_slice(&[0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0]);
+        data.extend_from_slice(&0u32.to_le_bytes());
+        data.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+        data.extend_from_slice(payload);
+        data
+    }
+
+    /// Music package with the module after an empty property
+    fn umx(class: &[u8]) -> Vec<u8> {
+        let mut data = UMX_TAG.to_le_bytes().to_vec();
+        data.extend_from_slice(&[61, 0, 0, 0, 0, 0, 0, 0]);
+        for value in [1u32, 36, 1, 53, 1, 46] {
+            data.extend_from_slice(&value.to_le_bytes());
+        }
+        data.extend_from_slice(b"Music\0");
+        data.extend_from_slice(&[0; 4]);
+        // import
+        data.extend_from_slice(&[0, 0, 0, 0, 0, 0, 0]);
+        // export: class, super, package, name, flags, size and offset
+        data.extend_from_slice(class);
+        data.extend_from_slice(&[0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        data.push(2 + XM.len() as u8);
+        let serial_at = data.len() + 2;
+        data.extend_from_slice(&[0x40 | (serial_at & 0x3F) as u8, (serial_at >> 6) as u8]);
+        data.extend_from_slice(&[0, XM.len() as u8]);
+        data.extend_from_slice(XM);
+        data
+    }
+
+    /// Zip file, deflate stored entries when `deflate` is set
+    #[cfg(feature = "std")]
+    fn zip(entries: &[&[u8]], deflate: bool) -> Vec<u8> {
+        let mut data = vec![];
+        for entry in entries {
+            let body = if deflate {
+                let mut body = vec![0x01];
+                body.extend_from_slice(&(entry.len() as u16).to_le_bytes());
+                body.extend_from_slice(&(!(entry.len() as u16)).to_le_bytes());
+                body.extend_from_slice(entry);
+                body
+            } else {
+                entry.to_vec()
+            };
+            data.extend_from_slice(b"PK\x03\x04\x14\x00\x00\x00");
+            data.extend_from_slice(&[if deflate { 8 } else { 0 }, 0]);
+            data.extend_from_slice(&[0; 8]);
+            data.extend_from_slice(&(body.len() as u32).to_le_bytes());
+            data.extend_from_slice(&(entry.len() as u32).to_le_bytes());
+            data.extend_from_slice(&[5, 0, 0, 0]);
+            data.extend_from_slice(b"x.mod");
+            data.extend(body);
+        }
+        data
+    }
+
+    #[test]
+    fn unwrap_mmcmp() {
+        let data = mmcmp(XM);
+        assert_eq!(&*unwrap(&data).unwrap(), XM);
+        assert!(unwrap(&data[0..data.len() - 1]).is_err());
+        assert_eq!(&*unwrap(XM).unwrap(), XM);
+        assert!(matches!(unwrap(XM).unwrap(), Cow::Borrowed(_)));
+    }
+
+    #[test]
+    fn unpack_random_mmcmp() {
+        let mut seed = 0x8765_4321u32;
+        let mut data = mmcmp(XM);
+        // compressed block
+        data[42] = 1;
+        for _ in 0..2000 {
+            seed ^= seed << 13;
+            seed ^= seed >> 17;
+            seed ^= seed << 5;
+            let at = 40 + seed as usize % (data.len() - 40);
+            data[at] = (seed >> 8) as u8;
+            let _ = mmcmp_unpack(&data);
+        }
+    }
+
+    #[test]
+    fn umx_compact_index() {
+        assert_eq!(umx_index(&[0x05], 0).unwrap(), (5, 1));
+        assert_eq!(umx_index(&[0x85], 0).unwrap(), (-5, 1));
+        assert_eq!(umx_index(&[0x41, 0x02], 0).unwrap(), (129, 2));
+        assert!(umx_index(&[0xC0, 0x80, 0x80, 0x80, 0x10], 0).is_err());
+        assert!(umx_index(&[0x41], 0).is_err());
+    }
+
+    #[test]
+    fn unwrap_umx() {
+        let data = umx(&[0x81]);
+        assert_eq!(&*unwrap(&data).unwrap(), XM);
+        assert_eq!(detect(&unwrap(&data).unwrap()), Some(ModuleFormat::Xm));
+        // not an import
+        assert!(unwrap(&umx(&[0x01])).is_err());
+        // i32::MIN class
+        assert!(matches!(
+            unwrap(&umx(&[0x40, 0x80, 0x80, 0x80, 0x10])),
+            Err(DecodeError::Other("UMX class is corrupted"))
+        ));
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn unwrap_zip() {
+        for deflate in [false, true] {
+            let data = zip(&[b"readme", XM], deflate);
+            assert_eq!(&*unwrap(&data).unwrap(), XM);
+        }
+        let data = zip(&[b"readme"], false);
+        assert!(unwrap(&data).is_err());
+
+        // zipped MMCMP file
+        let data = zip(&[&mmcmp(XM)], true);
+        assert_eq!(&*unwrap(&data).unwrap(), XM);
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn reject_nested_zip() {
+        let mut data = XM.to_vec();
+        for _ in 0..MAX_DEPTH - 1 {
+            data = zip(&[&data], false);
+        }
+        assert_eq!(&*unwrap(&data).unwrap(), XM);
+        for _ in 0..1000 {
+            data = zip(&[&data], false);
+        }
+        assert!(unwrap(&data).is_err());
+    }
+}
@@ -0,0 +1,543 @@
+use bincode::error::DecodeError;
+
+use crate::module::MAX_NUM_ROWS;
+use crate::prelude::*;
+
+use alloc::string::String;
+use alloc::string::ToString;
+use alloc::{vec, vec::Vec};
+
+const DBM_HEADER_SIZE: usize = 8;
+const DBM_INSTRUMENT_SIZE: usize = 50;
+const DBM_ENVELOPE_SIZE: usize = 136;
+const DBM_ENVELOPE_POINTS: usize = 32;
+const DBM_MAX_CHANNELS: usize = 254;
+/// Note value for key off
+const DBM_KEY_OFF: u8 = 0x1F;
+
+/// Instrument flags
+const DBM_LOOP: u16 = 0x01;
+const DBM_PINGPONG: u16 = 0x02;
+/// Envelope flags
+const DBM_ENV_ENABLED: u8 = 0x01;
+const DBM_ENV_SUSTAIN_A: u8 = 0x02;
+const DBM_ENV_LOOP: u8 = 0x04;
+const DBM_ENV_SUSTAIN_B: u8 = 0x08;
+
+fn get_string(data: &[u8]) -> String {
+    let end = data.iter().position(|&c| c == 0).unwrap_or(data.len());
+    String::from_utf8_lossy(&data[0..end]).trim().to_string()
+}
+
+fn get_u16(data: &[u8], at: usize) -> u16 {
+    u16::from_be_bytes([data[at], data[at + 1]])
+}
+
+fn get_u32(data: &[u8], at: usize) -> u32 {
+    u32::from_be_bytes([data[at], data[at + 1], data[at + 2], data[at + 3]])
+}
+
+#[derive(Default, Debug)]
+struct DbmInstrument {
+    name: String,
+    /// 1-based, 0 for none
+    sample: u16,
+    /// 0-64
+    volume: u16,
+    /// Sample rate of C-4
+    c4_rate: u32,
+    loop_start: u32,
+    loop_length: u32,
+    /// -128 left, 128 right
+    panning: i16,
+    flags: u16,
+    volume_envelope: Envelope,
+    panning_envelope: Envelope,
+}
+
+impl DbmInstrument {
+    fn load(data: &[u8]) -> Self {
+        Self {
+            name: get_string(&data[0..30]),
+            sample: get_u16(data, 30),
+            volume: get_u16(data, 32),
+            c4_rate: get_u32(data, 34),
+            loop_start: get_u32(data, 38),
+            loop_length: get_u32(data, 42),
+            panning: get_u16(data, 46) as i16,
+            flags: get_u16(data, 48),
+            ..Default::default()
+        }
+    }
+
+    fn loop_type(&self) -> LoopType {
+        if self.loop_length == 0 {
+            LoopType::No
+        } else if self.flags & DBM_PINGPONG != 0 {
+            LoopType::PingPong
+        } else if self.flags & DBM_LOOP != 0 {
+            LoopType::Forward
+        } else {
+            LoopType::No
+        }
+    }
+}
+
+#[derive(Default, Debug)]
+struct DbmSong {
+    name: String,
+    positions: Vec<usize>,
+}
+
+/// DigiBooster Pro Module
+#[derive(Default, Debug)]
+pub struct DbmModule {
+    title: String,
+    channel_count: usize,
+    instruments: Vec<DbmInstrument>,
+    audio: Vec<SampleDataType>,
+    songs: Vec<DbmSong>,
+    patterns: Vec<Pattern>,
+}
+
+impl DbmModule {
+    pub fn load(ser_dbm_module: &[u8]) -> Result<DbmModule, DecodeError> {
+        let mut dbm = DbmModule {
+            ..Default::default()
+        };
+
+        if ser_dbm_module.len() < DBM_HEADER_SIZE || &ser_dbm_module[0..4] != b"DBM0" {
+            return Err(DecodeError::Other("Not a DBM module?"));
+        }
+
+        // === chunks
+
+        let (mut instrument_count, mut sample_count, mut song_count, mut pattern_count) =
+            (0, 0, 0, 0);
+        let mut info = false;
+        let mut data = &ser_dbm_module[DBM_HEADER_SIZE..];
+        while data.len() >= 8 {
+            let id = &data[0..4];
+            let len = get_u32(data, 4) as usize;
+            if data.len() < 8 + len {
+                return Err(DecodeError::Other("DBM chunk is truncated"));
+            }
+            let chunk = &data[8..8 + len];
+            match id {
+                b"NAME" => dbm.title = get_string(chunk),
+                b"INFO" if chunk.len() >= 10 => {
+                    instrument_count = get_u16(chunk, 0) as usize;
+                    sample_count = get_u16(chunk, 2) as usize;
+                    song_count = get_u16(chunk, 4) as usize;
+                    pattern_count = get_u16(chunk, 6) as usize;
+                    dbm.channel_count = (get_u16(chunk, 8) as usize).clamp(1, DBM_MAX_CHANNELS);
+                    info = true;
+                }
+                b"SONG" => dbm.songs = Self::load_songs(chunk, song_count)?,
+                b"INST" => {
+                    dbm.instruments = chunk
+                        .chunks_exact(DBM_INSTRUMENT_SIZE)
+                        .take(instrument_count)
+                        .map(DbmInstrument::load)
+                        .collect();
+                }
+                b"PATT" => dbm.patterns = dbm.load_patterns(chunk, pattern_count)?,
+                b"SMPL" => dbm.audio = Self::load_samples(chunk, sample_count)?,
+                b"VENV" => dbm.load_envelopes(chunk, false)?,
+                b"PENV" => dbm.load_envelopes(chunk, true)?,
+                _ => {}
+            }
+            data = &data[8 + len..];
+        }
+
+        if !info {
+            return Err(DecodeError::Other("Not a DBM module?"));
+        }
+
+        let pattern_count = dbm.patterns.len();
+        for song in dbm.songs.iter_mut() {
+            song.positions.retain(|&p| p < pattern_count);
+        }
+
+        Ok(dbm)
+    }
+
+    fn load_songs(mut data: &[u8], count: usize) -> Result<Vec<DbmSong>, DecodeError> {
+        let mut songs = vec![];
+        for _ in 0..count {
+            if data.len() < 46 {
+                return Err(DecodeError::Other("DBM song is truncated"));
+            }
+            let len = get_u16(data, 44) as usize;
+            if data.len() < 46 + 2 * len {
+                return Err(DecodeError::Other("DBM song is truncated"));
+            }
+            songs.push(DbmSong {
+                name: get_string(&data[0..44]),
+                positions: (0..len)
+                    .map(|i| get_u16(data, 46 + 2 * i) as usize)
+                    .collect(),
+            });
+            data = &data[46 + 2 * len..];
+        }
+        Ok(songs)
+    }
+
+    fn load_patterns(&self, mut data: &[u8], count: usize) -> Result<Vec<Pattern>, DecodeError> {
+        let mut patterns = vec![];
+        for _ in 0..count {
+            if data.len() < 6 {
+                return Err(DecodeError::Other("DBM pattern is truncated"));
+            }
+            let rows = (get_u16(data, 0) as usize).clamp(1, MAX_NUM_ROWS);
+            let len = get_u32(data, 2) as usize;
+            if data.len() < 6 + len {
+                return Err(DecodeError::Other("DBM pattern is truncated"));
+            }
+            patterns.push(self.decode_pattern(&data[6..6 + len], rows));
+            data = &data[6 + len..];
+        }
+        Ok(patterns)
+    }
+
+    fn decode_pattern(&self, data: &[u8], rows: usize) -> Pattern {
+        let mut pattern: Pattern = vec![vec![PatternSlot::default(); self.channel_count]; rows];
+        let mut row = 0;
+        let mut i = 0;
+        let mut next = || {
+            let b = data.get(i).copied();
+            i += 1;
+            b
+        };
+        while row < rows {
+            let channel = match next() {
+                None => break,
+                Some(0) => {
+                    row += 1;
+                    continue;
+                }
+                Some(c) => c as usize - 1,
+            };
+            let mask = next().unwrap_or(0);
+            let mut field = |bit: u8| {
+                if mask & bit != 0 {
+                    next().unwrap_or(0)
+                } else {
+                    0
+                }
+            };
+            let note = field(0x01);
+            let instrument = field(0x02);
+            let first = (field(0x04), field(0x08));
+            let second = (field(0x10), field(0x20));
+
+            let Some(slot) = pattern[row].get_mut(channel) else {
+                continue;
+            };
+            slot.note = match note {
+                0 => Note::None,
+                DBM_KEY_OFF => Note::KeyOff,
+                _ => Note::try_from((note >> 4) * 12 + (note & 0x0F) + 1).unwrap_or(Note::None),
+            };
+            slot.instrument = instrument;
+
+            // two effects, the second one goes in the volume column when possible
+            let first = Self::efx_correction(first);
+            let second = Self::efx_correction(second);
+            let (effect, volume) = match (first, second) {
+                (Some(a), Some(b)) => {
+                    match (Self::to_volume_column(b), Self::to_volume_column(a)) {
+                        (Some(v), _) => (Some(a), Some(v)),
+                        (None, Some(v)) => (Some(b), Some(v)),
+                        // no room for both, keep the first one
+                        (None, None) => (Some(a), None),
+                    }
+                }
+                (Some(a), None) | (None, Some(a)) => (Some(a), None),
+                (None, None) => (None, None),
+            };
+            if let Some((effect_type, effect_parameter)) = effect {
+                slot.effect_type = effect_type;
+                slot.effect_parameter = effect_parameter;
+            }
+            if let Some(volume) = volume {
+                slot.volume = volume;
+            }
+        }
+        pattern
+    }
+
+    /// DBM effects are XM effects, without some of them
+    fn efx_correction((effect, param): (u8, u8)) -> Option<(u8, u8)> {
+        match effect {
+            0x00 if param == 0 => None,
+            // E3x plays backwards, E4x turns the channel off
+            0x0E if matches!(param >> 4, 0x3 | 0x4) => None,
+            0x0F if param == 0 => None,
+            0x00..=0x11 | 0x14 | 0x15 | 0x19 => Some((effect, param)),
+            // echo and reverb
+            _ => None,
+        }
+    }
+
+    /// XM volume column for some XM effects
+    fn to_volume_column((effect_type, param): (u8, u8)) -> Option<u8> {
+        match effect_type {
+            0x0C => Some(0x10 + param.min(64)),
+            0x0A if param & 0x0F == 0 => Some(0x70 | (param >> 4)),
+            0x0A if param & 0xF0 == 0 => Some(0x60 | param),
+            0x08 if param % 0x11 == 0 => Some(0xC0 | (param >> 4)),
+            _ => None,
+        }
+    }
+
+    /// Big endian signed data, 32 bits are reduced to 16 bits
+    fn load_samples(mut data: &[u8], count: usize) -> Result<Vec<SampleDataType>, DecodeError> {
+        let mut samples = vec![];
+        for _ in 0..count {
+            if data.len() < 8 {
+                return Err(DecodeError::Other("DBM sample is truncated"));
+            }
+            let flags = get_u32(data, 0);
+            let len = get_u32(data, 4) as usize;
+            let bytes = match flags & 0x07 {
+                0x02 => 2,
+                0x04 => 4,
+                _ => 1,
+            };
+            let size = (len * bytes).min(data.len() - 8);
+            let src = &data[8..8 + size];
+            samples.push(match bytes {
+                1 => SampleDataType::Depth8(src.iter().map(|&x| x as i8).collect()),
+                2 => SampleDataType::Depth16(
+                    src.chunks_exact(2)
+                        .map(|c| i16::from_be_bytes([c[0], c[1]]))
+                        .collect(),
+                ),
+                _ => SampleDataType::Depth16(
+                    src.chunks_exact(4)
+                        .map(|c| i16::from_be_bytes([c[0], c[1]]))
+                        .collect(),
+                ),
+            });
+            data = &data[8 + size..];
+        }
+        Ok(samples)
+    }
+
+    fn load_envelopes(&mut self, data: &[u8], panning: bool) -> Result<(), DecodeError> {
+        if data.len() < 2 {
+            return Err(DecodeError::Other("DBM envelope is truncated"));
+        }
+        let count = get_u16(data, 0) as usize;
+        for env in data[2..].chunks_exact(DBM_ENVELOPE_SIZE).take(count) {
+            let instrument = get_u16(env, 0) as usize;
+            let Some(instr) = instrument
+                .checked_sub(1)
+                .and_then(|i| self.instruments.get_mut(i))
+            else {
+                continue;
+            };
+            let flags = env[2];
+            let points = (env[3] as usize + 1).min(DBM_ENVELOPE_POINTS);
+            let (sustain_enabled, sustain_point) = if flags & DBM_ENV_SUSTAIN_A != 0 {
+                (true, env[4] as usize)
+            } else {
+                (flags & DBM_ENV_SUSTAIN_B != 0, env[7] as usize)
+            };
+            let envelope = Envelope {
+                enabled: flags & DBM_ENV_ENABLED != 0,
+                point: env[8..8 + 4 * points]
+                    .chunks_exact(4)
+                    .map(|p| {
+                        let value = get_u16(p, 2) as i16;
+                        EnvelopePoint {
+                            frame: get_u16(p, 0) as usize,
+                            value: if panning {
+                                (value.clamp(-128, 128) + 128) as f32 / 256.0
+                            } else {
+                                value.clamp(0, 64) as f32 / 64.0
+                            },
+                        }
+                    })
+                    .collect(),
+                sustain_enabled,
+                sustain_point: sustain_point.min(points - 1),
+                loop_enabled: flags & DBM_ENV_LOOP != 0,
+                loop_start_point: (env[5] as usize).min(points - 1),
+                loop_end_point: (env[6] as usize).min(points - 1),
+            };
+            if panning {
+                instr.panning_envelope = envelope;
+            } else {
+                instr.volume_envelope = envelope;
+            }
+        }
+        Ok(())
+    }
+
+    /// Number of songs
+    pub fn song_count(&self) -> usize {
+        self.songs.len()
+    }
+
+    /// Module of the first song
+    pub fn to_module(&self) -> Module {
+        self.to_song_module(0)
+    }
+
+    /// Module of one song, patterns and instruments are shared
+    pub fn to_song_module(&self, song: usize) -> Module {
+        let ph = PeriodHelper::new(FrequencyType::LinearFrequencies, false);
+        let song = self.songs.get(song);
+        let mut module = Module {
+            name: self.title.clone(),
+            comment: song.map(|s| s.name.clone()).unwrap_or_default(),
+            frequency_type: FrequencyType::LinearFrequencies,
+            pattern_order: song.map(|s| s.positions.clone()).unwrap_or_default(),
+            pattern: self.patterns.clone(),
+            ..Default::default()
+        };
+
+        for dbm_instr in &self.instruments {
+            let mut instr_def = InstrDefault {
+                volume_envelope: dbm_instr.volume_envelope.clone(),
+                panning_envelope: dbm_instr.panning_envelope.clone(),
+                ..Default::default()
+            };
+
+            let audio = (dbm_instr.sample as usize)
+                .checked_sub(1)
+                .and_then(|s| self.audio.get(s));
+            if let Some(audio) = audio {
+                let flags = dbm_instr.loop_type();
+                let (loop_start, loop_length) = match flags {
+                    LoopType::No => (0, 0),
+                    _ => (dbm_instr.loop_start, dbm_instr.loop_length),
+                };
+                let rn = ph.c4freq_to_relative_note(dbm_instr.c4_rate as f32);
+                let mut sample = Sample {
+                    name: dbm_instr.name.clone(),
+                    loop_start,
+                    loop_length,
+                    volume: dbm_instr.volume.min(64) as f32 / 64.0,
+                    finetune: rn.1,
+                    flags,
+                    panning: (dbm_instr.panning.clamp(-128, 128) + 128) as f32 / 256.0,
+                    relative_note: rn.0,
+                    data: audio.clone(),
+                };
+                sample.fix_loop();
+                instr_def.sample.push(sample);
+            }
+
+            // Create Instrument
+            let instr = Instrument {
+                name: dbm_instr.name.clone(),
+                instr_type: InstrumentType::Default(instr_def),
+                ..Default::default()
+            };
+
+            module.instrument.push(instr);
+        }
+
+        module
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk(id: &[u8; 4], data: &[u8]) -> Vec<u8> {
+        let mut chunk = id.to_vec();
+        chunk.extend_from_slice(&(data.len() as u32).to_be_bytes());
+        chunk.extend_from_slice(data);
+        chunk
+    }
+
+    /// One song, one pattern of 4 channels, sample 1 has 8 frames
+    fn dbm() -> Vec<u8> {
+        let mut data = b"DBM0\x03\x00\x00\x00".to_vec();
+        data.extend(chunk(b"NAME", b"synthetic"));
+        let info: Vec<u8> = [1u16, 1, 1, 1, 4]
+            .iter()
+            .flat_map(|x| x.to_be_bytes())
+            .collect();
+        data.extend(chunk(b"INFO", &info));
+
+        let mut song = b"song".to_vec();
+        song.resize(44, 0);
+        song.extend_from_slice(&[0, 1, 0, 0]);
+        data.extend(chunk(b"SONG", &song));
+
+        let mut instr = b"instrument".to_vec();
+        instr.resize(30, 0);
+        instr.extend_from_slice(&[0, 1, 0, 64]);
+        instr.extend_from_slice(&8363u32.to_be_bytes());
+        instr.resize(DBM_INSTRUMENT_SIZE, 0);
+        data.extend(chunk(b"INST", &instr));
+
+        // C-4, instrument 1, volume slide A20 and volume C30
+        let mut packed = vec![1, 0x3F, 0x40, 1, 0x0A, 0x20, 0x0C, 0x30, 0];
+        // key off on the next row
+        packed.extend_from_slice(&[2, 0x01, DBM_KEY_OFF, 0]);
+        let mut pattern = 64u16.to_be_bytes().to_vec();
+        pattern.extend_from_slice(&(packed.len() as u32).to_be_bytes());
+        pattern.extend_from_slice(&packed);
+        data.extend(chunk(b"PATT", &pattern));
+
+        let mut smpl = 1u32.to_be_bytes().to_vec();
+        smpl.extend_from_slice(&8u32.to_be_bytes());
+        smpl.extend((0..8).map(|x| x * 16));
+        data.extend(chunk(b"SMPL", &smpl));
+        data
+    }
+
+    #[test]
+    fn load_synthetic_module() {
+        let dbm = DbmModule::load(&dbm()).unwrap();
+        assert_eq!(dbm.song_count(), 1);
+        let module = dbm.to_module();
+        assert_eq!(module.name, "synthetic");
+        assert_eq!(module.comment, "song");
+        assert_eq!(module.pattern_order, vec![0]);
+        assert_eq!(module.pattern.len(), 1);
+
+        let pattern = &module.pattern[0];
+        assert_eq!(pattern.len(), 64);
+        assert_eq!(pattern[0].len(), 4);
+        let slot = &pattern[0][0];
+        assert_eq!(slot.note, Note::C4);
+        assert_eq!(slot.instrument, 1);
+        assert_eq!((slot.effect_type, slot.effect_parameter), (0x0A, 0x20));
+        assert_eq!(slot.volume, 0x40);
+        assert_eq!(pattern[1][1].note, Note::KeyOff);
+        let slots = pattern
+            .iter()
+            .flatten()
+            .filter(|s| **s != PatternSlot::default());
+        assert_eq!(slots.count(), 2);
+
+        assert_eq!(module.instrument.len(), 1);
+        let InstrumentType::Default(instr) = &module.instrument[0].instr_type else {
+            panic!("not a sample instrument");
+        };
+        let sample = &instr.sample[0];
+        assert_eq!(sample.name, "instrument");
+        assert_eq!(sample.volume, 1.0);
+        assert_eq!(sample.panning, 0.5);
+        assert!(matches!(sample.flags, LoopType::No));
+        let audio: Vec<i8> = (0..8).map(|x| x * 16).collect();
+        assert!(matches!(&sample.data, SampleDataType::Depth8(v) if *v == audio));
+    }
+
+    #[test]
+    fn reject_broken_modules() {
+        let data = dbm();
+        assert!(DbmModule::load(&data[..DBM_HEADER_SIZE - 1]).is_err());
+        assert!(DbmModule::load(&data[..data.len() - 1]).is_err());
+        // no INFO chunk
+        assert!(DbmModule::load(&data[..DBM_HEADER_SIZE + 17]).is_err());
+    }
+}
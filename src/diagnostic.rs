@@ -0,0 +1,501 @@
+use alloc::{vec, vec::Vec};
+
+use crate::envelope::Envelope;
+use crate::instrument::InstrumentType;
+use crate::module::{Module, MAX_NUM_ROWS};
+use crate::patternslot::PatternSlot;
+use crate::sample::LoopType;
+
+/// How bad a problem is
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Severity {
+    /// Playable, but probably not what the author wanted
+    Warning,
+    /// Players can fail or crash
+    Error,
+}
+
+/// Where a problem is
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Location {
+    Module,
+    /// Index in `pattern_order`
+    Order(usize),
+    Pattern(usize),
+    Row {
+        pattern: usize,
+        row: usize,
+    },
+    Slot {
+        pattern: usize,
+        row: usize,
+        channel: usize,
+    },
+    Instrument(usize),
+    Sample {
+        instrument: usize,
+        sample: usize,
+    },
+}
+
+/// Machine readable problem
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum DiagnosticCode {
+    /// `default_tempo` or `default_bpm` is 0
+    NullTempo,
+    /// `pattern_order` is empty
+    EmptyOrder,
+    /// `pattern_order` entry past `pattern`
+    OrderOutOfRange,
+    /// `restart_position` past `pattern_order`
+    RestartOutOfRange,
+    /// Pattern without any row
+    EmptyPattern,
+    /// More than `MAX_NUM_ROWS` rows
+    TooManyRows,
+    /// Row length is not the number of channels
+    RowLength,
+    /// Slot instrument past `instrument`
+    MissingInstrument,
+    /// Position jump (Bxx) past `pattern_order`
+    JumpOutOfRange,
+    /// Volume envelope sustain or loop point past its points
+    VolumeEnvelopePoints,
+    /// Volume envelope frames are not increasing
+    VolumeEnvelopeOrder,
+    /// Panning envelope sustain or loop point past its points
+    PanningEnvelopePoints,
+    /// Panning envelope frames are not increasing
+    PanningEnvelopeOrder,
+    /// `sample_for_note` entry past `sample`
+    SampleForNoteOutOfRange,
+    /// Sample loop ends after the sample data
+    SampleLoopOutOfRange,
+    /// Sample loop enabled with a null length
+    SampleEmptyLoop,
+}
+
+/// A problem found by `Module::validate()`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub location: Location,
+    pub code: DiagnosticCode,
+}
+
+impl Diagnostic {
+    pub fn new(severity: Severity, location: Location, code: DiagnosticCode) -> Self {
+        Self {
+            severity,
+            location,
+            code,
+        }
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+}
+
+impl Module {
+    /// Check every part of the module, nothing is changed
+    pub fn validate(&self) -> Vec<Diagnostic> {
+        use DiagnosticCode::*;
+        use Severity::*;
+
+        let mut diags: Vec<Diagnostic> = vec![];
+
+        if self.default_tempo == 0 || self.default_bpm == 0 {
+            diags.push(Diagnostic::new(Error, Location::Module, NullTempo));
+        }
+
+        // order
+        if self.pattern_order.is_empty() {
+            diags.push(Diagnostic::new(Warning, Location::Module, EmptyOrder));
+        }
+        for (i, &p) in self.pattern_order.iter().enumerate() {
+            if p >= self.pattern.len() {
+                diags.push(Diagnostic::new(Error, Location::Order(i), OrderOutOfRange));
+            }
+        }
+        if self.restart_position != 0 && self.restart_position >= self.pattern_order.len() {
+            diags.push(Diagnostic::new(Error, Location::Module, RestartOutOfRange));
+        }
+
+        // patterns
+        let channels = self.max_row_len();
+        for (p, pattern) in self.pattern.iter().enumerate() {
+            if pattern.is_empty() {
+                diags.push(Diagnostic::new(Error, Location::Pattern(p), EmptyPattern));
+            }
+            if pattern.len() > MAX_NUM_ROWS {
+                diags.push(Diagnostic::new(Error, Location::Pattern(p), TooManyRows));
+            }
+            for (r, row) in pattern.iter().enumerate() {
+                if row.len() != channels {
+                    let location = Location::Row { pattern: p, row: r };
+                    diags.push(Diagnostic::new(Error, location, RowLength));
+                }
+                for (c, slot) in row.iter().enumerate() {
+                    let location = Location::Slot {
+                        pattern: p,
+                        row: r,
+                        channel: c,
+                    };
+                    if slot.instrument as usize > self.instrument.len() {
+                        diags.push(Diagnostic::new(Warning, location, MissingInstrument));
+                    }
+                    if slot.effect_type == 0x0B
+                        && slot.effect_parameter as usize >= self.pattern_order.len()
+                    {
+                        diags.push(Diagnostic::new(Warning, location, JumpOutOfRange));
+                    }
+                }
+            }
+        }
+
+        // instruments
+        for (i, instr) in self.instrument.iter().enumerate() {
+            if let InstrumentType::Default(id) = &instr.instr_type {
+                let location = Location::Instrument(i);
+                Self::validate_envelope(
+                    &mut diags,
+                    location,
+                    &id.volume_envelope,
+                    VolumeEnvelopePoints,
+                    VolumeEnvelopeOrder,
+                );
+                Self::validate_envelope(
+                    &mut diags,
+                    location,
+                    &id.panning_envelope,
+                    PanningEnvelopePoints,
+                    PanningEnvelopeOrder,
+                );
+
+                if !id.sample.is_empty()
+                    && id
+                        .sample_for_note
+                        .iter()
+                        .any(|&s| s as usize >= id.sample.len())
+                {
+                    diags.push(Diagnostic::new(Error, location, SampleForNoteOutOfRange));
+                }
+
+                for (s, sample) in id.sample.iter().enumerate() {
+                    if let LoopType::No = sample.flags {
+                        continue;
+                    }
+                    let location = Location::Sample {
+                        instrument: i,
+                        sample: s,
+                    };
+                    if sample.loop_length == 0 {
+                        diags.push(Diagnostic::new(Warning, location, SampleEmptyLoop));
+                    } else if sample.loop_start as usize + sample.loop_length as usize
+                        > sample.len()
+                    {
+                        diags.push(Diagnostic::new(Error, location, SampleLoopOutOfRange));
+                    }
+                }
+            }
+        }
+
+        diags
+    }
+
+    fn validate_envelope(
+        diags: &mut Vec<Diagnostic>,
+        location: Location,
+        envelope: &Envelope,
+        points: DiagnosticCode,
+        order: DiagnosticCode,
+    ) {
+        // an empty envelope is unused
+        if envelope.point.is_empty() && !envelope.enabled {
+            return;
+        }
+        if !envelope.has_valid_points() {
+            diags.push(Diagnostic::new(Severity::Error, location, points));
+        }
+        if !envelope.is_sorted() {
+            diags.push(Diagnostic::new(Severity::Warning, location, order));
+        }
+    }
+
+    /// Fix what can be fixed, return what is still wrong
+    pub fn repair(&mut self) -> Vec<Diagnostic> {
+        let default = Module::default();
+        if self.default_tempo == 0 {
+            self.default_tempo = default.default_tempo;
+        }
+        if self.default_bpm == 0 {
+            self.default_bpm = default.default_bpm;
+        }
+
+        // patterns
+        self.normalize_pattern_lengths(MAX_NUM_ROWS);
+        let channels = self.max_row_len();
+        let instrument_qty = self.instrument.len();
+        for pattern in self.pattern.iter_mut() {
+            if pattern.is_empty() && channels != 0 {
+                pattern.push(vec![PatternSlot::default(); channels]);
+            }
+            for row in pattern.iter_mut() {
+                row.resize(channels, PatternSlot::default());
+                for slot in row.iter_mut() {
+                    if slot.instrument as usize > instrument_qty {
+                        slot.instrument = 0;
+                    }
+                }
+            }
+        }
+
+        // order, a jump to a removed order goes to the next one
+        let pattern_qty = self.pattern.len();
+        let mut new_order: Vec<usize> = Vec::with_capacity(self.pattern_order.len());
+        let mut kept = 0;
+        for &p in &self.pattern_order {
+            new_order.push(kept);
+            if p < pattern_qty {
+                kept += 1;
+            }
+        }
+        self.pattern_order.retain(|&p| p < pattern_qty);
+        if self.pattern_order.is_empty() && pattern_qty != 0 {
+            self.pattern_order.push(0);
+        }
+        let order_qty = self.pattern_order.len();
+        let renumber = |o: usize| new_order.get(o).copied().filter(|&n| n < order_qty);
+
+        self.restart_position = renumber(self.restart_position).unwrap_or(0);
+        for slot in self.pattern.iter_mut().flatten().flatten() {
+            if slot.effect_type == 0x0B {
+                match renumber(slot.effect_parameter as usize) {
+                    Some(new) => slot.effect_parameter = new as u8,
+                    None => {
+                        slot.effect_type = 0;
+                        slot.effect_parameter = 0;
+                    }
+                }
+            }
+        }
+
+        // instruments
+        for instr in self.instrument.iter_mut() {
+            if let InstrumentType::Default(id) = &mut instr.instr_type {
+                for envelope in [&mut id.volume_envelope, &mut id.panning_envelope] {
+                    let used = !envelope.point.is_empty() || envelope.enabled;
+                    if used && (!envelope.has_valid_points() || !envelope.is_sorted()) {
+                        *envelope = Envelope::default();
+                    }
+                }
+
+                let sample_qty = id.sample.len();
+                if sample_qty != 0 {
+                    for s in id.sample_for_note.iter_mut() {
+                        if *s as usize >= sample_qty {
+                            *s = 0;
+                        }
+                    }
+                }
+
+                for sample in id.sample.iter_mut() {
+                    sample.fix_loop();
+                }
+            }
+        }
+
+        self.validate()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::envelope::EnvelopePoint;
+    use crate::instr_default::InstrDefault;
+    use crate::instrument::Instrument;
+    use crate::module::Row;
+    use crate::sample::{Sample, SampleDataType};
+
+    fn row(channels: usize) -> Row {
+        vec![PatternSlot::default(); channels]
+    }
+
+    fn jump(order: u8) -> PatternSlot {
+        PatternSlot {
+            effect_type: 0x0B,
+            effect_parameter: order,
+            ..Default::default()
+        }
+    }
+
+    fn instrument() -> Instrument {
+        let mut id = InstrDefault::default();
+        id.sample.push(Sample {
+            name: "".into(),
+            loop_start: 0,
+            loop_length: 4,
+            volume: 1.0,
+            finetune: 0.0,
+            flags: LoopType::Forward,
+            panning: 0.5,
+            relative_note: 0,
+            data: SampleDataType::Depth8(vec![0; 8]),
+        });
+        Instrument {
+            instr_type: InstrumentType::Default(id),
+            ..Default::default()
+        }
+    }
+
+    fn module() -> Module {
+        Module {
+            pattern: vec![vec![row(2), row(2)], vec![row(2)], vec![row(2)]],
+            pattern_order: vec![0, 1, 2],
+            instrument: vec![instrument()],
+            ..Default::default()
+        }
+    }
+
+    fn codes(diags: &[Diagnostic]) -> Vec<(Location, DiagnosticCode)> {
+        diags.iter().map(|d| (d.location, d.code)).collect()
+    }
+
+    #[test]
+    fn valid_module() {
+        assert!(module().validate().is_empty());
+        let mut module = module();
+        assert!(module.repair().is_empty());
+    }
+
+    #[test]
+    fn find_problems() {
+        use DiagnosticCode::*;
+        let mut module = module();
+        module.default_bpm = 0;
+        module.pattern_order = vec![0, 3];
+        module.restart_position = 2;
+        module.pattern[1].clear();
+        module.pattern[2][0].pop();
+        module.pattern[0][0][0].instrument = 2;
+        module.pattern[0][1][1] = jump(2);
+        let InstrumentType::Default(id) = &mut module.instrument[0].instr_type else {
+            unreachable!()
+        };
+        id.volume_envelope.enabled = true;
+        id.panning_envelope.point = vec![
+            EnvelopePoint {
+                frame: 4,
+                value: 0.0,
+            },
+            EnvelopePoint {
+                frame: 2,
+                value: 0.0,
+            },
+        ];
+        id.sample_for_note[10] = 1;
+        id.sample[0].loop_start = 6;
+
+        let diags = module.validate();
+        assert_eq!(
+            codes(&diags),
+            vec![
+                (Location::Module, NullTempo),
+                (Location::Order(1), OrderOutOfRange),
+                (Location::Module, RestartOutOfRange),
+                (
+                    Location::Slot {
+                        pattern: 0,
+                        row: 0,
+                        channel: 0
+                    },
+                    MissingInstrument
+                ),
+                (
+                    Location::Slot {
+                        pattern: 0,
+                        row: 1,
+                        channel: 1
+                    },
+                    JumpOutOfRange
+                ),
+                (Location::Pattern(1), EmptyPattern),
+                (Location::Row { pattern: 2, row: 0 }, RowLength),
+                (Location::Instrument(0), VolumeEnvelopePoints),
+                (Location::Instrument(0), PanningEnvelopeOrder),
+                (Location::Instrument(0), SampleForNoteOutOfRange),
+                (
+                    Location::Sample {
+                        instrument: 0,
+                        sample: 0
+                    },
+                    SampleLoopOutOfRange
+                ),
+            ]
+        );
+        let errors = diags.iter().filter(|d| d.is_error()).count();
+        assert_eq!(errors, 8);
+
+        module.pattern_order.clear();
+        module.instrument[0].instr_type = InstrumentType::Empty;
+        module.pattern[0][0][0].instrument = 0;
+        module.pattern[0][1][1] = PatternSlot::default();
+        let diags = module.validate();
+        assert!(codes(&diags).contains(&(Location::Module, EmptyOrder)));
+    }
+
+    #[test]
+    fn repair_fixes_everything() {
+        let mut module = module();
+        module.default_tempo = 0;
+        module.pattern[1].clear();
+        module.pattern[2][0].pop();
+        module.pattern[0][0][0].instrument = 2;
+        let InstrumentType::Default(id) = &mut module.instrument[0].instr_type else {
+            unreachable!()
+        };
+        id.volume_envelope.enabled = true;
+        id.sample_for_note[10] = 1;
+        id.sample[0].loop_start = 6;
+
+        assert!(module.repair().is_empty());
+        assert_eq!(module.default_tempo, 6);
+        assert_eq!(module.pattern[1], vec![row(2)]);
+        assert_eq!(module.pattern[2][0].len(), 2);
+        assert_eq!(module.pattern[0][0][0].instrument, 0);
+        let InstrumentType::Default(id) = &module.instrument[0].instr_type else {
+            unreachable!()
+        };
+        assert!(!id.volume_envelope.enabled);
+        assert_eq!(id.sample_for_note[10], 0);
+        assert_eq!((id.sample[0].loop_start, id.sample[0].loop_length), (6, 2));
+    }
+
+    #[test]
+    fn repair_renumbers_jumps_and_restart() {
+        let mut module = module();
+        // orders 1 and 3 are removed
+        module.pattern_order = vec![0, 5, 2, 7, 1];
+        module.restart_position = 3;
+        module.pattern[0][0] = vec![jump(3), jump(9)];
+        module.pattern[0][1] = vec![jump(1), jump(4)];
+
+        assert!(module.repair().is_empty());
+        assert_eq!(module.pattern_order, vec![0, 2, 1]);
+        // a removed order is replaced by the next one
+        assert_eq!(module.restart_position, 2);
+        assert_eq!(module.pattern[0][0], vec![jump(2), PatternSlot::default()]);
+        assert_eq!(module.pattern[0][1], vec![jump(1), jump(2)]);
+
+        // nothing left after a removed last order
+        module.pattern_order.push(8);
+        module.restart_position = 3;
+        module.pattern[0][0][0] = jump(3);
+        assert!(module.repair().is_empty());
+        assert_eq!(module.pattern_order, vec![0, 2, 1]);
+        assert_eq!(module.restart_position, 0);
+        assert_eq!(module.pattern[0][0][0], PatternSlot::default());
+    }
+}
@@ -0,0 +1,521 @@
+use alloc::{vec, vec::Vec};
+
+use crate::module::Module;
+use crate::note::Note;
+use crate::patternslot::PatternSlot;
+use crate::resize::MAX_BREAK_ROW;
+
+/// Pattern slots an edit applies to, `None` selects everything
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Selection {
+    /// Index in `pattern`
+    pub pattern: Option<usize>,
+    pub channel: Option<usize>,
+    /// Slot instrument column, 1 is the first instrument
+    pub instrument: Option<u8>,
+}
+
+impl Selection {
+    fn contains(&self, pattern: usize, channel: usize, slot: &PatternSlot) -> bool {
+        self.pattern.is_none_or(|p| p == pattern)
+            && self.channel.is_none_or(|c| c == channel)
+            && self.instrument.is_none_or(|i| i == slot.instrument)
+    }
+}
+
+/// A pattern copy with its own pattern break rows
+struct BreakVariant {
+    source: usize,
+    pattern: usize,
+    /// Rows with a pattern break, and if it lands in the edited pattern
+    rows: Vec<(usize, bool)>,
+}
+
+/// XM set panning effect
+const EFFECT_SET_PANNING: u8 = 0x08;
+/// XM set volume effect
+const EFFECT_SET_VOLUME: u8 = 0x0C;
+/// XM pattern break effect
+const EFFECT_PATTERN_BREAK: u8 = 0x0D;
+
+impl Module {
+    /// Call `f` on every selected slot
+    pub fn for_each_slot(&mut self, selection: &Selection, mut f: impl FnMut(&mut PatternSlot)) {
+        for (p, pattern) in self.pattern.iter_mut().enumerate() {
+            if selection.pattern.is_some_and(|sp| sp != p) {
+                continue;
+            }
+            for row in pattern.iter_mut() {
+                for (c, slot) in row.iter_mut().enumerate() {
+                    if selection.contains(p, c, slot) {
+                        f(slot);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Move selected notes by `semitones`, clamped from C-0 to B-9
+    ///
+    /// `KeyOff` is not changed.
+    pub fn transpose(&mut self, selection: &Selection, semitones: i8) {
+        self.for_each_slot(selection, |slot| {
+            if slot.note.is_valid() {
+                let n = (slot.note.value() as i16 + semitones as i16)
+                    .clamp(Note::C0.value() as i16, Note::B9.value() as i16);
+                slot.note = Note::try_from(n as u8).unwrap();
+            }
+        });
+    }
+
+    /// Replace each slot instrument `i` by `map(i)`, instrument list is unchanged
+    pub fn remap_instruments(&mut self, map: impl Fn(u8) -> u8) {
+        for slot in self.pattern.iter_mut().flatten().flatten() {
+            if slot.instrument != 0 {
+                slot.instrument = map(slot.instrument);
+            }
+        }
+    }
+
+    /// Exchange instruments `a` and `b` (1 is the first instrument),
+    /// in the instrument list and in every pattern
+    pub fn swap_instruments(&mut self, a: u8, b: u8) {
+        if a == 0
+            || b == 0
+            || a as usize > self.instrument.len()
+            || b as usize > self.instrument.len()
+        {
+            return;
+        }
+        self.instrument.swap(a as usize - 1, b as usize - 1);
+        self.remap_instruments(|i| {
+            if i == a {
+                b
+            } else if i == b {
+                a
+            } else {
+                i
+            }
+        });
+    }
+
+    /// Exchange channels `a` and `b` in every row
+    pub fn swap_channels(&mut self, a: usize, b: usize) {
+        for row in self.pattern.iter_mut().flatten() {
+            if a < row.len() && b < row.len() {
+                row.swap(a, b);
+            }
+        }
+    }
+
+    /// Add an empty channel before `channel` in every row
+    pub fn insert_channel(&mut self, channel: usize) {
+        for row in self.pattern.iter_mut().flatten() {
+            row.insert(channel.min(row.len()), PatternSlot::default());
+        }
+    }
+
+    /// Remove `channel` from every row
+    pub fn delete_channel(&mut self, channel: usize) {
+        for row in self.pattern.iter_mut().flatten() {
+            if channel < row.len() {
+                row.remove(channel);
+            }
+        }
+    }
+
+    /// Add `count` empty rows before `row` in a pattern
+    ///
+    /// Pattern breaks (Dxx) to the moved rows follow them.
+    pub fn insert_rows(&mut self, pattern: usize, row: usize, count: usize) {
+        if count == 0 || pattern >= self.pattern.len() {
+            return;
+        }
+        let row = row.min(self.pattern[pattern].len());
+        let channels = self.max_row_len();
+        for p in self.shift_break_rows(pattern, |r| if r >= row { r + count } else { r }) {
+            self.pattern[p].splice(
+                row..row,
+                vec![vec![PatternSlot::default(); channels]; count],
+            );
+        }
+    }
+
+    /// Remove `count` rows from `row` in a pattern
+    ///
+    /// Pattern breaks (Dxx) to the moved rows follow them, breaks to
+    /// removed rows go to the row after them.
+    pub fn delete_rows(&mut self, pattern: usize, row: usize, count: usize) {
+        let Some(len) = self.pattern.get(pattern).map(|p| p.len()) else {
+            return;
+        };
+        let row = row.min(len);
+        let end = row.saturating_add(count).min(len);
+        if row == end {
+            return;
+        }
+        let shift = |r: usize| match r {
+            r if r >= end => r - (end - row),
+            r if r >= row => row,
+            r => r,
+        };
+        for p in self.shift_break_rows(pattern, shift) {
+            self.pattern[p].drain(row..end);
+        }
+    }
+
+    /// Change pattern breaks (Dxx) landing in `pattern` with `shift`,
+    /// return `pattern` and its copies
+    ///
+    /// Orders sharing a pattern must agree on the rows to change, else the
+    /// orders that differ get a copy. Breaks at the song end agree with all.
+    fn shift_break_rows(&mut self, pattern: usize, shift: impl Fn(usize) -> usize) -> Vec<usize> {
+        // (row, lands in `pattern`) for each break of each order
+        let landing: Vec<Vec<(usize, bool)>> = (0..self.pattern_order.len())
+            .map(|k| {
+                let Some(p) = self.pattern.get(self.pattern_order[k]) else {
+                    return vec![];
+                };
+                p.iter()
+                    .enumerate()
+                    .filter_map(|(r, row)| {
+                        let (jump, brk) = Self::row_flow(row)?;
+                        brk?;
+                        let target = self.played_order(jump.unwrap_or(k + 1))?;
+                        Some((r, self.pattern_order[target] == pattern))
+                    })
+                    .collect()
+            })
+            .collect();
+
+        let mut variants: Vec<BreakVariant> = vec![];
+        for (k, rows) in landing.into_iter().enumerate() {
+            let source = self.pattern_order[k];
+            if source >= self.pattern.len() {
+                continue;
+            }
+            let agree = |v: &BreakVariant| {
+                v.source == source
+                    && rows
+                        .iter()
+                        .all(|r| v.rows.iter().all(|w| w.0 != r.0 || w.1 == r.1))
+            };
+            if let Some(v) = variants.iter_mut().find(|v| agree(v)) {
+                self.pattern_order[k] = v.pattern;
+                for r in rows {
+                    if !v.rows.contains(&r) {
+                        v.rows.push(r);
+                    }
+                }
+                continue;
+            }
+            let p = if variants.iter().any(|v| v.source == source) {
+                self.pattern.push(self.pattern[source].clone());
+                self.pattern.len() - 1
+            } else {
+                source
+            };
+            self.pattern_order[k] = p;
+            variants.push(BreakVariant {
+                source,
+                pattern: p,
+                rows,
+            });
+        }
+
+        for v in &variants {
+            for &(r, _) in v.rows.iter().filter(|r| r.1) {
+                for slot in self.pattern[v.pattern][r].iter_mut() {
+                    if slot.effect_type != EFFECT_PATTERN_BREAK {
+                        continue;
+                    }
+                    let param = slot.effect_parameter;
+                    let new = shift((param >> 4) as usize * 10 + (param & 0x0F) as usize);
+                    if new <= MAX_BREAK_ROW {
+                        slot.effect_parameter = (((new / 10) << 4) | (new % 10)) as u8;
+                    }
+                }
+            }
+        }
+
+        let mut edited: Vec<usize> = vec![pattern];
+        edited.extend(
+            variants
+                .iter()
+                .filter(|v| v.source == pattern && v.pattern != pattern)
+                .map(|v| v.pattern),
+        );
+        edited
+    }
+
+    /// First order from `order` with a pattern to play
+    fn played_order(&self, order: usize) -> Option<usize> {
+        (order..self.pattern_order.len()).find(|&k| {
+            self.pattern
+                .get(self.pattern_order[k])
+                .is_some_and(|p| !p.is_empty())
+        })
+    }
+
+    /// Multiply selected volume column and Cxx volumes by `factor`, clamped to 64
+    pub fn scale_volume(&mut self, selection: &Selection, factor: f32) {
+        let scale = |v: u8| ((v as f32 * factor.max(0.0) + 0.5) as u8).min(64);
+        self.for_each_slot(selection, |slot| {
+            // volume column: 0x10..=0x50 sets volume 0..=64
+            if (0x10..=0x50).contains(&slot.volume) {
+                slot.volume = 0x10 + scale(slot.volume - 0x10);
+            }
+            if slot.effect_type == EFFECT_SET_VOLUME {
+                slot.effect_parameter = scale(slot.effect_parameter.min(64));
+            }
+        });
+    }
+
+    /// Set channel panning (0.0 left, 1.0 right) on the first played row
+    ///
+    /// Written as a set panning effect, or in the volume column when the
+    /// effect is used. The first pattern is copied if it is played again.
+    pub fn set_initial_panning(&mut self, panning: &[f32]) {
+        let Some(&first) = self.pattern_order.first() else {
+            return;
+        };
+        if first >= self.pattern.len() || self.pattern[first].is_empty() {
+            return;
+        }
+        let p = if self.pattern_order[1..].contains(&first) {
+            self.pattern.push(self.pattern[first].clone());
+            self.pattern_order[0] = self.pattern.len() - 1;
+            self.pattern.len() - 1
+        } else {
+            first
+        };
+        for (slot, &pan) in self.pattern[p][0].iter_mut().zip(panning) {
+            let pan = (pan.clamp(0.0, 1.0) * 255.0 + 0.5) as u8;
+            if slot.effect_type == 0 && slot.effect_parameter == 0 {
+                slot.effect_type = EFFECT_SET_PANNING;
+                slot.effect_parameter = pan;
+            } else if slot.volume == 0 {
+                // volume column: 0xC0..=0xCF sets panning
+                slot.volume = 0xC0 | (pan >> 4);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::module::Pattern;
+
+    fn slot(note: Note, instrument: u8) -> PatternSlot {
+        PatternSlot {
+            note,
+            instrument,
+            ..Default::default()
+        }
+    }
+
+    fn brk(row: u8) -> PatternSlot {
+        PatternSlot {
+            effect_type: EFFECT_PATTERN_BREAK,
+            effect_parameter: row,
+            ..Default::default()
+        }
+    }
+
+    /// Rows of 2 channels told apart by their instrument
+    fn pattern(id: u8, rows: usize) -> Pattern {
+        (0..rows)
+            .map(|r| vec![slot(Note::None, id), slot(Note::None, r as u8)])
+            .collect()
+    }
+
+    fn module(pattern: Vec<Pattern>, pattern_order: Vec<usize>) -> Module {
+        Module {
+            pattern,
+            pattern_order,
+            ..Default::default()
+        }
+    }
+
+    fn notes(module: &Module) -> Vec<Note> {
+        module.pattern[0].iter().map(|r| r[0].note).collect()
+    }
+
+    #[test]
+    fn transpose_clamps_and_keeps_key_off() {
+        let mut m = module(
+            vec![vec![
+                vec![slot(Note::C1, 1), slot(Note::C4, 2)],
+                vec![slot(Note::A9, 1), slot(Note::C4, 1)],
+                vec![slot(Note::KeyOff, 1), slot(Note::None, 1)],
+            ]],
+            vec![0],
+        );
+        let selection = Selection {
+            instrument: Some(1),
+            ..Default::default()
+        };
+        m.transpose(&selection, 5);
+        assert_eq!(notes(&m), vec![Note::F1, Note::B9, Note::KeyOff]);
+        // instrument 2 is not selected
+        assert_eq!(m.pattern[0][0][1].note, Note::C4);
+        assert_eq!(m.pattern[0][1][1].note, Note::F4);
+        assert_eq!(m.pattern[0][2][1].note, Note::None);
+
+        let selection = Selection {
+            channel: Some(0),
+            ..Default::default()
+        };
+        m.transpose(&selection, -20);
+        assert_eq!(notes(&m), vec![Note::C0, Note::Ds8, Note::KeyOff]);
+    }
+
+    #[test]
+    fn swap_and_remap_instruments() {
+        let mut m = module(vec![pattern(1, 1), pattern(2, 1), pattern(0, 1)], vec![0]);
+        m.instrument = vec![Default::default(), Default::default()];
+        m.instrument[0].name = "a".into();
+        m.swap_instruments(1, 2);
+        assert_eq!(m.instrument[1].name, "a");
+        let ids: Vec<u8> = m.pattern.iter().map(|p| p[0][0].instrument).collect();
+        assert_eq!(ids, vec![2, 1, 0]);
+        // out of range, nothing changes
+        m.swap_instruments(1, 3);
+        m.swap_instruments(0, 1);
+        assert_eq!(m.instrument[1].name, "a");
+
+        m.remap_instruments(|i| i + 10);
+        let ids: Vec<u8> = m.pattern.iter().map(|p| p[0][0].instrument).collect();
+        assert_eq!(ids, vec![12, 11, 0]);
+    }
+
+    #[test]
+    fn move_channels() {
+        let mut m = module(vec![pattern(7, 2)], vec![0]);
+        m.swap_channels(0, 1);
+        assert_eq!(
+            m.pattern[0][1],
+            vec![slot(Note::None, 1), slot(Note::None, 7)]
+        );
+        m.swap_channels(0, 5);
+        assert_eq!(m.pattern[0][1][1].instrument, 7);
+
+        m.insert_channel(1);
+        assert_eq!(m.max_row_len(), 3);
+        assert_eq!(m.pattern[0][1][1], PatternSlot::default());
+        // past the end, appended
+        m.insert_channel(9);
+        assert_eq!(m.pattern[0][1].len(), 4);
+
+        m.delete_channel(0);
+        m.delete_channel(9);
+        assert_eq!(m.pattern[0][1].len(), 3);
+        assert_eq!(m.pattern[0][1][1].instrument, 7);
+    }
+
+    #[test]
+    fn insert_and_delete_rows() {
+        let mut m = module(vec![pattern(1, 4), vec![]], vec![0, 1]);
+        m.insert_rows(0, 1, 2);
+        let rows: Vec<u8> = m.pattern[0].iter().map(|r| r[1].instrument).collect();
+        assert_eq!(rows, vec![0, 0, 0, 1, 2, 3]);
+        assert_eq!(m.pattern[0][1], vec![PatternSlot::default(); 2]);
+
+        // past the end, appended
+        m.insert_rows(0, 99, 1);
+        assert_eq!(m.pattern[0].len(), 7);
+        // nothing to do
+        m.insert_rows(0, 0, 0);
+        m.insert_rows(5, 0, 1);
+        assert_eq!(m.pattern[0].len(), 7);
+        // empty pattern
+        m.insert_rows(1, 3, 2);
+        assert_eq!(m.pattern[1], vec![vec![PatternSlot::default(); 2]; 2]);
+
+        m.delete_rows(0, 1, 2);
+        let rows: Vec<u8> = m.pattern[0].iter().map(|r| r[1].instrument).collect();
+        assert_eq!(rows, vec![0, 1, 2, 3, 0]);
+        m.delete_rows(0, 3, 99);
+        assert_eq!(m.pattern[0].len(), 3);
+        m.delete_rows(0, 5, 1);
+        m.delete_rows(0, 0, 0);
+        m.delete_rows(5, 0, 1);
+        assert_eq!(m.pattern[0].len(), 3);
+        m.delete_rows(1, 0, 2);
+        assert!(m.pattern[1].is_empty());
+        m.delete_rows(1, 0, 2);
+        assert!(m.pattern[1].is_empty());
+    }
+
+    #[test]
+    fn breaks_follow_inserted_and_deleted_rows() {
+        // order 0 breaks to row 0x12 of pattern 1, order 1 breaks to row 3
+        // of pattern 1 again through the empty pattern 2
+        let mut m = module(
+            vec![pattern(1, 4), pattern(2, 20), vec![]],
+            vec![0, 1, 2, 1],
+        );
+        m.pattern[0][3][0] = brk(0x12);
+        m.pattern[1][19][0] = brk(0x03);
+        m.insert_rows(1, 2, 3);
+        assert_eq!(m.pattern[0][3][0], brk(0x15));
+        assert_eq!(m.pattern[1][22][0], brk(0x06));
+        assert_eq!(m.timeline().rows[4].row, 15);
+
+        m.delete_rows(1, 4, 4);
+        assert_eq!(m.pattern[0][3][0], brk(0x11));
+        assert_eq!(m.pattern[1][18][0], brk(0x04));
+        // a break into removed rows goes to the row after them
+        m.delete_rows(1, 3, 2);
+        assert_eq!(m.pattern[1][16][0], brk(0x03));
+        assert_eq!(m.pattern_order, vec![0, 1, 2, 1]);
+    }
+
+    #[test]
+    fn shared_pattern_gets_a_copy() {
+        // pattern 0 breaks to row 2 of pattern 1 at order 0, of pattern 2 at order 2
+        let mut m = module(
+            vec![pattern(1, 4), pattern(2, 4), pattern(3, 4)],
+            vec![0, 1, 0, 2],
+        );
+        m.pattern[0][3][0] = brk(0x02);
+        m.insert_rows(1, 0, 1);
+        assert_eq!(m.pattern_order, vec![0, 1, 3, 2]);
+        assert_eq!(m.pattern[0][3][0], brk(0x03));
+        assert_eq!(m.pattern[3][3][0], brk(0x02));
+        assert_eq!(m.pattern[1].len(), 5);
+    }
+
+    #[test]
+    fn scale_volumes() {
+        let mut m = module(vec![pattern(1, 2)], vec![0]);
+        m.pattern[0][0][0].volume = 0x30;
+        m.pattern[0][0][1].effect_type = EFFECT_SET_VOLUME;
+        m.pattern[0][0][1].effect_parameter = 40;
+        m.pattern[0][1][0].volume = 0xC8;
+        m.scale_volume(&Selection::default(), 2.0);
+        assert_eq!(m.pattern[0][0][0].volume, 0x50);
+        assert_eq!(m.pattern[0][0][1].effect_parameter, 64);
+        assert_eq!(m.pattern[0][1][0].volume, 0xC8);
+        m.scale_volume(&Selection::default(), 0.5);
+        assert_eq!(m.pattern[0][0][0].volume, 0x30);
+        assert_eq!(m.pattern[0][0][1].effect_parameter, 32);
+    }
+
+    #[test]
+    fn initial_panning() {
+        let mut m = module(vec![pattern(1, 2)], vec![0, 0]);
+        m.pattern[0][0][1].effect_type = 0x0A;
+        m.set_initial_panning(&[0.0, 1.0]);
+        // the first pattern is played again, it gets a copy
+        assert_eq!(m.pattern_order, vec![1, 0]);
+        let row = &m.pattern[1][0];
+        assert_eq!(
+            (row[0].effect_type, row[0].effect_parameter),
+            (EFFECT_SET_PANNING, 0)
+        );
+        assert_eq!(row[1].volume, 0xCF);
+        assert_eq!(m.pattern[0][0][0].effect_type, 0);
+    }
+}
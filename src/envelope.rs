@@ -41,3 +41,230 @@ pub struct Envelope {
     /// index in `point`
     pub loop_end_point: usize,
 }
+
+impl Envelope {
+    /// Sustain and loop points exist, loop start is not after loop end
+    pub fn has_valid_points(&self) -> bool {
+        self.sustain_point < self.point.len()
+            && self.loop_start_point < self.point.len()
+            && self.loop_end_point < self.point.len()
+            && self.loop_start_point <= self.loop_end_point
+    }
+
+    /// Frames are strictly increasing
+    pub fn is_sorted(&self) -> bool {
+        self.point.windows(2).all(|w| w[0].frame < w[1].frame)
+    }
+}
+
+impl Envelope {
+    /// Value at `frame` between points, without sustain nor loop
+    pub fn value_at(&self, frame: usize) -> f32 {
+        let next = self.point.partition_point(|p| p.frame <= frame);
+        match (next.checked_sub(1), self.point.get(next)) {
+            (Some(a), Some(b)) => EnvelopePoint::lerp(&self.point[a], b, frame),
+            (Some(a), None) => self.point[a].value,
+            (None, Some(b)) => b.value,
+            (None, None) => 0.0,
+        }
+    }
+
+    /// Values of the first `ticks` ticks, key released at tick `key_off`
+    pub fn curve(&self, ticks: usize, key_off: Option<usize>) -> Vec<f32> {
+        let mut state = EnvelopeState::new(self);
+        let mut curve: Vec<f32> = Vec::with_capacity(ticks);
+        for tick in 0..ticks {
+            if key_off == Some(tick) {
+                state.key_off();
+            }
+            match state.next() {
+                Some(value) => curve.push(value),
+                None => break,
+            }
+        }
+        curve
+    }
+}
+
+/// Envelope playback like FT2, one value per tick
+///
+/// The envelope waits on the sustain point until key off and goes from
+/// loop end to loop start. A loop end on the sustain point only loops
+/// after key off.
+#[derive(Clone, Debug)]
+pub struct EnvelopeState<'a> {
+    envelope: &'a Envelope,
+    tick: usize,
+    released: bool,
+}
+
+impl<'a> EnvelopeState<'a> {
+    /// Key on at tick 0
+    pub fn new(envelope: &'a Envelope) -> Self {
+        Self {
+            envelope,
+            tick: 0,
+            released: false,
+        }
+    }
+
+    /// Restart from tick 0
+    pub fn key_on(&mut self) {
+        self.tick = 0;
+        self.released = false;
+    }
+
+    /// Leave the sustain point
+    pub fn key_off(&mut self) {
+        self.released = true;
+        self.loop_back();
+    }
+
+    pub fn is_released(&self) -> bool {
+        self.released
+    }
+
+    /// Current tick
+    pub fn position(&self) -> usize {
+        self.tick
+    }
+
+    /// Jump to a tick (Lxx)
+    pub fn set_position(&mut self, tick: usize) {
+        self.tick = tick;
+    }
+
+    /// Value at the current tick
+    pub fn value(&self) -> f32 {
+        self.envelope.value_at(self.tick)
+    }
+
+    fn frame(&self, point: usize) -> Option<usize> {
+        self.envelope.point.get(point).map(|p| p.frame)
+    }
+
+    fn sustained(&self) -> bool {
+        self.envelope.sustain_enabled && !self.released
+    }
+
+    fn loop_back(&mut self) {
+        let env = self.envelope;
+        if !env.loop_enabled || Some(self.tick) != self.frame(env.loop_end_point) {
+            return;
+        }
+        // FT2: loop end on the sustain point waits for key off
+        if self.sustained() && env.sustain_point == env.loop_end_point {
+            return;
+        }
+        if let Some(frame) = self.frame(env.loop_start_point) {
+            self.tick = frame;
+        }
+    }
+
+    fn advance(&mut self) {
+        if self.sustained() && Some(self.tick) == self.frame(self.envelope.sustain_point) {
+            return;
+        }
+        let last = self.envelope.point.last().map_or(0, |p| p.frame);
+        if self.tick >= last {
+            return;
+        }
+        self.tick += 1;
+        self.loop_back();
+    }
+}
+
+impl Iterator for EnvelopeState<'_> {
+    type Item = f32;
+
+    /// Value of this tick, `None` for a disabled or empty envelope
+    fn next(&mut self) -> Option<f32> {
+        if !self.envelope.enabled || self.envelope.point.is_empty() {
+            return None;
+        }
+        let value = self.value();
+        self.advance();
+        Some(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+
+    /// 0 up to 1 at tick 4, 0.5 at tick 8, 0 at tick 12
+    fn envelope() -> Envelope {
+        Envelope {
+            enabled: true,
+            point: [(0, 0.0), (4, 1.0), (8, 0.5), (12, 0.0)]
+                .iter()
+                .map(|&(frame, value)| EnvelopePoint { frame, value })
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn curve_without_sustain_nor_loop() {
+        let env = envelope();
+        assert_eq!(
+            env.curve(14, None),
+            vec![0.0, 0.25, 0.5, 0.75, 1.0, 0.875, 0.75, 0.625, 0.5, 0.375, 0.25, 0.125, 0.0, 0.0]
+        );
+        assert_eq!(env.value_at(100), 0.0);
+    }
+
+    #[test]
+    fn sustain_waits_for_key_off() {
+        let mut env = envelope();
+        env.sustain_enabled = true;
+        env.sustain_point = 1;
+        assert_eq!(
+            env.curve(8, Some(6)),
+            vec![0.0, 0.25, 0.5, 0.75, 1.0, 1.0, 1.0, 0.875]
+        );
+    }
+
+    #[test]
+    fn loop_goes_back_to_loop_start() {
+        let mut env = envelope();
+        env.loop_enabled = true;
+        env.loop_start_point = 1;
+        env.loop_end_point = 2;
+        assert_eq!(
+            env.curve(10, None),
+            vec![0.0, 0.25, 0.5, 0.75, 1.0, 0.875, 0.75, 0.625, 1.0, 0.875]
+        );
+    }
+
+    #[test]
+    fn loop_end_on_sustain_loops_after_key_off() {
+        let mut env = envelope();
+        env.sustain_enabled = true;
+        env.sustain_point = 2;
+        env.loop_enabled = true;
+        env.loop_start_point = 1;
+        env.loop_end_point = 2;
+        let curve = env.curve(12, Some(10));
+        assert_eq!(curve[8..], [0.5, 0.5, 1.0, 0.875]);
+    }
+
+    #[test]
+    fn state_position_and_key_on() {
+        let env = envelope();
+        let mut state = EnvelopeState::new(&env);
+        state.set_position(6);
+        assert_eq!(state.next(), Some(0.75));
+        assert_eq!(state.position(), 7);
+        state.key_off();
+        assert!(state.is_released());
+        state.key_on();
+        assert!(!state.is_released());
+        assert_eq!(state.next(), Some(0.0));
+
+        let disabled = Envelope::default();
+        assert_eq!(EnvelopeState::new(&disabled).next(), None);
+        assert!(disabled.curve(4, None).is_empty());
+    }
+}
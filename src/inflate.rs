@@ -0,0 +1,256 @@
+use alloc::{vec, vec::Vec};
+use bincode::error::DecodeError;
+
+const MAX_BITS: usize = 15;
+
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DISTANCE_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+/// Order of code length code lengths
+const CODE_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+
+fn corrupted() -> DecodeError {
+    DecodeError::Other("Deflate data is corrupted")
+}
+
+fn too_large() -> DecodeError {
+    DecodeError::Other("Deflate data is too large")
+}
+
+/// Bits read from the start, least significant first
+struct Bits<'a> {
+    data: &'a [u8],
+    pos: usize,
+    buffer: u32,
+    count: u32,
+}
+
+impl Bits<'_> {
+    fn read(&mut self, bits: u32) -> Result<u32, DecodeError> {
+        while self.count < bits {
+            let byte = *self.data.get(self.pos).ok_or_else(corrupted)?;
+            self.pos += 1;
+            self.buffer |= (byte as u32) << self.count;
+            self.count += 8;
+        }
+        let value = self.buffer & ((1u32 << bits) - 1);
+        self.buffer = self.buffer.checked_shr(bits).unwrap_or(0);
+        self.count -= bits;
+        Ok(value)
+    }
+
+    fn align(&mut self) {
+        self.buffer = 0;
+        self.count = 0;
+    }
+}
+
+/// Canonical Huffman code
+struct Huffman {
+    /// Codes of each length
+    count: [u16; MAX_BITS + 1],
+    /// Symbols sorted by code
+    symbol: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Self {
+        let mut count = [0u16; MAX_BITS + 1];
+        for &len in lengths {
+            count[len as usize] += 1;
+        }
+        let mut offset = [0u16; MAX_BITS + 2];
+        for len in 1..=MAX_BITS {
+            offset[len + 1] = offset[len] + count[len];
+        }
+        let mut symbol = vec![0; lengths.len()];
+        for (s, &len) in lengths.iter().enumerate() {
+            if len != 0 {
+                symbol[offset[len as usize] as usize] = s as u16;
+                offset[len as usize] += 1;
+            }
+        }
+        Self { count, symbol }
+    }
+
+    fn decode(&self, bits: &mut Bits) -> Result<u16, DecodeError> {
+        let (mut code, mut first, mut index) = (0i32, 0i32, 0i32);
+        for len in 1..=MAX_BITS {
+            code |= bits.read(1)? as i32;
+            let count = self.count[len] as i32;
+            if code - count < first {
+                return self
+                    .symbol
+                    .get((index + code - first) as usize)
+                    .copied()
+                    .ok_or_else(corrupted);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err(corrupted())
+    }
+}
+
+fn inflate_block(
+    bits: &mut Bits,
+    out: &mut Vec<u8>,
+    max_size: usize,
+    lengths: &Huffman,
+    distances: &Huffman,
+) -> Result<(), DecodeError> {
+    loop {
+        if out.len() > max_size {
+            return Err(too_large());
+        }
+        let symbol = lengths.decode(bits)? as usize;
+        match symbol {
+            0..=255 => out.push(symbol as u8),
+            256 => return Ok(()),
+            _ => {
+                let i = symbol - 257;
+                if i >= LENGTH_BASE.len() {
+                    return Err(corrupted());
+                }
+                let len = LENGTH_BASE[i] as usize + bits.read(LENGTH_EXTRA[i] as u32)? as usize;
+                let d = distances.decode(bits)? as usize;
+                if d >= DISTANCE_BASE.len() {
+                    return Err(corrupted());
+                }
+                let distance =
+                    DISTANCE_BASE[d] as usize + bits.read(DISTANCE_EXTRA[d] as u32)? as usize;
+                if distance > out.len() {
+                    return Err(corrupted());
+                }
+                let start = out.len() - distance;
+                for i in 0..len {
+                    out.push(out[start + i]);
+                }
+            }
+        }
+    }
+}
+
+fn dynamic_codes(bits: &mut Bits) -> Result<(Huffman, Huffman), DecodeError> {
+    let nlen = bits.read(5)? as usize + 257;
+    let ndist = bits.read(5)? as usize + 1;
+    let ncode = bits.read(4)? as usize + 4;
+
+    let mut code_lengths = [0u8; 19];
+    for &i in CODE_ORDER.iter().take(ncode) {
+        code_lengths[i] = bits.read(3)? as u8;
+    }
+    let code = Huffman::new(&code_lengths);
+
+    let mut lengths = vec![0u8; nlen + ndist];
+    let mut i = 0;
+    while i < nlen + ndist {
+        let (value, repeat) = match code.decode(bits)? {
+            symbol @ 0..=15 => (symbol as u8, 1),
+            16 if i > 0 => (lengths[i - 1], 3 + bits.read(2)? as usize),
+            17 => (0, 3 + bits.read(3)? as usize),
+            18 => (0, 11 + bits.read(7)? as usize),
+            _ => return Err(corrupted()),
+        };
+        if i + repeat > lengths.len() {
+            return Err(corrupted());
+        }
+        lengths[i..i + repeat].fill(value);
+        i += repeat;
+    }
+    Ok((
+        Huffman::new(&lengths[0..nlen]),
+        Huffman::new(&lengths[nlen..]),
+    ))
+}
+
+/// Inflate raw deflate data, return the data and the deflate size
+///
+/// Fails when the data grows over `max_size` bytes.
+pub(crate) fn inflate(data: &[u8], max_size: usize) -> Result<(Vec<u8>, usize), DecodeError> {
+    let mut bits = Bits {
+        data,
+        pos: 0,
+        buffer: 0,
+        count: 0,
+    };
+    let mut out = vec![];
+    loop {
+        let last = bits.read(1)? == 1;
+        match bits.read(2)? {
+            0 => {
+                // stored
+                bits.align();
+                let p = bits.pos;
+                let header = data.get(p..p + 4).ok_or_else(corrupted)?;
+                let len = u16::from_le_bytes([header[0], header[1]]) as usize;
+                let stored = data.get(p + 4..p + 4 + len).ok_or_else(corrupted)?;
+                if out.len() + len > max_size {
+                    return Err(too_large());
+                }
+                out.extend_from_slice(stored);
+                bits.pos = p + 4 + len;
+            }
+            1 => {
+                let mut lengths = [0u8; 288];
+                lengths[0..144].fill(8);
+                lengths[144..256].fill(9);
+                lengths[256..280].fill(7);
+                lengths[280..288].fill(8);
+                let fixed = (Huffman::new(&lengths), Huffman::new(&[5; 30]));
+                inflate_block(&mut bits, &mut out, max_size, &fixed.0, &fixed.1)?;
+            }
+            2 => {
+                let (lengths, distances) = dynamic_codes(&mut bits)?;
+                inflate_block(&mut bits, &mut out, max_size, &lengths, &distances)?;
+            }
+            _ => return Err(corrupted()),
+        }
+        if out.len() > max_size {
+            return Err(too_large());
+        }
+        if last {
+            return Ok((out, bits.pos));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// "xmrs " 20 times, fixed codes
+    const FIXED: [u8; 10] = [0xAB, 0xC8, 0x2D, 0x2A, 0x56, 0xA8, 0xA0, 0x2D, 0x01, 0x00];
+
+    #[test]
+    fn inflate_stored() {
+        let data = [0x01, 3, 0, 0xFC, 0xFF, b'x', b'm', b'r', 0xAA];
+        assert_eq!(inflate(&data, 16).unwrap(), (b"xmr".to_vec(), 8));
+        assert!(inflate(&data[0..7], 16).is_err());
+        assert!(inflate(&data, 2).is_err());
+    }
+
+    #[test]
+    fn inflate_fixed() {
+        let (out, used) = inflate(&FIXED, 1000).unwrap();
+        assert_eq!(out, b"xmrs ".repeat(20));
+        assert_eq!(used, FIXED.len());
+        assert!(inflate(&FIXED, 50).is_err());
+        assert!(inflate(&FIXED[0..5], 1000).is_err());
+    }
+}
@@ -9,7 +9,7 @@ use crate::sample::Sample;
 use alloc::{vec, vec::Vec};
 
 /// Historical XM Instrument
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct InstrDefault {
     #[serde(with = "BigArray")]
     pub sample_for_note: [u8; 96],
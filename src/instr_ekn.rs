@@ -1,7 +1,7 @@
 use serde::{Deserialize, Serialize};
 
 /// Euclidian Rythm Instrument
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct InstrEkn {
     /// Pulsation k
     pub events: u8,
@@ -1,7 +1,7 @@
 use serde::{Deserialize, Serialize};
 
 /// Midi Instrument
-#[derive(Default, Serialize, Deserialize, Debug)]
+#[derive(Default, Serialize, Deserialize, Clone, Debug)]
 pub struct InstrMidi {
     pub on: bool,
     pub channel: u8,
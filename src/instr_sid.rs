@@ -62,6 +62,18 @@ impl SidVoice {
             self.ctrl_gate = true;
         }
     }
+
+    /// Control register value, see `update_from_ctrl_register()`
+    pub fn ctrl_register(&self) -> u8 {
+        (self.ctrl_noise as u8) << 7
+            | (self.ctrl_pulse as u8) << 6
+            | (self.ctrl_sawtooth as u8) << 5
+            | (self.ctrl_triangle as u8) << 4
+            | (self.ctrl_test as u8) << 3
+            | (self.ctrl_rm as u8) << 2
+            | (self.ctrl_sync as u8) << 1
+            | self.ctrl_gate as u8
+    }
 }
 
 /// MOS6581 SID Instrument
@@ -17,8 +17,67 @@ pub enum Waveform {
     RampDown = 3,
 }
 
+/// Quarter of the auto-vibrato sine, 256 steps per cycle
+const SINE_QUARTER: [i8; 65] = [
+    0, 2, 3, 5, 6, 8, 9, 11, 12, 14, 16, 17, 19, 20, 22, 23, 24, 26, 27, 29, 30, 32, 33, 34, 36,
+    37, 38, 39, 41, 42, 43, 44, 45, 46, 47, 48, 49, 50, 51, 52, 53, 54, 55, 56, 56, 57, 58, 59, 59,
+    60, 60, 61, 61, 62, 62, 62, 63, 63, 63, 64, 64, 64, 64, 64, 64,
+];
+
+/// Auto-vibrato waveform tables of a tracker
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VibratoTable {
+    /// Sine and square begin negative, ramps begin at 0
+    #[default]
+    Ft2,
+    /// Sine begins positive, square is 64 then 0, ramp down from 64 to -64
+    It,
+}
+
 impl Waveform {
-    // instr autovib
+    /// Table value in -64..=64 at `pos`, 256 steps per cycle, as a period offset
+    pub fn table_value(&self, table: VibratoTable, pos: u8) -> i8 {
+        let sine = || {
+            let q = pos as usize & 63;
+            match pos >> 6 {
+                0 => SINE_QUARTER[q],
+                1 => SINE_QUARTER[64 - q],
+                2 => -SINE_QUARTER[q],
+                _ => -SINE_QUARTER[64 - q],
+            }
+        };
+        match table {
+            VibratoTable::Ft2 => match self {
+                Waveform::Sine => -sine(),
+                Waveform::Square => {
+                    if pos > 127 {
+                        64
+                    } else {
+                        -64
+                    }
+                }
+                Waveform::RampUp => ((((pos >> 1) as i16 + 64) & 127) - 64) as i8,
+                Waveform::RampDown => (((64 - (pos >> 1) as i16) & 127) - 64) as i8,
+            },
+            VibratoTable::It => {
+                let ramp_down = 64 - ((pos as i16 + 1) >> 1);
+                match self {
+                    Waveform::Sine => sine(),
+                    Waveform::Square => {
+                        if pos < 128 {
+                            64
+                        } else {
+                            0
+                        }
+                    }
+                    Waveform::RampUp => -ramp_down as i8,
+                    Waveform::RampDown => ramp_down as i8,
+                }
+            }
+        }
+    }
+
+    /// Instrument auto-vibrato, one cycle for `step` in 0..1, value in 0..1
     pub fn value(&self, step: f32) -> f32 {
         let step = step % 1.0;
         return match &self {
@@ -30,20 +89,9 @@ impl Waveform {
                     0.0
                 }
             }
-            Waveform::RampUp => {
-                if step < 0.5 {
-                    0.5 * step
-                } else {
-                    0.5 * step + 0.5
-                }
-            }
-            Waveform::RampDown => {
-                if step < 0.5 {
-                    1.0 - 0.5 * step
-                } else {
-                    -0.5 * step + 0.5
-                }
-            }
+            // FT2 ramps start at the middle, one jump per cycle
+            Waveform::RampUp => (step + 0.5) % 1.0,
+            Waveform::RampDown => (1.5 - step) % 1.0,
         };
     }
 }
@@ -56,3 +104,129 @@ pub struct InstrVibrato {
     pub depth: f32,
     pub sweep: f32,
 }
+
+impl InstrVibrato {
+    /// XM rate, 256 steps per cycle
+    fn rate(&self) -> u8 {
+        (self.speed * 63.0 * 4.0).round().clamp(0.0, 255.0) as u8
+    }
+
+    /// XM depth, 0..=15
+    fn xm_depth(&self) -> u16 {
+        (self.depth * 15.0 * 2.0).round().clamp(0.0, 255.0) as u16
+    }
+
+    /// XM sweep, ticks until the full depth
+    fn xm_sweep(&self) -> u16 {
+        (self.sweep * 255.0).round().clamp(0.0, 255.0) as u16
+    }
+}
+
+/// Instrument auto-vibrato playback, one pitch offset per tick from key on
+///
+/// Follows FT2: the depth grows from 0 during `sweep` ticks, and stops
+/// growing at key off. With `VibratoTable::It` the sweep goes on after key off.
+#[derive(Clone, Copy, Debug)]
+pub struct AutoVibratoState {
+    vibrato: InstrVibrato,
+    table: VibratoTable,
+    position: u8,
+    /// Depth in 1/256
+    amplitude: u16,
+    /// Amplitude added each tick, 0 when the sweep is over
+    sweep: u16,
+    released: bool,
+}
+
+impl AutoVibratoState {
+    /// Key on
+    pub fn new(vibrato: &InstrVibrato, table: VibratoTable) -> Self {
+        let mut state = Self {
+            vibrato: *vibrato,
+            table,
+            position: 0,
+            amplitude: 0,
+            sweep: 0,
+            released: false,
+        };
+        state.key_on();
+        state
+    }
+
+    /// Restart the waveform and the sweep
+    pub fn key_on(&mut self) {
+        let depth = self.vibrato.xm_depth() << 8;
+        let sweep = self.vibrato.xm_sweep();
+        self.position = 0;
+        self.released = false;
+        match depth.checked_div(sweep) {
+            Some(step) => {
+                self.amplitude = 0;
+                self.sweep = step;
+            }
+            None => {
+                self.amplitude = depth;
+                self.sweep = 0;
+            }
+        }
+    }
+
+    pub fn key_off(&mut self) {
+        self.released = true;
+    }
+
+    /// Period offset of the next tick, 64 per semitone, positive is lower
+    pub fn next_period_offset(&mut self) -> i16 {
+        let depth = self.vibrato.xm_depth();
+        if depth == 0 {
+            return 0;
+        }
+        let amplitude = if self.sweep == 0 {
+            self.amplitude
+        } else if self.released && self.table == VibratoTable::Ft2 {
+            // FT2 quirk: only the sweep step after key off
+            self.sweep
+        } else {
+            let mut amplitude = self.amplitude + self.sweep;
+            if amplitude >> 8 > depth {
+                amplitude = depth << 8;
+                self.sweep = 0;
+            }
+            self.amplitude = amplitude;
+            amplitude
+        };
+        self.position = self.position.wrapping_add(self.vibrato.rate());
+        let value = self.vibrato.waveform.table_value(self.table, self.position);
+        ((value as i32 * amplitude as i32) >> (6 + 8)) as i16
+    }
+}
+
+impl Iterator for AutoVibratoState {
+    type Item = f32;
+
+    /// Pitch offset of the next tick in semitones
+    fn next(&mut self) -> Option<f32> {
+        Some(-(self.next_period_offset() as f32) / 64.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn xm_values_survive_the_round_trip() {
+        for x in 0..=255u8 {
+            // as read by the XM loader
+            let vibrato = InstrVibrato {
+                waveform: Waveform::Sine,
+                speed: x as f32 / 63.0 / 4.0,
+                depth: x as f32 / 15.0 / 2.0,
+                sweep: x as f32 / 255.0,
+            };
+            assert_eq!(vibrato.rate(), x);
+            assert_eq!(vibrato.xm_depth(), x as u16);
+            assert_eq!(vibrato.xm_sweep(), x as u16);
+        }
+    }
+}
@@ -12,7 +12,7 @@ use alloc::string::String;
 //===========================================================================
 
 /// Instrument Type
-#[derive(Default, Serialize, Deserialize, Debug)]
+#[derive(Default, Serialize, Deserialize, Clone, Debug)]
 pub enum InstrumentType {
     /// No Instrument
     #[default]
@@ -32,7 +32,7 @@ pub enum InstrumentType {
 }
 
 /// Instrument with Steroid
-#[derive(Default, Serialize, Deserialize, Debug)]
+#[derive(Default, Serialize, Deserialize, Clone, Debug)]
 pub struct Instrument {
     pub name: String,
     pub instr_type: InstrumentType,
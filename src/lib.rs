@@ -25,8 +25,17 @@
 
 extern crate alloc;
 
+/// Unwrap modules from containers and compressed files
+pub mod container;
+/// Module validation and repair
+pub mod diagnostic;
+/// Bulk pattern edits
+pub mod edit;
 /// Envelope with Steroid
 pub mod envelope;
+/// Deflate decoder for gzip and zip files
+#[cfg(feature = "std")]
+mod inflate;
 /// Historical XM Instrument
 pub mod instr_default;
 /// Euclidian Rythm Instrument
@@ -47,12 +56,24 @@ pub mod instrument;
 pub mod module;
 /// A typical Note
 pub mod note;
+/// Remove unused and duplicated module data
+pub mod optimize;
 /// A typical pattern slot
 pub mod patternslot;
 /// Period Helper
 pub mod period_helper;
+/// Split, join and resize patterns
+pub mod resize;
 /// Sample with Steroid
 pub mod sample;
+/// Sample processing
+pub mod sample_dsp;
+/// SoundFont 2 and SFZ instrument export
+pub mod soundfont;
+/// Sub-song detection and extraction
+pub mod subsong;
+/// Song duration and row timeline
+pub mod timeline;
 
 /// Load and Save Historical XM files
 #[cfg(feature = "import_xm")]
@@ -70,6 +91,42 @@ pub mod s3m;
 #[cfg(feature = "import_sid")]
 pub mod sid;
 
+/// Load only Historical STM files
+#[cfg(feature = "import_stm")]
+pub mod stm;
+
+/// Load only Historical 669 files
+#[cfg(feature = "import_669")]
+pub mod c669;
+
+/// Load only Historical MTM files
+#[cfg(feature = "import_mtm")]
+pub mod mtm;
+
+/// Load only Historical ULT files
+#[cfg(feature = "import_ult")]
+pub mod ult;
+
+/// Load only Historical MED files
+#[cfg(feature = "import_med")]
+pub mod med;
+
+/// Load only Historical OKT files
+#[cfg(feature = "import_okt")]
+pub mod okt;
+
+/// Load only Historical DBM files
+#[cfg(feature = "import_dbm")]
+pub mod dbm;
+
+/// Load only Historical AHX and HVL files
+#[cfg(feature = "import_ahx")]
+pub mod ahx;
+
+/// Load and Save Standard MIDI files
+#[cfg(feature = "import_midi")]
+pub mod midi;
+
 /// The Xmrs Prelude
 pub mod prelude;
 
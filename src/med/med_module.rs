@@ -0,0 +1,674 @@
+use bincode::error::DecodeError;
+
+use crate::module::MAX_NUM_ROWS;
+use crate::prelude::*;
+
+use alloc::string::String;
+use alloc::string::ToString;
+use alloc::{vec, vec::Vec};
+
+const MMD_HEADER_SIZE: usize = 52;
+const MMD_SONG_SIZE: usize = 788;
+const MMD_SONG_SAMPLES: usize = 63;
+const MMD_MAX_TRACKS: usize = 64;
+
+/// Song flags
+const FLAG_VOLHEX: u8 = 0x10;
+const FLAG_8CHANNEL: u8 = 0x40;
+/// Song flags2
+const FLAG2_BMASK: u8 = 0x1F;
+const FLAG2_BPM: u8 = 0x20;
+const FLAG2_MIX: u8 = 0x80;
+/// Instrument extension flags
+const SSFLG_PINGPONG: u8 = 0x08;
+
+/// Instrument types
+const MMD_SYNTH: i16 = -1;
+const MMD_HYBRID: i16 = -2;
+const MMD_S16: i16 = 0x10;
+const MMD_STEREO: i16 = 0x20;
+/// SynthInstr waveform pointers
+const MMD_SYNTH_WF: usize = 278;
+
+fn truncated() -> DecodeError {
+    DecodeError::Other("MED module is truncated")
+}
+
+fn get_bytes(data: &[u8], at: usize, len: usize) -> Result<&[u8], DecodeError> {
+    data.get(at..at.checked_add(len).ok_or_else(truncated)?)
+        .ok_or_else(truncated)
+}
+
+fn get_u8(data: &[u8], at: usize) -> Result<u8, DecodeError> {
+    data.get(at).copied().ok_or_else(truncated)
+}
+
+fn get_u16(data: &[u8], at: usize) -> Result<u16, DecodeError> {
+    let b = get_bytes(data, at, 2)?;
+    Ok(u16::from_be_bytes([b[0], b[1]]))
+}
+
+fn get_u32(data: &[u8], at: usize) -> Result<u32, DecodeError> {
+    let b = get_bytes(data, at, 4)?;
+    Ok(u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+}
+
+fn get_string(data: &[u8], at: usize, len: usize) -> String {
+    let len = len.min(data.len().saturating_sub(at));
+    get_bytes(data, at, len)
+        .map(|b| {
+            let end = b.iter().position(|&c| c == 0).unwrap_or(b.len());
+            String::from_utf8_lossy(&b[0..end]).trim_end().to_string()
+        })
+        .unwrap_or_default()
+}
+
+#[derive(Default, Debug)]
+struct MedSample {
+    name: String,
+    /// Loop start and length, in words
+    rep: u16,
+    replen: u16,
+    /// 0-64
+    volume: u8,
+    transpose: i8,
+    /// 1/8 semitone
+    finetune: i8,
+    pingpong: bool,
+    /// Loop start and length in samples, replace rep and replen
+    long_loop: Option<(u32, u32)>,
+    /// Synth waveforms loop on the whole data
+    synth: bool,
+    /// One buffer, or one buffer by octave from the shortest
+    octaves: Vec<SampleDataType>,
+}
+
+impl MedSample {
+    /// Big endian signed data, left then right for stereo
+    fn sample_data(data: &[u8], bits16: bool, stereo: bool) -> SampleDataType {
+        match (bits16, stereo) {
+            (false, false) => SampleDataType::Depth8(data.iter().map(|&x| x as i8).collect()),
+            (true, false) => SampleDataType::Depth16(
+                data.chunks_exact(2)
+                    .map(|c| i16::from_be_bytes([c[0], c[1]]))
+                    .collect(),
+            ),
+            (false, true) => {
+                let (left, right) = data.split_at(data.len() / 2);
+                SampleDataType::Stereo8(
+                    left.iter()
+                        .zip(right)
+                        .flat_map(|(&l, &r)| [l as i8, r as i8])
+                        .collect(),
+                )
+            }
+            (true, true) => {
+                let (left, right) = data.split_at(data.len() / 4 * 2);
+                SampleDataType::Stereo16(
+                    left.chunks_exact(2)
+                        .zip(right.chunks_exact(2))
+                        .flat_map(|(l, r)| {
+                            [
+                                i16::from_be_bytes([l[0], l[1]]),
+                                i16::from_be_bytes([r[0], r[1]]),
+                            ]
+                        })
+                        .collect(),
+                )
+            }
+        }
+    }
+
+    /// InstrHdr: length, type, then data
+    fn load_audio(&mut self, data: &[u8], at: usize) -> Result<(), DecodeError> {
+        let len = get_u32(data, at)? as usize;
+        let kind = get_u16(data, at + 4)? as i16;
+        match kind {
+            MMD_SYNTH => {
+                // first waveform, a short loop
+                let wf = at + get_u32(data, at + MMD_SYNTH_WF)? as usize;
+                let wf_len = get_u16(data, wf)? as usize * 2;
+                let audio = get_bytes(data, wf + 2, wf_len)?;
+                self.octaves.push(Self::sample_data(audio, false, false));
+                self.synth = true;
+            }
+            MMD_HYBRID => {
+                // first waveform is a sample
+                let wf = at + get_u32(data, at + MMD_SYNTH_WF)? as usize;
+                if get_u16(data, wf + 4)? as i16 >= 0 {
+                    self.load_audio(data, wf)?;
+                }
+            }
+            _ if kind >= 0 => {
+                let stereo = kind & MMD_STEREO != 0;
+                let len = if stereo { len * 2 } else { len };
+                let audio = get_bytes(data, at + 6, len.min(data.len().saturating_sub(at + 6)))?;
+                let bits16 = kind & MMD_S16 != 0;
+                let octaves = match kind & 0x0F {
+                    1 => 5,
+                    2 => 3,
+                    3 => 2,
+                    4 => 4,
+                    5 => 6,
+                    6 => 7,
+                    _ => 1,
+                };
+                // each octave is twice the previous one
+                let unit = audio.len() / ((1 << octaves) - 1);
+                let mut start = 0;
+                for octave in 0..octaves {
+                    let end = if octaves == 1 {
+                        audio.len()
+                    } else {
+                        start + (unit << octave)
+                    };
+                    self.octaves
+                        .push(Self::sample_data(&audio[start..end], bits16, stereo));
+                    start = end;
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn to_instrument(&self) -> Instrument {
+        let mut instr_def = InstrDefault::default();
+        let count = self.octaves.len();
+        for (octave, audio) in self.octaves.iter().enumerate() {
+            let (flags, loop_start, loop_length) = if self.synth {
+                (LoopType::Forward, 0, 0)
+            } else if let Some((start, length)) = self.long_loop {
+                let flags = if length < 2 {
+                    LoopType::No
+                } else if self.pingpong {
+                    LoopType::PingPong
+                } else {
+                    LoopType::Forward
+                };
+                (flags, start, length)
+            } else if self.replen > 1 {
+                // loop is set for the shortest octave
+                let flags = if self.pingpong {
+                    LoopType::PingPong
+                } else {
+                    LoopType::Forward
+                };
+                (
+                    flags,
+                    (self.rep as u32 * 2) << octave,
+                    (self.replen as u32 * 2) << octave,
+                )
+            } else {
+                (LoopType::No, 0, 0)
+            };
+            let mut sample = Sample {
+                name: self.name.clone(),
+                loop_start,
+                loop_length,
+                volume: self.volume.min(64) as f32 / 64.0,
+                finetune: self.finetune as f32 / 8.0,
+                flags,
+                panning: 0.5,
+                // longer buffers are played faster
+                relative_note: self.transpose.saturating_add(12 * octave as i8),
+                data: audio.clone(),
+            };
+            if self.synth {
+                sample.loop_length = sample.len() as u32;
+            }
+            sample.fix_loop();
+            instr_def.sample.push(sample);
+        }
+
+        // lowest octaves use the longest buffers, from MED C-1
+        if count > 1 {
+            for (i, s) in instr_def.sample_for_note.iter_mut().enumerate() {
+                let octave = i.saturating_sub(36) / 12;
+                *s = (count - 1).saturating_sub(octave) as u8;
+            }
+        }
+
+        Instrument {
+            name: self.name.clone(),
+            instr_type: InstrumentType::Default(instr_def),
+            ..Default::default()
+        }
+    }
+}
+
+/// OctaMED MMD0, MMD1, MMD2 and MMD3 Module
+#[derive(Default, Debug)]
+pub struct MedModule {
+    /// MMD0 to MMD3
+    version: u8,
+    title: String,
+    annotation: String,
+    flags: u8,
+    flags2: u8,
+    /// Timer tempo or BPM
+    deftempo: u16,
+    /// Ticks per row
+    tempo2: u8,
+    transpose: i8,
+    samples: Vec<MedSample>,
+    positions: Vec<usize>,
+    /// -16 left, 16 right, only for MMD2 and later
+    panning: Vec<i8>,
+    patterns: Vec<Pattern>,
+}
+
+impl MedModule {
+    pub fn load(ser_med_module: &[u8]) -> Result<MedModule, DecodeError> {
+        let data = ser_med_module;
+        let mut med = MedModule {
+            ..Default::default()
+        };
+
+        // === load header
+
+        if data.len() < MMD_HEADER_SIZE
+            || &data[0..3] != b"MMD"
+            || !(b'0'..=b'3').contains(&data[3])
+        {
+            return Err(DecodeError::Other("Not a MED module?"));
+        }
+        med.version = data[3] - b'0';
+        let song = get_u32(data, 8)? as usize;
+        let block_array = get_u32(data, 16)? as usize;
+        let sample_array = get_u32(data, 24)? as usize;
+        let expansion = get_u32(data, 32)? as usize;
+
+        // === song
+
+        let song_data = get_bytes(data, song, MMD_SONG_SIZE)?;
+        let block_count = get_u16(song_data, 504)? as usize;
+        let song_length = get_u16(song_data, 506)? as usize;
+        med.deftempo = get_u16(song_data, 764)?;
+        med.transpose = song_data[766] as i8;
+        med.flags = song_data[767];
+        med.flags2 = song_data[768];
+        med.tempo2 = song_data[769];
+        let sample_count = (song_data[787] as usize).min(MMD_SONG_SAMPLES);
+
+        for s in song_data[0..MMD_SONG_SAMPLES * 8].chunks_exact(8) {
+            med.samples.push(MedSample {
+                rep: u16::from_be_bytes([s[0], s[1]]),
+                replen: u16::from_be_bytes([s[2], s[3]]),
+                volume: s[6],
+                transpose: s[7] as i8,
+                ..Default::default()
+            });
+        }
+        med.samples.truncate(sample_count);
+
+        // === positions
+
+        if med.version < 2 {
+            med.positions = song_data[508..508 + song_length.min(256)]
+                .iter()
+                .map(|&p| p as usize)
+                .filter(|&p| p < block_count)
+                .collect();
+        } else {
+            // sections of play sequences
+            let playseq_table = get_u32(song_data, 508)? as usize;
+            let section_table = get_u32(song_data, 512)? as usize;
+            let track_count = get_u16(song_data, 520)? as usize;
+            let playseq_count = get_u16(song_data, 522)? as usize;
+            let track_pans = get_u32(song_data, 524)? as usize;
+            let sections: Vec<usize> = if section_table == 0 {
+                vec![0]
+            } else {
+                (0..song_length)
+                    .map(|s| get_u16(data, section_table + 2 * s).map(|p| p as usize))
+                    .collect::<Result<_, _>>()?
+            };
+            for section in sections.into_iter().filter(|&s| s < playseq_count) {
+                let playseq = get_u32(data, playseq_table + 4 * section)? as usize;
+                let len = get_u16(data, playseq + 40)? as usize;
+                for i in 0..len {
+                    // high bit is a command
+                    let p = get_u16(data, playseq + 42 + 2 * i)? as usize;
+                    if p < block_count {
+                        med.positions.push(p);
+                    }
+                }
+            }
+            if track_pans != 0 {
+                med.panning = get_bytes(data, track_pans, track_count.min(MMD_MAX_TRACKS))?
+                    .iter()
+                    .map(|&p| p as i8)
+                    .collect();
+            }
+        }
+
+        // === blocks
+
+        // tracks, rows, slot size, data
+        let mut blocks: Vec<(usize, usize, usize, &[u8])> = vec![];
+        for b in 0..block_count {
+            let block = get_u32(data, block_array + 4 * b)? as usize;
+            let (tracks, rows, at, slot_size) = if med.version == 0 {
+                let tracks = get_u8(data, block)? as usize;
+                let rows = get_u8(data, block + 1)? as usize + 1;
+                (tracks, rows, block + 2, 3)
+            } else {
+                let tracks = get_u16(data, block)? as usize;
+                let rows = get_u16(data, block + 2)? as usize + 1;
+                (tracks, rows, block + 8, 4)
+            };
+            if tracks > MMD_MAX_TRACKS {
+                return Err(DecodeError::Other("Not a MED module?"));
+            }
+            blocks.push((
+                tracks,
+                rows,
+                slot_size,
+                get_bytes(data, at, tracks * rows * slot_size)?,
+            ));
+        }
+        let channels = blocks.iter().map(|b| b.0).max().unwrap_or(0).max(1);
+        for (tracks, rows, slot_size, block_data) in blocks {
+            let mut pattern: Pattern =
+                vec![vec![PatternSlot::default(); channels]; rows.min(MAX_NUM_ROWS)];
+            // a block without tracks has no data
+            for (i, slot_data) in block_data.chunks_exact(slot_size).enumerate() {
+                if let Some(row) = pattern.get_mut(i / tracks) {
+                    row[i % tracks] = med.decode_pattern_slot(slot_data);
+                }
+            }
+            med.patterns.push(pattern);
+        }
+
+        // === samples
+
+        for (i, sample) in med.samples.iter_mut().enumerate() {
+            let at = get_u32(data, sample_array + 4 * i)? as usize;
+            if sample_array != 0 && at != 0 {
+                sample.load_audio(data, at)?;
+            }
+        }
+
+        // === expansion, every field is optional
+
+        if expansion != 0 {
+            let exp = |offset: usize| get_u32(data, expansion + offset).unwrap_or(0) as usize;
+            let exp16 = |offset: usize| get_u16(data, expansion + offset).unwrap_or(0) as usize;
+
+            // finetune and long loops
+            let (exp_smp, entries, size) = (exp(4), exp16(8), exp16(10));
+            if exp_smp != 0 && size >= 4 {
+                for (i, sample) in med.samples.iter_mut().enumerate().take(entries) {
+                    let entry = get_bytes(data, exp_smp + i * size, size)?;
+                    sample.finetune = (entry[3] as i8).clamp(-8, 7);
+                    if size >= 6 {
+                        sample.pingpong = entry[5] & SSFLG_PINGPONG != 0;
+                    }
+                    if size >= 18 {
+                        let start =
+                            u32::from_be_bytes([entry[10], entry[11], entry[12], entry[13]]);
+                        let length =
+                            u32::from_be_bytes([entry[14], entry[15], entry[16], entry[17]]);
+                        if length != 0 {
+                            sample.long_loop = Some((start, length));
+                        }
+                    }
+                }
+            }
+
+            // annotation
+            let (annotation, len) = (exp(12), exp(16));
+            if annotation != 0 {
+                med.annotation = get_string(data, annotation, len);
+            }
+
+            // instrument names
+            let (iinfo, entries, size) = (exp(20), exp16(24), exp16(26));
+            if iinfo != 0 && size >= 40 {
+                for (i, sample) in med.samples.iter_mut().enumerate().take(entries) {
+                    sample.name = get_string(data, iinfo + i * size, 40);
+                }
+            }
+
+            let (song_name, len) = (exp(44), exp(48));
+            if song_name != 0 {
+                med.title = get_string(data, song_name, len);
+            }
+        }
+
+        Ok(med)
+    }
+
+    fn decode_pattern_slot(&self, data: &[u8]) -> PatternSlot {
+        let (note, instrument, effect_type, effect_parameter) = if self.version == 0 {
+            // xynnnnnn iiiicccc dddddddd, x and y are instrument bits 4 and 5
+            let instrument = ((data[0] & 0x80) >> 3) | ((data[0] & 0x40) >> 1) | (data[1] >> 4);
+            (data[0] & 0x3F, instrument, data[1] & 0x0F, data[2])
+        } else {
+            (data[0] & 0x7F, data[1] & 0x3F, data[2], data[3])
+        };
+        let mut slot = PatternSlot {
+            note: if note == 0 {
+                Note::None
+            } else {
+                // MED C-1 is the Amiga C-1
+                let n = 36 + note as i16 + self.transpose as i16;
+                u8::try_from(n)
+                    .ok()
+                    .and_then(|n| Note::try_from(n).ok())
+                    .unwrap_or(Note::None)
+            },
+            instrument,
+            effect_type,
+            effect_parameter,
+            ..Default::default()
+        };
+        self.efx_correction(&mut slot);
+        slot
+    }
+
+    /// Convert MED effects to XM effects
+    fn efx_correction(&self, n: &mut PatternSlot) {
+        let param = n.effect_parameter;
+        match n.effect_type {
+            0x00..=0x07 | 0x0B => {}
+            0x09 if param == 0 => n.effect_type = 0,
+            0x09 => {
+                // secondary tempo
+                n.effect_type = 0x0F;
+                n.effect_parameter = param.min(0x1F);
+            }
+            0x0A | 0x0D => n.effect_type = 0x0A,
+            0x0C => {
+                let volume = if self.flags & FLAG_VOLHEX != 0 {
+                    param
+                } else {
+                    (param >> 4) * 10 + (param & 0x0F)
+                };
+                n.effect_parameter = volume.min(64);
+            }
+            0x0F => match param {
+                0x00 => n.effect_type = 0x0D,
+                0x01..=0xF0 => n.effect_parameter = self.bpm(param as u16),
+                0xF1 => (n.effect_type, n.effect_parameter) = (0x0E, 0x93),
+                0xF2 => (n.effect_type, n.effect_parameter) = (0x0E, 0xD3),
+                0xF3 => (n.effect_type, n.effect_parameter) = (0x0E, 0x92),
+                0xF8 => (n.effect_type, n.effect_parameter) = (0x0E, 0x01),
+                0xF9 => (n.effect_type, n.effect_parameter) = (0x0E, 0x00),
+                0xFF => (n.effect_type, n.effect_parameter) = (0x0E, 0xC0),
+                _ => (n.effect_type, n.effect_parameter) = (0, 0),
+            },
+            0x11 => (n.effect_type, n.effect_parameter) = (0x0E, 0x10 | (param & 0x0F)),
+            0x12 => (n.effect_type, n.effect_parameter) = (0x0E, 0x20 | (param & 0x0F)),
+            0x14 => n.effect_type = 0x04,
+            0x15 => (n.effect_type, n.effect_parameter) = (0x0E, 0x50 | (param & 0x0F)),
+            0x16 => (n.effect_type, n.effect_parameter) = (0x0E, 0x60 | (param & 0x0F)),
+            0x18 => (n.effect_type, n.effect_parameter) = (0x0E, 0xC0 | (param & 0x0F)),
+            0x19 => n.effect_type = 0x09,
+            0x1A => (n.effect_type, n.effect_parameter) = (0x0E, 0xA0 | (param & 0x0F)),
+            0x1B => (n.effect_type, n.effect_parameter) = (0x0E, 0xB0 | (param & 0x0F)),
+            0x1D => {
+                // hexadecimal row
+                n.effect_type = 0x0D;
+                n.effect_parameter = ((param / 10) << 4) | (param % 10);
+            }
+            0x1E => (n.effect_type, n.effect_parameter) = (0x0E, 0xE0 | (param & 0x0F)),
+            0x1F if param & 0xF0 != 0 => {
+                (n.effect_type, n.effect_parameter) = (0x0E, 0xD0 | (param >> 4))
+            }
+            0x1F => (n.effect_type, n.effect_parameter) = (0x0E, 0x90 | (param & 0x0F)),
+            _ => (n.effect_type, n.effect_parameter) = (0, 0),
+        }
+    }
+
+    /// Beats per minute of a MED tempo
+    fn bpm(&self, tempo: u16) -> u8 {
+        let mix = self.flags2 & FLAG2_MIX != 0;
+        let bpm = if self.flags & FLAG_8CHANNEL != 0 && tempo > 0 {
+            const TEMPOS: [u16; 10] = [179, 164, 152, 141, 131, 123, 116, 110, 104, 99];
+            TEMPOS[tempo.min(10) as usize - 1] as f32
+        } else if !mix && (1..=10).contains(&tempo) {
+            // SoundTracker compatible tempo
+            6.0 * 1_773_447.0 / 14_500.0 / tempo as f32
+        } else if mix && tempo < 8 {
+            const TEMPOS: [u16; 8] = [47, 43, 40, 37, 35, 32, 30, 30];
+            TEMPOS[tempo as usize] as f32
+        } else if self.flags2 & FLAG2_BPM != 0 && !mix {
+            let lines_per_beat = (self.flags2 & FLAG2_BMASK) as f32 + 1.0;
+            tempo as f32 * lines_per_beat / 4.0
+        } else {
+            // CIA timer
+            tempo as f32 / 0.264
+        };
+        (bpm as u16).clamp(32, 255) as u8
+    }
+
+    pub fn to_module(&self) -> Module {
+        let mut module = Module {
+            name: self.title.clone(),
+            comment: self.annotation.clone(),
+            frequency_type: FrequencyType::AmigaFrequencies,
+            default_tempo: if self.tempo2 == 0 {
+                6
+            } else {
+                self.tempo2.min(0x1F) as u16
+            },
+            default_bpm: self.bpm(self.deftempo) as u16,
+            pattern_order: self.positions.clone(),
+            pattern: self.patterns.clone(),
+            ..Default::default()
+        };
+
+        module.instrument = self.samples.iter().map(|s| s.to_instrument()).collect();
+
+        if !self.panning.is_empty() {
+            let panning: Vec<f32> = self
+                .panning
+                .iter()
+                .map(|&p| (p.clamp(-16, 16) + 16) as f32 / 32.0)
+                .collect();
+            module.set_initial_panning(&panning);
+        }
+
+        module
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// MMD0 with an empty block then one block of 4 tracks, sample 1 has 8 frames
+    fn mmd0() -> Vec<u8> {
+        let song = MMD_HEADER_SIZE;
+        let block_array = song + MMD_SONG_SIZE;
+        let blocks = [block_array + 8, block_array + 10];
+        let sample_array = blocks[1] + 2 + 4 * 64 * 3;
+        let instr = sample_array + 4;
+
+        let mut data = b"MMD0".to_vec();
+        data.resize(MMD_HEADER_SIZE, 0);
+        data[8..12].copy_from_slice(&(song as u32).to_be_bytes());
+        data[16..20].copy_from_slice(&(block_array as u32).to_be_bytes());
+        data[24..28].copy_from_slice(&(sample_array as u32).to_be_bytes());
+
+        let mut song_data = [0u8; MMD_SONG_SIZE];
+        // no loop, volume 64
+        song_data[0..8].copy_from_slice(&[0, 0, 0, 1, 0, 0, 64, 0]);
+        // 2 blocks, 1 position, playing block 1
+        song_data[504..509].copy_from_slice(&[0, 2, 0, 1, 1]);
+        song_data[764..766].copy_from_slice(&6u16.to_be_bytes());
+        song_data[769] = 6;
+        song_data[787] = 1;
+        data.extend_from_slice(&song_data);
+        for block in blocks {
+            data.extend_from_slice(&(block as u32).to_be_bytes());
+        }
+
+        // no track, one row
+        data.extend_from_slice(&[0, 0]);
+        // 4 tracks, 64 rows
+        data.extend_from_slice(&[4, 63]);
+        let mut block = [0u8; 4 * 64 * 3];
+        // MED C-2, instrument 1, volume 64 in decimal
+        block[0..3].copy_from_slice(&[13, 0x1C, 0x64]);
+        data.extend_from_slice(&block);
+
+        data.extend_from_slice(&(instr as u32).to_be_bytes());
+        data.extend_from_slice(&8u32.to_be_bytes());
+        data.extend_from_slice(&0u16.to_be_bytes());
+        data.extend((0..8).map(|x| x * 16));
+        data
+    }
+
+    #[test]
+    fn load_synthetic_module() {
+        let module = MedModule::load(&mmd0()).unwrap().to_module();
+        assert_eq!(module.default_tempo, 6);
+        assert_eq!(module.pattern_order, vec![1]);
+        assert_eq!(module.pattern.len(), 2);
+
+        // the block without tracks is kept, so indexes do not move
+        assert_eq!(module.pattern[0].len(), 1);
+        assert!(module.pattern[0][0]
+            .iter()
+            .all(|s| *s == PatternSlot::default()));
+
+        let pattern = &module.pattern[1];
+        assert_eq!(pattern.len(), 64);
+        assert_eq!(pattern[0].len(), 4);
+        let slot = &pattern[0][0];
+        assert_eq!(slot.note, Note::C4);
+        assert_eq!(slot.instrument, 1);
+        assert_eq!((slot.effect_type, slot.effect_parameter), (0x0C, 64));
+        assert!(pattern
+            .iter()
+            .flatten()
+            .skip(1)
+            .all(|s| *s == PatternSlot::default()));
+
+        assert_eq!(module.instrument.len(), 1);
+        let InstrumentType::Default(instr) = &module.instrument[0].instr_type else {
+            panic!("not a sample instrument");
+        };
+        let sample = &instr.sample[0];
+        assert!(matches!(sample.flags, LoopType::No));
+        assert_eq!(sample.volume, 1.0);
+        let audio: Vec<i8> = (0..8).map(|x| (x * 16) as i8).collect();
+        assert!(matches!(&sample.data, SampleDataType::Depth8(v) if *v == audio));
+    }
+
+    #[test]
+    fn reject_broken_modules() {
+        let data = mmd0();
+        assert!(MedModule::load(&data[..MMD_HEADER_SIZE - 1]).is_err());
+        // the last block is cut
+        let sample_array = MMD_HEADER_SIZE + MMD_SONG_SIZE + 12 + 4 * 64 * 3;
+        assert!(MedModule::load(&data[..sample_array - 1]).is_err());
+
+        let mut bad = data;
+        bad[3] = b'4';
+        assert!(MedModule::load(&bad).is_err());
+    }
+}
@@ -0,0 +1,587 @@
+use alloc::{format, vec, vec::Vec};
+
+use crate::instrument::InstrumentType;
+use crate::module::Module;
+use crate::patternslot::PatternSlot;
+
+/// MIDI ticks per quarter note
+const DIVISION: u16 = 480;
+/// Linear period units per semitone, XM portamento speed is 4 units per tick
+const SEMITONE: i32 = 64;
+const BEND_CENTER: i32 = 0x2000;
+const GM_DRUMS: u8 = 9;
+
+const EFFECT_PORTA_UP: u8 = 0x01;
+const EFFECT_PORTA_DOWN: u8 = 0x02;
+const EFFECT_TONE_PORTA: u8 = 0x03;
+const EFFECT_TONE_PORTA_VOLSLIDE: u8 = 0x05;
+const EFFECT_SET_VOLUME: u8 = 0x0C;
+const EFFECT_EXTENDED: u8 = 0x0E;
+const EFFECT_SPEED_TEMPO: u8 = 0x0F;
+const EFFECT_KEY_OFF: u8 = 0x14;
+
+/// Standard MIDI File export settings
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MidiExportOptions {
+    /// Rows in a quarter note
+    pub rows_per_beat: u8,
+    /// Render portamento effects with pitch bends
+    pub pitch_bends: bool,
+    /// Pitch bend range in semitones, sent with RPN 0
+    pub bend_range: u8,
+}
+
+impl Default for MidiExportOptions {
+    fn default() -> Self {
+        Self {
+            rows_per_beat: 4,
+            pitch_bends: true,
+            bend_range: 12,
+        }
+    }
+}
+
+/// Events of one track, sorted when written
+#[derive(Default)]
+struct Track {
+    /// Absolute tick, priority at the same tick, message
+    events: Vec<(u32, u8, Vec<u8>)>,
+}
+
+impl Track {
+    fn meta(&mut self, tick: u32, kind: u8, data: &[u8]) {
+        let mut message = vec![0xFF, kind];
+        write_var_len(&mut message, data.len() as u32);
+        message.extend_from_slice(data);
+        self.events.push((tick, 0, message));
+    }
+
+    fn note_off(&mut self, tick: u32, channel: u8, key: u8) {
+        self.events.push((tick, 1, vec![0x80 | channel, key, 0]));
+    }
+
+    fn control(&mut self, tick: u32, message: Vec<u8>) {
+        self.events.push((tick, 2, message));
+    }
+
+    fn note_on(&mut self, tick: u32, channel: u8, key: u8, velocity: u8) {
+        self.events
+            .push((tick, 3, vec![0x90 | channel, key, velocity.clamp(1, 127)]));
+    }
+
+    fn save(mut self, end: u32) -> Vec<u8> {
+        self.events.sort_by_key(|e| (e.0, e.1));
+        let mut body = vec![];
+        let mut last = 0;
+        for (tick, _, message) in &self.events {
+            write_var_len(&mut body, tick - last);
+            body.extend_from_slice(message);
+            last = *tick;
+        }
+        // end of track
+        write_var_len(&mut body, end.saturating_sub(last));
+        body.extend_from_slice(&[0xFF, 0x2F, 0x00]);
+
+        let mut chunk = b"MTrk".to_vec();
+        chunk.extend_from_slice(&(body.len() as u32).to_be_bytes());
+        chunk.append(&mut body);
+        chunk
+    }
+}
+
+fn write_var_len(data: &mut Vec<u8>, value: u32) {
+    let mut bytes = vec![(value & 0x7F) as u8];
+    let mut value = value >> 7;
+    while value != 0 {
+        bytes.push(0x80 | (value & 0x7F) as u8);
+        value >>= 7;
+    }
+    data.extend(bytes.iter().rev());
+}
+
+/// Playback state of a module channel
+#[derive(Default)]
+struct Voice {
+    /// MIDI channel of the track
+    channel: u8,
+    /// 1-128, 0 for none
+    instrument: u8,
+    /// Key and MIDI channel of the note playing
+    playing: Option<(u8, u8)>,
+    /// MIDI channel, bank and program
+    program: Option<(u8, u8, u8)>,
+    /// In period units
+    bend: i32,
+    sent_bend: i32,
+    /// Tone portamento target, in period units from the note playing
+    target: i32,
+    /// Effect memories
+    porta_up: u8,
+    porta_down: u8,
+    tone_porta: u8,
+}
+
+impl Module {
+    /// MIDI channel, bank and program of an instrument, 1 is the first instrument
+    ///
+    /// `InstrDefault` instruments use the presets of `to_sf2()`.
+    fn midi_program(&self, instrument: u8, default_channel: u8) -> (u8, u8, u8) {
+        let index = (instrument as usize).wrapping_sub(1);
+        match self.instrument.get(index).map(|i| &i.instr_type) {
+            Some(InstrumentType::Midi(midi)) => {
+                (midi.channel & 0x0F, 0, midi.program.min(127) as u8)
+            }
+            Some(InstrumentType::Default(id)) => {
+                let (bank, program) = self.sf2_preset(index);
+                let channel = if id.midi.on {
+                    id.midi.channel & 0x0F
+                } else {
+                    default_channel
+                };
+                (channel, bank.min(127) as u8, program as u8)
+            }
+            _ => (default_channel, 0, 0),
+        }
+    }
+
+    /// Sounding MIDI key and default velocity of a note
+    fn midi_key(&self, slot: &PatternSlot, instrument: u8) -> Option<(u8, u8)> {
+        let key = slot.note.to_midi()? as i16;
+        if let Some(InstrumentType::Default(id)) = self
+            .instrument
+            .get((instrument as usize).wrapping_sub(1))
+            .map(|i| &i.instr_type)
+        {
+            let index = (slot.note.value() as usize - 1).min(95);
+            if let Some(sample) = id.sample.get(id.sample_for_note[index] as usize) {
+                let key = key + sample.relative_note as i16;
+                let velocity = (sample.volume.clamp(0.0, 1.0) * 127.0) as u8;
+                return Some((key.clamp(0, 127) as u8, velocity));
+            }
+        }
+        Some((key as u8, 127))
+    }
+
+    /// Standard MIDI File type 1: a tempo track, then one track per channel
+    ///
+    /// The song is played once, following `timeline()`. Notes are
+    /// transposed by the sample `relative_note`. Portamentos are linear
+    /// pitch bends, also for Amiga frequencies. `InstrDefault` instruments
+    /// select the banks and programs of `to_sf2()`.
+    pub fn to_midi(&self, options: &MidiExportOptions) -> Vec<u8> {
+        let rows_per_beat = options.rows_per_beat.max(1) as u32;
+        let row_ticks = DIVISION as u32 / rows_per_beat;
+        let bend_range = options.bend_range.clamp(1, 24) as i32;
+
+        let channels = self.max_row_len();
+        let mut tempo_track = Track::default();
+        tempo_track.meta(0, 0x03, self.name.as_bytes());
+
+        let mut tracks: Vec<Track> = (0..channels).map(|_| Track::default()).collect();
+        let mut voices: Vec<Voice> = (0..channels)
+            .map(|c| {
+                // keep the GM drum channel free
+                let c = (c % 15) as u8;
+                Voice {
+                    channel: if c >= GM_DRUMS { c + 1 } else { c },
+                    ..Default::default()
+                }
+            })
+            .collect();
+        for (c, track) in tracks.iter_mut().enumerate() {
+            track.meta(0, 0x03, format!("Channel {}", c + 1).as_bytes());
+        }
+
+        let mut speed = self.default_tempo.max(1) as u32;
+        let mut bpm = self.default_bpm.max(1) as u32;
+        let mut tempo: Option<u32> = None;
+        let mut bend_range_sent = vec![false; 16 * channels];
+
+        let mut tick: u32 = 0;
+        for entry in self.timeline().rows {
+            let Some(row) = self
+                .pattern_order
+                .get(entry.order)
+                .and_then(|&p| self.pattern.get(p))
+                .and_then(|p| p.get(entry.row))
+            else {
+                continue;
+            };
+
+            let mut row_delay = 0;
+            for slot in row {
+                match slot.effect_type {
+                    EFFECT_SPEED_TEMPO if slot.effect_parameter != 0 => {
+                        if slot.effect_parameter < 0x20 {
+                            speed = slot.effect_parameter as u32;
+                        } else {
+                            bpm = slot.effect_parameter as u32;
+                        }
+                    }
+                    EFFECT_EXTENDED if slot.effect_parameter >> 4 == 0xE => {
+                        row_delay = row_delay.max((slot.effect_parameter & 0x0F) as u32);
+                    }
+                    _ => {}
+                }
+            }
+
+            // a row lasts speed * 2.5 / bpm seconds and row_ticks MIDI ticks
+            let us_per_beat = (speed as u64 * 2_500_000 * DIVISION as u64
+                / (bpm as u64 * row_ticks as u64))
+                .min(0xFF_FFFF) as u32;
+            if tempo != Some(us_per_beat) {
+                tempo_track.meta(tick, 0x51, &us_per_beat.to_be_bytes()[1..4]);
+                tempo = Some(us_per_beat);
+            }
+
+            let at = |t: u32| tick + row_ticks * t / speed;
+            for (c, slot) in row.iter().enumerate().take(channels) {
+                let voice = &mut voices[c];
+                let track = &mut tracks[c];
+                if slot.instrument != 0 {
+                    voice.instrument = slot.instrument;
+                }
+
+                let delay = if slot.has_note_delay() {
+                    (slot.effect_parameter & 0x0F) as u32
+                } else {
+                    0
+                };
+                if delay >= speed {
+                    continue;
+                }
+
+                let volume = match slot.volume {
+                    0x10..=0x50 => Some(slot.volume - 0x10),
+                    _ if slot.effect_type == EFFECT_SET_VOLUME => {
+                        Some(slot.effect_parameter.min(64))
+                    }
+                    _ => None,
+                };
+
+                // === notes
+
+                if slot.note.is_valid() {
+                    if let Some((key, velocity)) = self.midi_key(slot, voice.instrument) {
+                        match voice.playing {
+                            Some((playing, _)) if slot.has_tone_portamento() => {
+                                voice.target = (key as i32 - playing as i32) * SEMITONE;
+                            }
+                            _ => {
+                                if let Some((playing, channel)) = voice.playing.take() {
+                                    track.note_off(at(delay), channel, playing);
+                                }
+                                let program = self.midi_program(voice.instrument, voice.channel);
+                                if voice.program != Some(program) {
+                                    // bank select, then program change
+                                    track.control(at(delay), vec![0xB0 | program.0, 0, program.1]);
+                                    track.control(at(delay), vec![0xC0 | program.0, program.2]);
+                                    if let Some(i) = self
+                                        .instrument
+                                        .get((voice.instrument as usize).wrapping_sub(1))
+                                    {
+                                        track.meta(at(delay), 0x04, i.name.as_bytes());
+                                    }
+                                    voice.program = Some(program);
+                                }
+                                let channel = program.0;
+                                if options.pitch_bends {
+                                    let sent = &mut bend_range_sent[c * 16 + channel as usize];
+                                    if !*sent {
+                                        // RPN 0: pitch bend sensitivity
+                                        for (cc, value) in
+                                            [(101, 0), (100, 0), (6, bend_range as u8), (38, 0)]
+                                        {
+                                            track.control(
+                                                at(delay),
+                                                vec![0xB0 | channel, cc, value],
+                                            );
+                                        }
+                                        *sent = true;
+                                    }
+                                }
+                                voice.bend = 0;
+                                voice.target = 0;
+                                if voice.sent_bend != 0 {
+                                    track.control(
+                                        at(delay),
+                                        vec![0xE0 | channel, 0x00, (BEND_CENTER >> 7) as u8],
+                                    );
+                                    voice.sent_bend = 0;
+                                }
+                                let velocity =
+                                    volume.map_or(velocity, |v| (v as u32 * 127 / 64) as u8);
+                                track.note_on(at(delay), channel, key, velocity);
+                                voice.playing = Some((key, channel));
+                            }
+                        }
+                    }
+                }
+
+                let cut = if slot.note.is_keyoff()
+                    || slot.note.is_note_cut()
+                    || slot.note.is_note_fade()
+                {
+                    Some(delay)
+                } else if slot.effect_type == EFFECT_KEY_OFF {
+                    Some(slot.effect_parameter as u32)
+                } else if slot.effect_type == EFFECT_EXTENDED && slot.effect_parameter >> 4 == 0xC {
+                    Some((slot.effect_parameter & 0x0F) as u32)
+                } else {
+                    None
+                };
+
+                // === pitch bends
+
+                if options.pitch_bends {
+                    if let Some((_, channel)) = voice.playing {
+                        let param = slot.effect_parameter;
+                        let mut per_tick = 0;
+                        let mut toward_target = false;
+                        match slot.effect_type {
+                            EFFECT_PORTA_UP => {
+                                if param != 0 {
+                                    voice.porta_up = param;
+                                }
+                                per_tick = 4 * voice.porta_up as i32;
+                            }
+                            EFFECT_PORTA_DOWN => {
+                                if param != 0 {
+                                    voice.porta_down = param;
+                                }
+                                per_tick = -4 * (voice.porta_down as i32);
+                            }
+                            EFFECT_TONE_PORTA | EFFECT_TONE_PORTA_VOLSLIDE => {
+                                if slot.effect_type == EFFECT_TONE_PORTA && param != 0 {
+                                    voice.tone_porta = param;
+                                }
+                                per_tick = 4 * voice.tone_porta as i32;
+                                toward_target = true;
+                            }
+                            EFFECT_EXTENDED => match param >> 4 {
+                                0x1 => voice.bend += 4 * (param & 0x0F) as i32,
+                                0x2 => voice.bend -= 4 * (param & 0x0F) as i32,
+                                _ => {}
+                            },
+                            _ => {}
+                        }
+                        if slot.volume >> 4 == 0x0F {
+                            if slot.volume & 0x0F != 0 {
+                                voice.tone_porta = (slot.volume & 0x0F) << 4;
+                            }
+                            per_tick = 4 * voice.tone_porta as i32;
+                            toward_target = true;
+                        }
+
+                        let limit = bend_range * SEMITONE;
+                        for t in 0..speed {
+                            if t > 0 && per_tick != 0 {
+                                voice.bend = if toward_target {
+                                    if voice.bend < voice.target {
+                                        (voice.bend + per_tick).min(voice.target)
+                                    } else {
+                                        (voice.bend - per_tick).max(voice.target)
+                                    }
+                                } else {
+                                    voice.bend + per_tick
+                                };
+                            }
+                            voice.bend = voice.bend.clamp(-limit, limit);
+                            if voice.bend != voice.sent_bend {
+                                let value = (BEND_CENTER + voice.bend * BEND_CENTER / limit)
+                                    .clamp(0, 0x3FFF);
+                                track.control(
+                                    at(t),
+                                    vec![0xE0 | channel, (value & 0x7F) as u8, (value >> 7) as u8],
+                                );
+                                voice.sent_bend = voice.bend;
+                            }
+                        }
+                    }
+                }
+
+                if let Some(t) = cut {
+                    if t < speed {
+                        if let Some((key, channel)) = voice.playing.take() {
+                            track.note_off(at(t), channel, key);
+                        }
+                    }
+                }
+            }
+
+            tick += row_ticks * (1 + row_delay);
+        }
+
+        // === file
+
+        for (voice, track) in voices.iter_mut().zip(tracks.iter_mut()) {
+            if let Some((key, channel)) = voice.playing.take() {
+                track.note_off(tick, channel, key);
+            }
+        }
+
+        let mut smf: Vec<u8> = b"MThd".to_vec();
+        smf.extend_from_slice(&6u32.to_be_bytes());
+        smf.extend_from_slice(&1u16.to_be_bytes());
+        smf.extend_from_slice(&(1 + channels as u16).to_be_bytes());
+        smf.extend_from_slice(&DIVISION.to_be_bytes());
+        smf.append(&mut tempo_track.save(tick));
+        for track in tracks {
+            smf.append(&mut track.save(tick));
+        }
+        smf
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::note::Note;
+
+    /// Events of each track: absolute tick and message
+    fn tracks(smf: &[u8]) -> Vec<Vec<(u32, Vec<u8>)>> {
+        let read_var_len = |at: &mut usize| {
+            let mut value = 0u32;
+            loop {
+                let b = smf[*at];
+                *at += 1;
+                value = (value << 7) | (b & 0x7F) as u32;
+                if b & 0x80 == 0 {
+                    return value;
+                }
+            }
+        };
+        let mut tracks = vec![];
+        let mut at = 14;
+        while at < smf.len() {
+            assert_eq!(&smf[at..at + 4], b"MTrk");
+            let len = u32::from_be_bytes([smf[at + 4], smf[at + 5], smf[at + 6], smf[at + 7]]);
+            let end = at + 8 + len as usize;
+            at += 8;
+            let (mut tick, mut events) = (0, vec![]);
+            while at < end {
+                tick += read_var_len(&mut at);
+                let start = at;
+                at += match smf[at] {
+                    0xFF => {
+                        at += 2;
+                        read_var_len(&mut at) as usize
+                    }
+                    0xC0..=0xDF => 2,
+                    _ => 3,
+                };
+                events.push((tick, smf[start..at].to_vec()));
+            }
+            tracks.push(events);
+        }
+        tracks
+    }
+
+    fn tempos(track: &[(u32, Vec<u8>)]) -> Vec<(u32, u32)> {
+        track
+            .iter()
+            .filter(|(_, m)| m[0..3] == [0xFF, 0x51, 0x03])
+            .map(|(t, m)| (*t, u32::from_be_bytes([0, m[3], m[4], m[5]])))
+            .collect()
+    }
+
+    /// One pattern of 8 rows and one channel
+    fn module(rows: &[(usize, PatternSlot)]) -> Module {
+        let mut pattern = vec![vec![PatternSlot::default()]; 8];
+        for (row, slot) in rows {
+            pattern[*row][0] = *slot;
+        }
+        Module {
+            pattern_order: vec![0],
+            pattern: vec![pattern],
+            ..Default::default()
+        }
+    }
+
+    fn note(note: Note) -> PatternSlot {
+        PatternSlot {
+            note,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn write_notes_and_tempo() {
+        let module = module(&[(0, note(Note::C4)), (4, note(Note::KeyOff))]);
+        let smf = module.to_midi(&MidiExportOptions::default());
+        assert_eq!(&smf[0..4], b"MThd");
+        assert_eq!(&smf[10..14], &[0, 2, 0x01, 0xE0]);
+
+        let tracks = tracks(&smf);
+        assert_eq!(tracks.len(), 2);
+        // 4 rows of 6 ticks at 125 bpm
+        assert_eq!(tempos(&tracks[0]), vec![(0, 480_000)]);
+        let key = Note::C4.to_midi().unwrap();
+        let notes: Vec<_> = tracks[1]
+            .iter()
+            .filter(|(_, m)| matches!(m[0] >> 4, 0x8 | 0x9))
+            .collect();
+        assert_eq!(notes.len(), 2);
+        assert_eq!(notes[0], &(0, vec![0x90, key, 127]));
+        assert_eq!(notes[1], &(4 * 120, vec![0x80, key, 0]));
+        assert_eq!(
+            tracks[1].last().unwrap(),
+            &(8 * 120, vec![0xFF, 0x2F, 0x00])
+        );
+    }
+
+    #[test]
+    fn tempo_follows_row_ticks() {
+        let module = module(&[(0, note(Note::C4)), (4, note(Note::KeyOff))]);
+        let options = MidiExportOptions {
+            rows_per_beat: 7,
+            ..Default::default()
+        };
+        let tracks = tracks(&module.to_midi(&options));
+        // 68 ticks by row, a row lasts 120 ms
+        assert_eq!(tempos(&tracks[0]), vec![(0, 120_000 * 480 / 68)]);
+        assert!(tracks[1].contains(&(4 * 68, vec![0x80, Note::C4.to_midi().unwrap(), 0])));
+    }
+
+    #[test]
+    fn select_sf2_presets() {
+        let mut slot = note(Note::C4);
+        slot.instrument = 130;
+        let mut module = module(&[(0, slot)]);
+        module.instrument = (0..130)
+            .map(|_| crate::instrument::Instrument {
+                instr_type: InstrumentType::Default(Default::default()),
+                ..Default::default()
+            })
+            .collect();
+        let tracks = tracks(&module.to_midi(&MidiExportOptions::default()));
+        let (bank, preset) = module.sf2_preset(129);
+        assert_eq!((bank, preset), (1, 1));
+        let messages: Vec<&[u8]> = tracks[1]
+            .iter()
+            .filter(|(_, m)| matches!(m[0] >> 4, 0xB | 0xC))
+            .map(|(_, m)| &m[..])
+            .collect();
+        assert_eq!(&messages[0..2], &[&[0xB0, 0, 1][..], &[0xC0, 1][..]]);
+    }
+
+    #[test]
+    fn clamp_slow_tempo() {
+        let speed = PatternSlot {
+            effect_type: EFFECT_SPEED_TEMPO,
+            effect_parameter: 0x1F,
+            ..Default::default()
+        };
+        let bpm = PatternSlot {
+            effect_parameter: 0x20,
+            ..speed
+        };
+        let module = module(&[(0, speed), (1, bpm)]);
+        let options = MidiExportOptions {
+            rows_per_beat: 255,
+            ..Default::default()
+        };
+        let tracks = tracks(&module.to_midi(&options));
+        assert_eq!(tempos(&tracks[0]).last().unwrap().1, 0xFF_FFFF);
+    }
+}
@@ -0,0 +1,470 @@
+use bincode::error::DecodeError;
+
+use crate::prelude::*;
+
+use alloc::string::String;
+use alloc::string::ToString;
+use alloc::{format, vec, vec::Vec};
+
+/// MIDI tempo when the file has none, 120 bpm
+const DEFAULT_US_PER_BEAT: u32 = 500_000;
+const GM_DRUMS: u8 = 9;
+/// Instrument numbers fit in a byte
+const MAX_INSTRUMENTS: usize = 255;
+/// Notes after the last pattern are dropped
+const MAX_PATTERNS: usize = 255;
+
+/// Standard MIDI File import settings
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MidiImportOptions {
+    /// Rows in a quarter note
+    pub rows_per_beat: u8,
+    pub rows_per_pattern: usize,
+    /// Ticks per row, tempo changes only set the BPM
+    pub speed: u8,
+    /// Notes which do not fit are dropped
+    pub max_channels: usize,
+}
+
+impl Default for MidiImportOptions {
+    fn default() -> Self {
+        Self {
+            rows_per_beat: 4,
+            rows_per_pattern: 64,
+            speed: 6,
+            max_channels: 32,
+        }
+    }
+}
+
+#[derive(Default, Debug, Clone, Copy)]
+struct MidiNote {
+    /// Start and end, in MIDI ticks
+    start: u32,
+    end: u32,
+    channel: u8,
+    key: u8,
+    velocity: u8,
+    program: u8,
+}
+
+/// Standard MIDI File, type 0 or 1
+#[derive(Default, Debug)]
+pub struct MidiFile {
+    name: String,
+    /// MIDI ticks per quarter note
+    division: u16,
+    notes: Vec<MidiNote>,
+    /// Tick and microseconds per quarter note
+    tempos: Vec<(u32, u32)>,
+}
+
+fn get_var_len(data: &[u8], at: &mut usize) -> Result<u32, DecodeError> {
+    let mut value: u32 = 0;
+    for _ in 0..4 {
+        let b = *data
+            .get(*at)
+            .ok_or(DecodeError::Other("MIDI track is truncated"))?;
+        *at += 1;
+        value = (value << 7) | (b & 0x7F) as u32;
+        if b & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    Err(DecodeError::Other("MIDI variable length is too long"))
+}
+
+impl MidiFile {
+    pub fn load(ser_midi: &[u8]) -> Result<MidiFile, DecodeError> {
+        let mut midi = MidiFile::default();
+
+        if ser_midi.len() < 14 || &ser_midi[0..4] != b"MThd" {
+            return Err(DecodeError::Other("Not a MIDI file?"));
+        }
+        let header_len = u32::from_be_bytes([ser_midi[4], ser_midi[5], ser_midi[6], ser_midi[7]]);
+        let format = u16::from_be_bytes([ser_midi[8], ser_midi[9]]);
+        let track_count = u16::from_be_bytes([ser_midi[10], ser_midi[11]]);
+        midi.division = u16::from_be_bytes([ser_midi[12], ser_midi[13]]);
+        if format > 1 {
+            return Err(DecodeError::Other(
+                "Only MIDI files type 0 and 1 are supported",
+            ));
+        }
+        if midi.division & 0x8000 != 0 || midi.division == 0 {
+            return Err(DecodeError::Other("SMPTE MIDI timing is not supported"));
+        }
+
+        let mut data = ser_midi.get(8 + header_len as usize..).unwrap_or(&[]);
+        let mut tracks = 0;
+        while tracks < track_count && data.len() >= 8 {
+            let len = u32::from_be_bytes([data[4], data[5], data[6], data[7]]) as usize;
+            let chunk = &data[8..(8 + len).min(data.len())];
+            if &data[0..4] == b"MTrk" {
+                midi.load_track(chunk)?;
+                tracks += 1;
+            }
+            data = &data[(8 + len).min(data.len())..];
+        }
+
+        midi.notes.sort_by_key(|n| (n.start, n.channel, n.key));
+        midi.tempos.sort_by_key(|t| t.0);
+        Ok(midi)
+    }
+
+    fn load_track(&mut self, data: &[u8]) -> Result<(), DecodeError> {
+        let error = || DecodeError::Other("MIDI track is truncated");
+        let mut at = 0;
+        let mut tick: u32 = 0;
+        let mut status: u8 = 0;
+        let mut programs = [0u8; 16];
+        // notes playing, oldest first
+        let mut playing: Vec<MidiNote> = vec![];
+
+        while at < data.len() {
+            tick = tick.saturating_add(get_var_len(data, &mut at)?);
+            let mut byte = *data.get(at).ok_or_else(error)?;
+            if byte & 0x80 != 0 {
+                at += 1;
+                if byte < 0xF0 {
+                    status = byte;
+                }
+            } else {
+                // running status
+                byte = status;
+            }
+
+            match byte {
+                0xFF => {
+                    let kind = *data.get(at).ok_or_else(error)?;
+                    at += 1;
+                    let len = get_var_len(data, &mut at)? as usize;
+                    let meta = data.get(at..at + len).ok_or_else(error)?;
+                    at += len;
+                    match kind {
+                        0x03 if self.name.is_empty() => {
+                            self.name = String::from_utf8_lossy(meta).trim().to_string();
+                        }
+                        0x51 if len == 3 => {
+                            let us = u32::from_be_bytes([0, meta[0], meta[1], meta[2]]);
+                            self.tempos.push((tick, us));
+                        }
+                        0x2F => break,
+                        _ => {}
+                    }
+                }
+                0xF0 | 0xF7 => {
+                    let len = get_var_len(data, &mut at)? as usize;
+                    at += len;
+                }
+                0x80..=0xEF => {
+                    let channel = byte & 0x0F;
+                    let size = if matches!(byte >> 4, 0xC | 0xD) { 1 } else { 2 };
+                    let args = data.get(at..at + size).ok_or_else(error)?;
+                    at += size;
+                    match byte >> 4 {
+                        0x9 if args[1] != 0 => playing.push(MidiNote {
+                            start: tick,
+                            end: tick,
+                            channel,
+                            key: args[0] & 0x7F,
+                            velocity: args[1] & 0x7F,
+                            program: programs[channel as usize],
+                        }),
+                        0x8 | 0x9 => {
+                            if let Some(i) = playing
+                                .iter()
+                                .position(|n| n.channel == channel && n.key == args[0])
+                            {
+                                let mut note = playing.remove(i);
+                                note.end = tick;
+                                self.notes.push(note);
+                            }
+                        }
+                        0xC => programs[channel as usize] = args[0] & 0x7F,
+                        _ => {}
+                    }
+                }
+                _ => return Err(DecodeError::Other("MIDI track is corrupted")),
+            }
+        }
+
+        // notes never released end with the track
+        for mut note in playing {
+            note.end = tick;
+            self.notes.push(note);
+        }
+        Ok(())
+    }
+
+    /// Tracker row of a MIDI tick
+    fn row(&self, tick: u32, rows_per_beat: u32) -> usize {
+        let division = self.division as u64;
+        ((tick as u64 * rows_per_beat as u64 + division / 2) / division) as usize
+    }
+
+    /// Tracker BPM of a MIDI tempo
+    fn bpm(us_per_beat: u32, speed: u32, rows_per_beat: u32) -> u16 {
+        let bpm = speed as u64 * 2_500_000 * rows_per_beat as u64 / us_per_beat.max(1) as u64;
+        bpm.clamp(32, 255) as u16
+    }
+
+    /// Quantize notes on rows, one note at a time per channel
+    pub fn to_module(&self, options: &MidiImportOptions) -> Module {
+        let rows_per_beat = options.rows_per_beat.max(1) as u32;
+        let rows_per_pattern = options.rows_per_pattern.clamp(1, MAX_NUM_ROWS);
+        let speed = options.speed.clamp(1, 0x1F) as u32;
+
+        let initial_tempo = match self.tempos.first() {
+            Some(&(0, us)) => us,
+            _ => DEFAULT_US_PER_BEAT,
+        };
+        let mut module = Module {
+            name: self.name.clone(),
+            comment: "XmRs reader".to_string(),
+            frequency_type: FrequencyType::LinearFrequencies,
+            default_tempo: speed as u16,
+            default_bpm: Self::bpm(initial_tempo, speed, rows_per_beat),
+            ..Default::default()
+        };
+
+        // === instruments, one per MIDI channel and program
+
+        let mut programs: Vec<(u8, u8)> = vec![];
+        for note in &self.notes {
+            let program = (note.channel, note.program);
+            if !programs.contains(&program) {
+                programs.push(program);
+            }
+        }
+        programs.sort();
+        if programs.len() > MAX_INSTRUMENTS {
+            // keep one instrument per MIDI channel, for the dropped programs
+            let mut channels: Vec<u8> = programs.iter().map(|p| p.0).collect();
+            channels.dedup();
+            let mut extra = MAX_INSTRUMENTS - channels.len();
+            let mut last = None;
+            programs.retain(|&(channel, _)| {
+                let first = last != Some(channel);
+                last = Some(channel);
+                if first {
+                    return true;
+                }
+                if extra == 0 {
+                    return false;
+                }
+                extra -= 1;
+                true
+            });
+        }
+        for &(channel, program) in &programs {
+            let name = if channel == GM_DRUMS {
+                "Drums".to_string()
+            } else {
+                format!("Program {}", program + 1)
+            };
+            module.instrument.push(Instrument {
+                name,
+                instr_type: InstrumentType::Midi(InstrMidi {
+                    on: true,
+                    channel,
+                    program: program as u16,
+                    bend: 0,
+                }),
+                ..Default::default()
+            });
+        }
+
+        // === channel allocation, the notes of a MIDI channel stay together
+
+        // start row, end row, note
+        let mut voices: Vec<Vec<(usize, usize, MidiNote)>> = vec![];
+        // MIDI channel of each module channel
+        let mut owners: Vec<u8> = vec![];
+        let max_rows = MAX_PATTERNS * rows_per_pattern;
+        for note in &self.notes {
+            let start = self.row(note.start, rows_per_beat);
+            if start >= max_rows {
+                continue;
+            }
+            let end = self.row(note.end, rows_per_beat).max(start + 1);
+            let free = |v: &Vec<(usize, usize, MidiNote)>| v.last().is_none_or(|l| l.1 <= start);
+            let voice = (0..voices.len()).find(|&c| owners[c] == note.channel && free(&voices[c]));
+            let voice = match voice {
+                Some(c) => c,
+                None if voices.len() < options.max_channels => {
+                    voices.push(vec![]);
+                    owners.push(note.channel);
+                    voices.len() - 1
+                }
+                None => continue,
+            };
+            voices[voice].push((start, end, *note));
+        }
+        // keep the MIDI channels in order
+        let mut order: Vec<usize> = (0..voices.len()).collect();
+        order.sort_by_key(|&c| owners[c]);
+
+        // === patterns
+
+        let last_row = voices
+            .iter()
+            .flat_map(|v| v.iter().map(|n| n.1))
+            .max()
+            .unwrap_or(0);
+        let channels = voices.len().max(1);
+        let mut rows = vec![vec![PatternSlot::default(); channels]; (last_row + 1).min(max_rows)];
+
+        for (c, &voice) in order.iter().enumerate() {
+            for (start, end, note) in &voices[voice] {
+                let slot = &mut rows[*start][c];
+                slot.note = Note::from_midi(note.key).unwrap_or(Note::None);
+                slot.instrument = programs
+                    .iter()
+                    .position(|&p| p == (note.channel, note.program))
+                    .or_else(|| programs.iter().position(|p| p.0 == note.channel))
+                    .map_or(0, |i| i as u8 + 1);
+                slot.volume = 0x10 + (note.velocity as u16 * 64 / 127) as u8;
+                if let Some(off) = rows.get_mut(*end) {
+                    if off[c].note.is_none() {
+                        off[c].note = Note::KeyOff;
+                    }
+                }
+            }
+        }
+
+        for &(tick, us) in &self.tempos {
+            let row = self.row(tick, rows_per_beat);
+            if row > 0 && row < rows.len() {
+                rows[row][0].effect_type = 0x0F;
+                rows[row][0].effect_parameter = Self::bpm(us, speed, rows_per_beat) as u8;
+            }
+        }
+
+        for pattern in rows.chunks(rows_per_pattern) {
+            module.pattern_order.push(module.pattern.len());
+            module.pattern.push(pattern.to_vec());
+        }
+
+        module
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn var_len(value: u32) -> Vec<u8> {
+        let mut bytes = vec![(value & 0x7F) as u8];
+        let mut value = value >> 7;
+        while value != 0 {
+            bytes.insert(0, 0x80 | (value & 0x7F) as u8);
+            value >>= 7;
+        }
+        bytes
+    }
+
+    /// Type 1 file, 480 ticks per quarter note, events are a delta and a message
+    fn smf(tracks: &[&[(u32, &[u8])]]) -> Vec<u8> {
+        let mut data = b"MThd\0\0\0\x06\0\x01".to_vec();
+        data.extend_from_slice(&(tracks.len() as u16).to_be_bytes());
+        data.extend_from_slice(&480u16.to_be_bytes());
+        for events in tracks {
+            let mut body = vec![];
+            for (delta, message) in events.iter() {
+                body.extend(var_len(*delta));
+                body.extend_from_slice(message);
+            }
+            body.extend_from_slice(&[0, 0xFF, 0x2F, 0]);
+            data.extend_from_slice(b"MTrk");
+            data.extend_from_slice(&(body.len() as u32).to_be_bytes());
+            data.extend(body);
+        }
+        data
+    }
+
+    #[test]
+    fn load_notes() {
+        let data = smf(&[
+            &[(0, b"\xFF\x03\x04test"), (0, b"\xFF\x51\x03\x07\xA1\x20")],
+            // program 6, C-4 for a quarter note, running status note off
+            &[(0, b"\xC0\x05"), (0, b"\x90\x3C\x64"), (480, b"\x3C\x00")],
+        ]);
+        let module = MidiFile::load(&data)
+            .unwrap()
+            .to_module(&MidiImportOptions::default());
+        assert_eq!(module.name, "test");
+        assert_eq!((module.default_tempo, module.default_bpm), (6, 120));
+        assert_eq!(module.instrument.len(), 1);
+        assert!(matches!(
+            module.instrument[0].instr_type,
+            InstrumentType::Midi(InstrMidi {
+                channel: 0,
+                program: 5,
+                ..
+            })
+        ));
+        assert_eq!(module.pattern.len(), 1);
+        let slot = module.pattern[0][0][0];
+        assert_eq!(slot.note, Note::from_midi(60).unwrap());
+        assert_eq!((slot.instrument, slot.volume), (1, 0x10 + 50));
+        assert_eq!(module.pattern[0][4][0].note, Note::KeyOff);
+    }
+
+    #[test]
+    fn fold_extra_programs() {
+        // 16 channels with 128 programs each
+        let mut events: Vec<(u32, Vec<u8>)> = vec![];
+        for channel in 0..16u8 {
+            for program in 0..128u8 {
+                events.push((0, vec![0xC0 | channel, program]));
+                events.push((0, vec![0x90 | channel, 60, 100]));
+                events.push((1, vec![0x80 | channel, 60, 0]));
+            }
+        }
+        let events: Vec<(u32, &[u8])> = events.iter().map(|(t, m)| (*t, &m[..])).collect();
+        let module = MidiFile::load(&smf(&[&events]))
+            .unwrap()
+            .to_module(&MidiImportOptions::default());
+        assert_eq!(module.instrument.len(), MAX_INSTRUMENTS);
+        for slot in module.pattern.iter().flatten().flatten() {
+            if slot.note.is_valid() {
+                assert_ne!(slot.instrument, 0);
+            }
+        }
+        // every MIDI channel keeps an instrument
+        for channel in 0..16 {
+            assert!(module.instrument.iter().any(|i| matches!(
+                i.instr_type,
+                InstrumentType::Midi(InstrMidi { channel: c, .. }) if c == channel
+            )));
+        }
+    }
+
+    #[test]
+    fn drop_late_notes() {
+        let data = smf(&[&[
+            (0, b"\x90\x3C\x64"),
+            (0x0FFF_FFFF, b"\x80\x3C\x00"),
+            (0, b"\x90\x3E\x64"),
+            (480, b"\x80\x3E\x00"),
+        ]]);
+        let module = MidiFile::load(&data)
+            .unwrap()
+            .to_module(&MidiImportOptions::default());
+        assert_eq!(module.pattern.len(), MAX_PATTERNS);
+        assert_eq!(module.pattern[0][0][0].note, Note::from_midi(60).unwrap());
+        assert_eq!(module.pattern[0][0].len(), 1);
+    }
+
+    #[test]
+    fn reject_broken_files() {
+        assert!(MidiFile::load(b"RIFF\0\0\0\x06\0\x01\0\x01\x01\xE0").is_err());
+        let mut data = smf(&[&[(0, b"\x90\x3C\x64")]]);
+        // SMPTE timing
+        data[12] = 0xE7;
+        assert!(MidiFile::load(&data).is_err());
+        let mut data = smf(&[&[(0, b"\xFF\x51\x03\x07\xA1\x20")]]);
+        data[25] = 0x10;
+        assert!(MidiFile::load(&data).is_err());
+    }
+}
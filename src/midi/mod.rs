@@ -0,0 +1,4 @@
+#![forbid(unsafe_code)]
+
+pub mod midi_export;
+pub mod midi_import;
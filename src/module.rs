@@ -24,7 +24,7 @@ pub type Row = Vec<PatternSlot>;
 pub type Pattern = Vec<Row>;
 
 /// SoundTracker Module with Steroid
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct Module {
     pub name: String,
     pub comment: String,
@@ -71,6 +71,15 @@ impl Module {
         }
     }
 
+    /// Largest row length, used as the number of channels
+    pub(crate) fn max_row_len(&self) -> usize {
+        self.pattern
+            .iter()
+            .flat_map(|p| p.iter().map(|r| r.len()))
+            .max()
+            .unwrap_or(0)
+    }
+
     /// get number of rows
     pub fn get_num_rows(&self, pat_idx: usize) -> usize {
         if self.pattern.len() != 0 {
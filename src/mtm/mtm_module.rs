@@ -0,0 +1,398 @@
+use bincode::error::DecodeError;
+
+use crate::prelude::*;
+
+use alloc::string::String;
+use alloc::string::ToString;
+use alloc::{vec, vec::Vec};
+
+const MTM_HEADER_SIZE: usize = 66;
+const MTM_SAMPLE_SIZE: usize = 37;
+const MTM_ORDERS: usize = 128;
+const MTM_ROWS: usize = 64;
+const MTM_TRACK_SIZE: usize = MTM_ROWS * 3;
+/// Tracks per pattern in the sequence, used or not
+const MTM_SEQUENCE_CHANNELS: usize = 32;
+
+#[derive(Default, Debug)]
+struct MtmSample {
+    name: String,
+    len: u32,
+    loop_start: u32,
+    loop_end: u32,
+    /// Amiga finetune in the low nibble
+    finetune: u8,
+    /// 0-64
+    volume: u8,
+    /// bit 0: 16 bits
+    attribute: u8,
+}
+
+impl MtmSample {
+    fn load(data: &[u8]) -> Self {
+        let u32_at =
+            |i: usize| u32::from_le_bytes([data[i], data[i + 1], data[i + 2], data[i + 3]]);
+        Self {
+            name: String::from_utf8_lossy(&data[0..22])
+                .trim_matches(char::from(0))
+                .trim()
+                .to_string(),
+            len: u32_at(22),
+            loop_start: u32_at(26),
+            loop_end: u32_at(30),
+            finetune: data[34],
+            volume: data[35],
+            attribute: data[36],
+        }
+    }
+
+    fn is_16bits(&self) -> bool {
+        self.attribute & 1 != 0
+    }
+
+    fn is_loop(&self) -> bool {
+        self.loop_end > self.loop_start.saturating_add(2) && self.loop_end <= self.len
+    }
+
+    /// Unsigned data, return the sample and the next data
+    fn get_sample_data<'a>(&self, data: &'a [u8]) -> (SampleDataType, &'a [u8]) {
+        let len = (self.len as usize).min(data.len());
+        let src = &data[0..len];
+        let sdt = if self.is_16bits() {
+            SampleDataType::Depth16(
+                src.chunks_exact(2)
+                    .map(|c| (u16::from_le_bytes([c[0], c[1]]) ^ 0x8000) as i16)
+                    .collect(),
+            )
+        } else {
+            SampleDataType::Depth8(src.iter().map(|&x| (x ^ 0x80) as i8).collect())
+        };
+        (sdt, &data[len..])
+    }
+}
+
+/// MultiTracker Module
+#[derive(Default, Debug)]
+pub struct MtmModule {
+    title: String,
+    comment: String,
+    samples: Vec<MtmSample>,
+    audio: Vec<SampleDataType>,
+    positions: Vec<u8>,
+    /// 0-15 for each channel
+    panning: Vec<u8>,
+    /// Track 0 is empty, file tracks begin at 1
+    tracks: Vec<Vec<PatternSlot>>,
+    /// Track index of each channel of each pattern
+    sequences: Vec<Vec<usize>>,
+}
+
+impl MtmModule {
+    pub fn load(ser_mtm_module: &[u8]) -> Result<MtmModule, DecodeError> {
+        let mut mtm = MtmModule {
+            ..Default::default()
+        };
+
+        // === load header
+
+        let s = MTM_HEADER_SIZE;
+        if ser_mtm_module.len() < s || &ser_mtm_module[0..3] != b"MTM" {
+            return Err(DecodeError::Other("Not an MTM module?"));
+        }
+        let header = &ser_mtm_module[0..s];
+        let data = &ser_mtm_module[s..];
+
+        mtm.title = String::from_utf8_lossy(&header[4..24])
+            .trim_matches(char::from(0))
+            .trim()
+            .to_string();
+        let track_count = u16::from_le_bytes([header[24], header[25]]) as usize;
+        let pattern_count = header[26] as usize + 1;
+        let order_count = header[27] as usize + 1;
+        let comment_size = u16::from_le_bytes([header[28], header[29]]) as usize;
+        let sample_count = header[30] as usize;
+        let rows = header[32] as usize;
+        let channel_count = header[33] as usize;
+        if channel_count == 0 || channel_count > 32 || rows == 0 || rows > MTM_ROWS {
+            return Err(DecodeError::Other("Not an MTM module?"));
+        }
+        mtm.panning = header[34..34 + channel_count].to_vec();
+
+        // === samples
+
+        let s = sample_count * MTM_SAMPLE_SIZE;
+        if data.len() < s {
+            return Err(DecodeError::Other("Not an MTM module?"));
+        }
+        mtm.samples = data[0..s]
+            .chunks_exact(MTM_SAMPLE_SIZE)
+            .map(MtmSample::load)
+            .collect();
+        let data = &data[s..];
+
+        // === positions
+
+        if data.len() < MTM_ORDERS {
+            return Err(DecodeError::Other("Not an MTM module?"));
+        }
+        mtm.positions = data[0..order_count.min(MTM_ORDERS)].to_vec();
+        let data = &data[MTM_ORDERS..];
+
+        // === tracks
+
+        let s = track_count * MTM_TRACK_SIZE;
+        if data.len() < s {
+            return Err(DecodeError::Other("MTM tracks are truncated"));
+        }
+        mtm.tracks.push(vec![PatternSlot::default(); rows]);
+        for track_data in data[0..s].chunks_exact(MTM_TRACK_SIZE) {
+            let track: Vec<PatternSlot> = track_data
+                .chunks_exact(3)
+                .take(rows)
+                .map(Self::decode_pattern_slot)
+                .collect();
+            mtm.tracks.push(track);
+        }
+        let data = &data[s..];
+
+        // === sequences
+
+        let s = pattern_count * MTM_SEQUENCE_CHANNELS * 2;
+        if data.len() < s {
+            return Err(DecodeError::Other("MTM sequences are truncated"));
+        }
+        mtm.sequences = data[0..s]
+            .chunks_exact(MTM_SEQUENCE_CHANNELS * 2)
+            .map(|seq| {
+                seq.chunks_exact(2)
+                    .take(channel_count)
+                    .map(|c| u16::from_le_bytes([c[0], c[1]]) as usize)
+                    .collect()
+            })
+            .collect();
+        let data = &data[s..];
+
+        // === comment, lines of 40 chars
+
+        let s = comment_size.min(data.len());
+        mtm.comment = data[0..s]
+            .chunks(40)
+            .map(|line| {
+                String::from_utf8_lossy(line)
+                    .trim_matches(char::from(0))
+                    .trim_end()
+                    .to_string()
+            })
+            .collect::<Vec<String>>()
+            .join("\n");
+        let mut data = &data[s..];
+
+        // === audio
+
+        for sample in &mtm.samples {
+            let (sdt, next) = sample.get_sample_data(data);
+            mtm.audio.push(sdt);
+            data = next;
+        }
+
+        Ok(mtm)
+    }
+
+    fn decode_pattern_slot(data: &[u8]) -> PatternSlot {
+        let note = data[0] >> 2;
+        let mut slot = PatternSlot {
+            note: if note == 0 {
+                Note::None
+            } else {
+                Note::try_from(25 + note).unwrap_or(Note::None)
+            },
+            instrument: ((data[0] & 0x03) << 4) | (data[1] >> 4),
+            // Protracker effects, like XM
+            effect_type: data[1] & 0x0F,
+            effect_parameter: data[2],
+            ..Default::default()
+        };
+        // volume slide, up first
+        if slot.effect_type == 0x0A && slot.effect_parameter & 0xF0 != 0 {
+            slot.effect_parameter &= 0xF0;
+        }
+        slot
+    }
+
+    pub fn to_module(&self) -> Module {
+        let mut module = Module {
+            name: self.title.clone(),
+            comment: self.comment.clone(),
+            frequency_type: FrequencyType::AmigaFrequencies,
+            pattern_order: self.positions.iter().map(|&x| x as usize).collect(),
+            ..Default::default()
+        };
+
+        // build patterns from tracks
+        let empty: &[PatternSlot] = self.tracks.first().map_or(&[], |t| t);
+        for sequence in &self.sequences {
+            let pattern: Pattern = (0..empty.len())
+                .map(|row| {
+                    sequence
+                        .iter()
+                        .map(|&t| self.tracks.get(t).map_or(empty, |t| t)[row])
+                        .collect()
+                })
+                .collect();
+            module.pattern.push(pattern);
+        }
+
+        for (mtm_sample, audio) in self.samples.iter().zip(&self.audio) {
+            let bytes = if mtm_sample.is_16bits() { 2 } else { 1 };
+            let (flags, loop_start, loop_length) = if mtm_sample.is_loop() {
+                (
+                    LoopType::Forward,
+                    mtm_sample.loop_start / bytes,
+                    (mtm_sample.loop_end - mtm_sample.loop_start) / bytes,
+                )
+            } else {
+                (LoopType::No, 0, 0)
+            };
+            let finetune = ((mtm_sample.finetune << 4) as i8) as f32 / 127.0;
+            let mut sample = Sample {
+                name: mtm_sample.name.clone(),
+                loop_start,
+                loop_length,
+                volume: mtm_sample.volume.min(64) as f32 / 64.0,
+                finetune: finetune.clamp(-1.0, 1.0),
+                flags,
+                panning: 0.5,
+                relative_note: 0,
+                data: audio.clone(),
+            };
+            sample.fix_loop();
+
+            // Create InstrDefault
+            let mut instr_def = InstrDefault::default();
+            instr_def.sample.push(sample);
+
+            // Create Instrument
+            let instr = Instrument {
+                name: mtm_sample.name.clone(),
+                instr_type: InstrumentType::Default(instr_def),
+                ..Default::default()
+            };
+
+            module.instrument.push(instr);
+        }
+
+        let panning: Vec<f32> = self.panning.iter().map(|&p| p as f32 / 15.0).collect();
+        module.set_initial_panning(&panning);
+
+        module
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// One pattern of 4 channels on one track, sample 1 has 8 frames
+    fn mtm() -> Vec<u8> {
+        let mut data = b"MTM\x10synthetic".to_vec();
+        data.resize(24, 0);
+        data.extend_from_slice(&1u16.to_le_bytes());
+        // last pattern, last order
+        data.extend_from_slice(&[0, 0]);
+        data.extend_from_slice(&40u16.to_le_bytes());
+        // samples, attribute, rows, channels
+        data.extend_from_slice(&[1, 0, 64, 4]);
+        data.extend_from_slice(&[0, 15, 15, 0]);
+        data.resize(MTM_HEADER_SIZE, 0);
+
+        let mut sample = b"sample".to_vec();
+        sample.resize(22, 0);
+        for value in [8u32, 0, 0] {
+            sample.extend_from_slice(&value.to_le_bytes());
+        }
+        sample.extend_from_slice(&[0, 64, 0]);
+        data.extend_from_slice(&sample);
+
+        data.extend_from_slice(&[0; MTM_ORDERS]);
+
+        let mut track = [0; MTM_TRACK_SIZE];
+        // C-4, instrument 1, volume slide A23
+        track[0..3].copy_from_slice(&[24 << 2, 0x1A, 0x23]);
+        data.extend_from_slice(&track);
+
+        let mut sequence = [0u16; MTM_SEQUENCE_CHANNELS];
+        sequence[0] = 1;
+        sequence[3] = 1;
+        data.extend(sequence.iter().flat_map(|t| t.to_le_bytes()));
+
+        let mut comment = b"hello".to_vec();
+        comment.resize(40, 0);
+        data.extend_from_slice(&comment);
+
+        data.extend((0..8).map(|x| 0x80 + x * 16));
+        data
+    }
+
+    #[test]
+    fn load_synthetic_module() {
+        let module = MtmModule::load(&mtm()).unwrap().to_module();
+        assert_eq!(module.name, "synthetic");
+        assert_eq!(module.comment, "hello");
+        assert_eq!(module.pattern_order, vec![0]);
+        assert_eq!(module.pattern.len(), 1);
+
+        let pattern = &module.pattern[0];
+        assert_eq!(pattern.len(), MTM_ROWS);
+        for channel in [0, 3] {
+            let slot = &pattern[0][channel];
+            assert_eq!(slot.note, Note::C4);
+            assert_eq!(slot.instrument, 1);
+            assert_eq!((slot.effect_type, slot.effect_parameter), (0x0A, 0x20));
+        }
+        // initial panning on the first row
+        assert_eq!(pattern[0][1].note, Note::None);
+        assert_eq!(pattern[0][1].effect_type, 0x08);
+        assert!(pattern[1..]
+            .iter()
+            .flatten()
+            .all(|s| *s == PatternSlot::default()));
+
+        assert_eq!(module.instrument.len(), 1);
+        let InstrumentType::Default(instr) = &module.instrument[0].instr_type else {
+            panic!("not a sample instrument");
+        };
+        let sample = &instr.sample[0];
+        assert_eq!(sample.name, "sample");
+        assert_eq!(sample.volume, 1.0);
+        let audio: Vec<i8> = (0..8).map(|x| (x * 16) as i8).collect();
+        assert!(matches!(&sample.data, SampleDataType::Depth8(v) if *v == audio));
+    }
+
+    #[test]
+    fn reject_broken_modules() {
+        let data = mtm();
+        assert!(MtmModule::load(&data[..MTM_HEADER_SIZE - 1]).is_err());
+        let tracks = MTM_HEADER_SIZE + MTM_SAMPLE_SIZE + MTM_ORDERS;
+        assert!(MtmModule::load(&data[..tracks + MTM_TRACK_SIZE - 1]).is_err());
+
+        let mut bad = data.clone();
+        bad[33] = 0;
+        assert!(MtmModule::load(&bad).is_err());
+        let mut bad = data;
+        bad[32] = MTM_ROWS as u8 + 1;
+        assert!(MtmModule::load(&bad).is_err());
+    }
+
+    #[test]
+    fn loop_start_near_the_end_of_range() {
+        let mut data = mtm();
+        let sample = MTM_HEADER_SIZE + 26;
+        data[sample..sample + 4].copy_from_slice(&(u32::MAX - 1).to_le_bytes());
+        data[sample + 4..sample + 8].copy_from_slice(&8u32.to_le_bytes());
+        let module = MtmModule::load(&data).unwrap().to_module();
+        let InstrumentType::Default(instr) = &module.instrument[0].instr_type else {
+            panic!("not a sample instrument");
+        };
+        assert!(matches!(instr.sample[0].flags, LoopType::No));
+    }
+}
@@ -1,8 +1,11 @@
 use core::cmp::PartialEq;
 use core::fmt::*;
+use core::str::FromStr;
 use num_enum::{IntoPrimitive, TryFromPrimitive};
 use serde::{Deserialize, Serialize};
 
+use crate::period_helper::PeriodHelper;
+
 /// 10 octaves with notes
 #[derive(
     Default,
@@ -151,6 +154,10 @@ pub enum Note {
     A9 = 118,
     As9 = 119,
     B9 = 120,
+    /// Note fade, IT `~~~`
+    NoteFade = 252,
+    /// Note cut, IT `^^^`
+    NoteCut = 253,
     /// Stop note
     KeyOff = 254, // Like S3M...NOT like XM (97)
 }
@@ -290,6 +297,8 @@ impl Debug for Note {
             Note::As9 => "A#9",
             Note::B9 => "B-9",
             // Stop note
+            Note::NoteFade => "~~~",
+            Note::NoteCut => "^^^",
             Note::KeyOff => "===",
         };
         write!(f, "{}", text)
@@ -307,6 +316,16 @@ impl Note {
         self.value() == 254
     }
 
+    #[inline(always)]
+    pub fn is_note_cut(&self) -> bool {
+        *self == Note::NoteCut
+    }
+
+    #[inline(always)]
+    pub fn is_note_fade(&self) -> bool {
+        *self == Note::NoteFade
+    }
+
     #[inline(always)]
     pub fn is_valid(&self) -> bool {
         let n: u8 = *self as u8;
@@ -317,4 +336,186 @@ impl Note {
     pub fn value(&self) -> u8 {
         *self as u8
     }
+    /// Move a note by `semitones`, `None` outside C-0..B-9
+    ///
+    /// Other values are not changed.
+    pub fn transpose(&self, semitones: i16) -> Option<Note> {
+        if !self.is_valid() {
+            return Some(*self);
+        }
+        let n = (self.value() as i16).checked_add(semitones)?;
+        if n < Note::C0.value() as i16 || n > Note::B9.value() as i16 {
+            return None;
+        }
+        Note::try_from(n as u8).ok()
+    }
+
+    /// MIDI note number, C-4 is 60
+    pub fn to_midi(&self) -> Option<u8> {
+        let midi = self.value() as u16 + 11;
+        if self.is_valid() && midi <= 127 {
+            Some(midi as u8)
+        } else {
+            None
+        }
+    }
+
+    /// From a MIDI note number, C-4 is 60
+    pub fn from_midi(midi: u8) -> Option<Note> {
+        let n = midi.checked_sub(11)?;
+        let note = Note::try_from(n).ok()?;
+        if note.is_valid() {
+            Some(note)
+        } else {
+            None
+        }
+    }
+
+    /// Frequency in Hz of a sample played at `PeriodHelper::C4_FREQ` on C-4,
+    /// `detune` in semitones
+    pub fn frequency(&self, ph: &PeriodHelper, detune: f32) -> Option<f32> {
+        if !self.is_valid() {
+            return None;
+        }
+        // C-0 is 0.0 for PeriodHelper
+        let period = ph.note_to_period((self.value() - 1) as f32 + detune);
+        Some(ph.period_to_frequency(period))
+    }
+}
+
+/// Text is not a note
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ParseNoteError;
+
+impl Display for ParseNoteError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        write!(f, "invalid note")
+    }
+}
+
+/// Parse the tracker text of `Debug`: "C#4", "C-4", "---", "===", "^^^", "~~~"
+///
+/// Lowercase and "C4" are accepted too.
+impl FromStr for Note {
+    type Err = ParseNoteError;
+
+    fn from_str(s: &str) -> core::result::Result<Self, Self::Err> {
+        let s = s.trim();
+        match s {
+            "---" => return Ok(Note::None),
+            "===" => return Ok(Note::KeyOff),
+            "^^^" => return Ok(Note::NoteCut),
+            "~~~" => return Ok(Note::NoteFade),
+            _ => {}
+        }
+        let mut chars = s.chars();
+        let semitone = match chars.next().map(|c| c.to_ascii_uppercase()) {
+            Some('C') => 0,
+            Some('D') => 2,
+            Some('E') => 4,
+            Some('F') => 5,
+            Some('G') => 7,
+            Some('A') => 9,
+            Some('B') => 11,
+            _ => return Err(ParseNoteError),
+        };
+        let rest = chars.as_str();
+        let (semitone, octave) = match rest.strip_prefix('#') {
+            // no E# nor B#
+            Some(_) if semitone == 4 || semitone == 11 => return Err(ParseNoteError),
+            Some(octave) => (semitone + 1, octave),
+            None => (semitone, rest.strip_prefix('-').unwrap_or(rest)),
+        };
+        if octave.len() != 1 {
+            return Err(ParseNoteError);
+        }
+        let octave: u8 = octave.parse().map_err(|_| ParseNoteError)?;
+        Note::try_from(1 + octave * 12 + semitone).map_err(|_| ParseNoteError)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::period_helper::FrequencyType;
+    use alloc::format;
+
+    fn all() -> impl Iterator<Item = Note> {
+        (0..=u8::MAX).filter_map(|n| Note::try_from(n).ok())
+    }
+
+    #[test]
+    fn text_round_trip() {
+        assert_eq!(all().count(), 124);
+        for note in all() {
+            assert_eq!(format!("{:?}", note).parse::<Note>(), Ok(note));
+        }
+    }
+
+    #[test]
+    fn parse_other_forms() {
+        assert_eq!("c4".parse::<Note>(), Ok(Note::C4));
+        assert_eq!(" F#2 ".parse::<Note>(), Ok(Note::Fs2));
+        assert_eq!("g#9".parse::<Note>(), Ok(Note::Gs9));
+        for text in ["", "H-4", "E#4", "B#4", "C-10", "C", "C-x", "=="] {
+            assert_eq!(text.parse::<Note>(), Err(ParseNoteError), "{}", text);
+        }
+    }
+
+    #[test]
+    fn midi_conversion() {
+        assert_eq!(Note::C4.to_midi(), Some(60));
+        assert_eq!(Note::A4.to_midi(), Some(69));
+        assert_eq!(Note::C0.to_midi(), Some(12));
+        assert_eq!(Note::G9.to_midi(), Some(127));
+        assert_eq!(Note::Gs9.to_midi(), None);
+        assert_eq!(Note::KeyOff.to_midi(), None);
+        assert_eq!(Note::None.to_midi(), None);
+
+        assert_eq!(Note::from_midi(60), Some(Note::C4));
+        assert_eq!(Note::from_midi(12), Some(Note::C0));
+        assert_eq!(Note::from_midi(11), None);
+        assert_eq!(Note::from_midi(0), None);
+        for note in all().filter(|n| n.to_midi().is_some()) {
+            assert_eq!(Note::from_midi(note.to_midi().unwrap()), Some(note));
+        }
+    }
+
+    #[test]
+    fn transpose_range_ends() {
+        assert_eq!(Note::C4.transpose(12), Some(Note::C5));
+        assert_eq!(Note::C4.transpose(-1), Some(Note::B3));
+        assert_eq!(Note::C0.transpose(0), Some(Note::C0));
+        assert_eq!(Note::C0.transpose(-1), None);
+        assert_eq!(Note::B9.transpose(1), None);
+        assert_eq!(Note::C0.transpose(119), Some(Note::B9));
+        assert_eq!(Note::B9.transpose(-119), Some(Note::C0));
+        assert_eq!(Note::C0.transpose(i16::MAX), None);
+        assert_eq!(Note::B9.transpose(i16::MIN), None);
+        // not a note, unchanged
+        assert_eq!(Note::KeyOff.transpose(5), Some(Note::KeyOff));
+        assert_eq!(Note::None.transpose(-5), Some(Note::None));
+    }
+
+    #[test]
+    fn kinds() {
+        assert!(Note::KeyOff.is_keyoff() && !Note::NoteCut.is_keyoff());
+        assert!(Note::NoteCut.is_note_cut() && Note::NoteFade.is_note_fade());
+        assert!(Note::C0.is_valid() && Note::B9.is_valid());
+        assert!(!Note::None.is_valid() && !Note::NoteFade.is_valid());
+        assert!(Note::None.is_none());
+    }
+
+    #[test]
+    fn frequency() {
+        let ph = PeriodHelper::new(FrequencyType::LinearFrequencies, false);
+        let c4 = Note::C4.frequency(&ph, 0.0).unwrap();
+        assert!((c4 - PeriodHelper::C4_FREQ).abs() < 0.5);
+        let c5 = Note::C5.frequency(&ph, 0.0).unwrap();
+        assert!((c5 / c4 - 2.0).abs() < 1e-3);
+        let detuned = Note::C4.frequency(&ph, 1.0).unwrap();
+        let cs4 = Note::Cs4.frequency(&ph, 0.0).unwrap();
+        assert!((detuned - cs4).abs() < 1e-2);
+        assert_eq!(Note::KeyOff.frequency(&ph, 0.0), None);
+    }
 }
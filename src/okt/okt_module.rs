@@ -0,0 +1,376 @@
+use bincode::error::DecodeError;
+
+use crate::prelude::*;
+
+use alloc::string::String;
+use alloc::string::ToString;
+use alloc::{vec, vec::Vec};
+
+const OKT_SAMPLE_SIZE: usize = 32;
+const OKT_ORDERS: usize = 128;
+/// Paula voices, each one can be split in two channels
+const OKT_VOICES: usize = 4;
+
+#[derive(Default, Debug)]
+struct OktSample {
+    name: String,
+    len: u32,
+    /// Loop start and length, in words
+    repeat: u16,
+    repeat_len: u16,
+    /// 0-64
+    volume: u8,
+}
+
+impl OktSample {
+    fn load(data: &[u8]) -> Self {
+        Self {
+            name: String::from_utf8_lossy(&data[0..20])
+                .trim_matches(char::from(0))
+                .trim()
+                .to_string(),
+            len: u32::from_be_bytes([data[20], data[21], data[22], data[23]]),
+            repeat: u16::from_be_bytes([data[24], data[25]]),
+            repeat_len: u16::from_be_bytes([data[26], data[27]]),
+            volume: data[29],
+        }
+    }
+
+    fn is_loop(&self) -> bool {
+        self.repeat_len > 1
+    }
+}
+
+/// Oktalyzer Module
+#[derive(Default, Debug)]
+pub struct OktModule {
+    /// Split voices
+    pairs: [bool; OKT_VOICES],
+    samples: Vec<OktSample>,
+    audio: Vec<Vec<i8>>,
+    /// Ticks per row
+    speed: u16,
+    positions: Vec<u8>,
+    patterns: Vec<Pattern>,
+}
+
+impl OktModule {
+    pub fn load(ser_okt_module: &[u8]) -> Result<OktModule, DecodeError> {
+        let mut okt = OktModule {
+            speed: 6,
+            ..Default::default()
+        };
+
+        if ser_okt_module.len() < 8 || &ser_okt_module[0..8] != b"OKTASONG" {
+            return Err(DecodeError::Other("Not an OKT module?"));
+        }
+
+        // === IFF like chunks
+
+        let mut pattern_count = 0;
+        let mut song_length = 0;
+        let mut bodies: Vec<&[u8]> = vec![];
+        let mut cmod = false;
+        let mut data = &ser_okt_module[8..];
+        while data.len() >= 8 {
+            let id = &data[0..4];
+            let len = u32::from_be_bytes([data[4], data[5], data[6], data[7]]) as usize;
+            // last sample is often truncated
+            let chunk = &data[8..(8 + len).min(data.len())];
+            match id {
+                b"CMOD" if chunk.len() >= 8 => {
+                    for (pair, c) in okt.pairs.iter_mut().zip(chunk.chunks_exact(2)) {
+                        *pair = c[0] != 0 || c[1] != 0;
+                    }
+                    cmod = true;
+                }
+                b"SAMP" => {
+                    okt.samples = chunk
+                        .chunks_exact(OKT_SAMPLE_SIZE)
+                        .map(OktSample::load)
+                        .collect();
+                }
+                b"SPEE" if chunk.len() >= 2 => {
+                    okt.speed = u16::from_be_bytes([chunk[0], chunk[1]]);
+                }
+                b"SLEN" if chunk.len() >= 2 => {
+                    pattern_count = u16::from_be_bytes([chunk[0], chunk[1]]) as usize;
+                }
+                b"PLEN" if chunk.len() >= 2 => {
+                    song_length = u16::from_be_bytes([chunk[0], chunk[1]]) as usize;
+                }
+                b"PATT" => {
+                    okt.positions = chunk[0..chunk.len().min(OKT_ORDERS)].to_vec();
+                }
+                b"PBOD" => {
+                    let pattern = okt.decode_pattern(chunk)?;
+                    okt.patterns.push(pattern);
+                }
+                b"SBOD" => bodies.push(chunk),
+                _ => {}
+            }
+            data = &data[(8 + len).min(data.len())..];
+        }
+
+        if !cmod || okt.samples.is_empty() {
+            return Err(DecodeError::Other("Not an OKT module?"));
+        }
+
+        okt.positions.truncate(song_length);
+        okt.positions
+            .retain(|&p| (p as usize) < pattern_count.min(okt.patterns.len()));
+
+        // === audio, only samples with data have a body
+
+        let mut bodies = bodies.into_iter();
+        for sample in &okt.samples {
+            let audio = if sample.len == 0 {
+                vec![]
+            } else {
+                bodies
+                    .next()
+                    .map(|b| {
+                        b.iter()
+                            .take(sample.len as usize)
+                            .map(|&x| x as i8)
+                            .collect()
+                    })
+                    .unwrap_or_default()
+            };
+            okt.audio.push(audio);
+        }
+
+        Ok(okt)
+    }
+
+    fn channel_count(&self) -> usize {
+        OKT_VOICES + self.pairs.iter().filter(|&&p| p).count()
+    }
+
+    fn decode_pattern(&self, data: &[u8]) -> Result<Pattern, DecodeError> {
+        if data.len() < 2 {
+            return Err(DecodeError::Other("OKT pattern is truncated"));
+        }
+        let rows = u16::from_be_bytes([data[0], data[1]]) as usize;
+        let channels = self.channel_count();
+        let size = rows * channels * 4;
+        if data.len() < 2 + size {
+            return Err(DecodeError::Other("OKT pattern is truncated"));
+        }
+
+        let pattern: Pattern = data[2..2 + size]
+            .chunks_exact(channels * 4)
+            .map(|row| row.chunks_exact(4).map(Self::decode_pattern_slot).collect())
+            .collect();
+        Ok(pattern)
+    }
+
+    fn decode_pattern_slot(data: &[u8]) -> PatternSlot {
+        let mut slot = PatternSlot::default();
+        // three octaves from the Amiga C-1
+        if (1..=36).contains(&data[0]) {
+            slot.note = Note::try_from(36 + data[0]).unwrap_or(Note::None);
+            slot.instrument = data[1].saturating_add(1);
+        }
+        Self::efx_correction(data[2], data[3], &mut slot);
+        slot
+    }
+
+    /// Convert OKT effects to XM effects
+    fn efx_correction(effect: u8, param: u8, n: &mut PatternSlot) {
+        let (effect_type, effect_parameter) = match effect {
+            // period slides
+            1 if param & 0x0F != 0 => (0x01, param & 0x0F),
+            2 if param & 0x0F != 0 => (0x02, param & 0x0F),
+            // A, B and C: XM has only one arpeggio
+            10..=12 => (0x00, param),
+            // F: filter
+            15 => (0x0E, if param != 0 { 0x00 } else { 0x01 }),
+            // H and L: note up or down once
+            17 | 21 => {
+                let semitones = if effect == 17 {
+                    param as i16
+                } else {
+                    -(param as i16)
+                };
+                if let Some(note) = n.note.transpose(semitones) {
+                    n.note = note;
+                }
+                (0, 0)
+            }
+            // P: position jump, decimal
+            25 => (0x0B, (param >> 4) * 10 + (param & 0x0F)),
+            // R: release
+            27 => (0x14, 0),
+            // S: ticks per row
+            28 if param & 0x0F != 0 => (0x0F, param & 0x0F),
+            // V: volume and volume slides in the volume column
+            31 => {
+                n.volume = match param {
+                    0x00..=0x40 => 0x10 + param,
+                    0x41..=0x4F => 0x60 | (param & 0x0F),
+                    0x51..=0x5F => 0x70 | (param & 0x0F),
+                    0x61..=0x6F => 0x80 | (param & 0x0F),
+                    0x71..=0x7F => 0x90 | (param & 0x0F),
+                    _ => 0,
+                };
+                (0, 0)
+            }
+            // D and U slide every tick by notes, no XM equivalent
+            _ => (0, 0),
+        };
+        n.effect_type = effect_type;
+        n.effect_parameter = effect_parameter;
+    }
+
+    pub fn to_module(&self) -> Module {
+        let mut module = Module {
+            comment: "XmRs reader".to_string(),
+            frequency_type: FrequencyType::AmigaFrequencies,
+            default_tempo: self.speed.clamp(1, 0x1F),
+            default_bpm: 125,
+            pattern_order: self.positions.iter().map(|&x| x as usize).collect(),
+            pattern: self.patterns.clone(),
+            ..Default::default()
+        };
+
+        for (okt_sample, audio) in self.samples.iter().zip(&self.audio) {
+            let (flags, loop_start, loop_length) = if okt_sample.is_loop() {
+                (
+                    LoopType::Forward,
+                    okt_sample.repeat as u32 * 2,
+                    okt_sample.repeat_len as u32 * 2,
+                )
+            } else {
+                (LoopType::No, 0, 0)
+            };
+            let mut sample = Sample {
+                name: okt_sample.name.clone(),
+                loop_start,
+                loop_length,
+                volume: okt_sample.volume.min(64) as f32 / 64.0,
+                finetune: 0.0,
+                flags,
+                panning: 0.5,
+                relative_note: 0,
+                data: SampleDataType::Depth8(audio.clone()),
+            };
+            sample.fix_loop();
+
+            // Create InstrDefault
+            let mut instr_def = InstrDefault::default();
+            instr_def.sample.push(sample);
+
+            // Create Instrument
+            let instr = Instrument {
+                name: okt_sample.name.clone(),
+                instr_type: InstrumentType::Default(instr_def),
+                ..Default::default()
+            };
+
+            module.instrument.push(instr);
+        }
+
+        // Amiga voices are left, right, right, left
+        let panning: Vec<f32> = self
+            .pairs
+            .iter()
+            .zip([0.0, 1.0, 1.0, 0.0])
+            .flat_map(|(&pair, pan)| vec![pan; if pair { 2 } else { 1 }])
+            .collect();
+        module.set_initial_panning(&panning);
+
+        module
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk(id: &[u8; 4], data: &[u8]) -> Vec<u8> {
+        let mut chunk = id.to_vec();
+        chunk.extend_from_slice(&(data.len() as u32).to_be_bytes());
+        chunk.extend_from_slice(data);
+        chunk
+    }
+
+    /// Voice 2 is split, one pattern of 2 rows, sample 1 has 8 frames
+    fn okt() -> Vec<u8> {
+        let mut data = b"OKTASONG".to_vec();
+        data.extend(chunk(b"CMOD", &[0, 0, 0, 1, 0, 0, 0, 0]));
+        let mut sample = b"sample".to_vec();
+        sample.resize(20, 0);
+        sample.extend_from_slice(&8u32.to_be_bytes());
+        sample.resize(29, 0);
+        sample.push(64);
+        sample.resize(OKT_SAMPLE_SIZE, 0);
+        data.extend(chunk(b"SAMP", &sample));
+        data.extend(chunk(b"SPEE", &5u16.to_be_bytes()));
+        data.extend(chunk(b"SLEN", &1u16.to_be_bytes()));
+        data.extend(chunk(b"PLEN", &1u16.to_be_bytes()));
+        data.extend(chunk(b"PATT", &[0; OKT_ORDERS]));
+
+        let mut pattern = 2u16.to_be_bytes().to_vec();
+        // C-4, instrument 1, volume 64
+        pattern.extend_from_slice(&[13, 0, 31, 0x40]);
+        // notes out of the three octaves
+        pattern.extend_from_slice(&[37, 0, 0, 0]);
+        pattern.extend_from_slice(&[0xFF, 0xFF, 0, 0]);
+        pattern.extend_from_slice(&[0; 8]);
+        // lowest note, last instrument
+        pattern.extend_from_slice(&[1, 0xFF, 0, 0]);
+        pattern.extend_from_slice(&[0; 16]);
+        data.extend(chunk(b"PBOD", &pattern));
+
+        data.extend(chunk(b"SBOD", &[0, 16, 32, 48, 64, 80, 96, 112]));
+        data
+    }
+
+    #[test]
+    fn load_synthetic_module() {
+        let module = OktModule::load(&okt()).unwrap().to_module();
+        assert_eq!(module.default_tempo, 5);
+        assert_eq!(module.pattern_order, vec![0]);
+        assert_eq!(module.pattern.len(), 1);
+
+        let pattern = &module.pattern[0];
+        assert_eq!(pattern.len(), 2);
+        assert_eq!(pattern[0].len(), 5);
+        let slot = &pattern[0][0];
+        assert_eq!(slot.note, Note::C4);
+        assert_eq!(slot.instrument, 1);
+        assert_eq!(slot.volume, 0x50);
+        for slot in &pattern[0][1..3] {
+            assert_eq!(slot.note, Note::None);
+            assert_eq!(slot.instrument, 0);
+        }
+        assert_eq!(pattern[1][0].note, Note::C3);
+        assert_eq!(pattern[1][0].instrument, 255);
+
+        assert_eq!(module.instrument.len(), 1);
+        let InstrumentType::Default(instr) = &module.instrument[0].instr_type else {
+            panic!("not a sample instrument");
+        };
+        let sample = &instr.sample[0];
+        assert_eq!(sample.name, "sample");
+        assert_eq!(sample.volume, 1.0);
+        assert!(matches!(sample.flags, LoopType::No));
+        let audio: Vec<i8> = (0..8).map(|x| x * 16).collect();
+        assert!(matches!(&sample.data, SampleDataType::Depth8(v) if *v == audio));
+    }
+
+    #[test]
+    fn reject_broken_modules() {
+        let data = okt();
+        assert!(OktModule::load(&data[..7]).is_err());
+        // no CMOD chunk
+        assert!(OktModule::load(&[b"OKTASONG".as_slice(), &data[24..]].concat()).is_err());
+
+        // pattern of 3 rows with data for 2
+        let pbod = data.windows(4).position(|w| w == b"PBOD").unwrap();
+        let mut bad = data;
+        bad[pbod + 9] = 3;
+        assert!(OktModule::load(&bad).is_err());
+    }
+}
@@ -0,0 +1,347 @@
+use alloc::{vec, vec::Vec};
+
+use crate::instrument::InstrumentType;
+use crate::module::{Module, Pattern};
+use crate::sample::LoopType;
+
+/// What `Module::optimize()` removed
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct OptimizeReport {
+    /// Identical patterns merged into one
+    pub merged_patterns: usize,
+    /// Patterns not referenced by `pattern_order`
+    pub removed_patterns: usize,
+    /// Instruments never used by a pattern slot
+    pub removed_instruments: usize,
+    /// Samples never used by `sample_for_note`
+    pub removed_samples: usize,
+    /// Sample frames after the loop end
+    pub trimmed_frames: usize,
+    /// Serialized module size before minus after
+    pub bytes_saved: usize,
+}
+
+impl Module {
+    /// Remove everything the song does not play, see `OptimizeReport`
+    pub fn optimize(&mut self) -> OptimizeReport {
+        let size_before = self.serialized_size();
+        let removed_patterns = self.remove_unused_patterns();
+        let merged_patterns = self.merge_patterns();
+        let removed_instruments = self.remove_unused_instruments();
+        let (removed_samples, trimmed_frames) = self.optimize_samples();
+        OptimizeReport {
+            merged_patterns,
+            removed_patterns,
+            removed_instruments,
+            removed_samples,
+            trimmed_frames,
+            bytes_saved: size_before.saturating_sub(self.serialized_size()),
+        }
+    }
+
+    /// Keep one copy of identical patterns, return how many were merged
+    ///
+    /// Patterns not referenced by `pattern_order` are removed too.
+    pub fn merge_patterns(&mut self) -> usize {
+        let mut dest: Vec<Pattern> = vec![];
+        let mut new_index: Vec<Option<usize>> = vec![None; self.pattern.len()];
+        let mut merged = 0;
+
+        for &p in &self.pattern_order {
+            if p >= self.pattern.len() || new_index[p].is_some() {
+                continue;
+            }
+            let index = match dest.iter().position(|d| *d == self.pattern[p]) {
+                Some(index) => {
+                    merged += 1;
+                    index
+                }
+                None => {
+                    dest.push(self.pattern[p].clone());
+                    dest.len() - 1
+                }
+            };
+            new_index[p] = Some(index);
+        }
+
+        self.keep_patterns(dest, &new_index);
+        merged
+    }
+
+    /// Remove patterns not referenced by `pattern_order`, return how many
+    pub fn remove_unused_patterns(&mut self) -> usize {
+        let mut dest: Vec<Pattern> = vec![];
+        let mut new_index: Vec<Option<usize>> = vec![None; self.pattern.len()];
+        for &p in &self.pattern_order {
+            if p < self.pattern.len() && new_index[p].is_none() {
+                new_index[p] = Some(dest.len());
+                dest.push(self.pattern[p].clone());
+            }
+        }
+        let removed = self.pattern.len() - dest.len();
+        self.keep_patterns(dest, &new_index);
+        removed
+    }
+
+    /// Remove instruments no pattern slot uses, return how many
+    pub fn remove_unused_instruments(&mut self) -> usize {
+        // slot instrument 1 is index 0
+        let instrument_qty = self.instrument.len();
+        let mut used = vec![false; instrument_qty];
+        for slot in self.pattern.iter().flatten().flatten() {
+            if slot.instrument != 0 && (slot.instrument as usize) <= instrument_qty {
+                used[slot.instrument as usize - 1] = true;
+            }
+        }
+        let mut new_instr: Vec<u8> = vec![0; instrument_qty];
+        let mut kept = 0;
+        for (i, &u) in used.iter().enumerate() {
+            if u {
+                kept += 1;
+                new_instr[i] = kept as u8;
+            }
+        }
+        for slot in self.pattern.iter_mut().flatten().flatten() {
+            if slot.instrument == 0 {
+                continue;
+            }
+            slot.instrument = match new_instr.get(slot.instrument as usize - 1) {
+                Some(&i) => i,
+                // still missing after the removal
+                None => u8::try_from(kept + 1).unwrap_or(slot.instrument),
+            };
+        }
+        let mut index = 0;
+        self.instrument.retain(|_| {
+            index += 1;
+            used[index - 1]
+        });
+
+        instrument_qty - kept
+    }
+
+    /// Remove unused samples and data after the loop end,
+    /// return how many samples and frames were removed
+    fn optimize_samples(&mut self) -> (usize, usize) {
+        let mut removed_samples = 0;
+        let mut trimmed_frames = 0;
+
+        for instr in self.instrument.iter_mut() {
+            if let InstrumentType::Default(id) = &mut instr.instr_type {
+                let sample_qty = id.sample.len();
+                let mut used = vec![false; sample_qty];
+                for &s in id.sample_for_note.iter() {
+                    if let Some(u) = used.get_mut(s as usize) {
+                        *u = true;
+                    }
+                }
+                // only samples 0 to 255 can be used
+                let mut new_sample: Vec<u8> = vec![0; sample_qty];
+                let mut kept: usize = 0;
+                for (s, &u) in used.iter().enumerate() {
+                    if u {
+                        new_sample[s] = kept as u8;
+                        kept += 1;
+                    }
+                }
+                for s in id.sample_for_note.iter_mut() {
+                    if let Some(&n) = new_sample.get(*s as usize) {
+                        *s = n;
+                    }
+                }
+                let mut index = 0;
+                id.sample.retain(|_| {
+                    index += 1;
+                    used[index - 1]
+                });
+                removed_samples += sample_qty - kept;
+
+                for sample in id.sample.iter_mut() {
+                    if let LoopType::No = sample.flags {
+                        continue;
+                    }
+                    let loop_end = sample.loop_start as usize + sample.loop_length as usize;
+                    if loop_end < sample.len() {
+                        trimmed_frames += sample.len() - loop_end;
+                        sample.truncate(loop_end);
+                    }
+                }
+            }
+        }
+
+        (removed_samples, trimmed_frames)
+    }
+
+    /// Replace patterns by `dest`, orders to missing patterns stay missing
+    fn keep_patterns(&mut self, dest: Vec<Pattern>, new_index: &[Option<usize>]) {
+        for p in self.pattern_order.iter_mut() {
+            *p = new_index.get(*p).copied().flatten().unwrap_or(dest.len());
+        }
+        self.pattern = dest;
+    }
+
+    fn serialized_size(&self) -> usize {
+        bincode::serde::encode_to_vec(self, bincode::config::legacy()).map_or(0, |v| v.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::instr_default::InstrDefault;
+    use crate::instrument::Instrument;
+    use crate::patternslot::PatternSlot;
+    use crate::sample::{Sample, SampleDataType};
+    use alloc::string::ToString;
+
+    /// One row pattern playing `instrument`
+    fn pattern(instrument: u8) -> Pattern {
+        vec![vec![PatternSlot {
+            instrument,
+            ..Default::default()
+        }]]
+    }
+
+    fn sample(name: &str, len: usize, flags: LoopType) -> Sample {
+        Sample {
+            name: name.to_string(),
+            loop_start: 2,
+            loop_length: 4,
+            volume: 1.0,
+            finetune: 0.0,
+            flags,
+            panning: 0.5,
+            relative_note: 0,
+            data: SampleDataType::Depth8(vec![0; len]),
+        }
+    }
+
+    fn instrument(name: &str) -> Instrument {
+        Instrument {
+            name: name.to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn names(module: &Module) -> Vec<&str> {
+        module.instrument.iter().map(|i| i.name.as_str()).collect()
+    }
+
+    #[test]
+    fn remove_unused_patterns() {
+        let mut module = Module {
+            pattern: vec![pattern(1), pattern(2), pattern(3), pattern(4)],
+            pattern_order: vec![3, 1, 3, 9],
+            ..Default::default()
+        };
+        assert_eq!(module.remove_unused_patterns(), 2);
+        assert_eq!(module.pattern, vec![pattern(4), pattern(2)]);
+        // the missing pattern stays missing
+        assert_eq!(module.pattern_order, vec![0, 1, 0, 2]);
+    }
+
+    #[test]
+    fn merge_identical_patterns() {
+        let mut module = Module {
+            pattern: vec![pattern(1), pattern(2), pattern(1), pattern(2), pattern(3)],
+            pattern_order: vec![2, 0, 3, 1, 2],
+            ..Default::default()
+        };
+        assert_eq!(module.merge_patterns(), 2);
+        assert_eq!(module.pattern, vec![pattern(1), pattern(2)]);
+        assert_eq!(module.pattern_order, vec![0, 0, 1, 1, 0]);
+    }
+
+    #[test]
+    fn remove_unused_instruments() {
+        let mut module = Module {
+            pattern: vec![pattern(2), pattern(4), pattern(0), pattern(7)],
+            pattern_order: vec![0, 1, 2, 3],
+            instrument: ["a", "b", "c", "d", "e"].map(instrument).to_vec(),
+            ..Default::default()
+        };
+        assert_eq!(module.remove_unused_instruments(), 3);
+        assert_eq!(names(&module), vec!["b", "d"]);
+        let slots: Vec<u8> = module.pattern.iter().map(|p| p[0][0].instrument).collect();
+        // a missing instrument stays missing, right after the last one
+        assert_eq!(slots, vec![1, 2, 0, 3]);
+    }
+
+    #[test]
+    fn missing_instrument_255() {
+        let mut module = Module {
+            pattern: vec![pattern(255)],
+            pattern_order: vec![0],
+            instrument: vec![instrument("a"); 254],
+            ..Default::default()
+        };
+        module.pattern[0][0].push(PatternSlot {
+            instrument: 254,
+            ..Default::default()
+        });
+        assert_eq!(module.remove_unused_instruments(), 253);
+        assert_eq!(module.pattern[0][0][0].instrument, 2);
+        assert_eq!(module.pattern[0][0][1].instrument, 1);
+    }
+
+    #[test]
+    fn remove_unused_samples() {
+        let mut id = InstrDefault {
+            sample: vec![
+                sample("a", 8, LoopType::Forward),
+                sample("b", 8, LoopType::No),
+                sample("c", 10, LoopType::PingPong),
+            ],
+            sample_for_note: [2; 96],
+            ..Default::default()
+        };
+        id.sample_for_note[0] = 1;
+        let mut module = Module {
+            pattern: vec![pattern(1)],
+            pattern_order: vec![0],
+            instrument: vec![Instrument {
+                instr_type: InstrumentType::Default(id),
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        let report = module.optimize();
+        assert_eq!(report.removed_samples, 1);
+        // "c" ends at its loop end
+        assert_eq!(report.trimmed_frames, 4);
+        assert!(report.bytes_saved > 0);
+
+        let InstrumentType::Default(id) = &module.instrument[0].instr_type else {
+            unreachable!()
+        };
+        let samples: Vec<(&str, usize)> = id
+            .sample
+            .iter()
+            .map(|s| (s.name.as_str(), s.len()))
+            .collect();
+        assert_eq!(samples, vec![("b", 8), ("c", 6)]);
+        assert_eq!(id.sample_for_note[0], 0);
+        assert!(id.sample_for_note[1..].iter().all(|&s| s == 1));
+    }
+
+    #[test]
+    fn optimize_report() {
+        let mut module = Module {
+            pattern: vec![pattern(1), pattern(1), pattern(2)],
+            pattern_order: vec![0, 1],
+            instrument: ["a", "b"].map(instrument).to_vec(),
+            ..Default::default()
+        };
+        let report = module.optimize();
+        assert_eq!(
+            (
+                report.removed_patterns,
+                report.merged_patterns,
+                report.removed_instruments
+            ),
+            (1, 1, 1)
+        );
+        assert_eq!(module.pattern_order, vec![0, 0]);
+        assert_eq!(names(&module), vec!["a"]);
+    }
+}
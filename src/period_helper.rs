@@ -77,7 +77,14 @@ impl PeriodHelper {
     /// return period
     #[inline(always)]
     fn linear_frequency_to_period(freq: f32) -> f32 {
-        (6.0 * 12.0 * 16.0 * 4.0) - (12.0 * 16.0 * 4.0) * (freq / Self::C4_FREQ).log2()
+        // micromath log2() is only accurate above 1.0
+        let ratio = freq / Self::C4_FREQ;
+        let octaves = if ratio < 1.0 {
+            -(1.0 / ratio).log2()
+        } else {
+            ratio.log2()
+        };
+        (6.0 * 12.0 * 16.0 * 4.0) - (12.0 * 16.0 * 4.0) * octaves
     }
 
     // ==== Amiga
@@ -0,0 +1,645 @@
+use alloc::{vec, vec::Vec};
+use core::ops::Range;
+
+use crate::module::{Module, Pattern, Row, MAX_NUM_ROWS};
+use crate::patternslot::PatternSlot;
+
+/// XM effect numbers kept correct by the resize functions
+const EFFECT_POSITION_JUMP: u8 = 0x0B;
+const EFFECT_PATTERN_BREAK: u8 = 0x0D;
+
+/// Highest row a pattern break (Dxx, decimal) can reach
+pub(crate) const MAX_BREAK_ROW: usize = 159;
+
+/// How a row leaves its pattern
+#[derive(Clone, Copy, PartialEq)]
+enum Flow {
+    /// Dxx: row of the next order
+    Break(usize),
+    /// Bxx to an order, with Dxx for a row other than 0
+    Jump(usize, usize),
+}
+
+impl Flow {
+    /// Pattern break when possible, unless the row had a position jump
+    fn new(order: usize, target_order: usize, target_row: usize, jump: bool) -> Self {
+        if !jump && target_order == order + 1 {
+            Flow::Break(target_row)
+        } else {
+            Flow::Jump(target_order, target_row)
+        }
+    }
+
+    /// The flow goes to the target when played at `order`
+    fn reaches(&self, order: usize, target_order: usize, target_row: usize) -> bool {
+        match *self {
+            Flow::Break(r) => target_order == order + 1 && r == target_row,
+            Flow::Jump(o, r) => target_order == o && r == target_row,
+        }
+    }
+
+    /// Dxx parameter
+    fn bcd(row: usize) -> u8 {
+        (((row / 10) << 4) | (row % 10)) as u8
+    }
+}
+
+/// A pattern copy with its own row flows
+struct Variant {
+    source: usize,
+    pattern: usize,
+    /// Row and how it leaves the pattern
+    flows: Vec<(usize, Flow)>,
+}
+
+/// Old order rows copied into a new order
+struct Piece {
+    old_order: usize,
+    old_rows: Range<usize>,
+    /// First row in the new pattern
+    new_row: usize,
+}
+
+/// A new `pattern_order` entry and where its rows come from
+struct NewOrder {
+    pattern: usize,
+    pieces: Vec<Piece>,
+}
+
+impl NewOrder {
+    fn whole(pattern: usize, old_order: usize, rows: usize) -> Self {
+        Self {
+            pattern,
+            pieces: vec![Piece {
+                old_order,
+                old_rows: 0..rows,
+                new_row: 0,
+            }],
+        }
+    }
+}
+
+impl Module {
+    /// Cut a pattern in two before `at_row`, the second part is inserted
+    /// at `idx + 1` and played right after the first one
+    ///
+    /// False when a jump or a break can not be written, the module is
+    /// then unchanged.
+    pub fn split_pattern(&mut self, idx: usize, at_row: usize) -> bool {
+        let mut cuts: Vec<Vec<usize>> = vec![vec![]; self.pattern.len()];
+        if let Some(c) = cuts.get_mut(idx) {
+            c.push(at_row);
+        }
+        self.split_patterns(&cuts)
+    }
+
+    /// Split every pattern longer than `max` rows (at most `MAX_NUM_ROWS`)
+    ///
+    /// Patterns whose jumps or breaks can not be written are left whole,
+    /// false if any is still longer than `max`.
+    pub fn normalize_pattern_lengths(&mut self, max: usize) -> bool {
+        let max = max.clamp(1, MAX_NUM_ROWS);
+        let cuts =
+            |p: &Pattern| -> Vec<usize> { (1..p.len().div_ceil(max)).map(|i| i * max).collect() };
+        let all: Vec<Vec<usize>> = self.pattern.iter().map(cuts).collect();
+        if all.iter().all(|c| c.is_empty()) || self.split_patterns(&all) {
+            return true;
+        }
+
+        // one pattern at a time, from the last one to keep the indexes
+        let mut whole: Vec<usize> = vec![];
+        let mut tries = self.pattern.len() + self.pattern_order.len();
+        while let Some(p) = (0..self.pattern.len())
+            .rev()
+            .find(|&p| self.pattern[p].len() > max && !whole.contains(&p))
+        {
+            if tries == 0 {
+                break;
+            }
+            tries -= 1;
+            let mut single: Vec<Vec<usize>> = vec![vec![]; self.pattern.len()];
+            single[p] = cuts(&self.pattern[p]);
+            let added = single[p].len();
+            if self.split_patterns(&single) {
+                whole
+                    .iter_mut()
+                    .filter(|w| **w > p)
+                    .for_each(|w| *w += added);
+            } else {
+                whole.push(p);
+            }
+        }
+        false
+    }
+
+    /// Append pattern `a` followed by pattern `b` as a new pattern,
+    /// used where `b` is played right after `a`, return its index
+    ///
+    /// Return `pattern.len()` and leave the module unchanged when a jump
+    /// or a break can not be written.
+    pub fn join_patterns(&mut self, a: usize, b: usize) -> usize {
+        let joined = self.pattern.len();
+        if a >= joined || b >= joined {
+            return joined;
+        }
+        let (len_a, len_b) = (self.pattern[a].len(), self.pattern[b].len());
+
+        let mut orders: Vec<NewOrder> = vec![];
+        let mut o = 0;
+        while o < self.pattern_order.len() {
+            let p = self.pattern_order[o];
+            if p == a && self.pattern_order.get(o + 1) == Some(&b) {
+                orders.push(NewOrder {
+                    pattern: joined,
+                    pieces: vec![
+                        Piece {
+                            old_order: o,
+                            old_rows: 0..len_a,
+                            new_row: 0,
+                        },
+                        Piece {
+                            old_order: o + 1,
+                            old_rows: 0..len_b,
+                            new_row: len_a,
+                        },
+                    ],
+                });
+                o += 2;
+            } else {
+                orders.push(NewOrder::whole(p, o, self.pattern_len(p)));
+                o += 1;
+            }
+        }
+
+        let mut patterns = self.pattern.clone();
+        let mut pattern = self.pattern[a].clone();
+        pattern.extend_from_slice(&self.pattern[b]);
+        patterns.push(pattern);
+        if self.rebuild(patterns, orders) {
+            joined
+        } else {
+            self.pattern.len()
+        }
+    }
+
+    /// Cut or extend a pattern with empty rows
+    ///
+    /// False when a jump or a break can not be written, the module is
+    /// then unchanged.
+    pub fn resize_pattern(&mut self, idx: usize, rows: usize) -> bool {
+        if idx >= self.pattern.len() {
+            return false;
+        }
+        let kept = rows.min(self.pattern[idx].len());
+        let orders: Vec<NewOrder> = self
+            .pattern_order
+            .iter()
+            .enumerate()
+            .map(|(o, &p)| {
+                let len = if p == idx { kept } else { self.pattern_len(p) };
+                NewOrder::whole(p, o, len)
+            })
+            .collect();
+
+        let channels = self.max_row_len();
+        let mut patterns = self.pattern.clone();
+        patterns[idx].resize(rows, vec![PatternSlot::default(); channels]);
+        self.rebuild(patterns, orders)
+    }
+
+    /// Cut patterns before each row of `cuts[pattern]`
+    fn split_patterns(&mut self, cuts: &[Vec<usize>]) -> bool {
+        // each pattern becomes a list of row ranges
+        let mut first_part: Vec<usize> = vec![];
+        let mut parts: Vec<Range<usize>> = vec![];
+        let mut patterns: Vec<Pattern> = vec![];
+        for (p, pattern) in self.pattern.iter().enumerate() {
+            first_part.push(parts.len());
+            let mut start = 0;
+            let mut rows: Vec<usize> = cuts[p]
+                .iter()
+                .copied()
+                .filter(|&r| r > 0 && r < pattern.len())
+                .collect();
+            rows.sort_unstable();
+            rows.dedup();
+            rows.push(pattern.len());
+            for end in rows {
+                parts.push(start..end);
+                patterns.push(pattern[start..end].to_vec());
+                start = end;
+            }
+        }
+        first_part.push(parts.len());
+
+        let mut orders: Vec<NewOrder> = vec![];
+        for (o, &p) in self.pattern_order.iter().enumerate() {
+            if p >= self.pattern.len() {
+                orders.push(NewOrder::whole(
+                    p - self.pattern.len() + patterns.len(),
+                    o,
+                    0,
+                ));
+                continue;
+            }
+            let (first, last) = (first_part[p], first_part[p + 1]);
+            for (part, rows) in parts.iter().enumerate().take(last).skip(first) {
+                orders.push(NewOrder {
+                    pattern: part,
+                    pieces: vec![Piece {
+                        old_order: o,
+                        old_rows: rows.clone(),
+                        new_row: 0,
+                    }],
+                });
+            }
+        }
+        self.rebuild(patterns, orders)
+    }
+
+    /// Install new patterns and orders, then fix jump and break targets
+    ///
+    /// Nothing is changed when a jump or a break can not be written.
+    fn rebuild(&mut self, mut patterns: Vec<Pattern>, orders: Vec<NewOrder>) -> bool {
+        let old_order_qty = self.pattern_order.len();
+        let mut new_orders: Vec<Vec<usize>> = vec![vec![]; old_order_qty];
+        for (k, order) in orders.iter().enumerate() {
+            for piece in &order.pieces {
+                new_orders[piece.old_order].push(k);
+            }
+        }
+        // where an old (order, row) is now played
+        let find = |o: usize, r: usize| -> Option<(usize, usize)> {
+            for &k in &new_orders[o] {
+                for piece in orders[k].pieces.iter().filter(|p| p.old_order == o) {
+                    if piece.old_rows.contains(&r) {
+                        return Some((k, piece.new_row + r - piece.old_rows.start));
+                    }
+                }
+            }
+            None
+        };
+        let position = |o: usize, r: usize| -> (usize, usize) {
+            if o >= old_order_qty {
+                return (orders.len(), 0);
+            }
+            // rows past the pattern end break to its row 0
+            find(o, r)
+                .or_else(|| find(o, 0))
+                .unwrap_or((new_orders[o].first().copied().unwrap_or(orders.len()), 0))
+        };
+
+        // targets of each new order: (row, target order, target row, has Bxx)
+        let mut wanted: Vec<Vec<(usize, usize, usize, bool)>> = vec![vec![]; orders.len()];
+        for (k, order) in orders.iter().enumerate() {
+            for piece in &order.pieces {
+                let old_pattern = self.pattern_order[piece.old_order];
+                for r in piece.old_rows.clone() {
+                    let Some((jump, brk)) = self
+                        .pattern
+                        .get(old_pattern)
+                        .and_then(|p| Self::row_flow(&p[r]))
+                    else {
+                        continue;
+                    };
+                    // a jump past the last order still ends the song
+                    let to = jump.unwrap_or(piece.old_order + 1);
+                    let (nk, nr) = position(to, brk.unwrap_or(0));
+                    let row = piece.new_row + r - piece.old_rows.start;
+                    wanted[k].push((row, nk, nr, jump.is_some()));
+                }
+            }
+        }
+
+        // orders sharing a pattern must share its flows, else they get a copy
+        let mut pattern_order: Vec<usize> = orders.iter().map(|o| o.pattern).collect();
+        let mut variants: Vec<Variant> = vec![];
+        for (k, targets) in wanted.iter().enumerate() {
+            if targets.is_empty() {
+                continue;
+            }
+            let p = orders[k].pattern;
+            let fits = |flows: &[(usize, Flow)]| {
+                targets.iter().all(|&(row, nk, nr, _)| {
+                    flows
+                        .iter()
+                        .filter(|(r, _)| *r == row)
+                        .all(|(_, f)| f.reaches(k, nk, nr))
+                })
+            };
+            match variants.iter().find(|v| v.source == p && fits(&v.flows)) {
+                Some(v) => pattern_order[k] = v.pattern,
+                None => {
+                    let v = if variants.iter().any(|v| v.source == p) {
+                        patterns.push(patterns[p].clone());
+                        patterns.len() - 1
+                    } else {
+                        p
+                    };
+                    let flows = targets
+                        .iter()
+                        .map(|&(row, nk, nr, jump)| (row, Flow::new(k, nk, nr, jump)))
+                        .collect();
+                    variants.push(Variant {
+                        source: p,
+                        pattern: v,
+                        flows,
+                    });
+                    pattern_order[k] = v;
+                }
+            }
+        }
+        for Variant {
+            pattern: v, flows, ..
+        } in variants
+        {
+            for (r, flow) in flows {
+                if let Some(row) = patterns[v].get_mut(r) {
+                    if !Self::set_row_flow(row, flow) {
+                        return false;
+                    }
+                }
+            }
+        }
+
+        self.restart_position = position(self.restart_position, 0).0;
+        if self.restart_position >= orders.len() {
+            self.restart_position = 0;
+        }
+        self.pattern_order = pattern_order;
+        self.pattern = patterns;
+        true
+    }
+
+    /// Position jump order and pattern break row of a row, `None` without both
+    pub(crate) fn row_flow(row: &Row) -> Option<(Option<usize>, Option<usize>)> {
+        let mut jump: Option<usize> = None;
+        let mut brk: Option<usize> = None;
+        for slot in row {
+            let param = slot.effect_parameter;
+            match slot.effect_type {
+                EFFECT_POSITION_JUMP => jump = Some(param as usize),
+                EFFECT_PATTERN_BREAK => {
+                    brk = Some((param >> 4) as usize * 10 + (param & 0x0F) as usize)
+                }
+                _ => {}
+            }
+        }
+        (jump.is_some() || brk.is_some()).then_some((jump, brk))
+    }
+
+    /// Write Bxx and Dxx of a row, false and nothing written if they
+    /// do not fit
+    fn set_row_flow(row: &mut Row, flow: Flow) -> bool {
+        let jump_slot = row
+            .iter()
+            .rposition(|s| s.effect_type == EFFECT_POSITION_JUMP);
+        let break_slot = row
+            .iter()
+            .rposition(|s| s.effect_type == EFFECT_PATTERN_BREAK);
+
+        match flow {
+            Flow::Break(r) => {
+                let Some(slot) = break_slot.or(jump_slot) else {
+                    return false;
+                };
+                if r > MAX_BREAK_ROW {
+                    return false;
+                }
+                for (c, s) in row.iter_mut().enumerate() {
+                    if c == slot {
+                        s.effect_type = EFFECT_PATTERN_BREAK;
+                        s.effect_parameter = Flow::bcd(r);
+                    } else if s.effect_type == EFFECT_POSITION_JUMP {
+                        s.effect_type = 0;
+                        s.effect_parameter = 0;
+                    }
+                }
+            }
+            Flow::Jump(order, r) => {
+                if order > u8::MAX as usize || r > MAX_BREAK_ROW {
+                    return false;
+                }
+                let jump_slot = match jump_slot {
+                    Some(c) => Some(c),
+                    None if r == 0 => break_slot,
+                    None => Self::free_slot(row, None),
+                };
+                let Some(jump_slot) = jump_slot else {
+                    return false;
+                };
+                let break_slot = match break_slot {
+                    Some(c) if c != jump_slot => Some(c),
+                    _ if r == 0 => None,
+                    _ => match Self::free_slot(row, Some(jump_slot)) {
+                        Some(c) => Some(c),
+                        None => return false,
+                    },
+                };
+
+                row[jump_slot].effect_type = EFFECT_POSITION_JUMP;
+                row[jump_slot].effect_parameter = order as u8;
+                if let Some(c) = break_slot {
+                    row[c].effect_type = EFFECT_PATTERN_BREAK;
+                    row[c].effect_parameter = Flow::bcd(r);
+                }
+            }
+        }
+        true
+    }
+
+    /// First slot without effect, other than `used`
+    fn free_slot(row: &Row, used: Option<usize>) -> Option<usize> {
+        row.iter()
+            .enumerate()
+            .position(|(c, s)| Some(c) != used && s.effect_type == 0 && s.effect_parameter == 0)
+    }
+    fn pattern_len(&self, p: usize) -> usize {
+        self.pattern.get(p).map_or(0, |p| p.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Time and content of each row played, without Bxx and Dxx, then the
+    /// duration and the loop start time
+    fn played(module: &Module) -> Vec<(u32, Row)> {
+        let timeline = module.timeline();
+        let mut rows: Vec<(u32, Row)> = timeline
+            .rows
+            .iter()
+            .map(|e| {
+                let mut row = module.pattern[module.pattern_order[e.order]][e.row].clone();
+                for slot in row.iter_mut() {
+                    if matches!(
+                        slot.effect_type,
+                        EFFECT_POSITION_JUMP | EFFECT_PATTERN_BREAK
+                    ) {
+                        slot.effect_type = 0;
+                        slot.effect_parameter = 0;
+                    }
+                }
+                (e.time.to_bits(), row)
+            })
+            .collect();
+        rows.push((timeline.duration.to_bits(), vec![]));
+        if let Some(start) = timeline.loop_start {
+            rows.push((start.time.to_bits(), vec![]));
+        }
+        rows
+    }
+
+    /// Rows told apart by their instrument and volume
+    fn pattern(id: u8, rows: usize, channels: usize) -> Pattern {
+        (0..rows)
+            .map(|r| {
+                vec![
+                    PatternSlot {
+                        instrument: id,
+                        volume: r as u8,
+                        ..Default::default()
+                    };
+                    channels
+                ]
+            })
+            .collect()
+    }
+
+    fn module(patterns: Vec<Pattern>, pattern_order: Vec<usize>) -> Module {
+        Module {
+            pattern: patterns,
+            pattern_order,
+            ..Default::default()
+        }
+    }
+
+    fn effect(module: &mut Module, p: usize, row: usize, channel: usize, effect: u8, param: u8) {
+        let slot = &mut module.pattern[p][row][channel];
+        slot.effect_type = effect;
+        slot.effect_parameter = param;
+    }
+
+    #[test]
+    fn normalize_keeps_playback() {
+        let mut m = module(
+            vec![pattern(1, 200, 2), pattern(2, 100, 2), pattern(3, 10, 2)],
+            vec![0, 1, 0, 2],
+        );
+        // break to row 70 of the next order, then a jump back to row 5
+        effect(&mut m, 0, 150, 0, EFFECT_PATTERN_BREAK, 0x70);
+        effect(&mut m, 1, 90, 1, EFFECT_POSITION_JUMP, 2);
+        effect(&mut m, 1, 90, 0, EFFECT_PATTERN_BREAK, 0x05);
+        effect(&mut m, 2, 3, 0, 0x0F, 3);
+        let before = played(&m);
+
+        assert!(m.normalize_pattern_lengths(64));
+        assert!(m.pattern.iter().all(|p| p.len() <= 64));
+        assert_eq!(played(&m), before);
+    }
+
+    #[test]
+    fn split_keeps_playback() {
+        let mut m = module(vec![pattern(1, 64, 1), pattern(2, 64, 1)], vec![0, 1, 0]);
+        effect(&mut m, 0, 40, 0, EFFECT_PATTERN_BREAK, 0x32);
+        effect(&mut m, 1, 20, 0, EFFECT_POSITION_JUMP, 2);
+        let before = played(&m);
+
+        assert!(m.split_pattern(1, 32));
+        assert_eq!(m.pattern_order.len(), 4);
+        assert_eq!(played(&m), before);
+    }
+
+    #[test]
+    fn join_keeps_playback() {
+        let mut m = module(vec![pattern(1, 16, 2), pattern(2, 16, 2)], vec![0, 1, 0, 1]);
+        // a break past the end of pattern 1 plays its row 0
+        effect(&mut m, 1, 8, 0, EFFECT_PATTERN_BREAK, 0x40);
+        effect(&mut m, 0, 12, 0, EFFECT_PATTERN_BREAK, 0x04);
+        let before = played(&m);
+
+        let joined = m.join_patterns(0, 1);
+        assert_eq!(joined, 2);
+        // the two orders break to different places: one has a copy
+        assert_eq!(m.pattern_order, vec![2, 3]);
+        assert_eq!(played(&m), before);
+    }
+
+    #[test]
+    fn jump_past_the_end_still_ends_the_song() {
+        let mut m = module(vec![pattern(1, 8, 1), pattern(2, 8, 1)], vec![0, 1]);
+        // order 2 does not exist before the split
+        effect(&mut m, 0, 4, 0, EFFECT_POSITION_JUMP, 2);
+        let before = played(&m);
+
+        assert!(m.split_pattern(0, 2));
+        assert_eq!(m.pattern_order.len(), 3);
+        assert_eq!(played(&m), before);
+    }
+
+    #[test]
+    fn full_row_leaves_the_module_unchanged() {
+        let mut m = module(vec![pattern(1, 16, 1), pattern(2, 16, 1)], vec![0, 1]);
+        // after the split, row 10 of pattern 1 needs Bxx and Dxx on one channel
+        effect(&mut m, 0, 5, 0, EFFECT_PATTERN_BREAK, 0x10);
+        let (patterns, order) = (m.pattern.clone(), m.pattern_order.clone());
+
+        assert!(!m.split_pattern(1, 8));
+        assert_eq!(m.pattern, patterns);
+        assert_eq!(m.pattern_order, order);
+        assert_eq!(m.join_patterns(0, 1), 2);
+    }
+
+    #[test]
+    fn random_modules_keep_playback() {
+        let mut seed: u32 = 0x2545_F491;
+        let mut random = move |n: usize| {
+            seed ^= seed << 13;
+            seed ^= seed >> 17;
+            seed ^= seed << 5;
+            seed as usize % n
+        };
+        for _ in 0..500 {
+            let channels = 1 + random(2);
+            let patterns: Vec<Pattern> = (0..1 + random(4))
+                .map(|p| pattern(p as u8 + 1, 1 + random(80), channels))
+                .collect();
+            let order: Vec<usize> = (0..1 + random(6)).map(|_| random(patterns.len())).collect();
+            let mut m = module(patterns, order);
+            for p in 0..m.pattern.len() {
+                for r in 0..m.pattern[p].len() {
+                    let c = random(channels);
+                    match random(40) {
+                        0 => effect(&mut m, p, r, c, EFFECT_POSITION_JUMP, random(8) as u8),
+                        1 | 2 => {
+                            let param = Flow::bcd(random(90));
+                            effect(&mut m, p, r, c, EFFECT_PATTERN_BREAK, param)
+                        }
+                        3 => effect(&mut m, p, r, c, 0x0F, 1 + random(0xFF) as u8),
+                        _ => {}
+                    }
+                }
+            }
+            let before = played(&m);
+            let (patterns, order) = (m.pattern.clone(), m.pattern_order.clone());
+
+            let changed = match random(3) {
+                0 => {
+                    m.normalize_pattern_lengths(1 + random(40));
+                    true
+                }
+                1 => m.split_pattern(random(m.pattern.len()), random(80)),
+                _ => {
+                    m.join_patterns(random(m.pattern.len()), random(m.pattern.len()))
+                        != m.pattern.len()
+                }
+            };
+            if !changed {
+                assert_eq!(m.pattern, patterns);
+                assert_eq!(m.pattern_order, order);
+            }
+            assert_eq!(played(&m), before);
+        }
+    }
+}
@@ -5,6 +5,7 @@ use serde::Deserialize;
 
 use crate::prelude::*;
 
+use alloc::format;
 use alloc::string::String;
 use alloc::string::ToString;
 use alloc::{vec, vec::Vec};
@@ -345,16 +346,6 @@ impl S3mModule {
         s3m.positions = data[0..s].to_vec();
         let data = &data[s..];
 
-        // remove pattern separators (254)
-        s3m.positions.retain(|&x| x != 254);
-        // cut on first eop (255)
-        s3m.positions = s3m
-            .positions
-            .iter()
-            .take_while(|&x| *x != 255)
-            .cloned()
-            .collect();
-
         // === sample offsets
 
         let s = 2 * s3m.header.instrument_count as usize;
@@ -409,9 +400,34 @@ impl S3mModule {
             s3m.patterns.push(pattern);
         }
 
+        s3m.remove_separators();
+
         Ok(s3m)
     }
 
+    /// Remove pattern separators (254), keep eop (255) between songs
+    ///
+    /// Position jumps are renumbered, a jump to a separator goes to the next order.
+    fn remove_separators(&mut self) {
+        let mut new_order: Vec<usize> = Vec::with_capacity(self.positions.len() + 1);
+        let mut kept = 0;
+        for &x in &self.positions {
+            new_order.push(kept);
+            if x != 254 {
+                kept += 1;
+            }
+        }
+        new_order.push(kept);
+
+        for slot in self.patterns.iter_mut().flatten().flatten() {
+            if slot.effect_type == 0xB {
+                let old = (slot.effect_parameter as usize).min(self.positions.len());
+                slot.effect_parameter = new_order[old] as u8;
+            }
+        }
+        self.positions.retain(|&x| x != 254);
+    }
+
     // load one pattern row
     fn process_pattern_row(data: &[u8]) -> Result<(Vec<PatternSlot>, &[u8]), DecodeError> {
         let mut d2 = data;
@@ -508,7 +524,13 @@ impl S3mModule {
         module.frequency_type = FrequencyType::LinearFrequencies;
         module.default_tempo = self.header.speed as u16;
         module.default_bpm = self.header.tempo as u16;
-        module.pattern_order = self.positions.iter().map(|&x| x as usize).collect();
+        // cut on first eop (255)
+        module.pattern_order = self
+            .positions
+            .iter()
+            .take_while(|&x| *x != 255)
+            .map(|&x| x as usize)
+            .collect();
         module.pattern = self.patterns.clone();
 
         for s3m_meta_instr in &self.instruments {
@@ -567,4 +589,114 @@ impl S3mModule {
 
         module
     }
+
+    /// One module for each song, songs are separated by eop (255)
+    /// or hidden behind position jumps
+    pub fn to_modules(&self) -> Vec<Module> {
+        let mut module = self.to_module();
+
+        // eop becomes a pattern that stops the song, order indexes are kept
+        let stop = module.pattern.len();
+        let mut row = vec![PatternSlot::default(); module.max_row_len().max(1)];
+        row[0].effect_type = 0x0F;
+        row[0].effect_parameter = 0;
+        module.pattern.push(vec![row]);
+        module.pattern_order = self
+            .positions
+            .iter()
+            .map(|&x| if x == 255 { stop } else { x as usize })
+            .collect();
+
+        // trailing eop are not songs
+        module
+            .subsongs()
+            .iter()
+            .filter(|song| song.orders.iter().any(|&o| module.pattern_order[o] != stop))
+            .enumerate()
+            .map(|(song_number, song)| {
+                let mut m = module.subsong_to_module(song);
+                m.name = format!("{} {}", self.header.title, song_number);
+                m
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// One row patterns, `jumps` holds the S3M Bxx parameter of each one
+    fn s3m(positions: &[u8], jumps: &[Option<u8>]) -> Vec<u8> {
+        let mut data = b"songs".to_vec();
+        data.resize(28, 0);
+        data.extend_from_slice(&[0x1A, 0x10, 0, 0]);
+        for value in [positions.len(), 0, jumps.len(), 0, 0x1320, 2] {
+            data.extend_from_slice(&(value as u16).to_le_bytes());
+        }
+        data.extend_from_slice(b"SCRM");
+        // global volume, speed, tempo, master volume
+        data.extend_from_slice(&[64, 6, 125, 0xB0]);
+        data.resize(96, 0);
+        data.extend_from_slice(positions);
+
+        let pointers = data.len();
+        data.resize(pointers + 2 * jumps.len(), 0);
+        for (p, jump) in jumps.iter().enumerate() {
+            data.resize(data.len().next_multiple_of(16), 0);
+            let paragraph = (data.len() / 16) as u16;
+            data[pointers + 2 * p..pointers + 2 * p + 2].copy_from_slice(&paragraph.to_le_bytes());
+            let row: Vec<u8> = match jump {
+                Some(order) => vec![0x80, 2, *order, 0],
+                None => vec![0],
+            };
+            data.extend_from_slice(&(row.len() as u16 + 2).to_le_bytes());
+            data.extend_from_slice(&row);
+        }
+        data
+    }
+
+    fn jump(module: &Module, order: usize) -> Option<u8> {
+        let slot = &module.pattern[module.pattern_order[order]][0][0];
+        (slot.effect_type == 0x0B).then_some(slot.effect_parameter)
+    }
+
+    #[test]
+    fn separators_renumber_position_jumps() {
+        let data = s3m(&[0, 254, 254, 1, 2, 255], &[Some(4), None, Some(1)]);
+        let module = S3mModule::load(&data).unwrap().to_module();
+        assert_eq!(module.pattern_order, vec![0, 1, 2]);
+        // order 4 is now 2, a jump to a separator goes to the next order
+        assert_eq!(jump(&module, 0), Some(2));
+        assert_eq!(jump(&module, 2), Some(1));
+    }
+
+    #[test]
+    fn split_songs() {
+        // orders 0, 2, 3 | 1, 2, 3 | 4, 5 looping back across a separator
+        let data = s3m(
+            &[0, 254, 1, 2, 255, 3, 254, 4, 255, 255],
+            &[Some(3), None, None, None, Some(5)],
+        );
+        let modules = S3mModule::load(&data).unwrap().to_modules();
+        assert_eq!(modules.len(), 3);
+
+        assert_eq!(modules[0].name, "songs 0");
+        assert_eq!(modules[0].pattern_order.len(), 3);
+        assert_eq!(jump(&modules[0], 0), Some(1));
+        assert_eq!(jump(&modules[0], 1), None);
+        let end = &modules[0].pattern[modules[0].pattern_order[2]][0][0];
+        assert_eq!((end.effect_type, end.effect_parameter), (0x0F, 0));
+
+        assert_eq!(modules[1].name, "songs 1");
+        assert_eq!(modules[1].pattern_order, vec![0, 1, 2]);
+        assert_eq!(modules[1].pattern.len(), 3);
+        assert_eq!(jump(&modules[1], 0), None);
+
+        assert_eq!(modules[2].pattern_order, vec![0, 1]);
+        assert_eq!(jump(&modules[2], 1), Some(0));
+        let timeline = modules[2].timeline();
+        assert!(timeline.loops_forever);
+        assert_eq!(timeline.loop_start.map(|e| e.order), Some(0));
+    }
 }
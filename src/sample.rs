@@ -15,14 +15,44 @@ pub enum LoopType {
 }
 
 /// is sample recorded with 8 or 16 bits depth
+///
+/// Since 0.9 stereo samples have their own variants, `to_mono()` gives
+/// the old mono data.
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub enum SampleDataType {
     Depth8(Vec<i8>),
     Depth16(Vec<i16>),
+    /// Left and right frames interleaved
+    Stereo8(Vec<i8>),
+    /// Left and right frames interleaved
+    Stereo16(Vec<i16>),
+}
+
+impl SampleDataType {
+    pub fn is_stereo(&self) -> bool {
+        matches!(self, Self::Stereo8(_) | Self::Stereo16(_))
+    }
+
+    /// Left and right channels mixed, mono data is cloned
+    pub fn to_mono(&self) -> Self {
+        match self {
+            Self::Stereo8(v) => Self::Depth8(
+                v.chunks_exact(2)
+                    .map(|f| ((f[0] as i16 + f[1] as i16) / 2) as i8)
+                    .collect(),
+            ),
+            Self::Stereo16(v) => Self::Depth16(
+                v.chunks_exact(2)
+                    .map(|f| ((f[0] as i32 + f[1] as i32) / 2) as i16)
+                    .collect(),
+            ),
+            mono => mono.clone(),
+        }
+    }
 }
 
 /// A Real Data sample
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct Sample {
     /// Name
     pub name: String,
@@ -50,22 +80,59 @@ impl Sample {
         match &self.data {
             SampleDataType::Depth8(v) => v.len(),
             SampleDataType::Depth16(v) => v.len(),
+            SampleDataType::Stereo8(v) => v.len() / 2,
+            SampleDataType::Stereo16(v) => v.len() / 2,
         }
     }
 
-    /// return sample at seek
+    /// return sample at seek, stereo channels are mixed
     pub fn at(&self, seek: usize) -> f32 {
         match &self.data {
             SampleDataType::Depth8(v) => v[seek] as f32 / 128.0,
             SampleDataType::Depth16(v) => v[seek] as f32 / 32768.0,
+            SampleDataType::Stereo8(v) => (v[2 * seek] as f32 + v[2 * seek + 1] as f32) / 256.0,
+            SampleDataType::Stereo16(v) => (v[2 * seek] as f32 + v[2 * seek + 1] as f32) / 65536.0,
+        }
+    }
+
+    /// Shorten the sample data to `len` frames
+    pub fn truncate(&mut self, len: usize) {
+        match &mut self.data {
+            SampleDataType::Depth8(v) => v.truncate(len),
+            SampleDataType::Depth16(v) => v.truncate(len),
+            SampleDataType::Stereo8(v) => v.truncate(2 * len),
+            SampleDataType::Stereo16(v) => v.truncate(2 * len),
         }
     }
 
     /// return sample size (8 or 16 bits)
     pub fn bits(&self) -> u8 {
         match &self.data {
-            SampleDataType::Depth8(_) => 8,
-            SampleDataType::Depth16(_) => 16,
+            SampleDataType::Depth8(_) | SampleDataType::Stereo8(_) => 8,
+            SampleDataType::Depth16(_) | SampleDataType::Stereo16(_) => 16,
+        }
+    }
+
+    /// 1 for mono, 2 for stereo
+    pub fn channels(&self) -> usize {
+        if self.data.is_stereo() {
+            2
+        } else {
+            1
+        }
+    }
+
+    /// Keep the loop inside the data, disable it when empty
+    pub fn fix_loop(&mut self) {
+        if let LoopType::No = self.flags {
+            return;
+        }
+        let len = self.len() as u32;
+        self.loop_start = self.loop_start.min(len);
+        self.loop_length = self.loop_length.min(len - self.loop_start);
+        if self.loop_length == 0 {
+            self.flags = LoopType::No;
+            self.loop_start = 0;
         }
     }
 }
@@ -0,0 +1,500 @@
+#[cfg(feature = "micromath")]
+use micromath::F32Ext;
+#[cfg(feature = "libm")]
+use num_traits::float::Float;
+
+use alloc::vec::Vec;
+use core::ops::Range;
+
+use crate::period_helper::{FrequencyType, PeriodHelper};
+use crate::sample::{LoopType, Sample, SampleDataType};
+
+impl Sample {
+    /// Frames in [-1..1], stereo channels interleaved
+    fn to_f32(&self) -> Vec<f32> {
+        match &self.data {
+            SampleDataType::Depth8(v) | SampleDataType::Stereo8(v) => {
+                v.iter().map(|&x| x as f32 / 128.0).collect()
+            }
+            SampleDataType::Depth16(v) | SampleDataType::Stereo16(v) => {
+                v.iter().map(|&x| x as f32 / 32768.0).collect()
+            }
+        }
+    }
+
+    /// Replace the data, bits and channels are kept
+    fn set_f32(&mut self, data: &[f32]) {
+        let to_i8 = |x: &f32| (x * 128.0).round().clamp(-128.0, 127.0) as i8;
+        let to_i16 = |x: &f32| (x * 32768.0).round().clamp(-32768.0, 32767.0) as i16;
+        self.data = match self.data {
+            SampleDataType::Depth8(_) => SampleDataType::Depth8(data.iter().map(to_i8).collect()),
+            SampleDataType::Depth16(_) => {
+                SampleDataType::Depth16(data.iter().map(to_i16).collect())
+            }
+            SampleDataType::Stereo8(_) => SampleDataType::Stereo8(data.iter().map(to_i8).collect()),
+            SampleDataType::Stereo16(_) => {
+                SampleDataType::Stereo16(data.iter().map(to_i16).collect())
+            }
+        };
+    }
+
+    fn loop_end(&self) -> usize {
+        self.loop_start as usize + self.loop_length as usize
+    }
+
+    fn set_loop(&mut self, start: usize, end: usize) {
+        self.loop_start = start as u32;
+        self.loop_length = end.saturating_sub(start) as u32;
+        self.fix_loop();
+    }
+
+    /// C-4 playback rate in Hz, from `relative_note` and `finetune`
+    pub fn rate(&self) -> f32 {
+        let ph = PeriodHelper::new(FrequencyType::LinearFrequencies, false);
+        ph.relative_note_to_c4freq(self.relative_note as f32, self.finetune)
+            .unwrap_or(PeriodHelper::C4_FREQ)
+    }
+
+    /// Resample to `rate` Hz at C-4, `relative_note` and `finetune` keep the pitch
+    ///
+    /// Linear interpolation, frames are averaged when the rate goes down.
+    pub fn resample(&mut self, rate: f32) {
+        let old_rate = self.rate();
+        let len = self.len();
+        if rate <= 0.0 || len == 0 {
+            return;
+        }
+        let ratio = rate / old_rate;
+        let channels = self.channels();
+        let src = self.to_f32();
+        let new_len = ((len as f32 * ratio).round() as usize).max(1);
+        let step = 1.0 / ratio;
+
+        let mut dst: Vec<f32> = Vec::with_capacity(new_len * channels);
+        for i in 0..new_len {
+            let pos = i as f32 * step;
+            for c in 0..channels {
+                let frame = |f: usize| src[f.min(len - 1) * channels + c];
+                let value = if step > 1.0 {
+                    let first = pos as usize;
+                    let last = ((pos + step) as usize).clamp(first + 1, len);
+                    (first..last).map(frame).sum::<f32>() / (last - first) as f32
+                } else {
+                    let first = pos as usize;
+                    let frac = pos - first as f32;
+                    frame(first) * (1.0 - frac) + frame(first + 1) * frac
+                };
+                dst.push(value);
+            }
+        }
+        self.set_f32(&dst);
+
+        let start = (self.loop_start as f32 * ratio).round() as usize;
+        let end = (self.loop_end() as f32 * ratio).round() as usize;
+        self.set_loop(start, end);
+
+        let ph = PeriodHelper::new(FrequencyType::LinearFrequencies, false);
+        let (relative_note, finetune) = ph.c4freq_to_relative_note(rate);
+        self.relative_note = relative_note;
+        self.finetune = finetune;
+    }
+
+    /// Amplify the data so that the loudest frame is at full scale
+    pub fn normalize(&mut self) {
+        let mut data = self.to_f32();
+        let peak = data.iter().fold(0.0f32, |m, &x| m.max(x.abs()));
+        if peak == 0.0 {
+            return;
+        }
+        let gain = 1.0 / peak;
+        data.iter_mut().for_each(|x| *x *= gain);
+        self.set_f32(&data);
+    }
+
+    /// Play the data backwards, the loop is mirrored
+    pub fn reverse(&mut self) {
+        let len = self.len();
+        match &mut self.data {
+            SampleDataType::Depth8(v) => v.reverse(),
+            SampleDataType::Depth16(v) => v.reverse(),
+            SampleDataType::Stereo8(v) => {
+                v.reverse();
+                v.chunks_exact_mut(2).for_each(|f| f.swap(0, 1));
+            }
+            SampleDataType::Stereo16(v) => {
+                v.reverse();
+                v.chunks_exact_mut(2).for_each(|f| f.swap(0, 1));
+            }
+        }
+        self.fix_loop();
+        if let LoopType::No = self.flags {
+            return;
+        }
+        self.set_loop(len - self.loop_end(), len - self.loop_start as usize);
+    }
+
+    /// Keep only `frames`, the loop is cut to fit
+    pub fn crop(&mut self, frames: Range<usize>) {
+        let len = self.len();
+        let start = frames.start.min(len);
+        let end = frames.end.clamp(start, len);
+        let channels = self.channels();
+        let range = start * channels..end * channels;
+        match &mut self.data {
+            SampleDataType::Depth8(v) | SampleDataType::Stereo8(v) => {
+                v.truncate(range.end);
+                v.drain(..range.start);
+            }
+            SampleDataType::Depth16(v) | SampleDataType::Stereo16(v) => {
+                v.truncate(range.end);
+                v.drain(..range.start);
+            }
+        }
+        if let LoopType::No = self.flags {
+            return;
+        }
+        let loop_start = (self.loop_start as usize).clamp(start, end) - start;
+        let loop_end = self.loop_end().clamp(start, end) - start;
+        self.set_loop(loop_start, loop_end);
+    }
+
+    /// Linear fade from silence over the first `frames`
+    pub fn fade_in(&mut self, frames: usize) {
+        let len = self.len();
+        let frames = frames.min(len);
+        self.apply_gain(0..frames, |i| i as f32 / frames as f32);
+    }
+
+    /// Linear fade to silence over the last `frames`
+    pub fn fade_out(&mut self, frames: usize) {
+        let len = self.len();
+        let frames = frames.min(len);
+        self.apply_gain(len - frames..len, |i| (len - 1 - i) as f32 / frames as f32);
+    }
+
+    fn apply_gain(&mut self, frames: Range<usize>, gain: impl Fn(usize) -> f32) {
+        if frames.is_empty() {
+            return;
+        }
+        let channels = self.channels();
+        let mut data = self.to_f32();
+        for i in frames {
+            let g = gain(i);
+            for c in 0..channels {
+                data[i * channels + c] *= g;
+            }
+        }
+        self.set_f32(&data);
+    }
+
+    /// Convert to 8 bits with a triangular dither
+    pub fn to_8bits(&mut self) {
+        // xorshift, always the same noise
+        let mut seed: u32 = 0x2545_F491;
+        let mut noise = || {
+            seed ^= seed << 13;
+            seed ^= seed >> 17;
+            seed ^= seed << 5;
+            (seed >> 16) as i32 - 0x8000
+        };
+        let mut dither = |v: &Vec<i16>| -> Vec<i8> {
+            v.iter()
+                .map(|&x| {
+                    // two uniform noises of +/- half an 8 bits step
+                    let tpdf = (noise() + noise()) >> 8;
+                    ((x as i32 + tpdf + 0x80) >> 8).clamp(-128, 127) as i8
+                })
+                .collect()
+        };
+        self.data = match &self.data {
+            SampleDataType::Depth16(v) => SampleDataType::Depth8(dither(v)),
+            SampleDataType::Stereo16(v) => SampleDataType::Stereo8(dither(v)),
+            _ => return,
+        };
+    }
+
+    /// Convert to 16 bits
+    pub fn to_16bits(&mut self) {
+        let widen = |v: &Vec<i8>| -> Vec<i16> { v.iter().map(|&x| (x as i16) << 8).collect() };
+        self.data = match &self.data {
+            SampleDataType::Depth8(v) => SampleDataType::Depth16(widen(v)),
+            SampleDataType::Stereo8(v) => SampleDataType::Stereo16(widen(v)),
+            _ => return,
+        };
+    }
+
+    /// Mix left and right channels
+    pub fn to_mono(&mut self) {
+        self.data = self.data.to_mono();
+    }
+
+    /// Copy the channel to left and right
+    pub fn to_stereo(&mut self) {
+        self.data = match &self.data {
+            SampleDataType::Depth8(v) => {
+                SampleDataType::Stereo8(v.iter().flat_map(|&x| [x, x]).collect())
+            }
+            SampleDataType::Depth16(v) => {
+                SampleDataType::Stereo16(v.iter().flat_map(|&x| [x, x]).collect())
+            }
+            _ => return,
+        };
+    }
+
+    /// Blend the last `frames` of the loop with the frames before its start,
+    /// the loop end then flows smoothly into the loop start
+    pub fn crossfade_loop(&mut self, frames: usize) {
+        if let LoopType::No = self.flags {
+            return;
+        }
+        let start = self.loop_start as usize;
+        let end = self.loop_end().min(self.len());
+        let frames = frames.min(start).min(end - start.min(end));
+        if frames == 0 {
+            return;
+        }
+        let channels = self.channels();
+        let mut data = self.to_f32();
+        for i in 0..frames {
+            let t = (i + 1) as f32 / (frames + 1) as f32;
+            for c in 0..channels {
+                let tail = (end - frames + i) * channels + c;
+                let before = (start - frames + i) * channels + c;
+                data[tail] = data[tail] * (1.0 - t) + data[before] * t;
+            }
+        }
+        self.set_f32(&data);
+    }
+
+    /// Frame nearest to `frame` where the signal crosses zero
+    pub fn find_zero_crossing(&self, frame: usize) -> Option<usize> {
+        let len = self.len();
+        let crossing = |i: usize| {
+            let (a, b) = (self.at(i - 1), self.at(i));
+            b == 0.0 || (a < 0.0) != (b < 0.0)
+        };
+        let frame = frame.min(len);
+        for distance in 0..len {
+            let after = frame + distance;
+            if after > 0 && after < len && crossing(after) {
+                return Some(after);
+            }
+            if distance <= frame && frame - distance > 0 && crossing(frame - distance) {
+                return Some(frame - distance);
+            }
+        }
+        None
+    }
+
+    /// Move the loop start and end to the nearest zero crossings
+    pub fn snap_loop_to_zero_crossings(&mut self) {
+        if let LoopType::No = self.flags {
+            return;
+        }
+        let start = self.loop_start as usize;
+        let end = self.loop_end();
+        let new_start = self.find_zero_crossing(start).unwrap_or(start);
+        let new_end = self.find_zero_crossing(end).unwrap_or(end);
+        if new_end > new_start {
+            self.set_loop(new_start, new_end);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+
+    fn sample(data: SampleDataType, loop_start: u32, loop_length: u32) -> Sample {
+        Sample {
+            name: "".into(),
+            loop_start,
+            loop_length,
+            volume: 1.0,
+            finetune: 0.0,
+            flags: LoopType::Forward,
+            panning: 0.5,
+            relative_note: 0,
+            data,
+        }
+    }
+
+    #[test]
+    fn reverse_mirrors_the_loop() {
+        let mut s = sample(SampleDataType::Depth8(vec![0, 1, 2, 3, 4, 5, 6, 7]), 2, 4);
+        s.reverse();
+        assert!(matches!(&s.data, SampleDataType::Depth8(v) if v == &[7, 6, 5, 4, 3, 2, 1, 0]));
+        assert_eq!((s.loop_start, s.loop_length), (2, 4));
+
+        let mut s = sample(SampleDataType::Stereo16(vec![0, 10, 1, 11, 2, 12]), 0, 1);
+        s.reverse();
+        assert!(matches!(&s.data, SampleDataType::Stereo16(v) if v == &[2, 12, 1, 11, 0, 10]));
+        assert_eq!((s.loop_start, s.loop_length), (2, 1));
+    }
+
+    #[test]
+    fn reverse_fixes_a_loop_past_the_end() {
+        let mut s = sample(SampleDataType::Depth16(vec![1, 2, 3]), 10, 4);
+        s.reverse();
+        assert!(matches!(s.flags, LoopType::No));
+
+        let mut s = sample(SampleDataType::Depth16(vec![1, 2, 3, 4]), 1, 100);
+        s.reverse();
+        assert_eq!((s.loop_start, s.loop_length), (0, 3));
+    }
+
+    #[test]
+    fn normalize_to_full_scale() {
+        let mut s = sample(SampleDataType::Depth8(vec![0, 32, -64, 16]), 0, 4);
+        s.normalize();
+        assert!(matches!(&s.data, SampleDataType::Depth8(v) if v == &[0, 64, -128, 32]));
+
+        let mut s = sample(SampleDataType::Stereo16(vec![100, -200, 50, 0]), 0, 2);
+        s.normalize();
+        assert!(matches!(&s.data, SampleDataType::Stereo16(v) if v == &[16384, -32768, 8192, 0]));
+
+        // silence is kept
+        let mut s = sample(SampleDataType::Depth16(vec![0; 4]), 0, 4);
+        s.normalize();
+        assert!(matches!(&s.data, SampleDataType::Depth16(v) if v == &[0; 4]));
+    }
+
+    #[test]
+    fn fades() {
+        let mut s = sample(SampleDataType::Depth8(vec![100; 6]), 0, 6);
+        s.fade_in(4);
+        assert!(matches!(&s.data, SampleDataType::Depth8(v) if v == &[0, 25, 50, 75, 100, 100]));
+
+        let mut s = sample(
+            SampleDataType::Stereo8(vec![100, -100, 100, -100, 100, -100]),
+            0,
+            3,
+        );
+        s.fade_out(2);
+        assert!(matches!(&s.data, SampleDataType::Stereo8(v) if v == &[100, -100, 50, -50, 0, 0]));
+
+        // longer than the sample
+        let mut s = sample(SampleDataType::Depth16(vec![1000, 1000]), 0, 2);
+        s.fade_out(10);
+        assert!(matches!(&s.data, SampleDataType::Depth16(v) if v == &[500, 0]));
+        s.fade_in(0);
+        assert!(matches!(&s.data, SampleDataType::Depth16(v) if v == &[500, 0]));
+    }
+
+    #[test]
+    fn resample_keeps_the_pitch() {
+        let data: Vec<i16> = (0..100).map(|x| x * 100).collect();
+        let mut s = sample(SampleDataType::Depth16(data), 20, 40);
+        let rate = s.rate();
+        s.resample(rate / 2.0);
+        assert_eq!(s.len(), 50);
+        assert_eq!((s.loop_start, s.loop_length), (10, 20));
+        assert!((s.rate() - rate / 2.0).abs() < 1.0);
+        assert_eq!(s.relative_note, -12);
+        // averaged frames
+        assert!(matches!(&s.data, SampleDataType::Depth16(v) if v[1] == 250));
+
+        let mut s = sample(SampleDataType::Stereo8(vec![0, 0, 100, -100]), 0, 2);
+        s.resample(s.rate() * 2.0);
+        assert_eq!(s.len(), 4);
+        assert_eq!(s.relative_note, 12);
+        assert!(matches!(&s.data, SampleDataType::Stereo8(v) if v[..4] == [0, 0, 50, -50]));
+
+        // nothing to do
+        let mut s = sample(SampleDataType::Depth8(vec![]), 0, 0);
+        s.resample(22050.0);
+        assert_eq!(s.len(), 0);
+        let mut s = sample(SampleDataType::Depth8(vec![1, 2]), 0, 2);
+        s.resample(0.0);
+        assert_eq!(s.relative_note, 0);
+    }
+
+    #[test]
+    fn bit_depth_conversions() {
+        let mut s = sample(SampleDataType::Depth8(vec![-128, -1, 0, 127]), 0, 4);
+        s.to_16bits();
+        assert!(matches!(&s.data, SampleDataType::Depth16(v) if v == &[-32768, -256, 0, 32512]));
+        s.to_8bits();
+        let SampleDataType::Depth8(v) = &s.data else {
+            panic!("not 8 bits");
+        };
+        assert!(v
+            .iter()
+            .zip([-128, -1, 0, 127])
+            .all(|(&a, b)| (a as i16 - b).abs() <= 1));
+
+        // triangular dither: one step of noise plus the rounding, no offset
+        let data: Vec<i16> = (0..1000).map(|x| (x * 61 - 30000) as i16).collect();
+        let mut s = sample(SampleDataType::Stereo16(data.clone()), 0, 500);
+        s.to_8bits();
+        let SampleDataType::Stereo8(v) = &s.data else {
+            panic!("not stereo 8 bits");
+        };
+        assert_eq!(v.len(), data.len());
+        let errors: Vec<i32> = v
+            .iter()
+            .zip(&data)
+            .map(|(&a, &b)| a as i32 * 256 - b as i32)
+            .collect();
+        assert!(errors.iter().all(|e| e.abs() <= 384));
+        assert!(errors.iter().sum::<i32>().abs() / 1000 < 16);
+
+        s.to_16bits();
+        assert!(matches!(&s.data, SampleDataType::Stereo16(v) if v.len() == 1000));
+        // already 16 bits
+        s.to_16bits();
+        assert!(matches!(&s.data, SampleDataType::Stereo16(_)));
+    }
+
+    #[test]
+    fn channel_conversions() {
+        let mut s = sample(SampleDataType::Depth8(vec![1, -2]), 0, 2);
+        s.to_stereo();
+        assert!(matches!(&s.data, SampleDataType::Stereo8(v) if v == &[1, 1, -2, -2]));
+        assert_eq!((s.len(), s.channels()), (2, 2));
+        s.to_stereo();
+        assert_eq!(s.len(), 2);
+
+        let mut s = sample(SampleDataType::Stereo16(vec![100, 300, -100, -300]), 0, 2);
+        s.to_mono();
+        assert!(matches!(&s.data, SampleDataType::Depth16(v) if v == &[200, -200]));
+        assert_eq!(s.channels(), 1);
+    }
+
+    #[test]
+    fn crop_cuts_the_loop() {
+        let mut s = sample(SampleDataType::Stereo8((0..20).collect()), 2, 6);
+        s.crop(4..20);
+        assert_eq!(s.len(), 6);
+        assert!(matches!(&s.data, SampleDataType::Stereo8(v) if v[0] == 8));
+        assert_eq!((s.loop_start, s.loop_length), (0, 4));
+
+        s.crop(5..2);
+        assert_eq!(s.len(), 0);
+        assert!(matches!(s.flags, LoopType::No));
+    }
+
+    #[test]
+    fn crossfade_and_zero_crossings() {
+        let mut s = sample(
+            SampleDataType::Depth8(vec![0, 0, 0, 0, 90, 90, 90, 90]),
+            2,
+            6,
+        );
+        s.crossfade_loop(2);
+        assert!(matches!(&s.data, SampleDataType::Depth8(v) if v == &[0, 0, 0, 0, 90, 90, 60, 30]));
+
+        let mut s = sample(
+            SampleDataType::Depth16(vec![5, 3, -2, -4, 6, 8, -1, 2]),
+            1,
+            4,
+        );
+        assert_eq!(s.find_zero_crossing(0), Some(2));
+        assert_eq!(s.find_zero_crossing(4), Some(4));
+        // the later crossing first at the same distance
+        assert_eq!(s.find_zero_crossing(5), Some(6));
+        s.snap_loop_to_zero_crossings();
+        assert_eq!((s.loop_start, s.loop_length), (2, 4));
+    }
+}
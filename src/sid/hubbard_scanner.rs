@@ -0,0 +1,477 @@
+use alloc::{vec, vec::Vec};
+
+use super::one_sid::HubbardDriver;
+use super::psid_file::PsidFile;
+
+/// Wildcard byte in a 6502 code signature
+const ANY: u16 = 0x100;
+
+/// Find Rob Hubbard player routines in a C64 memory image
+///
+/// Each table is located from the code that reads it, so offsets are
+/// found whatever the load address. A file can hold several players
+/// (Sanxion), one `HubbardDriver` is returned for each.
+pub struct HubbardScanner;
+
+impl HubbardScanner {
+    /// TAY; LDA ptl,Y; STA zp; LDA pth,Y; STA zp+1
+    const SIG_PATTERN_PTR: [u16; 10] = [0xA8, 0xB9, ANY, ANY, 0x85, ANY, 0xB9, ANY, ANY, 0x85];
+    /// LDA instr,X; STA $D402,Y
+    const SIG_INSTR: [u16; 6] = [0xBD, ANY, ANY, 0x99, 0x02, 0xD4];
+    /// TAX; LDA list,X; STA abs,Y; INX; INY; CPY #2*tracks
+    const SIG_SONG_LIST: [u16; 10] = [0xAA, 0xBD, ANY, ANY, 0x99, ANY, ANY, 0xE8, 0xC8, 0xC0];
+    /// LDA list,X; STA zp; LDA list+tracks,X; STA zp
+    const SIG_SINGLE_SONG: [u16; 9] = [0xBD, ANY, ANY, 0x85, ANY, 0xBD, ANY, ANY, 0x85];
+    /// DEC speed; BPL; LDA resetspd; STA speed
+    const SIG_SPEED: [u16; 9] = [0xCE, ANY, ANY, 0x10, ANY, 0xAD, ANY, ANY, 0x8D];
+    /// LDA (zp),Y; BPL instr; STA abs,X; INY; LDA (zp),Y: 2 bytes portamento
+    const SIG_PORTAMENTO_WIDE: [u16; 9] = [0xB1, ANY, 0x10, ANY, 0x9D, ANY, ANY, 0xC8, 0xB1];
+    /// AND #$60; CMP #$60: compressed track events
+    const SIG_COMPRESSED: [u16; 4] = [0x29, 0x60, 0xC9, 0x60];
+    /// AND #$02; BEQ: instrument skydive flag, or second effect table
+    const SIG_SKYDIVE: [u16; 3] = [0x29, 0x02, 0xF0];
+    /// ASL; ASL; ASL; ASL; TAY; LDA fx,Y
+    const SIG_FX_V1: [u16; 6] = [0x0A, 0x0A, 0x0A, 0x0A, 0xA8, 0xB9];
+    /// STA $D403,Y; PHA; LDA fx+3,X; PHA
+    const SIG_FX_V2: [u16; 8] = [0x99, 0x03, 0xD4, 0x48, 0xBD, ANY, ANY, 0x48];
+
+    /// Longest channel list or track accepted
+    const MAX_SEQUENCE_LEN: usize = 1024;
+
+    /// Search every Hubbard player in the PSID data
+    pub fn scan(psid: &PsidFile) -> Vec<HubbardDriver> {
+        let mem = psid.memory();
+        Self::scan_memory(&mem, psid.load_address as usize, psid.end_address())
+    }
+
+    /// Search every Hubbard player code between `start` and `end`
+    pub fn scan_memory(mem: &[u8], start: usize, end: usize) -> Vec<HubbardDriver> {
+        let end = end.min(mem.len());
+        let mut drivers: Vec<HubbardDriver> = vec![];
+
+        for anchor in Self::find(mem, start, end, &Self::SIG_PATTERN_PTR) {
+            if let Some(driver) = Self::scan_player(mem, start, end, anchor) {
+                drivers.push(driver);
+            }
+        }
+        drivers
+    }
+
+    fn scan_player(mem: &[u8], start: usize, end: usize, anchor: usize) -> Option<HubbardDriver> {
+        let mut drv = HubbardDriver {
+            patt_ptl_offset: Self::word(mem, anchor + 2),
+            patt_pth_offset: Self::word(mem, anchor + 7),
+            ..Default::default()
+        };
+        if drv.patt_pth_offset <= drv.patt_ptl_offset
+            || drv.patt_pth_offset - drv.patt_ptl_offset > 0x80
+        {
+            return None;
+        }
+        drv.patt_qty = drv.patt_pth_offset - drv.patt_ptl_offset;
+
+        // pulse width low and high bytes are read one after the other
+        let instr: Vec<(usize, usize)> = Self::find(mem, start, end, &Self::SIG_INSTR)
+            .into_iter()
+            .filter(|&i| {
+                let next = if Self::byte(mem, i + 6) == 0x48 {
+                    i + 7
+                } else {
+                    i + 6
+                };
+                Self::byte(mem, next) == 0xBD
+                    && Self::word(mem, next + 1) == Self::word(mem, i + 1) + 1
+            })
+            .map(|i| (i, Self::word(mem, i + 1)))
+            .collect();
+        drv.instr_offset = Self::nearest(&instr, anchor)?;
+
+        let song_list: Vec<(usize, usize)> = Self::find(mem, start, end, &Self::SIG_SONG_LIST)
+            .into_iter()
+            .filter(|&i| (2..=6).contains(&Self::byte(mem, i + 10)))
+            .map(|i| (i, i))
+            .collect();
+        if let Some(i) = Self::nearest(&song_list, anchor) {
+            drv.song_list_offset = Self::word(mem, i + 2);
+            drv.song_track_qty = Self::byte(mem, i + 10) as usize / 2;
+            if drv.patt_ptl_offset <= drv.song_list_offset {
+                return None;
+            }
+            drv.song_list_qty =
+                (drv.patt_ptl_offset - drv.song_list_offset) / (2 * drv.song_track_qty);
+        } else {
+            let single_song: Vec<(usize, usize)> =
+                Self::find(mem, start, end, &Self::SIG_SINGLE_SONG)
+                    .into_iter()
+                    .filter(|&i| {
+                        let delta = Self::word(mem, i + 6).wrapping_sub(Self::word(mem, i + 1));
+                        (1..=3).contains(&delta)
+                    })
+                    .map(|i| (i, i))
+                    .collect();
+            let i = Self::nearest(&single_song, anchor)?;
+            drv.song_list_offset = Self::word(mem, i + 1);
+            drv.song_track_qty = Self::word(mem, i + 6) - drv.song_list_offset;
+            drv.song_list_qty = 1;
+        }
+        if drv.song_list_qty == 0
+            || drv.song_list_offset + drv.song_list_qty * 2 * drv.song_track_qty > mem.len()
+            || drv.patt_pth_offset + drv.patt_qty > mem.len()
+        {
+            return None;
+        }
+
+        // only keep tracks really used by the channels
+        let mut max_track = 0;
+        for song in 0..drv.song_list_qty {
+            let offset = drv.song_list_offset + song * 2 * drv.song_track_qty;
+            for j in 0..drv.song_track_qty {
+                let channel =
+                    mem[offset + j] as usize | (mem[offset + j + drv.song_track_qty] as usize) << 8;
+                let len = Self::sequence_len(mem, channel, |b| b & 0x80 != 0)?;
+                for &t in &mem[channel..channel + len] {
+                    max_track = max_track.max(t as usize);
+                }
+            }
+        }
+        drv.patt_qty = drv.patt_qty.min(max_track + 1);
+
+        let speed: Vec<(usize, usize)> = Self::find(mem, start, end, &Self::SIG_SPEED)
+            .into_iter()
+            .filter(|&i| Self::word(mem, i + 1) == Self::word(mem, i + 9))
+            .map(|i| (i, Self::word(mem, i + 6)))
+            .collect();
+        drv.resetspd = match Self::nearest(&speed, anchor) {
+            Some(adr) => Self::byte(mem, adr) as usize,
+            None => 1,
+        };
+
+        let mut tracks: Vec<usize> = vec![];
+        for i in 0..drv.patt_qty {
+            let track = mem[drv.patt_ptl_offset + i] as usize
+                | (mem[drv.patt_pth_offset + i] as usize) << 8;
+            Self::sequence_len(mem, track, |b| b == 0xFF)?;
+            tracks.push(track);
+        }
+
+        // LDA instr+5,Y; BNE +3: vibrato depth and speed packed in one byte
+        let vibrato = [
+            0xB9,
+            (drv.instr_offset + 5) & 0xFF,
+            (drv.instr_offset + 5) >> 8,
+            0xD0,
+            0x03,
+            0x4C,
+        ]
+        .map(|b| b as u16);
+        // the track reader tells the portamento size (v20) and the
+        // compressed events (v30)
+        let has = |signature: &[u16]| !Self::find(mem, start, end, signature).is_empty();
+        drv.version = if has(&Self::SIG_PORTAMENTO_WIDE) && has(&Self::SIG_COMPRESSED) {
+            30
+        } else if has(&Self::SIG_PORTAMENTO_WIDE) {
+            20
+        } else if has(&vibrato) {
+            15
+        } else {
+            10
+        };
+
+        let mut max_instr = 0;
+        for &track in &tracks {
+            Self::parse_track(mem, track, drv.version >= 20, &mut max_instr);
+        }
+
+        if drv.version < 20 {
+            let fx: Vec<(usize, usize)> = Self::find(mem, start, end, &Self::SIG_FX_V1)
+                .into_iter()
+                .map(|i| (i, Self::word(mem, i + 6)))
+                .collect();
+            if let Some(fx) = Self::nearest(&fx, anchor) {
+                let next = Self::next_table(&drv, fx, end);
+                drv.fx_v1_offset = fx;
+                drv.fx_v1_qty =
+                    Self::table_len(mem, fx, next, 16, |e| e.iter().all(|&b| b == 0)).min(16);
+            }
+            (drv.skydive_v1_when, drv.skydive_v1_add) = Self::skydive(mem, start, end, anchor);
+        } else {
+            let fx: Vec<(usize, usize)> = Self::find(mem, start, end, &Self::SIG_FX_V2)
+                .into_iter()
+                .map(|i| (i, Self::word(mem, i + 5)))
+                .collect();
+            if let Some(fx) = Self::nearest(&fx, anchor) {
+                drv.fx_v2_offset = fx - 3;
+            }
+        }
+
+        // instruments until the next table, without the silent ones at its end
+        let next = Self::next_table(&drv, drv.instr_offset, end);
+        drv.instr_qty = Self::table_len(mem, drv.instr_offset, next, 8, |e| e[2] == 0)
+            .clamp(max_instr + 1, 128);
+        if drv.fx_v2_offset != 0 {
+            drv.fx_v2_qty = drv.instr_qty;
+        }
+
+        Some(drv)
+    }
+
+    /// Closest table after `table`, or `end`
+    fn next_table(drv: &HubbardDriver, table: usize, end: usize) -> usize {
+        [
+            drv.song_list_offset,
+            drv.patt_ptl_offset,
+            drv.patt_pth_offset,
+            drv.instr_offset,
+            drv.fx_v1_offset,
+            drv.fx_v2_offset,
+        ]
+        .into_iter()
+        .filter(|&adr| adr > table)
+        .min()
+        .unwrap_or(end)
+        .min(end)
+    }
+
+    /// Entries of `size` bytes from `table` to `next`, without the
+    /// `unused` ones at its end
+    fn table_len(
+        mem: &[u8],
+        table: usize,
+        next: usize,
+        size: usize,
+        unused: impl Fn(&[u8]) -> bool,
+    ) -> usize {
+        let mut len = next.saturating_sub(table) / size;
+        while len > 0
+            && mem
+                .get(table + (len - 1) * size..table + len * size)
+                .is_none_or(&unused)
+        {
+            len -= 1;
+        }
+        len
+    }
+
+    /// Skydive length test and frequency step, read from the player code
+    ///
+    /// The block after the flag test skips notes shorter than `n` rows
+    /// with `AND #$1F; CMP #n; BCC`, then each INC or DEC raises or lowers
+    /// the pitch by 256. Blocks which do not write the frequency are
+    /// another effect, the v20 effect table.
+    fn skydive(mem: &[u8], start: usize, end: usize, anchor: usize) -> (usize, isize) {
+        let mut found: Vec<(usize, (usize, isize))> = vec![];
+        for i in Self::find(mem, start, end, &Self::SIG_SKYDIVE) {
+            let offset = Self::byte(mem, i + 3);
+            if offset >= 0x80 {
+                continue;
+            }
+            let stop = i + 4 + offset as usize;
+            let (mut when, mut add, mut writes_freq) = (0, 0, false);
+            let mut pc = i + 4;
+            while pc < stop {
+                let op = Self::byte(mem, pc);
+                match op {
+                    // AND #$1F; CMP #n; BCC
+                    0xC9 if Self::word(mem, pc - 2) == 0x1F29
+                        && Self::byte(mem, pc + 2) == 0x90 =>
+                    {
+                        when = Self::byte(mem, pc + 1).saturating_sub(1) as usize
+                    }
+                    // INC zp,X or abs,X
+                    0xF6 | 0xFE => add += 256,
+                    // DEC zp,X or abs,X
+                    0xD6 | 0xDE => add -= 256,
+                    // STA $D401,Y
+                    0x99 if Self::word(mem, pc + 1) == 0xD401 => writes_freq = true,
+                    _ => {}
+                }
+                pc += Self::op_len(op);
+            }
+            if writes_freq {
+                found.push((i, (when, add)));
+            }
+        }
+        found
+            .into_iter()
+            .min_by_key(|(code, _)| code.abs_diff(anchor))
+            .map_or((0, 0), |(_, skydive)| skydive)
+    }
+
+    /// Length of a 6502 instruction
+    fn op_len(op: u8) -> usize {
+        match (op >> 4, op & 0x0F) {
+            (0x2, 0x0) | (_, 0xC..=0xF) => 3,
+            (0x0 | 0x4 | 0x6, 0x0) | (_, 0x8 | 0xA) => 1,
+            (high, 0x9 | 0xB) if high & 1 == 1 => 3,
+            _ => 2,
+        }
+    }
+
+    /// Walk a track, true if it ends on its 0xFF
+    ///
+    /// Track bytes: flags, [instrument or portamento], note
+    fn parse_track(mem: &[u8], start: usize, wide_portamento: bool, max_instr: &mut usize) -> bool {
+        let end = mem.len().min(start + Self::MAX_SEQUENCE_LEN);
+        let mut index = start;
+        while index < end {
+            let flags = Self::byte(mem, index);
+            if flags == 0xFF {
+                return true;
+            }
+            if flags & 0b0100_0000 == 0 {
+                index += 1;
+                if flags & 0b1000_0000 != 0 {
+                    let value = Self::byte(mem, index);
+                    if value & 0b1000_0000 == 0 {
+                        *max_instr = (*max_instr).max(value as usize);
+                    } else if wide_portamento {
+                        index += 1;
+                    }
+                    index += 1;
+                }
+                if Self::byte(mem, index) == 0xFF {
+                    return false;
+                }
+            }
+            index += 1;
+        }
+        false
+    }
+
+    /// Length of a sequence before its `is_end` byte, if terminated
+    fn sequence_len(mem: &[u8], start: usize, is_end: impl Fn(u8) -> bool) -> Option<usize> {
+        let end = mem.len().min(start + Self::MAX_SEQUENCE_LEN);
+        mem.get(start..end)?.iter().position(|&b| is_end(b))
+    }
+
+    /// Value of the candidate whose code is the closest to `anchor`
+    fn nearest(candidates: &[(usize, usize)], anchor: usize) -> Option<usize> {
+        candidates
+            .iter()
+            .min_by_key(|(code, _)| code.abs_diff(anchor))
+            .map(|&(_, value)| value)
+    }
+
+    fn byte(mem: &[u8], offset: usize) -> u8 {
+        mem.get(offset).copied().unwrap_or(0)
+    }
+
+    fn word(mem: &[u8], offset: usize) -> usize {
+        Self::byte(mem, offset) as usize | (Self::byte(mem, offset + 1) as usize) << 8
+    }
+
+    fn find(mem: &[u8], start: usize, end: usize, signature: &[u16]) -> Vec<usize> {
+        let mut found: Vec<usize> = vec![];
+        if end < start + signature.len() {
+            return found;
+        }
+        for i in start..=end - signature.len() {
+            if signature
+                .iter()
+                .zip(&mem[i..])
+                .all(|(&s, &b)| s == ANY || s == b as u16)
+            {
+                found.push(i);
+            }
+        }
+        found
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sid::sid_registry::SidRegistry;
+
+    /// Tables where the scanner reads the player code differently from the
+    /// hand-tuned registry: tune, driver, field, scanner and registry values
+    const DIFFERENCES: &[(&str, usize, &str, isize, isize)] = &[
+        ("ACE II", 0, "fx_v2_offset", 0xE624, 0),
+        ("ACE II", 0, "fx_v2_qty", 10, 0),
+        ("Crazy Comets", 0, "fx_v1_qty", 16, 1),
+        ("The Human Race", 0, "version", 10, 20),
+        ("The Human Race", 0, "song_list_qty", 5, 3),
+        ("The Human Race", 0, "patt_qty", 79, 58),
+        ("The Human Race", 0, "skydive_v1_when", 16, 0),
+        ("The Human Race", 0, "skydive_v1_add", 256, -256),
+        ("The Last V8", 0, "skydive_v1_when", 30, 0),
+        ("Sanxion", 1, "instr_qty", 19, 20),
+        ("Sanxion", 1, "fx_v2_offset", 0xC555, 0xC5F5),
+        ("Sanxion", 1, "fx_v2_qty", 19, 20),
+        ("Spellbound", 0, "fx_v1_qty", 10, 16),
+        ("Thing on a Spring", 0, "instr_qty", 15, 45),
+        ("Thing on a Spring", 0, "fx_v1_offset", 0xCDA2, 0xCE92),
+        ("Thing on a Spring", 0, "fx_v1_qty", 16, 1),
+        ("Thing on a Spring", 0, "skydive_v1_add", 256, 0),
+        ("Zoids", 0, "resetspd", 2, 1),
+        ("Zoids", 0, "skydive_v1_when", 15, 0),
+        ("Zoids", 0, "skydive_v1_add", -256, 0),
+    ];
+
+    fn fields(d: &HubbardDriver) -> [(&'static str, isize); 16] {
+        [
+            ("version", d.version as isize),
+            ("song_track_qty", d.song_track_qty as isize),
+            ("song_list_offset", d.song_list_offset as isize),
+            ("song_list_qty", d.song_list_qty as isize),
+            ("patt_ptl_offset", d.patt_ptl_offset as isize),
+            ("patt_pth_offset", d.patt_pth_offset as isize),
+            ("patt_qty", d.patt_qty as isize),
+            ("instr_offset", d.instr_offset as isize),
+            ("instr_qty", d.instr_qty as isize),
+            ("fx_v1_offset", d.fx_v1_offset as isize),
+            ("fx_v1_qty", d.fx_v1_qty as isize),
+            ("fx_v2_offset", d.fx_v2_offset as isize),
+            ("fx_v2_qty", d.fx_v2_qty as isize),
+            ("resetspd", d.resetspd as isize),
+            ("skydive_v1_when", d.skydive_v1_when as isize),
+            ("skydive_v1_add", d.skydive_v1_add),
+        ]
+    }
+
+    #[test]
+    fn scanner_finds_the_registry_tables() {
+        let songs: [&[u8]; 13] = [
+            include_bytes!("songs/ace_2.sid"),
+            include_bytes!("songs/commando.sid"),
+            include_bytes!("songs/crazy_comets.sid"),
+            include_bytes!("songs/delta.sid"),
+            include_bytes!("songs/human_race.sid"),
+            include_bytes!("songs/international_karate.sid"),
+            include_bytes!("songs/last_v8.sid"),
+            include_bytes!("songs/lightforce.sid"),
+            include_bytes!("songs/monty_on_the_run.sid"),
+            include_bytes!("songs/sanxion.sid"),
+            include_bytes!("songs/spellbound.sid"),
+            include_bytes!("songs/thing_on_a_spring.sid"),
+            include_bytes!("songs/zoids.sid"),
+        ];
+        let mut differences = 0;
+        for song in songs {
+            let psid = PsidFile::load(song).unwrap();
+            let known = SidRegistry::find(&psid);
+            assert!(!known.is_empty(), "{} is not in the registry", psid.name);
+            let scanned = HubbardScanner::scan(&psid);
+            assert_eq!(scanned.len(), known.len(), "{}", psid.name);
+            for (i, (scan, known)) in scanned.iter().zip(known).enumerate() {
+                for ((field, scan), (_, known)) in
+                    fields(scan).into_iter().zip(fields(&known.driver))
+                {
+                    if scan == known {
+                        continue;
+                    }
+                    assert!(
+                        DIFFERENCES.contains(&(psid.name.as_str(), i, field, scan, known)),
+                        "{} {}: scanner {:#X}, registry {:#X}",
+                        psid.name,
+                        field,
+                        scan,
+                        known
+                    );
+                    differences += 1;
+                }
+            }
+        }
+        assert_eq!(differences, DIFFERENCES.len());
+    }
+}
@@ -1,3 +1,4 @@
+use super::sid_chip::SidChip;
 use crate::{instr_sid::SidVoice, prelude::*};
 use alloc::format;
 use alloc::string::String;
@@ -12,86 +13,38 @@ pub struct InstrHelper;
 
 impl InstrHelper {
     fn sid_to_sample(voice: &SidVoice) -> Sample {
-        let mut name = String::from("Unknown Waveform");
-        let mut data = SampleDataType::Depth8(vec![0; 128]); // Par défaut: silence
-        let flags = LoopType::Forward;
-
-        // Définir les paramètres basés sur les contrôles de SidVoice
-        if voice.ctrl_noise {
-            name = String::from("Noise");
-            data = Self::generate_sid_noise_sample(); // Génération d'un sample bruité
+        // 128 points per oscillator period, noise needs more periods not to sound periodic
+        let periods = if voice.ctrl_noise { 32 } else { 1 };
+        let length = 128 * periods;
+
+        let name = if voice.ctrl_noise {
+            String::from("Noise")
         } else if voice.ctrl_pulse {
-            name = format!("Pulse Wave {}", voice.pw);
-            data = Self::generate_pulse_sample(voice.pw); // Génération d'un sample onde carrée avec largeur d'impulsion
-                                                          // flags = LoopType::Forward;  // Peut-être que les ondes carrées peuvent boucler en avant
+            format!("Pulse Wave {}", voice.pw)
         } else if voice.ctrl_sawtooth {
-            name = String::from("Sawtooth Wave");
-            data = Self::generate_sawtooth_sample(); // Génération d'un sample onde dent de scie
-                                                     // finetune = 0.25;  // Une dent de scie peut avoir un finetune particulier
+            String::from("Sawtooth Wave")
         } else if voice.ctrl_triangle {
-            name = String::from("Triangle Wave");
-            data = Self::generate_triangle_sample(); // Génération d'un sample onde triangle
-                                                     // flags = LoopType::PingPong;  // Exemple: onde triangle en ping-pong
-        }
+            String::from("Triangle Wave")
+        } else {
+            String::from("Unknown Waveform")
+        };
+
+        let data: Vec<i8> = SidChip::render_waveform(voice, length, periods)
+            .iter()
+            .map(|&x| (x >> 8) as i8)
+            .collect();
 
-        // Définir le sample
         Sample {
             name,
             loop_start: 0,
-            loop_length: 128, // Exemple arbitraire: longueur de boucle
-            volume: 1.0,      // Volume par défaut
+            loop_length: length as u32,
+            volume: 1.0,
             finetune: 0.0,
-            flags,
+            flags: LoopType::Forward,
             panning: 0.5,
-            relative_note: 0, // Correspond à C-4 par défaut
-            data,
-        }
-    }
-
-    fn generate_sid_noise_sample() -> SampleDataType {
-        let mut lfsr: u16 = 0xACE1;
-        let mut noise_data: Vec<i8> = vec![0; 128];
-
-        for i in 0..128 {
-            let new_bit = (lfsr >> 0) ^ (lfsr >> 1) & 1;
-            lfsr = (lfsr >> 1) | (new_bit << 15);
-            let value_u8 = (lfsr & 0xFF) as u8;
-            noise_data[i] = value_u8.wrapping_sub(128) as i8;
-        }
-
-        SampleDataType::Depth8(noise_data)
-    }
-
-    fn generate_pulse_sample(pw: u16) -> SampleDataType {
-        let mut pulse_data: Vec<i8> = vec![0; 128];
-        let duty_cycle = (pw as f32 / 4095.0 * 128.0) as usize;
-        for i in 0..128 {
-            pulse_data[i] = if i < duty_cycle { 127 } else { -127 };
+            relative_note: 0, // C-4
+            data: SampleDataType::Depth8(data),
         }
-        SampleDataType::Depth8(pulse_data)
-    }
-
-    fn generate_sawtooth_sample() -> SampleDataType {
-        let sawtooth_data: Vec<i8> = (0..128)
-            .map(|x| (2 * x as u8).wrapping_sub(128) as i8)
-            .collect();
-        SampleDataType::Depth8(sawtooth_data)
-    }
-
-    fn generate_triangle_sample() -> SampleDataType {
-        let mut triangle_data: Vec<i8> = vec![0; 128];
-
-        for i in 0..128 {
-            let value = if i < 64 {
-                ((4 * i) as u8).wrapping_sub(128) as i8
-            } else {
-                ((4 * (127 - i)) as u8).wrapping_sub(128) as i8
-            };
-
-            triangle_data[i as usize] = value;
-        }
-
-        SampleDataType::Depth8(triangle_data)
     }
 
     fn gen_adr_frames(bpm: u16, speed: u8) -> [u16; 16] {
@@ -125,7 +78,10 @@ impl InstrHelper {
 
         if attack != 0 {
             let attack_frame = adr_frames[attack as usize];
-            point.push(EnvelopePoint { frame: 0, value: 0.0 });
+            point.push(EnvelopePoint {
+                frame: 0,
+                value: 0.0,
+            });
             seek += attack_frame;
             point.push(EnvelopePoint {
                 frame: attack_frame,
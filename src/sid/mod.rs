@@ -1,7 +1,12 @@
 #![forbid(unsafe_code)]
 
 pub(crate) mod instr_helper;
-pub(crate) mod one_sid;
+pub mod hubbard_scanner;
+pub mod one_sid;
 pub(crate) mod pattern_helper;
+pub mod psid_file;
+pub mod sid_chip;
 pub mod sid_module;
-pub(crate) mod sound_fx;
+pub mod sid_player;
+pub mod sid_registry;
+pub mod sound_fx;
@@ -1,57 +1,139 @@
 use crate::{instr_robsid::RobEffects, instr_sid::SidVoice, prelude::*};
+use alloc::string::{String, ToString};
 use alloc::{vec, vec::Vec};
 
+use bincode::error::DecodeError;
+
+use super::hubbard_scanner::HubbardScanner;
+use super::psid_file::PsidFile;
+use super::sid_registry::{KnownSid, SidRegistry};
+
 use super::pattern_helper::PatternHelper;
 use super::sid_module::SidModule;
 use super::sound_fx::SoundFx;
 
+/// Rob Hubbard driver tables, as C64 addresses
+#[derive(Default, Clone, Debug)]
+pub struct HubbardDriver {
+    pub version: usize,
+    pub song_track_qty: usize,
+    pub song_list_offset: usize,
+    pub song_list_qty: usize,
+    pub patt_ptl_offset: usize,
+    pub patt_pth_offset: usize,
+    pub patt_qty: usize,
+    pub instr_offset: usize,
+    pub instr_qty: usize,
+    pub fx_v1_offset: usize,
+    pub fx_v1_qty: usize,
+    pub fx_v2_offset: usize,
+    pub fx_v2_qty: usize,
+    pub resetspd: usize,
+    pub skydive_v1_when: usize, // When length > skydive_v1_when
+    pub skydive_v1_add: isize,  // XXX if freq+skydive_v1_add<0x10000
+}
+
 #[derive(Clone, Debug)]
 pub struct OneSid {
-    pub song: &'static [u8],
-    pub name: &'static str,
-    pub author: &'static str,
-    pub copyright: &'static str,
-    version: usize,
-    load_adress: usize,
-    song_track_qty: usize,
-    song_list_offset: usize,
-    song_list_qty: usize,
-    patt_ptl_offset: usize,
-    patt_pth_offset: usize,
-    patt_qty: usize,
-    instr_offset: usize,
-    instr_qty: usize,
-    fx_v1_offset: usize,
-    fx_v1_qty: usize,
-    fx_v2_offset: usize,
-    fx_v2_qty: usize,
-    pub resetspd: usize,
-    skydive_v1_when: usize, // When length > skydive_v1_when
-    skydive_v1_add: isize,  // XXX if freq+skydive_v1_add<0x10000
+    pub psid: PsidFile,
+    pub name: String,
+    pub author: String,
+    pub copyright: String,
+    pub driver: HubbardDriver,
 }
 
 impl OneSid {
-    pub fn to_sidmodule(&self) -> SidModule {
+    /// Use PSID header strings with the given driver tables
+    pub fn new(psid: PsidFile, driver: HubbardDriver) -> Self {
+        OneSid {
+            name: psid.name.clone(),
+            author: psid.author.clone(),
+            copyright: psid.released.clone(),
+            psid,
+            driver,
+        }
+    }
+
+    /// Find the Rob Hubbard driver tables in any PSID file
+    pub fn detect(psid: PsidFile) -> Result<Self, DecodeError> {
+        match HubbardScanner::scan(&psid).into_iter().next() {
+            Some(driver) => Ok(OneSid::new(psid, driver)),
+            None => Err(DecodeError::Other("Rob Hubbard driver not found")),
+        }
+    }
+
+    /// One `OneSid` for each Rob Hubbard player found in the file
+    pub fn detect_all(psid: PsidFile) -> Vec<Self> {
+        HubbardScanner::scan(&psid)
+            .into_iter()
+            .map(|driver| OneSid::new(psid.clone(), driver))
+            .collect()
+    }
+
+    /// Use the strings and driver tables of a known tune
+    pub fn from_known(psid: PsidFile, known: &KnownSid) -> Self {
+        OneSid {
+            psid,
+            name: known.name.to_string(),
+            author: known.author.to_string(),
+            copyright: known.copyright.to_string(),
+            driver: known.driver.clone(),
+        }
+    }
+
+    /// Known tunes use the registry, others are detected
+    pub fn from_psid(psid: PsidFile) -> Vec<Self> {
+        let known = SidRegistry::find(&psid);
+        if known.is_empty() {
+            OneSid::detect_all(psid)
+        } else {
+            known
+                .into_iter()
+                .map(|k| OneSid::from_known(psid.clone(), k))
+                .collect()
+        }
+    }
+
+    #[cfg(feature = "sid_songs")]
+    fn bundled(song: &'static [u8], name: &str) -> Self {
+        let psid = PsidFile::load(song).unwrap();
+        OneSid::from_known(psid, SidRegistry::get(name).unwrap())
+    }
+}
+
+impl OneSid {
+    /// Read the driver tables, an error if they are out of the C64 memory
+    pub fn to_sidmodule(&self) -> Result<SidModule, DecodeError> {
+        let memory = self.psid.memory();
+        let mem = |at: usize| {
+            memory.get(at).copied().ok_or(DecodeError::Other(
+                "Rob Hubbard table is out of the C64 memory",
+            ))
+        };
+        let drv = &self.driver;
+        if !(1..=3).contains(&drv.song_track_qty) {
+            return Err(DecodeError::Other("Rob Hubbard songs have 1 to 3 channels"));
+        }
+
         // ********* CHANNELS
         let mut channels_ptr: Vec<usize> = vec![];
-        for i in 0..self.song_list_qty {
-            let offset =
-                126 + self.song_list_offset - self.load_adress + i * 2 * self.song_track_qty;
-            for j in 0..self.song_track_qty {
+        for i in 0..drv.song_list_qty {
+            let offset = drv.song_list_offset + i * 2 * drv.song_track_qty;
+            for j in 0..drv.song_track_qty {
                 channels_ptr.push(
-                    self.song[offset + j] as usize
-                        | (self.song[offset + j + self.song_track_qty] as usize) << 8,
+                    mem(offset + j)? as usize
+                        | (mem(offset + j + drv.song_track_qty)? as usize) << 8,
                 );
             }
         }
 
         let mut channels: Vec<Vec<u8>> = vec![];
         for offset in &channels_ptr {
-            let file_offset = *offset as usize + 126 - self.load_adress;
+            let file_offset = *offset;
             let mut tracks: Vec<u8> = vec![];
             let mut j = 0;
-            while self.song[file_offset + j] & 0x80 == 0 {
-                tracks.push(self.song[file_offset + j]);
+            while mem(file_offset + j)? & 0x80 == 0 {
+                tracks.push(mem(file_offset + j)?);
                 j += 1;
             }
             if self.name == "Delta" {
@@ -65,13 +147,16 @@ impl OneSid {
             channels.push(tracks);
         }
 
+        if channels.iter().any(|c| c.is_empty()) {
+            return Err(DecodeError::Other("Rob Hubbard song has an empty channel"));
+        }
+
         // ********* SONGS
         let mut songs: Vec<Vec<usize>> = vec![];
-        for i in 0..self.song_list_qty {
+        for i in 0..drv.song_list_qty {
             let mut s: Vec<usize> = vec![];
-            for j in 0..self.song_track_qty {
-                //let k = channels_ptr[i * self.song_track_qty + j] + 126 - self.load_adress;
-                let k = channels_ptr[i * self.song_track_qty + j];
+            for j in 0..drv.song_track_qty {
+                let k = channels_ptr[i * drv.song_track_qty + j];
                 let index = match channels_ptr.iter().position(|&x| x == k) {
                     Some(index) => index,
                     None => 0,
@@ -83,22 +168,22 @@ impl OneSid {
 
         // ********* TRACKS
         let mut tracks_ptr: Vec<u16> = vec![];
-        for i in 0..self.patt_qty {
-            let offset_low_file = 126 + self.patt_ptl_offset - self.load_adress;
-            let offset_high_file = 126 + self.patt_pth_offset - self.load_adress;
-            let offset: u16 = self.song[offset_low_file + i] as u16
-                | (self.song[offset_high_file + i] as u16) << 8;
+        for i in 0..drv.patt_qty {
+            let offset_low_file = drv.patt_ptl_offset;
+            let offset_high_file = drv.patt_pth_offset;
+            let offset: u16 =
+                mem(offset_low_file + i)? as u16 | (mem(offset_high_file + i)? as u16) << 8;
             tracks_ptr.push(offset);
         }
 
         let mut tracks: Vec<Vec<u8>> = vec![];
-        for i in 0..self.patt_qty {
-            let file_offset = 126 + tracks_ptr[i] as usize - self.load_adress;
+        for i in 0..drv.patt_qty {
+            let file_offset = tracks_ptr[i] as usize;
             let mut track: Vec<u8> = vec![];
             let mut j = 0;
             loop {
-                track.push(self.song[file_offset + j]);
-                if self.song[file_offset + j] == 0xff {
+                track.push(mem(file_offset + j)?);
+                if mem(file_offset + j)? == 0xff {
                     break;
                 }
                 j += 1;
@@ -106,63 +191,67 @@ impl OneSid {
             tracks.push(track);
         }
 
+        if tracks.is_empty() && !channels.is_empty() {
+            return Err(DecodeError::Other("Rob Hubbard song has no pattern"));
+        }
+
         // ********* INSTRUMENTS
 
         let mut instruments: Vec<InstrRobSid> = vec![];
-        for i in 0..self.instr_qty {
-            let file_offset = 126 + self.instr_offset - self.load_adress;
+        for i in 0..drv.instr_qty {
+            let file_offset = drv.instr_offset;
             let start = file_offset + i * 8;
 
             let mut voice = SidVoice::default();
-            voice.pw = self.song[start] as u16 | (self.song[start + 1] as u16) << 8;
-            voice.update_from_ctrl_register(self.song[start + 2]);
-            voice.ad = self.song[start + 3];
-            voice.sr = self.song[start + 4];
+            voice.pw = mem(start)? as u16 | (mem(start + 1)? as u16) << 8;
+            voice.update_from_ctrl_register(mem(start + 2)?);
+            voice.ad = mem(start + 3)?;
+            voice.sr = mem(start + 4)?;
 
             let mut isid = InstrSid::default();
             isid.voice[0] = voice;
 
             let mut re = RobEffects::default();
-            if self.version == 10 {
-                re.vibrato_depth = self.song[start + 5] << 2; // original seems 0..3
+            if drv.version == 10 {
+                re.vibrato_depth = mem(start + 5)? << 2; // original seems 0..3
                 re.vibrato_div = 7;
             } else {
-                re.vibrato_depth = (self.song[start + 5] & 0b0_1111_000) >> 3;
-                re.vibrato_div = self.song[start + 5] & 0b0000_111;
+                re.vibrato_depth = (mem(start + 5)? & 0b0_1111_000) >> 3;
+                re.vibrato_div = mem(start + 5)? & 0b0000_111;
             }
             if re.vibrato_depth != 0 {
                 re.vibrato = true;
             }
 
-            let mask_lo: u8 = if let 15 = self.version {
+            let mask_lo: u8 = if let 15 = drv.version {
                 0b0000_1111
             } else {
                 0b000_11111
             };
-            let mask_hi: u8 = if let 15 = self.version {
+            let mask_hi: u8 = if let 15 = drv.version {
                 0b1111_0000
             } else {
                 0b111_00000
             };
 
-            re.pw_delay = (self.song[start + 6] & mask_lo) as u16;
-            re.pw_speed = (self.song[start + 6] & mask_hi) as i8;
+            re.pw_delay = (mem(start + 6)? & mask_lo) as u16;
+            re.pw_speed = (mem(start + 6)? & mask_hi) as i8;
 
             //FIXME
-            let _commando_change_pw_effect = (self.song[start + 7] & 0b0000_1000) != 0;
-            let _fx_use = (self.song[start + 7] & 0b00_111_000) >> 3;
-            if self.song[start + 7] & 0b0000_0001 != 0 {
+            let _commando_change_pw_effect = (mem(start + 7)? & 0b0000_1000) != 0;
+            let _fx_use = (mem(start + 7)? & 0b00_111_000) >> 3;
+            if mem(start + 7)? & 0b0000_0001 != 0 {
                 re.drum = true;
             }
-            if self.song[start + 7] & 0b0000_0010 != 0 {
+            if mem(start + 7)? & 0b0000_0010 != 0 {
                 re.skydive = true;
             }
-            if self.song[start + 7] & 0b0000_0100 != 0 {
+            if mem(start + 7)? & 0b0000_0100 != 0 {
                 re.arpeggio = true;
             }
 
-            re.skydive_config_if = self.skydive_v1_when as u8;
-            re.skydive_config_add = self.skydive_v1_add as u8;
+            re.skydive_config_if = drv.skydive_v1_when as u8;
+            re.skydive_config_add = drv.skydive_v1_add as u8;
 
             let mut irsid = InstrRobSid::default();
             irsid.sid = isid;
@@ -173,37 +262,36 @@ impl OneSid {
 
         // ********* SOUNDFX
         let mut sfxs: Vec<SoundFx> = vec![];
-        for i in 0..self.fx_v1_qty {
-            let file_offset = 126 + self.fx_v1_offset - self.load_adress;
+        for i in 0..drv.fx_v1_qty {
+            let file_offset = drv.fx_v1_offset;
             let start = file_offset + i * 16;
 
             // play current music freq at end of incdec_counter counter
-            let incdec_start_at_end = (self.song[start] & 0b1000_0000) == 0;
-            let incdec_counter = if (self.song[start] & 0b0011_0000) == 0b0010_0000 {
-                self.song[start] as i8 & 0b0000_1111
+            let incdec_start_at_end = (mem(start)? & 0b1000_0000) == 0;
+            let incdec_counter = if (mem(start)? & 0b0011_0000) == 0b0010_0000 {
+                mem(start)? as i8 & 0b0000_1111
             } else {
-                -(self.song[start] as i8 & 0b0000_1111)
+                -(mem(start)? as i8 & 0b0000_1111)
             };
-            let note_start = self.song[start + 1]; // as u16|(self.song[start+2] as u16)<<8;
-            let note_delta = self.song[start + 8] & 0b0011_1111;
-            let note_end = self.song[start + 15] & 0b00_111111;
+            let note_start = mem(start + 1)?; // as u16|(mem(start+2)? as u16)<<8;
+            let note_delta = mem(start + 8)? & 0b0011_1111;
+            let note_end = mem(start + 15)? & 0b00_111111;
             let flipflop_voice1_ctrl =
-                ((self.song[start + 8] as u16 | (self.song[start + 9] as u16) << 8) & 0b0100_0000)
-                    != 0;
-            let voice0_ctrl = (self.song[start + 15] & 0b1000_0000) != 0;
-            let voice1_ctrl = (self.song[start + 15] & 0b0100_0000) != 0;
+                ((mem(start + 8)? as u16 | (mem(start + 9)? as u16) << 8) & 0b0100_0000) != 0;
+            let voice0_ctrl = (mem(start + 15)? & 0b1000_0000) != 0;
+            let voice1_ctrl = (mem(start + 15)? & 0b0100_0000) != 0;
 
             let mut voice0 = SidVoice::default();
-            voice0.pw = self.song[start + 3] as u16 | (self.song[start + 4] as u16) << 8;
-            voice0.update_from_ctrl_register(self.song[start + 5]);
-            voice0.ad = self.song[start + 6];
-            voice0.sr = self.song[start + 7];
+            voice0.pw = mem(start + 3)? as u16 | (mem(start + 4)? as u16) << 8;
+            voice0.update_from_ctrl_register(mem(start + 5)?);
+            voice0.ad = mem(start + 6)?;
+            voice0.sr = mem(start + 7)?;
 
             let mut voice1 = SidVoice::default();
-            voice1.pw = self.song[start + 10] as u16 | (self.song[start + 11] as u16) << 8;
-            voice0.update_from_ctrl_register(self.song[start + 12]);
-            voice1.ad = self.song[start + 13];
-            voice1.sr = self.song[start + 14];
+            voice1.pw = mem(start + 10)? as u16 | (mem(start + 11)? as u16) << 8;
+            voice1.update_from_ctrl_register(mem(start + 12)?);
+            voice1.ad = mem(start + 13)?;
+            voice1.sr = mem(start + 14)?;
 
             let sfx = SoundFx {
                 incdec_start_at_end,
@@ -220,379 +308,357 @@ impl OneSid {
             sfxs.push(sfx);
         }
 
-        SidModule {
+        Ok(SidModule {
             sid: self.clone(),
-            pattern_helper: PatternHelper::new(self.version, songs, channels, tracks),
+            pattern_helper: PatternHelper::new(drv.version, songs, channels, tracks),
             instruments,
             soundfx: sfxs,
-        }
+        })
     }
 }
 
 impl OneSid {
+    #[cfg(feature = "sid_songs")]
     pub fn get_sid_commando() -> Self {
-        OneSid {
-            song: include_bytes!("songs/commando.sid"),
-            name: "Commando",
-            author: "Rob Hubbard",
-            copyright: "1985 Elite",
-            version: 10,
-            load_adress: 0x5000,
-            song_track_qty: 3,
-            song_list_offset: 0x56FF,
-            song_list_qty: 3,
-            patt_ptl_offset: 0x5711,
-            patt_pth_offset: 0x573E,
-            patt_qty: 45,
-            instr_offset: 0x5591,
-            instr_qty: 13,
-            fx_v1_offset: 0x55F9,
-            fx_v1_qty: 16,
-            fx_v2_offset: 0,
-            fx_v2_qty: 0,
-            resetspd: 2, // [2, 3, 2]
-            skydive_v1_when: 2,
-            skydive_v1_add: 512,
-        }
+        OneSid::bundled(include_bytes!("songs/commando.sid"), "Commando")
     }
 
+    #[cfg(feature = "sid_songs")]
     pub fn get_sid_crazy_comets() -> Self {
-        OneSid {
-            song: include_bytes!("songs/crazy_comets.sid"),
-            name: "Crazy Comets",
-            author: "Rob Hubbard",
-            copyright: "1985 Martech",
-            version: 10,
-            load_adress: 0x5000,
-            song_track_qty: 3,
-            song_list_offset: 0x5732,
-            song_list_qty: 2,
-            patt_ptl_offset: 0x573E,
-            patt_pth_offset: 0x5773,
-            patt_qty: 53,
-            instr_offset: 0x5574,
-            instr_qty: 23,
-            fx_v1_offset: 0x562C,
-            fx_v1_qty: 1,
-            fx_v2_offset: 0,
-            fx_v2_qty: 0,
-            resetspd: 2,
-            skydive_v1_when: 16,
-            skydive_v1_add: -256,
-        }
+        OneSid::bundled(include_bytes!("songs/crazy_comets.sid"), "Crazy Comets")
     }
 
+    #[cfg(feature = "sid_songs")]
     pub fn get_sid_last_v8() -> Self {
-        OneSid {
-            song: include_bytes!("songs/last_v8.sid"),
-            name: "The Last V8",
-            author: "Rob Hubbard",
-            copyright: "1985 MAD/Mastertronic",
-            version: 10,
-            load_adress: 0x8010,
-            song_track_qty: 3,
-            song_list_offset: 0x8797,
-            song_list_qty: 3,
-            patt_ptl_offset: 0x87A9,
-            patt_pth_offset: 0x87C6,
-            patt_qty: 28,
-            instr_offset: 0x85A1,
-            instr_qty: 19,
-            fx_v1_offset: 0x8699,
-            fx_v1_qty: 12,
-            fx_v2_offset: 0,
-            fx_v2_qty: 0,
-            resetspd: 1,
-            skydive_v1_when: 0,
-            skydive_v1_add: -256,
-        }
+        OneSid::bundled(include_bytes!("songs/last_v8.sid"), "The Last V8")
     }
 
+    #[cfg(feature = "sid_songs")]
     pub fn get_sid_monty_on_the_run() -> Self {
-        OneSid {
-            song: include_bytes!("songs/monty_on_the_run.sid"),
-            name: "Monty on the Run",
-            author: "Rob Hubbard",
-            copyright: "1985 Gremlin Graphics",
-            version: 10,
-            load_adress: 0x8000,
-            song_track_qty: 3,
-            song_list_offset: 0x856C,
-            song_list_qty: 3,
-            patt_ptl_offset: 0x857E,
-            patt_pth_offset: 0x85CB,
-            patt_qty: 77,
-            instr_offset: 0x93B4,
-            instr_qty: 20,
-            fx_v1_offset: 0x9454,
-            fx_v1_qty: 16,
-            fx_v2_offset: 0,
-            fx_v2_qty: 0,
-            resetspd: 1,
-            skydive_v1_when: 0,
-            skydive_v1_add: -256,
-        }
+        OneSid::bundled(
+            include_bytes!("songs/monty_on_the_run.sid"),
+            "Monty on the Run",
+        )
     }
 
+    #[cfg(feature = "sid_songs")]
     pub fn get_sid_thing_on_a_spring() -> Self {
-        OneSid {
-            song: include_bytes!("songs/thing_on_a_spring.sid"),
-            name: "Thing on a Spring",
-            author: "Rob Hubbard",
-            copyright: "1985 Gremlin Graphics",
-            version: 10,
-            load_adress: 0xC000,
-            song_track_qty: 3,
-            song_list_offset: 0xC509,
-            song_list_qty: 1,
-            patt_ptl_offset: 0xC50F,
-            patt_pth_offset: 0xC533,
-            patt_qty: 36,
-            instr_offset: 0xCD2A,
-            instr_qty: 45,
-            fx_v1_offset: 0xCE92,
-            fx_v1_qty: 1,
-            fx_v2_offset: 0,
-            fx_v2_qty: 0,
-            resetspd: 1,
-            skydive_v1_when: 0,
-            skydive_v1_add: 0,
-        }
+        OneSid::bundled(
+            include_bytes!("songs/thing_on_a_spring.sid"),
+            "Thing on a Spring",
+        )
     }
 
+    #[cfg(feature = "sid_songs")]
     pub fn get_sid_zoid() -> Self {
-        OneSid {
-            song: include_bytes!("songs/zoids.sid"),
-            name: "Zoids",
-            author: "Rob Hubbard",
-            copyright: "1986 Martech",
-            version: 10,
-            load_adress: 0x1000,
-            song_track_qty: 3,
-            song_list_offset: 0x14FC,
-            song_list_qty: 3,
-            patt_ptl_offset: 0x150E,
-            patt_pth_offset: 0x152D,
-            patt_qty: 31,
-            instr_offset: 0x147E,
-            instr_qty: 15,
-            fx_v1_offset: 0,
-            fx_v1_qty: 0,
-            fx_v2_offset: 0,
-            fx_v2_qty: 0,
-            resetspd: 1,
-            skydive_v1_when: 0,
-            skydive_v1_add: 0,
-        }
+        OneSid::bundled(include_bytes!("songs/zoids.sid"), "Zoids")
     }
 
     //------------------------------------------
 
+    #[cfg(feature = "sid_songs")]
     pub fn get_sid_ace_2() -> Self {
-        OneSid {
-            song: include_bytes!("songs/ace_2.sid"),
-            name: "ACE II",
-            author: "Rob Hubbard",
-            copyright: "1987 Arcade",
-            version: 20,
-            load_adress: 0xE000,
-            song_track_qty: 3,
-            song_list_offset: 0xE67C,
-            song_list_qty: 1,
-            patt_ptl_offset: 0xE682,
-            patt_pth_offset: 0xE6A8,
-            patt_qty: 38,
-            instr_offset: 0xE5CB,
-            instr_qty: 10,
-            fx_v1_offset: 0,
-            fx_v1_qty: 0,
-            fx_v2_offset: 0,
-            fx_v2_qty: 0,
-            resetspd: 1,
-            skydive_v1_when: 0,
-            skydive_v1_add: 0,
-        }
+        OneSid::bundled(include_bytes!("songs/ace_2.sid"), "ACE II")
     }
 
+    #[cfg(feature = "sid_songs")]
     pub fn get_sid_delta() -> Self {
-        OneSid {
-            song: include_bytes!("songs/delta.sid"),
-            name: "Delta",
-            author: "Rob Hubbard",
-            copyright: "1987 Thalamus",
-            version: 30, // Compression _and_ pattern loop in channels
-            load_adress: 0xBC00,
-            song_track_qty: 3,
-            song_list_offset: 0xC4F4,
-            song_list_qty: 13,
-            patt_ptl_offset: 0xC542,
-            patt_pth_offset: 0xC5AF,
-            patt_qty: 109,
-            instr_offset: 0xC38E,
-            instr_qty: 22,
-            fx_v1_offset: 0,
-            fx_v1_qty: 0,
-            fx_v2_offset: 0xC43E,
-            fx_v2_qty: 22,
-            resetspd: 1,
-            skydive_v1_when: 0,
-            skydive_v1_add: 0,
-        }
+        OneSid::bundled(include_bytes!("songs/delta.sid"), "Delta")
     }
 
+    #[cfg(feature = "sid_songs")]
     pub fn get_sid_human_race() -> Self {
-        OneSid {
-            song: include_bytes!("songs/human_race.sid"),
-            name: "The Human Race",
-            author: "Rob Hubbard",
-            copyright: "1985 Mastertronic",
-            version: 20,
-            load_adress: 0x0980,
-            song_track_qty: 2,
-            song_list_offset: 0x0E9F,
-            song_list_qty: 3,
-            patt_ptl_offset: 0x0EB3,
-            patt_pth_offset: 0x0F02,
-            patt_qty: 58,
-            instr_offset: 0x0DE3,
-            instr_qty: 23,
-            fx_v1_offset: 0,
-            fx_v1_qty: 0,
-            fx_v2_offset: 0,
-            fx_v2_qty: 0,
-            resetspd: 3,
-            skydive_v1_when: 0,
-            skydive_v1_add: -256,
-        }
+        OneSid::bundled(include_bytes!("songs/human_race.sid"), "The Human Race")
     }
 
+    #[cfg(feature = "sid_songs")]
     pub fn get_sid_international_karate() -> Self {
-        OneSid {
-            song: include_bytes!("songs/international_karate.sid"),
-            name: "International Karate",
-            author: "Rob Hubbard",
-            copyright: "1986 System 3",
-            version: 20,
-            load_adress: 0xAE00,
-            song_track_qty: 3,
-            song_list_offset: 0xB3B0,
-            song_list_qty: 1,
-            patt_ptl_offset: 0xB3B6,
-            patt_pth_offset: 0xB3EB,
-            patt_qty: 53,
-            instr_offset: 0xB308,
-            instr_qty: 20,
-            fx_v1_offset: 0,
-            fx_v1_qty: 0,
-            fx_v2_offset: 0,
-            fx_v2_qty: 0,
-            resetspd: 2,
-            skydive_v1_when: 0,
-            skydive_v1_add: 0,
-        }
+        OneSid::bundled(
+            include_bytes!("songs/international_karate.sid"),
+            "International Karate",
+        )
     }
 
+    #[cfg(feature = "sid_songs")]
     pub fn get_sid_lightforce() -> Self {
-        OneSid {
-            song: include_bytes!("songs/lightforce.sid"),
-            name: "Lightforce",
-            author: "Rob Hubbard",
-            copyright: "1986 Faster Than Light (FTL)",
-            version: 20,
-            load_adress: 0xF000,
-            song_track_qty: 3,
-            song_list_offset: 0xF778,
-            song_list_qty: 1,
-            patt_ptl_offset: 0xF77E,
-            patt_pth_offset: 0xF79D,
-            patt_qty: 31,
-            instr_offset: 0xF618,
-            instr_qty: 22,
-            fx_v1_offset: 0,
-            fx_v1_qty: 0,
-            fx_v2_offset: 0xF6C8,
-            fx_v2_qty: 22,
-            resetspd: 2,
-            skydive_v1_when: 0,
-            skydive_v1_add: 0,
-        }
+        OneSid::bundled(include_bytes!("songs/lightforce.sid"), "Lightforce")
     }
 
+    #[cfg(feature = "sid_songs")]
     pub fn get_sid_sanxion_song_1() -> Self {
-        OneSid {
-            song: include_bytes!("songs/sanxion.sid"),
-            name: "Sanxion Song 1",
-            author: "Rob Hubbard",
-            copyright: "1986 Thalamus",
-            version: 20,
-            load_adress: 0xB000,
-            song_track_qty: 3,
-            song_list_offset: 0xB73C,
-            song_list_qty: 1,
-            patt_ptl_offset: 0xB742,
-            patt_pth_offset: 0xB75D,
-            patt_qty: 27,
-            instr_offset: 0xB56C,
-            instr_qty: 29,
-            fx_v1_offset: 0,
-            fx_v1_qty: 0,
-            fx_v2_offset: 0xB654,
-            fx_v2_qty: 29,
-            resetspd: 2,
-            skydive_v1_when: 0,
-            skydive_v1_add: 0,
-        }
+        OneSid::bundled(include_bytes!("songs/sanxion.sid"), "Sanxion Song 1")
     }
 
+    #[cfg(feature = "sid_songs")]
     pub fn get_sid_sanxion_song_2() -> Self {
-        OneSid {
-            song: include_bytes!("songs/sanxion.sid"),
-            name: "Sanxion Song 2",
-            author: "Rob Hubbard",
-            copyright: "1986 Thalamus",
+        OneSid::bundled(include_bytes!("songs/sanxion.sid"), "Sanxion Song 2")
+    }
+
+    #[cfg(feature = "sid_songs")]
+    pub fn get_sid_spellbound() -> Self {
+        OneSid::bundled(include_bytes!("songs/spellbound.sid"), "Spellbound")
+    }
+
+    #[cfg(feature = "sid_songs")]
+    pub fn get_sid_thrust() -> Self {
+        let psid = PsidFile::load(include_bytes!("songs/thrust.sid")).unwrap();
+        OneSid::detect(psid).unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn psid(data: Vec<u8>) -> PsidFile {
+        PsidFile {
+            version: 2,
+            load_address: 0x1000,
+            data,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn tables_out_of_memory_are_errors() {
+        let driver = HubbardDriver {
             version: 20,
-            load_adress: 0xB000,
             song_track_qty: 3,
-            song_list_offset: 0xC5F5,
+            song_list_offset: 0xFFFE,
             song_list_qty: 1,
-            patt_ptl_offset: 0xC5FB,
-            patt_pth_offset: 0xC633,
-            patt_qty: 56,
-            instr_offset: 0xC4B5,
-            instr_qty: 20,
-            fx_v1_offset: 0,
-            fx_v1_qty: 0,
-            fx_v2_offset: 0xC5F5,
-            fx_v2_qty: 20,
-            resetspd: 2,
-            skydive_v1_when: 0,
-            skydive_v1_add: 0,
+            ..Default::default()
+        };
+        let sid = OneSid::new(psid(vec![0; 0x100]), driver);
+        assert!(sid.to_sidmodule().is_err());
+
+        // a channel never ends
+        let driver = HubbardDriver {
+            version: 20,
+            song_track_qty: 1,
+            song_list_offset: 0x1000,
+            song_list_qty: 1,
+            ..Default::default()
+        };
+        let mut data = vec![0u8; 0xF000];
+        data[0] = 0x10;
+        data[1] = 0x10;
+        let sid = OneSid::new(psid(data), driver);
+        assert!(sid.to_sidmodule().is_err());
+    }
+
+    #[test]
+    fn random_tables_do_not_panic() {
+        let mut seed: u32 = 0x1234_5678;
+        let mut random = move || {
+            seed ^= seed << 13;
+            seed ^= seed >> 17;
+            seed ^= seed << 5;
+            seed
+        };
+        let mut loaded = 0;
+        for i in 0..300 {
+            // tracks end with 0xFF
+            let mut data: Vec<u8> = (0..0x400)
+                .map(|j| if j % 32 == 31 { 0xFF } else { random() as u8 })
+                .collect();
+            // song list at 0x1000, pattern pointers at 0x1010 and 0x1020
+            let mut pointer = |at: usize, data: &mut Vec<u8>, qty: usize| {
+                let address = 0x1100 + (random() % 0x300) as u16;
+                data[at] = address as u8;
+                data[at + qty] = (address >> 8) as u8;
+            };
+            for j in 0..3 {
+                pointer(j, &mut data, 3);
+            }
+            for j in 0..16 {
+                pointer(0x10 + j, &mut data, 16);
+            }
+            let driver = HubbardDriver {
+                version: [10, 15, 20, 30][i % 4],
+                song_track_qty: 3,
+                song_list_offset: 0x1000,
+                song_list_qty: 1,
+                patt_ptl_offset: 0x1010,
+                patt_pth_offset: 0x1020,
+                patt_qty: 16,
+                instr_offset: 0x1040,
+                instr_qty: 8,
+                fx_v1_offset: 0x1080,
+                fx_v1_qty: 4,
+                ..Default::default()
+            };
+            if let Ok(sid) = OneSid::new(psid(data), driver).to_sidmodule() {
+                loaded += 1;
+                sid.to_modules(false);
+                sid.soundfx_to_modules(false);
+            }
         }
+        assert!(loaded > 10);
     }
 
-    pub fn get_sid_spellbound() -> Self {
-        OneSid {
-            song: include_bytes!("songs/spellbound.sid"),
-            name: "Spellbound",
-            author: "Rob Hubbard",
-            copyright: "1986 MAD/Mastertronic",
-            version: 15, // XXX WARN: 0xE0E5 second byte & 0b1000_0000 is instrnr: not a vibrato! no vibrato. Using version 15 -- check soundfx too
-            load_adress: 0xE000,
-            song_track_qty: 3,
-            song_list_offset: 0xE6B6,
-            song_list_qty: 3,
-            patt_ptl_offset: 0xE6C8,
-            patt_pth_offset: 0xE6F2,
-            patt_qty: 42,
-            instr_offset: 0xE548,
-            instr_qty: 25,
-            fx_v1_offset: 0xE610,
-            fx_v1_qty: 16,
-            fx_v2_offset: 0,
-            fx_v2_qty: 0,
-            resetspd: 1,
-            skydive_v1_when: 0,
-            skydive_v1_add: 256,
+    #[test]
+    fn bundled_tunes_load() {
+        let songs: [&[u8]; 14] = [
+            include_bytes!("songs/ace_2.sid"),
+            include_bytes!("songs/commando.sid"),
+            include_bytes!("songs/crazy_comets.sid"),
+            include_bytes!("songs/delta.sid"),
+            include_bytes!("songs/human_race.sid"),
+            include_bytes!("songs/international_karate.sid"),
+            include_bytes!("songs/last_v8.sid"),
+            include_bytes!("songs/lightforce.sid"),
+            include_bytes!("songs/monty_on_the_run.sid"),
+            include_bytes!("songs/sanxion.sid"),
+            include_bytes!("songs/spellbound.sid"),
+            include_bytes!("songs/thing_on_a_spring.sid"),
+            include_bytes!("songs/thrust.sid"),
+            include_bytes!("songs/zoids.sid"),
+        ];
+        for song in songs {
+            let sids = SidModule::load_all(song).unwrap();
+            assert!(!sids.is_empty());
+            for sid in sids {
+                assert!(!sid.to_modules(false).is_empty());
+            }
         }
     }
+
+    #[test]
+    fn sound_effects_become_modules() {
+        let mut data = vec![0u8; 0x300];
+        // one song of one channel playing track 0, an empty track
+        data[0x000] = 0x00;
+        data[0x001] = 0x11;
+        data[0x010] = 0x20;
+        data[0x011] = 0x11;
+        data[0x100] = 0x00;
+        data[0x101] = 0xFF;
+        data[0x120] = 0xFF;
+        let fx: [[u8; 16]; 3] = [
+            // 8 frames per note, C-0 up to D#5 on voice 0
+            [
+                0x28,
+                0,
+                0,
+                0x00,
+                0x08,
+                0x41,
+                0x09,
+                0xA0,
+                1,
+                0,
+                0,
+                0,
+                0,
+                0,
+                0,
+                0x80 | 63,
+            ],
+            // every frame, E-3 down to C-3 on voice 1, gate flip-flop
+            [
+                0x21,
+                40,
+                0,
+                0,
+                0,
+                0,
+                0,
+                0,
+                0x40 | 2,
+                0,
+                0x00,
+                0x04,
+                0x41,
+                0x0A,
+                0xB0,
+                0x40 | 36,
+            ],
+            // one note on both voices
+            [
+                0x21,
+                12,
+                0,
+                0,
+                0,
+                0x11,
+                0,
+                0,
+                0,
+                0,
+                0,
+                0,
+                0x21,
+                0,
+                0,
+                0xC0 | 12,
+            ],
+        ];
+        for (i, f) in fx.iter().enumerate() {
+            data[0x200 + 16 * i..0x210 + 16 * i].copy_from_slice(f);
+        }
+        let mut psid = psid(data);
+        psid.name = "Effects".into();
+        let driver = HubbardDriver {
+            version: 10,
+            song_track_qty: 1,
+            song_list_offset: 0x1000,
+            song_list_qty: 1,
+            patt_ptl_offset: 0x1010,
+            patt_pth_offset: 0x1011,
+            patt_qty: 1,
+            fx_v1_offset: 0x1200,
+            fx_v1_qty: 3,
+            ..Default::default()
+        };
+        let sid = OneSid::new(psid, driver).to_sidmodule().unwrap();
+        let modules = sid.soundfx_to_modules(true);
+        assert_eq!(modules.len(), 3);
+        let names: Vec<&str> = modules.iter().map(|m| m.name.as_str()).collect();
+        assert_eq!(names, vec!["Effects fx 0", "Effects fx 1", "Effects fx 2"]);
+
+        let rows = |m: &Module| -> Vec<Row> {
+            m.pattern_order
+                .iter()
+                .flat_map(|&p| m.pattern[p].clone())
+                .collect()
+        };
+
+        // 64 notes of 8 frames and the release, split in patterns of 256 rows
+        let m = &modules[0];
+        assert_eq!(m.default_tempo, 1);
+        assert_eq!(m.pattern_order.len(), 3);
+        assert!(m.pattern.iter().all(|p| p.len() <= 256));
+        let all = rows(m);
+        assert_eq!(all.len(), 64 * 8 + 1);
+        assert_eq!(all[0][0].note, Note::C0);
+        assert_eq!(all[8][0].note, Note::Cs0);
+        assert_eq!(all[63 * 8][0].note, Note::Ds5);
+        assert!(all[..64 * 8].iter().all(|r| r[1].note.is_none()));
+        assert_eq!(all[64 * 8][0].note, Note::KeyOff);
+        let InstrumentType::RobSid(instr) = &m.instrument[0].instr_type else {
+            panic!("not a Rob Hubbard instrument");
+        };
+        assert_eq!(
+            (instr.sid.voice[0].pw, instr.sid.voice[0].ad),
+            (0x0800, 0x09)
+        );
+
+        // voice 1 only, its gate closes every other note
+        let all = rows(&modules[1]);
+        let voice1: Vec<(Note, u8)> = all.iter().map(|r| (r[1].note, r[1].instrument)).collect();
+        assert_eq!(
+            voice1,
+            vec![
+                (Note::E3, 2),
+                (Note::KeyOff, 0),
+                (Note::C3, 2),
+                (Note::KeyOff, 0)
+            ]
+        );
+        assert!(all[..3].iter().all(|r| r[0].note.is_none()));
+
+        let all = rows(&modules[2]);
+        assert_eq!(all.len(), 2);
+        assert_eq!((all[0][0].note, all[0][1].note), (Note::C1, Note::C1));
+    }
 }
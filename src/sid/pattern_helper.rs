@@ -10,6 +10,9 @@ pub struct PatternHelper {
 }
 
 impl PatternHelper {
+    /// Channels of unrelated lengths can need millions of rows to end together
+    const MAX_SONG_ROWS: usize = 1 << 16;
+
     pub fn new(
         version: usize,
         songs: Vec<Vec<usize>>,
@@ -29,6 +32,11 @@ impl PatternHelper {
         let mut index: usize = 0;
         let mut last_instr = 0;
 
+        // an event reads up to 4 bytes, a broken track stops on the padding
+        let source_len = source.len();
+        let mut source = source.clone();
+        source.extend_from_slice(&[255; 4]);
+
         while source[index] != 255 {
             let mut current = PatternSlot::default();
 
@@ -69,13 +77,9 @@ impl PatternHelper {
                                 current.instrument = 1 + source[index] & 0b0111_1111;
                                 last_instr = current.instrument;
                             } else {
-                                if index + 2 >= source.len() {
-                                    #[cfg(feature = "std")]
-                                    println!(
-                                        "Track {}, International Karate overflow?",
-                                        _track_index
-                                    );
-                                    index -= 2;
+                                if index + 2 >= source_len {
+                                    // International Karate overflow?
+                                    index = index.saturating_sub(2);
                                 } else {
                                     let p: u16 = ((source[index] as u16 & 0b0011_1111) << 8)
                                         | source[index + 1] as u16;
@@ -167,24 +171,26 @@ impl PatternHelper {
     pub fn get_patterns(&self, song_number: usize) -> Vec<Pattern> {
         let tracks = self.get_tracks();
         let pattern_order = self.get_pattern_order(song_number);
+        if tracks.is_empty() || pattern_order.iter().any(|o| o.is_empty()) {
+            return vec![];
+        }
         let po_len = pattern_order.len();
         let mut all_ok: Vec<bool> = vec![false; po_len];
         let mut i_n: [usize; 3] = [0; 3];
         let mut patterns: Vec<Pattern> = vec![];
+        let mut song_rows: usize = 0;
 
         loop {
             let mut trks: Vec<&Vec<PatternSlot>> = vec![];
             for k in 0..po_len {
                 if pattern_order[k][i_n[k]] as usize >= tracks.len() {
-                    #[cfg(feature = "std")]
-                    println!("No way!? {}", pattern_order[k][i_n[k]]);
                     // special case for commando!?
                     let what_to_do: usize = match pattern_order[k][i_n[k]] {
                         111 => 3,
                         112 => 2,
                         _ => 0,
                     };
-                    trks.push(&tracks[what_to_do]);
+                    trks.push(&tracks[what_to_do.min(tracks.len() - 1)]);
                 } else {
                     trks.push(&tracks[pattern_order[k][i_n[k]] as usize]);
                 }
@@ -202,15 +208,13 @@ impl PatternHelper {
                         }
                         j[k] = 0;
                         if pattern_order[k][i_n[k]] as usize >= tracks.len() {
-                            #[cfg(feature = "std")]
-                            println!("No way!? {}", pattern_order[k][i_n[k]]);
                             // special case for commando!?
                             let what_to_do: usize = match pattern_order[k][i_n[k]] {
                                 111 => 3,
                                 112 => 2,
                                 _ => 0,
                             };
-                            trks[k] = &tracks[what_to_do];
+                            trks[k] = &tracks[what_to_do.min(tracks.len() - 1)];
                         } else {
                             trks[k] = &tracks[pattern_order[k][i_n[k]] as usize];
                         }
@@ -218,11 +222,16 @@ impl PatternHelper {
                             trks_total_len = trks[k].len();
                         }
                     }
-                    line.push(trks[k][j[k]]);
+                    line.push(trks[k].get(j[k]).copied().unwrap_or_default());
                     j[k] += 1;
                 }
                 trks_total_len -= 1;
                 pattern.push(line);
+                song_rows += 1;
+                if song_rows >= Self::MAX_SONG_ROWS {
+                    patterns.push(pattern);
+                    return patterns;
+                }
             }
 
             patterns.push(pattern);
@@ -246,46 +255,37 @@ impl PatternHelper {
     }
 
     pub fn cleanup_patterns(source: &Vec<Pattern>) -> (Vec<Pattern>, Vec<usize>) {
-        let mut dest: Vec<Pattern> = Vec::new();
-        let mut order: Vec<usize> = Vec::new();
-        let mut seen_map: Vec<(Pattern, usize)> = Vec::new(); // Vec of (Pattern, index in dest)
-
-        for pattern in source.iter() {
-            if let Some(&(_, idx)) = seen_map.iter().find(|(p, _)| p == pattern) {
-                order.push(idx);
-            } else {
-                let new_idx = dest.len();
-                dest.push(pattern.clone());
-                seen_map.push((pattern.clone(), new_idx));
-                order.push(new_idx);
-            }
-        }
-
-        (dest, order)
+        let mut module = Module {
+            pattern: source.clone(),
+            pattern_order: (0..source.len()).collect(),
+            ..Default::default()
+        };
+        module.merge_patterns();
+        (module.pattern, module.pattern_order)
     }
+}
 
-    pub fn split_large_patterns(patterns: &mut Vec<Pattern>) {
-        let max_size = 256;
-        let mut i = 0;
-
-        while i < patterns.len() {
-            let current_pattern = &patterns[i];
-
-            if current_pattern.len() > max_size {
-                let num_splits = (current_pattern.len() + max_size - 1) / max_size;
-                let mut new_patterns = Vec::new();
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-                for j in 0..num_splits {
-                    let start = j * max_size;
-                    let end = current_pattern.len().min(start + max_size);
-                    let new_pattern = current_pattern[start..end].to_vec();
-                    new_patterns.push(new_pattern);
-                }
+    #[test]
+    fn unknown_tracks_play_the_first_one() {
+        // two notes of one row, then a note of 2 rows
+        let tracks = vec![vec![0x00, 0x40, 0x00, 0x41, 0xFF], vec![0x01, 0x42, 0xFF]];
+        let helper =
+            PatternHelper::new(20, vec![vec![0, 1]], vec![vec![56, 1], vec![1, 0]], tracks);
+        let patterns = helper.get_patterns(0);
+        assert_eq!(patterns.len(), 2);
+        assert_eq!(patterns[0].len(), 2);
+        assert_eq!(patterns[0][0].len(), 2);
+    }
 
-                patterns.splice(i..=i, new_patterns);
-            } else {
-                i += 1;
-            }
-        }
+    #[test]
+    fn empty_tables_give_no_pattern() {
+        let helper = PatternHelper::new(20, vec![vec![0]], vec![vec![0]], vec![]);
+        assert!(helper.get_patterns(0).is_empty());
+        let helper = PatternHelper::new(20, vec![vec![0]], vec![vec![]], vec![vec![0xFF]]);
+        assert!(helper.get_patterns(0).is_empty());
     }
 }
@@ -0,0 +1,259 @@
+use bincode::error::DecodeError;
+
+use alloc::string::String;
+use alloc::string::ToString;
+use alloc::{vec, vec::Vec};
+
+use super::sid_chip::{SidModel, NTSC_CLOCK, PAL_CLOCK};
+
+/// Video standard the tune was written for (PSID v2+ flags, bits 2-3)
+#[derive(Default, Clone, Copy, Debug, PartialEq)]
+pub enum PsidClock {
+    #[default]
+    Unknown,
+    Pal,
+    Ntsc,
+    PalAndNtsc,
+}
+
+/// SID model the tune was written for (PSID v2+ flags)
+#[derive(Default, Clone, Copy, Debug, PartialEq)]
+pub enum PsidModel {
+    #[default]
+    Unknown,
+    Mos6581,
+    Mos8580,
+    Mos6581And8580,
+}
+
+impl PsidModel {
+    fn from_bits(bits: u16) -> Self {
+        match bits & 0b11 {
+            1 => PsidModel::Mos6581,
+            2 => PsidModel::Mos8580,
+            3 => PsidModel::Mos6581And8580,
+            _ => PsidModel::Unknown,
+        }
+    }
+
+    /// Emulator model, 6581 by default
+    pub fn to_sid_model(&self) -> SidModel {
+        match self {
+            PsidModel::Mos8580 => SidModel::Mos8580,
+            _ => SidModel::Mos6581,
+        }
+    }
+}
+
+/// PSID/RSID file (v1 to v4)
+#[derive(Default, Clone, Debug)]
+pub struct PsidFile {
+    /// `RSID` magic: real C64 environment needed
+    pub rsid: bool,
+    pub version: u16,
+    pub data_offset: u16,
+    /// C64 address where `data` is loaded
+    pub load_address: u16,
+    pub init_address: u16,
+    /// 0 if the init routine installs its own interrupt handler
+    pub play_address: u16,
+    pub songs: u16,
+    /// 1-based
+    pub start_song: u16,
+    /// bit n: song n+1 uses CIA timer (1) or vertical blank interrupt (0)
+    pub speed: u32,
+    pub name: String,
+    pub author: String,
+    pub released: String,
+    pub flags: u16,
+    pub start_page: u8,
+    pub page_length: u8,
+    /// v3+, 0 if none, else (address - 0xD000) >> 4
+    pub second_sid_address: u8,
+    /// v4+, 0 if none, else (address - 0xD000) >> 4
+    pub third_sid_address: u8,
+    /// C64 data, without the optional 2 bytes load address
+    pub data: Vec<u8>,
+}
+
+impl PsidFile {
+    pub const HEADER_V1_SIZE: usize = 0x76;
+    pub const HEADER_V2_SIZE: usize = 0x7C;
+
+    fn be16(data: &[u8], offset: usize) -> u16 {
+        u16::from_be_bytes([data[offset], data[offset + 1]])
+    }
+
+    fn string32(data: &[u8]) -> String {
+        // Latin-1 in the original files
+        let s: String = data
+            .iter()
+            .take_while(|&&c| c != 0)
+            .map(|&c| c as char)
+            .collect();
+        s.trim().to_string()
+    }
+
+    pub fn load(ser_psid: &[u8]) -> Result<PsidFile, DecodeError> {
+        if ser_psid.len() < Self::HEADER_V1_SIZE {
+            return Err(DecodeError::Other("Not a PSID/RSID file?"));
+        }
+        let rsid = match &ser_psid[0..4] {
+            b"PSID" => false,
+            b"RSID" => true,
+            _ => return Err(DecodeError::Other("Not a PSID/RSID file?")),
+        };
+
+        let mut psid = PsidFile {
+            rsid,
+            version: Self::be16(ser_psid, 0x04),
+            data_offset: Self::be16(ser_psid, 0x06),
+            load_address: Self::be16(ser_psid, 0x08),
+            init_address: Self::be16(ser_psid, 0x0A),
+            play_address: Self::be16(ser_psid, 0x0C),
+            songs: Self::be16(ser_psid, 0x0E),
+            start_song: Self::be16(ser_psid, 0x10),
+            speed: u32::from_be_bytes([
+                ser_psid[0x12],
+                ser_psid[0x13],
+                ser_psid[0x14],
+                ser_psid[0x15],
+            ]),
+            name: Self::string32(&ser_psid[0x16..0x36]),
+            author: Self::string32(&ser_psid[0x36..0x56]),
+            released: Self::string32(&ser_psid[0x56..0x76]),
+            ..Default::default()
+        };
+
+        if psid.version == 0 || psid.version > 4 || (rsid && psid.version == 1) {
+            return Err(DecodeError::Other("Unsupported PSID/RSID version"));
+        }
+
+        if psid.version >= 2 {
+            if ser_psid.len() < Self::HEADER_V2_SIZE {
+                return Err(DecodeError::Other("PSID header too short?"));
+            }
+            psid.flags = Self::be16(ser_psid, 0x76);
+            psid.start_page = ser_psid[0x78];
+            psid.page_length = ser_psid[0x79];
+            if psid.version >= 3 {
+                psid.second_sid_address = ser_psid[0x7A];
+            }
+            if psid.version >= 4 {
+                psid.third_sid_address = ser_psid[0x7B];
+            }
+        }
+
+        let mut offset = psid.data_offset as usize;
+        if offset < Self::HEADER_V1_SIZE || offset > ser_psid.len() {
+            return Err(DecodeError::Other("PSID data offset out of file?"));
+        }
+
+        if psid.load_address == 0 {
+            if offset + 2 > ser_psid.len() {
+                return Err(DecodeError::Other("PSID load address missing?"));
+            }
+            psid.load_address = u16::from_le_bytes([ser_psid[offset], ser_psid[offset + 1]]);
+            offset += 2;
+        }
+
+        let max_len = 0x10000 - psid.load_address as usize;
+        let end = ser_psid.len().min(offset + max_len);
+        psid.data = ser_psid[offset..end].to_vec();
+
+        if psid.songs == 0 {
+            psid.songs = 1;
+        }
+        if psid.start_song == 0 || psid.start_song > psid.songs {
+            psid.start_song = 1;
+        }
+
+        Ok(psid)
+    }
+
+    /// Song uses CIA timer instead of vertical blank interrupt (song is 1-based)
+    pub fn is_cia_timer(&self, song: u16) -> bool {
+        let bit = (song.max(1) - 1).min(31);
+        self.speed & (1 << bit) != 0
+    }
+
+    pub fn clock(&self) -> PsidClock {
+        match (self.flags >> 2) & 0b11 {
+            1 => PsidClock::Pal,
+            2 => PsidClock::Ntsc,
+            3 => PsidClock::PalAndNtsc,
+            _ => PsidClock::Unknown,
+        }
+    }
+
+    /// System clock in Hz, PAL by default
+    pub fn clock_frequency(&self) -> u32 {
+        match self.clock() {
+            PsidClock::Ntsc => NTSC_CLOCK,
+            _ => PAL_CLOCK,
+        }
+    }
+
+    /// Model of the first SID
+    pub fn sid_model(&self) -> PsidModel {
+        PsidModel::from_bits(self.flags >> 4)
+    }
+
+    /// Model of the second SID (v3+)
+    pub fn second_sid_model(&self) -> PsidModel {
+        PsidModel::from_bits(self.flags >> 6)
+    }
+
+    /// Model of the third SID (v4+)
+    pub fn third_sid_model(&self) -> PsidModel {
+        PsidModel::from_bits(self.flags >> 8)
+    }
+
+    /// First address after `data`
+    pub fn end_address(&self) -> usize {
+        self.load_address as usize + self.data.len()
+    }
+
+    /// 64KB C64 memory image with `data` at `load_address`
+    pub fn memory(&self) -> Vec<u8> {
+        let mut mem: Vec<u8> = vec![0; 0x10000];
+        let start = self.load_address as usize;
+        mem[start..start + self.data.len()].copy_from_slice(&self.data);
+        mem
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn psid_v2_header() {
+        let data = include_bytes!("songs/commando.sid");
+        let psid = PsidFile::load(data).unwrap();
+        assert!(!psid.rsid);
+        assert_eq!(psid.name, "Commando");
+        assert_eq!(psid.author, "Rob Hubbard");
+        assert_eq!(psid.end_address(), psid.load_address as usize + psid.data.len());
+        let memory = psid.memory();
+        assert_eq!(memory.len(), 0x10000);
+        assert_eq!(memory[psid.load_address as usize], psid.data[0]);
+    }
+
+    #[test]
+    fn psid_errors() {
+        assert!(PsidFile::load(b"PSID").is_err());
+        let mut header = vec![0u8; PsidFile::HEADER_V2_SIZE];
+        header[0..4].copy_from_slice(b"PSID");
+        // version 5
+        header[5] = 5;
+        assert!(PsidFile::load(&header).is_err());
+        // version 2, data offset past the end
+        header[5] = 2;
+        header[7] = 0xFF;
+        assert!(PsidFile::load(&header).is_err());
+        // version 2, load address in the data, which is missing
+        header[7] = PsidFile::HEADER_V2_SIZE as u8;
+        assert!(PsidFile::load(&header).is_err());
+    }
+}
@@ -0,0 +1,741 @@
+use alloc::{vec, vec::Vec};
+
+use crate::instr_sid::{InstrSid, SidVoice};
+
+/// MOS SID chip revision
+#[derive(Default, Clone, Copy, Debug, PartialEq)]
+pub enum SidModel {
+    #[default]
+    Mos6581,
+    Mos8580,
+}
+
+/// C64 PAL system clock (Hz)
+pub const PAL_CLOCK: u32 = 985_248;
+/// C64 NTSC system clock (Hz)
+pub const NTSC_CLOCK: u32 = 1_022_727;
+
+/// Oscillator: 24-bit phase accumulator, noise LFSR, waveform selector
+#[derive(Clone, Debug)]
+struct WaveGenerator {
+    accumulator: u32,
+    shift_register: u32,
+    freq: u16,
+    pw: u16,
+    waveform: u8,
+    test: bool,
+    ring_mod: bool,
+    sync: bool,
+    msb_rising: bool,
+}
+
+impl Default for WaveGenerator {
+    fn default() -> Self {
+        Self {
+            accumulator: 0,
+            shift_register: 0x7F_FFF8,
+            freq: 0,
+            pw: 0,
+            waveform: 0,
+            test: false,
+            ring_mod: false,
+            sync: false,
+            msb_rising: false,
+        }
+    }
+}
+
+impl WaveGenerator {
+    fn write_ctrl(&mut self, ctrl: u8) {
+        let test = ctrl & 0b0000_1000 != 0;
+        self.waveform = ctrl >> 4;
+        self.ring_mod = ctrl & 0b0000_0100 != 0;
+        self.sync = ctrl & 0b0000_0010 != 0;
+        if test {
+            // test bit holds the oscillator and clears the LFSR
+            self.accumulator = 0;
+            self.shift_register = 0;
+        } else if self.test {
+            self.shift_register = 0x7F_FFF8;
+        }
+        self.test = test;
+    }
+
+    fn clock(&mut self) {
+        self.advance(self.freq as u32);
+    }
+
+    fn advance(&mut self, step: u32) {
+        if self.test {
+            return;
+        }
+        let previous = self.accumulator;
+        self.accumulator = (self.accumulator + step) & 0xFF_FFFF;
+        self.msb_rising = !previous & self.accumulator & 0x80_0000 != 0;
+
+        // LFSR is clocked when bit 19 of the accumulator goes high
+        if !previous & self.accumulator & 0x08_0000 != 0 {
+            self.clock_shift_register();
+        }
+    }
+
+    /// 23 bits LFSR, taps on bits 22 and 17
+    fn clock_shift_register(&mut self) {
+        let bit0 = ((self.shift_register >> 22) ^ (self.shift_register >> 17)) & 1;
+        self.shift_register = ((self.shift_register << 1) & 0x7F_FFFF) | bit0;
+    }
+
+    fn triangle(&self, ring_source: u32) -> u16 {
+        let msb = if self.ring_mod {
+            (self.accumulator ^ ring_source) & 0x80_0000
+        } else {
+            self.accumulator & 0x80_0000
+        };
+        let acc = if msb != 0 {
+            !self.accumulator
+        } else {
+            self.accumulator
+        };
+        ((acc >> 11) & 0xFFF) as u16
+    }
+
+    fn sawtooth(&self) -> u16 {
+        (self.accumulator >> 12) as u16
+    }
+
+    fn pulse(&self) -> u16 {
+        if self.test || (self.accumulator >> 12) as u16 >= self.pw {
+            0xFFF
+        } else {
+            0
+        }
+    }
+
+    fn noise(&self) -> u16 {
+        let sr = self.shift_register;
+        (((sr & 0x40_0000) >> 11)
+            | ((sr & 0x10_0000) >> 10)
+            | ((sr & 0x01_0000) >> 7)
+            | ((sr & 0x00_2000) >> 5)
+            | ((sr & 0x00_0800) >> 4)
+            | ((sr & 0x00_0080) >> 1)
+            | ((sr & 0x00_0010) << 1)
+            | ((sr & 0x00_0004) << 2)) as u16
+    }
+
+    /// 12 bits output. Combined waveforms are approximated by a logical AND,
+    /// as the real chips pull the output lines down.
+    fn output(&self, ring_source: u32) -> u16 {
+        if self.waveform == 0 {
+            return 0;
+        }
+        let mut out: u16 = 0xFFF;
+        if self.waveform & 0b0001 != 0 {
+            out &= self.triangle(ring_source);
+        }
+        if self.waveform & 0b0010 != 0 {
+            out &= self.sawtooth();
+        }
+        if self.waveform & 0b0100 != 0 {
+            out &= self.pulse();
+        }
+        if self.waveform & 0b1000 != 0 {
+            out &= self.noise();
+        }
+        out
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum EnvelopeState {
+    Attack,
+    DecaySustain,
+    Release,
+}
+
+/// ADSR: 15-bit rate counter, 8-bit envelope counter, exponential decay
+#[derive(Clone, Debug)]
+struct EnvelopeGenerator {
+    rate_counter: u16,
+    rate_period: u16,
+    exponential_counter: u8,
+    exponential_counter_period: u8,
+    counter: u8,
+    hold_zero: bool,
+    state: EnvelopeState,
+    gate: bool,
+    attack: u8,
+    decay: u8,
+    sustain: u8,
+    release: u8,
+}
+
+impl Default for EnvelopeGenerator {
+    fn default() -> Self {
+        Self {
+            rate_counter: 0,
+            rate_period: Self::RATE_PERIOD[0],
+            exponential_counter: 0,
+            exponential_counter_period: 1,
+            counter: 0,
+            hold_zero: true,
+            state: EnvelopeState::Release,
+            gate: false,
+            attack: 0,
+            decay: 0,
+            sustain: 0,
+            release: 0,
+        }
+    }
+}
+
+impl EnvelopeGenerator {
+    /// Cycles between two envelope counter steps
+    const RATE_PERIOD: [u16; 16] = [
+        9, 32, 63, 95, 149, 220, 267, 313, 392, 977, 1954, 3126, 3907, 11720, 19532, 31251,
+    ];
+
+    fn write_ctrl(&mut self, ctrl: u8) {
+        let gate = ctrl & 0b0000_0001 != 0;
+        if !self.gate && gate {
+            self.state = EnvelopeState::Attack;
+            self.rate_period = Self::RATE_PERIOD[self.attack as usize];
+            self.hold_zero = false;
+        } else if self.gate && !gate {
+            self.state = EnvelopeState::Release;
+            self.rate_period = Self::RATE_PERIOD[self.release as usize];
+        }
+        self.gate = gate;
+    }
+
+    fn write_ad(&mut self, ad: u8) {
+        self.attack = ad >> 4;
+        self.decay = ad & 0x0F;
+        match self.state {
+            EnvelopeState::Attack => self.rate_period = Self::RATE_PERIOD[self.attack as usize],
+            EnvelopeState::DecaySustain => {
+                self.rate_period = Self::RATE_PERIOD[self.decay as usize]
+            }
+            EnvelopeState::Release => {}
+        }
+    }
+
+    fn write_sr(&mut self, sr: u8) {
+        self.sustain = sr >> 4;
+        self.release = sr & 0x0F;
+        if self.state == EnvelopeState::Release {
+            self.rate_period = Self::RATE_PERIOD[self.release as usize];
+        }
+    }
+
+    fn clock(&mut self) {
+        // The ADSR bug: the rate counter is only compared for equality, so
+        // lowering the rate period below the counter makes it wrap at 15 bits
+        // before the next step occurs.
+        self.rate_counter += 1;
+        if self.rate_counter & 0x8000 != 0 {
+            self.rate_counter = (self.rate_counter + 1) & 0x7FFF;
+        }
+        if self.rate_counter != self.rate_period {
+            return;
+        }
+        self.rate_counter = 0;
+
+        if self.state != EnvelopeState::Attack {
+            self.exponential_counter = self.exponential_counter.wrapping_add(1);
+            if self.exponential_counter != self.exponential_counter_period {
+                return;
+            }
+        }
+        self.exponential_counter = 0;
+
+        if self.hold_zero {
+            return;
+        }
+
+        match self.state {
+            EnvelopeState::Attack => {
+                self.counter = self.counter.wrapping_add(1);
+                if self.counter == 0xFF {
+                    self.state = EnvelopeState::DecaySustain;
+                    self.rate_period = Self::RATE_PERIOD[self.decay as usize];
+                }
+            }
+            EnvelopeState::DecaySustain => {
+                if self.counter != self.sustain * 0x11 {
+                    self.counter = self.counter.wrapping_sub(1);
+                }
+            }
+            EnvelopeState::Release => {
+                self.counter = self.counter.wrapping_sub(1);
+            }
+        }
+
+        self.exponential_counter_period = match self.counter {
+            0xFF => 1,
+            0x5D => 2,
+            0x36 => 4,
+            0x1A => 8,
+            0x0E => 16,
+            0x06 => 30,
+            0x00 => {
+                self.hold_zero = true;
+                1
+            }
+            _ => self.exponential_counter_period,
+        };
+    }
+}
+
+/// Multimode state variable filter
+#[derive(Default, Clone, Debug)]
+struct Filter {
+    fc: u16,
+    resonance: u8,
+    /// voice0, voice1, voice2, external input
+    routing: u8,
+    mode: u8,
+    volume: u8,
+    voice3_off: bool,
+    low: f32,
+    band: f32,
+}
+
+impl Filter {
+    /// (fc, Hz) points of a measured 6581, from reSID (`f0_points_6581` in
+    /// filter.cc, Dag Lem). The cutoff drops at 1024 because the MSB of the
+    /// 6581 cutoff DAC is weaker than the sum of the lower bits.
+    const CUTOFF_6581: [(u16, f32); 27] = [
+        (0, 220.0),
+        (128, 230.0),
+        (256, 250.0),
+        (384, 300.0),
+        (512, 420.0),
+        (640, 780.0),
+        (768, 1600.0),
+        (832, 2300.0),
+        (896, 3200.0),
+        (960, 4300.0),
+        (992, 5000.0),
+        (1008, 5400.0),
+        (1016, 5700.0),
+        (1023, 6000.0),
+        (1024, 4600.0),
+        (1032, 4800.0),
+        (1056, 5300.0),
+        (1088, 6000.0),
+        (1120, 6600.0),
+        (1152, 7200.0),
+        (1280, 9500.0),
+        (1408, 12000.0),
+        (1536, 14500.0),
+        (1664, 16000.0),
+        (1792, 17100.0),
+        (1920, 17700.0),
+        (2047, 18000.0),
+    ];
+
+    fn cutoff(&self, model: SidModel) -> f32 {
+        match model {
+            // close to linear, about 12 kHz at 2047
+            SidModel::Mos8580 => 30.0 + self.fc as f32 * 5.8,
+            SidModel::Mos6581 => {
+                let points = &Self::CUTOFF_6581;
+                for w in points.windows(2) {
+                    let (x0, y0) = w[0];
+                    let (x1, y1) = w[1];
+                    if self.fc >= x0 && self.fc < x1 {
+                        let p = (self.fc - x0) as f32 / (x1 - x0) as f32;
+                        return y0 + (y1 - y0) * p;
+                    }
+                }
+                points[points.len() - 1].1
+            }
+        }
+    }
+
+    fn clock(&mut self, input: f32, w0: f32) -> f32 {
+        let q = 0.707 + self.resonance as f32 / 15.0;
+        let high = input - self.low - self.band / q;
+        self.band += w0 * high;
+        self.low += w0 * self.band;
+
+        let mut out = 0.0;
+        if self.mode & 0b001 != 0 {
+            out += self.low;
+        }
+        if self.mode & 0b010 != 0 {
+            out += self.band;
+        }
+        if self.mode & 0b100 != 0 {
+            out += high;
+        }
+        out
+    }
+}
+
+/// MOS6581/8580 SID emulator
+///
+/// Voice routing for synchronize & ring modulation follows `SidVoice`:
+/// voice0 from voice2, voice1 from voice0, voice2 from voice1.
+#[derive(Clone, Debug)]
+pub struct SidChip {
+    pub model: SidModel,
+    /// System clock (Hz), see `PAL_CLOCK` and `NTSC_CLOCK`
+    pub clock_frequency: u32,
+    wave: [WaveGenerator; 3],
+    envelope: [EnvelopeGenerator; 3],
+    filter: Filter,
+    w0: f32,
+    cycle_fraction: f32,
+}
+
+impl Default for SidChip {
+    fn default() -> Self {
+        Self::new(SidModel::Mos6581, PAL_CLOCK)
+    }
+}
+
+impl SidChip {
+    pub fn new(model: SidModel, clock_frequency: u32) -> Self {
+        let mut sid = Self {
+            model,
+            clock_frequency,
+            wave: Default::default(),
+            envelope: Default::default(),
+            filter: Filter::default(),
+            w0: 0.0,
+            cycle_fraction: 0.0,
+        };
+        sid.update_w0();
+        sid
+    }
+
+    fn update_w0(&mut self) {
+        let f = self.filter.cutoff(self.model).min(16000.0);
+        self.w0 = core::f32::consts::TAU * f / self.clock_frequency as f32;
+    }
+
+    /// Write a SID register (0x00..=0x18)
+    pub fn write(&mut self, register: u8, value: u8) {
+        match register {
+            0x00..=0x14 => {
+                let v = (register / 7) as usize;
+                let wave = &mut self.wave[v];
+                match register % 7 {
+                    0 => wave.freq = (wave.freq & 0xFF00) | value as u16,
+                    1 => wave.freq = (wave.freq & 0x00FF) | (value as u16) << 8,
+                    2 => wave.pw = (wave.pw & 0x0F00) | value as u16,
+                    3 => wave.pw = (wave.pw & 0x00FF) | ((value & 0x0F) as u16) << 8,
+                    4 => {
+                        wave.write_ctrl(value);
+                        self.envelope[v].write_ctrl(value);
+                    }
+                    5 => self.envelope[v].write_ad(value),
+                    _ => self.envelope[v].write_sr(value),
+                }
+            }
+            0x15 => {
+                self.filter.fc = (self.filter.fc & 0x7F8) | (value & 0x07) as u16;
+                self.update_w0();
+            }
+            0x16 => {
+                self.filter.fc = (self.filter.fc & 0x007) | (value as u16) << 3;
+                self.update_w0();
+            }
+            0x17 => {
+                self.filter.resonance = value >> 4;
+                self.filter.routing = value & 0x0F;
+            }
+            0x18 => {
+                self.filter.voice3_off = value & 0b1000_0000 != 0;
+                self.filter.mode = (value >> 4) & 0b111;
+                self.filter.volume = value & 0x0F;
+            }
+            _ => {}
+        }
+    }
+
+    /// Load voices and filter registers from an instrument (frequencies are untouched)
+    pub fn set_instrument(&mut self, instr: &InstrSid) {
+        for (i, voice) in instr.voice.iter().enumerate() {
+            self.set_voice(i, voice);
+        }
+        let fc = instr.fc & 0x7FF;
+        self.write(0x15, (fc & 0x07) as u8);
+        self.write(0x16, (fc >> 3) as u8);
+        let routing = instr
+            .filter_gate
+            .iter()
+            .enumerate()
+            .fold(0u8, |acc, (i, &g)| acc | (g as u8) << i);
+        self.write(0x17, (instr.filter_resonance & 0x0F) << 4 | routing);
+        self.write(
+            0x18,
+            (instr.mute_voice3 as u8) << 7
+                | (instr.high_pass as u8) << 6
+                | (instr.band_pass as u8) << 5
+                | (instr.low_pass as u8) << 4
+                | (instr.main_volume & 0x0F),
+        );
+    }
+
+    /// Load one voice registers (frequency is untouched), `voice` is 0..3
+    pub fn set_voice(&mut self, voice: usize, sv: &SidVoice) {
+        if voice >= 3 {
+            return;
+        }
+        let base = 7 * voice as u8;
+        self.write(base + 2, sv.pw as u8);
+        self.write(base + 3, (sv.pw >> 8) as u8);
+        self.write(base + 5, sv.ad);
+        self.write(base + 6, sv.sr);
+        self.write(base + 4, sv.ctrl_register());
+    }
+
+    /// Set voice oscillator frequency register, `voice` is 0..3
+    pub fn set_frequency(&mut self, voice: usize, freq: u16) {
+        if voice >= 3 {
+            return;
+        }
+        let base = 7 * voice as u8;
+        self.write(base, freq as u8);
+        self.write(base + 1, (freq >> 8) as u8);
+    }
+
+    /// Open or close a voice gate, keeping the other control bits
+    pub fn set_gate(&mut self, voice: usize, gate: bool) {
+        let Some(w) = self.wave.get(voice) else {
+            return;
+        };
+        let ctrl = w.waveform << 4
+            | (w.test as u8) << 3
+            | (w.ring_mod as u8) << 2
+            | (w.sync as u8) << 1
+            | gate as u8;
+        self.write(7 * voice as u8 + 4, ctrl);
+    }
+
+    /// return frequency register from Hz
+    pub fn frequency_register(&self, hz: f32) -> u16 {
+        (hz * 16_777_216.0 / self.clock_frequency as f32).clamp(0.0, 65535.0) as u16
+    }
+
+    /// return Hz from frequency register
+    pub fn register_frequency(&self, freq: u16) -> f32 {
+        freq as f32 * self.clock_frequency as f32 / 16_777_216.0
+    }
+
+    /// Current 8 bits envelope level of a voice
+    pub fn envelope_level(&self, voice: usize) -> u8 {
+        self.envelope.get(voice).map_or(0, |e| e.counter)
+    }
+
+    /// Run one clock cycle, return output in [-1..1]
+    pub fn clock(&mut self) -> f32 {
+        for v in 0..3 {
+            self.wave[v].clock();
+            self.envelope[v].clock();
+        }
+
+        // hard sync: voice0 from voice2, voice1 from voice0, voice2 from voice1
+        for v in 0..3 {
+            let source = (v + 2) % 3;
+            if self.wave[v].sync && self.wave[source].msb_rising {
+                self.wave[v].accumulator = 0;
+            }
+        }
+
+        let mut filtered: f32 = 0.0;
+        let mut unfiltered: f32 = 0.0;
+        for v in 0..3 {
+            let source = self.wave[(v + 2) % 3].accumulator;
+            let wave = self.wave[v].output(source) as f32 - 2048.0;
+            let out = wave * self.envelope[v].counter as f32 / (2048.0 * 255.0);
+            if self.filter.routing & (1 << v) != 0 {
+                filtered += out;
+            } else if v != 2 || !self.filter.voice3_off {
+                unfiltered += out;
+            }
+        }
+
+        let w0 = self.w0;
+        let out = self.filter.clock(filtered, w0) + unfiltered;
+        out * self.filter.volume as f32 / (15.0 * 3.0)
+    }
+
+    /// Render `out.len()` samples at `sample_rate`
+    pub fn render(&mut self, sample_rate: u32, out: &mut [i16]) {
+        let cycles_per_sample = self.clock_frequency as f32 / sample_rate as f32;
+        for o in out.iter_mut() {
+            self.cycle_fraction += cycles_per_sample;
+            let cycles = self.cycle_fraction as usize;
+            self.cycle_fraction -= cycles as f32;
+            let mut acc = 0.0;
+            for _ in 0..cycles {
+                acc += self.clock();
+            }
+            if cycles != 0 {
+                acc /= cycles as f32;
+            }
+            *o = (acc * 32767.0).clamp(-32768.0, 32767.0) as i16;
+        }
+    }
+
+    /// Render `periods` oscillator periods of a voice waveform into `length` points,
+    /// without envelope. Useful to build looped samples.
+    pub fn render_waveform(voice: &SidVoice, length: usize, periods: usize) -> Vec<i16> {
+        let mut wave = WaveGenerator {
+            pw: voice.pw & 0x0FFF,
+            ..Default::default()
+        };
+        wave.write_ctrl(voice.ctrl_register() & !0b0000_1110);
+        let step = ((periods as u64) << 24) / length.max(1) as u64;
+
+        let mut data: Vec<i16> = vec![0; length];
+        for d in data.iter_mut() {
+            *d = ((wave.output(0) as i32 - 2048) << 4) as i16;
+            wave.advance(step as u32);
+        }
+        data
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lfsr_has_maximal_length() {
+        let mut wave = WaveGenerator::default();
+        let seed = wave.shift_register;
+        let mut period = 0u32;
+        loop {
+            wave.clock_shift_register();
+            period += 1;
+            if wave.shift_register == seed || period > 1 << 23 {
+                break;
+            }
+        }
+        assert_eq!(period, (1 << 23) - 1);
+    }
+
+    #[test]
+    fn lfsr_is_clocked_by_bit_19_and_reset_by_test_bit() {
+        let mut wave = WaveGenerator::default();
+        let seed = wave.shift_register;
+        wave.advance(0x04_0000);
+        assert_eq!(wave.shift_register, seed);
+        wave.advance(0x04_0000);
+        assert_ne!(wave.shift_register, seed);
+
+        wave.write_ctrl(0b1000_1000);
+        assert_eq!(wave.shift_register, 0);
+        assert_eq!(wave.noise(), 0);
+        wave.write_ctrl(0b1000_0000);
+        assert_eq!(wave.shift_register, seed);
+    }
+
+    #[test]
+    fn combined_waveforms_are_anded() {
+        let mut wave = WaveGenerator {
+            pw: 0x800,
+            ..Default::default()
+        };
+        for accumulator in (0..0x100_0000).step_by(0x1_2345) {
+            wave.accumulator = accumulator;
+            let triangle = wave.triangle(0);
+            let sawtooth = wave.sawtooth();
+            let pulse = wave.pulse();
+
+            wave.waveform = 0b0011;
+            assert_eq!(wave.output(0), triangle & sawtooth);
+            wave.waveform = 0b0110;
+            assert_eq!(wave.output(0), sawtooth & pulse);
+            wave.waveform = 0b0111;
+            assert_eq!(wave.output(0), triangle & sawtooth & pulse);
+        }
+    }
+
+    fn cycles_until(
+        envelope: &mut EnvelopeGenerator,
+        f: impl Fn(&EnvelopeGenerator) -> bool,
+    ) -> u32 {
+        let mut cycles = 0;
+        while !f(envelope) && cycles < 1_000_000 {
+            envelope.clock();
+            cycles += 1;
+        }
+        cycles
+    }
+
+    #[test]
+    fn adsr_attack_decay_sustain_release() {
+        let mut envelope = EnvelopeGenerator::default();
+        // fastest attack and decay, sustain 8, fastest release
+        envelope.write_ad(0x00);
+        envelope.write_sr(0x80);
+        envelope.write_ctrl(0x01);
+
+        let attack = cycles_until(&mut envelope, |e| e.counter == 0xFF);
+        assert!((255 * 9 - 9..=255 * 9 + 9).contains(&attack));
+        assert_eq!(envelope.state, EnvelopeState::DecaySustain);
+
+        cycles_until(&mut envelope, |e| e.counter == 0x88);
+        for _ in 0..10_000 {
+            envelope.clock();
+        }
+        assert_eq!(envelope.counter, 0x88);
+
+        envelope.write_ctrl(0x00);
+        cycles_until(&mut envelope, |e| e.counter == 0);
+        for _ in 0..10_000 {
+            envelope.clock();
+        }
+        assert_eq!(envelope.counter, 0);
+        assert!(envelope.hold_zero);
+    }
+
+    #[test]
+    fn adsr_rate_counter_bug() {
+        let mut envelope = EnvelopeGenerator::default();
+        envelope.write_ad(0x00);
+        envelope.write_sr(0xF0);
+        envelope.write_ctrl(0x01);
+        cycles_until(&mut envelope, |e| e.counter == 0xFF);
+
+        // slowest release, then the fastest once the rate counter is past it
+        envelope.write_sr(0xFF);
+        envelope.write_ctrl(0x00);
+        for _ in 0..1000 {
+            envelope.clock();
+        }
+        assert_eq!(envelope.counter, 0xFF);
+        envelope.write_sr(0xF0);
+        let delay = cycles_until(&mut envelope, |e| e.counter != 0xFF);
+        assert!((0x8000 - 1000..0x8000).contains(&delay), "{}", delay);
+    }
+
+    #[test]
+    fn cutoff_6581_drops_at_dac_msb() {
+        let mut filter = Filter {
+            fc: 1023,
+            ..Default::default()
+        };
+        let below = filter.cutoff(SidModel::Mos6581);
+        filter.fc = 1024;
+        let above = filter.cutoff(SidModel::Mos6581);
+        assert!(above < below);
+        filter.fc = 2047;
+        assert_eq!(filter.cutoff(SidModel::Mos6581), 18000.0);
+    }
+
+    #[test]
+    fn voice_out_of_range_is_ignored() {
+        let mut sid = SidChip::default();
+        sid.set_voice(3, &SidVoice::default());
+        sid.set_frequency(3, 0x1234);
+        sid.set_gate(3, true);
+        assert_eq!(sid.envelope_level(3), 0);
+    }
+}
@@ -3,9 +3,12 @@ use alloc::{vec, vec::Vec};
 
 use crate::prelude::*;
 
+use bincode::error::DecodeError;
+
 use super::instr_helper::InstrHelper;
-use super::one_sid::OneSid;
+use super::one_sid::{HubbardDriver, OneSid};
 use super::pattern_helper::PatternHelper;
+use super::psid_file::PsidFile;
 use super::sound_fx::SoundFx;
 
 #[derive(Debug)]
@@ -17,6 +20,9 @@ pub struct SidModule {
 }
 
 impl SidModule {
+    /// Longer tracks are split
+    const MAX_PATTERN_ROWS: usize = 256;
+
     pub fn to_modules(&self, original_instruments: bool) -> Vec<Module> {
         let mut modules: Vec<Module> = vec![];
 
@@ -27,14 +33,12 @@ impl SidModule {
                 "{} - {} (song #{})",
                 self.sid.copyright, self.sid.author, song_number
             );
-            module.default_tempo = (1 + self.sid.resetspd) as u16;
-
-            let mut patterns = self.pattern_helper.get_patterns(song_number);
-            PatternHelper::split_large_patterns(&mut patterns);
-            let (patterns, pattern_order) = PatternHelper::cleanup_patterns(&patterns);
+            module.default_tempo = (1 + self.sid.driver.resetspd) as u16;
 
-            module.pattern = patterns;
-            module.pattern_order = pattern_order;
+            module.pattern = self.pattern_helper.get_patterns(song_number);
+            module.pattern_order = (0..module.pattern.len()).collect();
+            module.normalize_pattern_lengths(Self::MAX_PATTERN_ROWS);
+            module.merge_patterns();
 
             let idst = InstrHelper::irss_to_instruments(&self.instruments, original_instruments);
             module.instrument = idst;
@@ -44,79 +48,153 @@ impl SidModule {
 
         return modules;
     }
+
+    /// One short module for each sound effect, one row per frame
+    pub fn soundfx_to_modules(&self, original_instruments: bool) -> Vec<Module> {
+        let mut modules: Vec<Module> = vec![];
+
+        for (fx_number, sfx) in self.soundfx.iter().enumerate() {
+            let mut module = Module {
+                name: format!("{} fx {}", self.sid.name, fx_number),
+                comment: format!(
+                    "{} - {} (sound effect #{})",
+                    self.sid.copyright, self.sid.author, fx_number
+                ),
+                default_tempo: 1,
+                pattern_order: vec![0],
+                pattern: vec![sfx.get_pattern()],
+                instrument: InstrHelper::irss_to_instruments(
+                    &sfx.to_instruments(),
+                    original_instruments,
+                ),
+                ..Default::default()
+            };
+            module.normalize_pattern_lengths(Self::MAX_PATTERN_ROWS);
+
+            modules.push(module);
+        }
+
+        modules
+    }
 }
 
 impl SidModule {
+    /// Import a Rob Hubbard PSID/RSID file, first player only
+    pub fn load(ser_psid: &[u8]) -> Result<Self, DecodeError> {
+        match Self::load_all(ser_psid)?.into_iter().next() {
+            Some(sid) => Ok(sid),
+            None => Err(DecodeError::Other("Rob Hubbard driver not found")),
+        }
+    }
+
+    /// Import each Rob Hubbard player of a PSID/RSID file
+    ///
+    /// Driver tables come from the registry for known tunes, else they are
+    /// detected. Players whose tables can't be read are skipped.
+    pub fn load_all(ser_psid: &[u8]) -> Result<Vec<Self>, DecodeError> {
+        let psid = PsidFile::load(ser_psid)?;
+        Ok(OneSid::from_psid(psid)
+            .iter()
+            .filter_map(|sid| sid.to_sidmodule().ok())
+            .collect())
+    }
+
+    /// Import a Rob Hubbard PSID/RSID file using its driver tables
+    pub fn load_with_driver(ser_psid: &[u8], driver: HubbardDriver) -> Result<Self, DecodeError> {
+        let psid = PsidFile::load(ser_psid)?;
+        OneSid::new(psid, driver).to_sidmodule()
+    }
+
+    #[cfg(feature = "sid_songs")]
     pub fn get_sid_commando() -> Self {
         let sid = OneSid::get_sid_commando();
-        return sid.to_sidmodule();
+        return sid.to_sidmodule().unwrap();
     }
 
+    #[cfg(feature = "sid_songs")]
     pub fn get_sid_crazy_comets() -> Self {
         let sid = OneSid::get_sid_crazy_comets();
-        return sid.to_sidmodule();
+        return sid.to_sidmodule().unwrap();
     }
 
+    #[cfg(feature = "sid_songs")]
     pub fn get_sid_last_v8() -> Self {
         let sid = OneSid::get_sid_last_v8();
-        return sid.to_sidmodule();
+        return sid.to_sidmodule().unwrap();
     }
 
+    #[cfg(feature = "sid_songs")]
     pub fn get_sid_monty_on_the_run() -> Self {
         let sid = OneSid::get_sid_monty_on_the_run();
-        return sid.to_sidmodule();
+        return sid.to_sidmodule().unwrap();
     }
 
+    #[cfg(feature = "sid_songs")]
     pub fn get_sid_thing_on_a_spring() -> Self {
         let sid = OneSid::get_sid_thing_on_a_spring();
-        return sid.to_sidmodule();
+        return sid.to_sidmodule().unwrap();
     }
 
+    #[cfg(feature = "sid_songs")]
     pub fn get_sid_zoid() -> Self {
         let sid = OneSid::get_sid_zoid();
-        return sid.to_sidmodule();
+        return sid.to_sidmodule().unwrap();
     }
 
     //--------------------------
     // WIP
 
+    #[cfg(feature = "sid_songs")]
     pub fn get_sid_ace_2() -> Self {
         let sid = OneSid::get_sid_ace_2();
-        return sid.to_sidmodule();
+        return sid.to_sidmodule().unwrap();
     }
 
+    #[cfg(feature = "sid_songs")]
     pub fn get_sid_delta() -> Self {
         let sid = OneSid::get_sid_delta();
-        return sid.to_sidmodule();
+        return sid.to_sidmodule().unwrap();
     }
 
+    #[cfg(feature = "sid_songs")]
     pub fn get_sid_human_race() -> Self {
         let sid = OneSid::get_sid_human_race();
-        return sid.to_sidmodule();
+        return sid.to_sidmodule().unwrap();
     }
 
+    #[cfg(feature = "sid_songs")]
     pub fn get_sid_international_karate() -> Self {
         let sid = OneSid::get_sid_international_karate();
-        return sid.to_sidmodule();
+        return sid.to_sidmodule().unwrap();
     }
 
+    #[cfg(feature = "sid_songs")]
     pub fn get_sid_lightforce() -> Self {
         let sid = OneSid::get_sid_lightforce();
-        return sid.to_sidmodule();
+        return sid.to_sidmodule().unwrap();
     }
 
+    #[cfg(feature = "sid_songs")]
     pub fn get_sid_sanxion_song_1() -> Self {
         let sid = OneSid::get_sid_sanxion_song_1();
-        return sid.to_sidmodule();
+        return sid.to_sidmodule().unwrap();
     }
 
+    #[cfg(feature = "sid_songs")]
     pub fn get_sid_sanxion_song_2() -> Self {
         let sid = OneSid::get_sid_sanxion_song_2();
-        return sid.to_sidmodule();
+        return sid.to_sidmodule().unwrap();
     }
 
+    #[cfg(feature = "sid_songs")]
     pub fn get_sid_spellbound() -> Self {
         let sid = OneSid::get_sid_spellbound();
-        return sid.to_sidmodule();
+        return sid.to_sidmodule().unwrap();
+    }
+
+    #[cfg(feature = "sid_songs")]
+    pub fn get_sid_thrust() -> Self {
+        let sid = OneSid::get_sid_thrust();
+        sid.to_sidmodule().unwrap()
     }
 }
@@ -0,0 +1,374 @@
+use alloc::{vec, vec::Vec};
+
+#[cfg(feature = "micromath")]
+use micromath::F32Ext;
+#[cfg(feature = "libm")]
+use num_traits::float::Float;
+
+use super::sid_chip::{SidChip, SidModel, PAL_CLOCK};
+use crate::instr_robsid::RobEffects;
+use crate::instr_sid::SidVoice;
+use crate::prelude::*;
+use crate::timeline::TimelineEntry;
+
+/// Frequency of the note 0 (C-0) of the Rob Hubbard driver table, in Hz
+const C0_HZ: f32 = 16.3516;
+/// Pulse width sweep limits of the Rob Hubbard driver
+const PW_HIGH: u16 = 0x0E00;
+const PW_LOW: u16 = 0x0800;
+
+/// Rob Hubbard driver state of one voice
+#[derive(Default, Clone, Debug)]
+struct RobVoice {
+    instrument: Option<usize>,
+    /// Note index, 0 is C-0
+    note: u8,
+    /// Note length in rows, used by the skydive
+    length: usize,
+    /// Frames since the note start
+    frame: usize,
+    freq: u16,
+    /// Portamento, added to `freq` each frame
+    slide: i16,
+    pw: u16,
+    pw_down: bool,
+    pw_delay: u16,
+    gate: bool,
+}
+
+/// Play Rob Hubbard modules on an emulated SID
+///
+/// Modules come from `SidModule::to_modules(true)` or
+/// `SidModule::soundfx_to_modules(true)`: the channel `n` is the SID voice
+/// `n`, a note starts the `InstrRobSid` of its instrument and a release row
+/// closes the gate. `RobEffects` are played once per tick, as the original
+/// driver does once per PAL frame.
+pub struct SidPlayer<'a> {
+    module: &'a Module,
+    sid: SidChip,
+    sample_rate: u32,
+    rows: Vec<TimelineEntry>,
+    /// Ticks of each row
+    row_ticks: Vec<usize>,
+    sample_fraction: f32,
+    row: usize,
+    tick: usize,
+    counter: usize,
+    voices: [RobVoice; 3],
+}
+
+impl<'a> SidPlayer<'a> {
+    pub fn new(module: &'a Module, model: SidModel, sample_rate: u32) -> Self {
+        let timeline = module.timeline();
+        let row_ticks = timeline.rows.iter().map(|e| e.ticks.max(1)).collect();
+
+        let mut sid = SidChip::new(model, PAL_CLOCK);
+        // no filter, full volume
+        sid.write(0x18, 0x0F);
+
+        Self {
+            module,
+            sid,
+            sample_rate,
+            rows: timeline.rows,
+            row_ticks,
+            sample_fraction: 0.0,
+            row: 0,
+            tick: 0,
+            counter: 0,
+            voices: Default::default(),
+        }
+    }
+
+    /// SID frequency register of a Rob Hubbard note
+    fn note_frequency(note: u8) -> u16 {
+        let hz = C0_HZ * 2.0f32.powf(note as f32 / 12.0);
+        (hz * 16_777_216.0 / PAL_CLOCK as f32)
+            .round()
+            .clamp(0.0, 65535.0) as u16
+    }
+
+    fn instrument(&self, voice: usize) -> Option<(&SidVoice, RobEffects)> {
+        let instr = self.module.instrument.get(self.voices[voice].instrument?)?;
+        match &instr.instr_type {
+            InstrumentType::RobSid(irs) => Some((&irs.sid.voice[0], irs.fx[0])),
+            InstrumentType::Sid(sid) => Some((&sid.voice[0], RobEffects::default())),
+            _ => None,
+        }
+    }
+
+    fn slot(&self, row: usize, channel: usize) -> Option<&'a PatternSlot> {
+        let entry = self.rows.get(row)?;
+        let pattern = self.module.pattern_order.get(entry.order)?;
+        self.module
+            .pattern
+            .get(*pattern)?
+            .get(entry.row)?
+            .get(channel)
+    }
+
+    /// Rows until the next event of a channel
+    fn note_length(&self, row: usize, channel: usize) -> usize {
+        (row + 1..self.rows.len())
+            .position(|r| {
+                self.slot(r, channel)
+                    .is_some_and(|s| !s.note.is_none() || s.volume == 0x10)
+            })
+            .map_or(self.rows.len() - row, |n| n + 1)
+    }
+
+    fn start_row(&mut self) {
+        for channel in 0..3 {
+            let Some(slot) = self.slot(self.row, channel) else {
+                continue;
+            };
+            if slot.instrument != 0 {
+                self.voices[channel].instrument = Some(slot.instrument as usize - 1);
+            }
+
+            if slot.note.is_valid() {
+                let length = self.note_length(self.row, channel);
+                let voice = &mut self.voices[channel];
+                voice.note = slot.note.value() - 1;
+                voice.length = length;
+                voice.frame = 0;
+                voice.freq = Self::note_frequency(voice.note);
+                voice.slide = match slot.effect_type {
+                    1 => slot.effect_parameter as i16,
+                    2 => -(slot.effect_parameter as i16),
+                    _ => 0,
+                };
+                voice.pw_down = false;
+                voice.pw_delay = 0;
+                voice.gate = true;
+                if let Some((sv, _)) = self.instrument(channel) {
+                    let sv = *sv;
+                    self.voices[channel].pw = sv.pw & 0x0FFF;
+                    // gate off then on restarts the envelope attack
+                    self.sid.set_voice(
+                        channel,
+                        &SidVoice {
+                            ctrl_gate: false,
+                            ..sv
+                        },
+                    );
+                }
+            } else if slot.note.is_keyoff()
+                || slot.note.is_note_cut()
+                || (slot.note.is_none() && slot.volume == 0x10)
+            {
+                self.voices[channel].gate = false;
+            }
+        }
+    }
+
+    /// Rob Hubbard effects of one frame, then SID registers
+    fn play_tick(&mut self) {
+        for channel in 0..3 {
+            let Some((sv, fx)) = self.instrument(channel) else {
+                continue;
+            };
+            let sv = *sv;
+            let voice = &mut self.voices[channel];
+            let mut ctrl = sv.ctrl_register() & 0xFE;
+
+            if voice.frame != 0 {
+                voice.freq = voice.freq.wrapping_add_signed(voice.slide);
+            }
+            let mut freq = voice.freq;
+
+            if fx.drum {
+                // noise on the first frame, then a fast fall
+                if voice.frame == 0 {
+                    ctrl = 0x80;
+                }
+                voice.freq = voice.freq.saturating_sub(0x100);
+            } else if fx.skydive && voice.length > fx.skydive_config_if as usize {
+                if self.counter % 2 == 1 {
+                    let step = match fx.skydive_config_add as i8 {
+                        0 => -1,
+                        step => step,
+                    };
+                    let high = (voice.freq >> 8) as i16 + step as i16;
+                    if (0..=0xFF).contains(&high) {
+                        voice.freq = (voice.freq & 0xFF) | (high as u16) << 8;
+                    }
+                }
+            } else if fx.vibrato && voice.slide == 0 {
+                let step = self.counter / fx.vibrato_div.max(1) as usize;
+                let phase = match step & 7 {
+                    p if p >= 4 => p ^ 7,
+                    p => p,
+                } as u32;
+                let next = Self::note_frequency(voice.note.saturating_add(1));
+                let semitone = next.saturating_sub(Self::note_frequency(voice.note)) as u32;
+                let delta = semitone * fx.vibrato_depth as u32 / 16 * phase / 3;
+                freq = freq.saturating_add(delta as u16);
+            }
+            if fx.arpeggio && self.counter % 2 == 1 {
+                freq = Self::note_frequency(voice.note.saturating_add(12));
+            }
+
+            let pw_speed = fx.pw_speed as u8 as u16;
+            if pw_speed != 0 {
+                if voice.pw_delay == 0 {
+                    voice.pw_delay = fx.pw_delay;
+                    if voice.pw_down {
+                        voice.pw = voice.pw.saturating_sub(pw_speed);
+                        voice.pw_down = voice.pw >= PW_LOW;
+                    } else {
+                        voice.pw = (voice.pw + pw_speed).min(0x0FFF);
+                        voice.pw_down = voice.pw >= PW_HIGH;
+                    }
+                } else {
+                    voice.pw_delay -= 1;
+                }
+            }
+
+            let base = 7 * channel as u8;
+            self.sid.write(base + 2, voice.pw as u8);
+            self.sid.write(base + 3, (voice.pw >> 8) as u8);
+            self.sid.set_frequency(channel, freq);
+            self.sid.write(base + 4, ctrl | voice.gate as u8);
+            voice.frame += 1;
+        }
+        self.counter += 1;
+    }
+
+    /// Render the next tick into `out`, `false` at the end of the song
+    pub fn render_tick(&mut self, out: &mut Vec<i16>) -> bool {
+        if self.row >= self.rows.len() {
+            return false;
+        }
+        if self.tick == 0 {
+            self.start_row();
+        }
+        self.play_tick();
+
+        // the tick length follows speed and tempo changes (Fxx)
+        self.sample_fraction += self.sample_rate as f32 * self.rows[self.row].tick_seconds;
+        let samples = self.sample_fraction as usize;
+        self.sample_fraction -= samples as f32;
+        let start = out.len();
+        out.resize(start + samples, 0);
+        self.sid.render(self.sample_rate, &mut out[start..]);
+
+        self.tick += 1;
+        if self.tick >= self.row_ticks[self.row] {
+            self.tick = 0;
+            self.row += 1;
+        }
+        true
+    }
+
+    /// Render the whole song once, mono 16-bit
+    pub fn render_song(mut self) -> Vec<i16> {
+        let mut out: Vec<i16> = vec![];
+        while self.render_tick(&mut out) {}
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::instr_robsid::InstrRobSid;
+
+    fn module(instrument: InstrRobSid) -> Module {
+        let mut pattern = vec![vec![PatternSlot::default(); 3]; 16];
+        pattern[0][0].note = Note::C4;
+        pattern[0][0].instrument = 1;
+        pattern[8][0].volume = 0x10;
+        Module {
+            default_tempo: 3,
+            pattern: vec![pattern],
+            pattern_order: vec![0],
+            instrument: vec![Instrument {
+                instr_type: InstrumentType::RobSid(instrument),
+                ..Default::default()
+            }],
+            ..Default::default()
+        }
+    }
+
+    fn pulse() -> InstrRobSid {
+        let mut instr = InstrRobSid::default();
+        instr.sid.voice[0] = SidVoice {
+            pw: 0x0800,
+            ctrl_pulse: true,
+            ad: 0x09,
+            sr: 0xA0,
+            ..Default::default()
+        };
+        instr
+    }
+
+    #[test]
+    fn hubbard_note_table() {
+        // C-0 and A-4 of the Rob Hubbard PAL table
+        assert_eq!(SidPlayer::note_frequency(0), 0x0116);
+        assert_eq!(SidPlayer::note_frequency(57), 0x1D45);
+    }
+
+    #[test]
+    fn note_is_played_then_released() {
+        let module = module(pulse());
+        let rate = 44100;
+        let pcm = SidPlayer::new(&module, SidModel::Mos6581, rate).render_song();
+        let expected = (module.timeline().duration * rate as f32) as usize;
+        assert!(pcm.len().abs_diff(expected) <= 1);
+
+        let level = |range: core::ops::Range<usize>| {
+            pcm[range]
+                .iter()
+                .map(|&x| (x as i32).abs())
+                .max()
+                .unwrap_or(0)
+        };
+        let row = pcm.len() / 16;
+        assert!(level(0..row * 8) > 1000);
+        // zero release: silent one row after the release
+        assert!(level(row * 9..pcm.len()) < 100);
+    }
+
+    #[test]
+    fn pulse_width_sweep() {
+        let mut instr = pulse();
+        instr.fx[0].pw_speed = 0x40;
+        let module = module(instr);
+        let mut player = SidPlayer::new(&module, SidModel::Mos8580, 8000);
+        let mut out = vec![];
+        let mut pw = vec![];
+        for _ in 0..40 {
+            player.render_tick(&mut out);
+            pw.push(player.voices[0].pw);
+        }
+        assert!(pw.iter().any(|&w| w >= PW_HIGH));
+        assert!(pw.windows(2).any(|w| w[1] < w[0]));
+        assert!(pw.iter().all(|&w| w <= 0x0FFF));
+    }
+
+    #[test]
+    fn tempo_change_is_followed() {
+        let mut module = module(pulse());
+        // 8 rows at 125 BPM, then 8 rows at 250 BPM
+        module.pattern[0][8][1].effect_type = 0x0F;
+        module.pattern[0][8][1].effect_parameter = 250;
+        let pcm = SidPlayer::new(&module, SidModel::Mos8580, 10000).render_song();
+        // 24 ticks of 200 samples, then 24 ticks of 100 samples
+        assert_eq!(pcm.len(), 24 * 200 + 24 * 100);
+    }
+
+    #[test]
+    fn bundled_tune_is_rendered() {
+        let data = include_bytes!("songs/monty_on_the_run.sid");
+        let sid = super::super::sid_module::SidModule::load(data).unwrap();
+        let modules = sid.to_modules(true);
+        let mut player = SidPlayer::new(&modules[0], SidModel::Mos6581, 22050);
+        let mut out = vec![];
+        for _ in 0..200 {
+            assert!(player.render_tick(&mut out));
+        }
+        assert!(out.iter().any(|&x| x.abs() > 1000));
+    }
+}
@@ -0,0 +1,484 @@
+use alloc::vec::Vec;
+
+use super::one_sid::HubbardDriver;
+use super::psid_file::PsidFile;
+
+/// Hand-tuned Rob Hubbard driver tables of a known PSID file
+#[derive(Clone, Debug)]
+pub struct KnownSid {
+    /// PSID header name
+    pub title: &'static str,
+    pub load_address: u16,
+    /// `SidRegistry::data_hash()` of the PSID data
+    pub data_hash: u32,
+    pub name: &'static str,
+    pub author: &'static str,
+    pub copyright: &'static str,
+    pub driver: HubbardDriver,
+}
+
+/// Known Rob Hubbard tunes, a file can hold several players (Sanxion)
+pub const KNOWN_SIDS: &[KnownSid] = &[
+    KnownSid {
+        title: "Commando",
+        load_address: 0x5000,
+        data_hash: 0x30394ACC,
+        name: "Commando",
+        author: "Rob Hubbard",
+        copyright: "1985 Elite",
+        driver: HubbardDriver {
+            version: 10,
+            song_track_qty: 3,
+            song_list_offset: 0x56FF,
+            song_list_qty: 3,
+            patt_ptl_offset: 0x5711,
+            patt_pth_offset: 0x573E,
+            patt_qty: 45,
+            instr_offset: 0x5591,
+            instr_qty: 13,
+            fx_v1_offset: 0x55F9,
+            fx_v1_qty: 16,
+            fx_v2_offset: 0,
+            fx_v2_qty: 0,
+            resetspd: 2, // [2, 3, 2]
+            skydive_v1_when: 2,
+            skydive_v1_add: 512,
+        },
+    },
+    KnownSid {
+        title: "Crazy Comets",
+        load_address: 0x5000,
+        data_hash: 0xFA44069E,
+        name: "Crazy Comets",
+        author: "Rob Hubbard",
+        copyright: "1985 Martech",
+        driver: HubbardDriver {
+            version: 10,
+            song_track_qty: 3,
+            song_list_offset: 0x5732,
+            song_list_qty: 2,
+            patt_ptl_offset: 0x573E,
+            patt_pth_offset: 0x5773,
+            patt_qty: 53,
+            instr_offset: 0x5574,
+            instr_qty: 23,
+            fx_v1_offset: 0x562C,
+            fx_v1_qty: 1,
+            fx_v2_offset: 0,
+            fx_v2_qty: 0,
+            resetspd: 2,
+            skydive_v1_when: 16,
+            skydive_v1_add: -256,
+        },
+    },
+    KnownSid {
+        title: "The Last V8",
+        load_address: 0x8010,
+        data_hash: 0xAFB546DD,
+        name: "The Last V8",
+        author: "Rob Hubbard",
+        copyright: "1985 MAD/Mastertronic",
+        driver: HubbardDriver {
+            version: 10,
+            song_track_qty: 3,
+            song_list_offset: 0x8797,
+            song_list_qty: 3,
+            patt_ptl_offset: 0x87A9,
+            patt_pth_offset: 0x87C6,
+            patt_qty: 28,
+            instr_offset: 0x85A1,
+            instr_qty: 19,
+            fx_v1_offset: 0x8699,
+            fx_v1_qty: 12,
+            fx_v2_offset: 0,
+            fx_v2_qty: 0,
+            resetspd: 1,
+            skydive_v1_when: 0,
+            skydive_v1_add: -256,
+        },
+    },
+    KnownSid {
+        title: "Monty on the Run",
+        load_address: 0x8000,
+        data_hash: 0x6980C2A2,
+        name: "Monty on the Run",
+        author: "Rob Hubbard",
+        copyright: "1985 Gremlin Graphics",
+        driver: HubbardDriver {
+            version: 10,
+            song_track_qty: 3,
+            song_list_offset: 0x856C,
+            song_list_qty: 3,
+            patt_ptl_offset: 0x857E,
+            patt_pth_offset: 0x85CB,
+            patt_qty: 77,
+            instr_offset: 0x93B4,
+            instr_qty: 20,
+            fx_v1_offset: 0x9454,
+            fx_v1_qty: 16,
+            fx_v2_offset: 0,
+            fx_v2_qty: 0,
+            resetspd: 1,
+            skydive_v1_when: 0,
+            skydive_v1_add: -256,
+        },
+    },
+    KnownSid {
+        title: "Thing on a Spring",
+        load_address: 0xC000,
+        data_hash: 0xC41F81BA,
+        name: "Thing on a Spring",
+        author: "Rob Hubbard",
+        copyright: "1985 Gremlin Graphics",
+        driver: HubbardDriver {
+            version: 10,
+            song_track_qty: 3,
+            song_list_offset: 0xC509,
+            song_list_qty: 1,
+            patt_ptl_offset: 0xC50F,
+            patt_pth_offset: 0xC533,
+            patt_qty: 36,
+            instr_offset: 0xCD2A,
+            instr_qty: 45,
+            fx_v1_offset: 0xCE92,
+            fx_v1_qty: 1,
+            fx_v2_offset: 0,
+            fx_v2_qty: 0,
+            resetspd: 1,
+            skydive_v1_when: 0,
+            skydive_v1_add: 0,
+        },
+    },
+    KnownSid {
+        title: "Zoids",
+        load_address: 0x1000,
+        data_hash: 0xCFA1D131,
+        name: "Zoids",
+        author: "Rob Hubbard",
+        copyright: "1986 Martech",
+        driver: HubbardDriver {
+            version: 10,
+            song_track_qty: 3,
+            song_list_offset: 0x14FC,
+            song_list_qty: 3,
+            patt_ptl_offset: 0x150E,
+            patt_pth_offset: 0x152D,
+            patt_qty: 31,
+            instr_offset: 0x147E,
+            instr_qty: 15,
+            fx_v1_offset: 0,
+            fx_v1_qty: 0,
+            fx_v2_offset: 0,
+            fx_v2_qty: 0,
+            resetspd: 1,
+            skydive_v1_when: 0,
+            skydive_v1_add: 0,
+        },
+    },
+    KnownSid {
+        title: "ACE II",
+        load_address: 0xE000,
+        data_hash: 0xDF57FB90,
+        name: "ACE II",
+        author: "Rob Hubbard",
+        copyright: "1987 Arcade",
+        driver: HubbardDriver {
+            version: 20,
+            song_track_qty: 3,
+            song_list_offset: 0xE67C,
+            song_list_qty: 1,
+            patt_ptl_offset: 0xE682,
+            patt_pth_offset: 0xE6A8,
+            patt_qty: 38,
+            instr_offset: 0xE5CB,
+            instr_qty: 10,
+            fx_v1_offset: 0,
+            fx_v1_qty: 0,
+            fx_v2_offset: 0,
+            fx_v2_qty: 0,
+            resetspd: 1,
+            skydive_v1_when: 0,
+            skydive_v1_add: 0,
+        },
+    },
+    KnownSid {
+        title: "Delta",
+        load_address: 0xBC00,
+        data_hash: 0x831768BD,
+        name: "Delta",
+        author: "Rob Hubbard",
+        copyright: "1987 Thalamus",
+        driver: HubbardDriver {
+            version: 30, // Compression _and_ pattern loop in channels
+            song_track_qty: 3,
+            song_list_offset: 0xC4F4,
+            song_list_qty: 13,
+            patt_ptl_offset: 0xC542,
+            patt_pth_offset: 0xC5AF,
+            patt_qty: 109,
+            instr_offset: 0xC38E,
+            instr_qty: 22,
+            fx_v1_offset: 0,
+            fx_v1_qty: 0,
+            fx_v2_offset: 0xC43E,
+            fx_v2_qty: 22,
+            resetspd: 1,
+            skydive_v1_when: 0,
+            skydive_v1_add: 0,
+        },
+    },
+    KnownSid {
+        title: "The Human Race",
+        load_address: 0x0980,
+        data_hash: 0x47838B41,
+        name: "The Human Race",
+        author: "Rob Hubbard",
+        copyright: "1985 Mastertronic",
+        driver: HubbardDriver {
+            version: 20,
+            song_track_qty: 2,
+            song_list_offset: 0x0E9F,
+            song_list_qty: 3,
+            patt_ptl_offset: 0x0EB3,
+            patt_pth_offset: 0x0F02,
+            patt_qty: 58,
+            instr_offset: 0x0DE3,
+            instr_qty: 23,
+            fx_v1_offset: 0,
+            fx_v1_qty: 0,
+            fx_v2_offset: 0,
+            fx_v2_qty: 0,
+            resetspd: 3,
+            skydive_v1_when: 0,
+            skydive_v1_add: -256,
+        },
+    },
+    KnownSid {
+        title: "International Karate",
+        load_address: 0xAE00,
+        data_hash: 0xFE28AF5C,
+        name: "International Karate",
+        author: "Rob Hubbard",
+        copyright: "1986 System 3",
+        driver: HubbardDriver {
+            version: 20,
+            song_track_qty: 3,
+            song_list_offset: 0xB3B0,
+            song_list_qty: 1,
+            patt_ptl_offset: 0xB3B6,
+            patt_pth_offset: 0xB3EB,
+            patt_qty: 53,
+            instr_offset: 0xB308,
+            instr_qty: 20,
+            fx_v1_offset: 0,
+            fx_v1_qty: 0,
+            fx_v2_offset: 0,
+            fx_v2_qty: 0,
+            resetspd: 2,
+            skydive_v1_when: 0,
+            skydive_v1_add: 0,
+        },
+    },
+    KnownSid {
+        title: "Lightforce",
+        load_address: 0xF000,
+        data_hash: 0x0508202F,
+        name: "Lightforce",
+        author: "Rob Hubbard",
+        copyright: "1986 Faster Than Light (FTL)",
+        driver: HubbardDriver {
+            version: 20,
+            song_track_qty: 3,
+            song_list_offset: 0xF778,
+            song_list_qty: 1,
+            patt_ptl_offset: 0xF77E,
+            patt_pth_offset: 0xF79D,
+            patt_qty: 31,
+            instr_offset: 0xF618,
+            instr_qty: 22,
+            fx_v1_offset: 0,
+            fx_v1_qty: 0,
+            fx_v2_offset: 0xF6C8,
+            fx_v2_qty: 22,
+            resetspd: 2,
+            skydive_v1_when: 0,
+            skydive_v1_add: 0,
+        },
+    },
+    KnownSid {
+        title: "Sanxion",
+        load_address: 0xB000,
+        data_hash: 0x3566F2D5,
+        name: "Sanxion Song 1",
+        author: "Rob Hubbard",
+        copyright: "1986 Thalamus",
+        driver: HubbardDriver {
+            version: 20,
+            song_track_qty: 3,
+            song_list_offset: 0xB73C,
+            song_list_qty: 1,
+            patt_ptl_offset: 0xB742,
+            patt_pth_offset: 0xB75D,
+            patt_qty: 27,
+            instr_offset: 0xB56C,
+            instr_qty: 29,
+            fx_v1_offset: 0,
+            fx_v1_qty: 0,
+            fx_v2_offset: 0xB654,
+            fx_v2_qty: 29,
+            resetspd: 2,
+            skydive_v1_when: 0,
+            skydive_v1_add: 0,
+        },
+    },
+    KnownSid {
+        title: "Sanxion",
+        load_address: 0xB000,
+        data_hash: 0x3566F2D5,
+        name: "Sanxion Song 2",
+        author: "Rob Hubbard",
+        copyright: "1986 Thalamus",
+        driver: HubbardDriver {
+            version: 20,
+            song_track_qty: 3,
+            song_list_offset: 0xC5F5,
+            song_list_qty: 1,
+            patt_ptl_offset: 0xC5FB,
+            patt_pth_offset: 0xC633,
+            patt_qty: 56,
+            instr_offset: 0xC4B5,
+            instr_qty: 20,
+            fx_v1_offset: 0,
+            fx_v1_qty: 0,
+            fx_v2_offset: 0xC5F5,
+            fx_v2_qty: 20,
+            resetspd: 2,
+            skydive_v1_when: 0,
+            skydive_v1_add: 0,
+        },
+    },
+    KnownSid {
+        title: "Spellbound",
+        load_address: 0xE000,
+        data_hash: 0xB2331BEE,
+        name: "Spellbound",
+        author: "Rob Hubbard",
+        copyright: "1986 MAD/Mastertronic",
+        driver: HubbardDriver {
+            version: 15, // XXX WARN: 0xE0E5 second byte & 0b1000_0000 is instrnr: not a vibrato! no vibrato. Using version 15 -- check soundfx too
+            song_track_qty: 3,
+            song_list_offset: 0xE6B6,
+            song_list_qty: 3,
+            patt_ptl_offset: 0xE6C8,
+            patt_pth_offset: 0xE6F2,
+            patt_qty: 42,
+            instr_offset: 0xE548,
+            instr_qty: 25,
+            fx_v1_offset: 0xE610,
+            fx_v1_qty: 16,
+            fx_v2_offset: 0,
+            fx_v2_qty: 0,
+            resetspd: 1,
+            skydive_v1_when: 0,
+            skydive_v1_add: 256,
+        },
+    },
+];
+
+pub struct SidRegistry;
+
+impl SidRegistry {
+    /// FNV-1a hash
+    pub fn data_hash(data: &[u8]) -> u32 {
+        data.iter().fold(0x811C_9DC5, |hash, &b| {
+            (hash ^ b as u32).wrapping_mul(0x0100_0193)
+        })
+    }
+
+    /// Known tune by its name
+    pub fn get(name: &str) -> Option<&'static KnownSid> {
+        KNOWN_SIDS.iter().find(|k| k.name == name)
+    }
+
+    /// Known parameter sets for this file, by data hash then by title and load address
+    pub fn find(psid: &PsidFile) -> Vec<&'static KnownSid> {
+        let hash = Self::data_hash(&psid.data);
+        let by_hash: Vec<&'static KnownSid> =
+            KNOWN_SIDS.iter().filter(|k| k.data_hash == hash).collect();
+        if !by_hash.is_empty() {
+            return by_hash;
+        }
+        KNOWN_SIDS
+            .iter()
+            .filter(|k| k.title == psid.name && k.load_address == psid.load_address)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sid::one_sid::OneSid;
+
+    fn commando() -> PsidFile {
+        PsidFile::load(include_bytes!("songs/commando.sid")).unwrap()
+    }
+
+    #[test]
+    fn fnv1a_hash() {
+        assert_eq!(SidRegistry::data_hash(b""), 0x811C_9DC5);
+        assert_eq!(SidRegistry::data_hash(b"a"), 0xE40C_292C);
+        assert_eq!(SidRegistry::data_hash(b"foobar"), 0xBF9C_F968);
+    }
+
+    #[test]
+    fn find_by_hash() {
+        let psid = commando();
+        assert_eq!(SidRegistry::data_hash(&psid.data), 0x3039_4ACC);
+        let known = SidRegistry::find(&psid);
+        assert_eq!(known.len(), 1);
+        assert_eq!(known[0].name, "Commando");
+
+        // the hash wins over the title
+        let mut psid = commando();
+        psid.name = "Something else".into();
+        assert_eq!(SidRegistry::find(&psid)[0].name, "Commando");
+
+        let sanxion = PsidFile::load(include_bytes!("songs/sanxion.sid")).unwrap();
+        assert_eq!(SidRegistry::find(&sanxion).len(), 2);
+    }
+
+    #[test]
+    fn find_by_title_and_load_address() {
+        // another rip of the same tune
+        let mut psid = commando();
+        psid.data.push(0);
+        let known = SidRegistry::find(&psid);
+        assert_eq!(known.len(), 1);
+        assert_eq!(known[0].name, "Commando");
+
+        psid.load_address += 1;
+        assert!(SidRegistry::find(&psid).is_empty());
+    }
+
+    #[test]
+    fn unknown_tune_uses_the_scanner() {
+        let mut psid = commando();
+        psid.data.push(0);
+        psid.name = "Not in the registry".into();
+        assert!(SidRegistry::find(&psid).is_empty());
+        assert!(SidRegistry::get("Not in the registry").is_none());
+
+        let sids = OneSid::from_psid(psid);
+        assert_eq!(sids.len(), 1);
+        // header strings, driver tables found by the scanner
+        assert_eq!(sids[0].name, "Not in the registry");
+        let known = &SidRegistry::get("Commando").unwrap().driver;
+        assert_eq!(sids[0].driver.song_list_offset, known.song_list_offset);
+        assert_eq!(sids[0].driver.instr_offset, known.instr_offset);
+        assert_eq!(sids[0].driver.patt_qty, known.patt_qty);
+
+        let sids = OneSid::from_psid(commando());
+        assert_eq!(sids[0].copyright, "1985 Elite");
+    }
+}
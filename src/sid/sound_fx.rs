@@ -1,5 +1,8 @@
 use crate::instr_sid::SidVoice;
+use crate::prelude::*;
+use alloc::{vec, vec::Vec};
 
+/// Rob Hubbard v10 sound effect
 #[derive(Debug)]
 pub struct SoundFx {
     pub incdec_start_at_end: bool,
@@ -13,3 +16,78 @@ pub struct SoundFx {
     pub voice0: SidVoice,
     pub voice1: SidVoice,
 }
+
+impl SoundFx {
+    /// Longest note sweep kept
+    const MAX_STEPS: usize = 64;
+
+    /// Frames between two notes of the sweep
+    pub fn step_frames(&self) -> usize {
+        self.incdec_counter.unsigned_abs().max(1) as usize
+    }
+
+    /// Notes from `note_start` to `note_end`, `note_delta` by `note_delta`
+    pub fn notes(&self) -> Vec<u8> {
+        let end = self.note_end.min(95);
+        let mut note = self.note_start.min(95);
+        let mut notes = vec![note];
+        if self.note_delta != 0 {
+            while note != end && notes.len() < Self::MAX_STEPS {
+                note = if note < end {
+                    note.saturating_add(self.note_delta).min(end)
+                } else {
+                    note.saturating_sub(self.note_delta).max(end)
+                };
+                notes.push(note);
+            }
+        }
+        notes
+    }
+
+    /// Voice 0 then voice 1 as Rob Hubbard instruments
+    pub fn to_instruments(&self) -> Vec<InstrRobSid> {
+        [self.voice0, self.voice1]
+            .iter()
+            .map(|voice| {
+                let mut irsid = InstrRobSid::default();
+                irsid.sid.voice[0] = *voice;
+                irsid
+            })
+            .collect()
+    }
+
+    /// One row per frame, voice 0 on channel 0 and voice 1 on channel 1
+    pub fn get_pattern(&self) -> Pattern {
+        let use_voice0 = self.voice0_ctrl || !self.voice1_ctrl;
+        let step_frames = self.step_frames();
+        let mut pattern: Pattern = vec![];
+
+        for (step, &n) in self.notes().iter().enumerate() {
+            let note: Note = (1 + n).try_into().unwrap();
+            let mut row: Row = vec![PatternSlot::default(); 2];
+            if use_voice0 {
+                row[0].note = note;
+                row[0].instrument = 1;
+            }
+            if self.voice1_ctrl {
+                // gate is toggled at each step
+                if self.flipflop_voice1_ctrl && step % 2 == 1 {
+                    row[1].note = Note::KeyOff;
+                } else {
+                    row[1].note = note;
+                    row[1].instrument = 2;
+                }
+            }
+            pattern.push(row);
+            for _ in 1..step_frames {
+                pattern.push(vec![PatternSlot::default(); 2]);
+            }
+        }
+
+        let mut row: Row = vec![PatternSlot::default(); 2];
+        row[0].note = Note::KeyOff;
+        row[1].note = Note::KeyOff;
+        pattern.push(row);
+        pattern
+    }
+}
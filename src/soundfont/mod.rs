@@ -0,0 +1,6 @@
+#![forbid(unsafe_code)]
+
+mod patch;
+
+pub mod sf2;
+pub mod sfz;
@@ -0,0 +1,312 @@
+use alloc::{vec, vec::Vec};
+
+#[cfg(feature = "micromath")]
+use micromath::F32Ext;
+#[cfg(feature = "libm")]
+use num_traits::float::Float;
+
+use crate::envelope::Envelope;
+use crate::instr_default::InstrDefault;
+use crate::period_helper::PeriodHelper;
+use crate::sample::{LoopType, Sample, SampleDataType};
+
+/// Seconds per tracker tick, at 125 bpm
+const TICK: f32 = 0.02;
+/// MIDI key of C-4, played at `PeriodHelper::C4_FREQ`
+pub(crate) const ROOT_KEY: u8 = 60;
+/// Sample rate of every exported sample
+pub(crate) const SAMPLE_RATE: u32 = PeriodHelper::C4_FREQ as u32;
+
+/// A sample played on a key range
+pub(crate) struct Zone<'a> {
+    /// Index in `InstrDefault::sample`
+    pub index: usize,
+    pub sample: &'a Sample,
+    pub low_key: u8,
+    pub high_key: u8,
+}
+
+/// Keys of each sample, as sounding notes of `Module::to_midi()`
+///
+/// Notes are moved by the sample `relative_note`, the lowest and highest
+/// zones are extended to the whole keyboard.
+pub(crate) fn zones(instr: &InstrDefault) -> Vec<Zone<'_>> {
+    let mut keys: [Option<usize>; 128] = [None; 128];
+    for (i, &s) in instr.sample_for_note.iter().enumerate() {
+        let Some(sample) = instr.sample.get(s as usize) else {
+            continue;
+        };
+        if sample.len() == 0 {
+            continue;
+        }
+        // note i + 1, C-4 is the MIDI key 60
+        let key = i as i32 + 12 + sample.relative_note as i32;
+        if (0..128).contains(&key) && keys[key as usize].is_none() {
+            keys[key as usize] = Some(s as usize);
+        }
+    }
+
+    let mut zones: Vec<Zone> = vec![];
+    for (key, index) in keys.iter().enumerate() {
+        let Some(index) = *index else {
+            continue;
+        };
+        match zones.last_mut() {
+            Some(zone) if zone.index == index && zone.high_key as usize + 1 == key => {
+                zone.high_key = key as u8;
+            }
+            _ => zones.push(Zone {
+                index,
+                sample: &instr.sample[index],
+                low_key: key as u8,
+                high_key: key as u8,
+            }),
+        }
+    }
+    if let Some(zone) = zones.first_mut() {
+        zone.low_key = 0;
+    }
+    if let Some(zone) = zones.last_mut() {
+        zone.high_key = 127;
+    }
+    zones
+}
+
+/// Mono 16-bit frames and loop `(start, end)`, ping-pong loops are unrolled
+pub(crate) fn pcm16(sample: &Sample) -> (Vec<i16>, Option<(u32, u32)>) {
+    let mut pcm: Vec<i16> = match sample.data.to_mono() {
+        SampleDataType::Depth8(v) => v.iter().map(|&x| (x as i16) << 8).collect(),
+        SampleDataType::Depth16(v) => v,
+        _ => vec![],
+    };
+    let start = sample.loop_start as usize;
+    let end = (start + sample.loop_length as usize).min(pcm.len());
+    if start >= end {
+        return (pcm, None);
+    }
+    match sample.flags {
+        LoopType::No => (pcm, None),
+        LoopType::Forward => {
+            pcm.truncate(end);
+            (pcm, Some((start as u32, end as u32)))
+        }
+        LoopType::PingPong => {
+            pcm.truncate(end);
+            let back: Vec<i16> = pcm[start + 1..end.saturating_sub(1).max(start + 1)]
+                .iter()
+                .rev()
+                .copied()
+                .collect();
+            pcm.extend(back);
+            let end = pcm.len() as u32;
+            (pcm, Some((start as u32, end)))
+        }
+    }
+}
+
+/// Sample finetune in cents
+pub(crate) fn tune(sample: &Sample) -> i32 {
+    (sample.finetune.clamp(-1.0, 1.0) * 100.0).round() as i32
+}
+
+/// Attenuation of a linear volume in dB, 0 is full volume
+pub(crate) fn attenuation(volume: f32) -> f32 {
+    if volume <= 0.0 {
+        144.0
+    } else {
+        (-20.0 * volume.min(1.0).log10()).min(144.0)
+    }
+}
+
+/// Volume envelope approximation, in seconds and linear level
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Adsr {
+    pub attack: f32,
+    pub decay: f32,
+    pub sustain: f32,
+    pub release: f32,
+}
+
+impl Adsr {
+    /// Attack to the highest point, decay to the sustain point or to the end
+    pub fn from_instrument(instr: &InstrDefault) -> Self {
+        let env: &Envelope = &instr.volume_envelope;
+        let fadeout = (instr.volume_fadeout > 0.0).then(|| TICK / instr.volume_fadeout);
+        if !env.enabled || env.point.is_empty() || !env.has_valid_points() {
+            // key off stops the sample at once without fadeout
+            return Self {
+                attack: 0.0,
+                decay: 0.0,
+                sustain: 1.0,
+                release: fadeout.unwrap_or(0.01),
+            };
+        }
+
+        let last = env.point.len() - 1;
+        let sustain = if env.sustain_enabled {
+            env.sustain_point
+        } else {
+            last
+        };
+        let peak = (0..=sustain)
+            .rev()
+            .max_by(|&a, &b| env.point[a].value.total_cmp(&env.point[b].value))
+            .unwrap_or(0);
+        let seconds = |a: usize, b: usize| {
+            env.point[b].frame.saturating_sub(env.point[a].frame) as f32 * TICK
+        };
+
+        // after key off the envelope goes on to its end
+        let release = match fadeout {
+            Some(fadeout) => fadeout,
+            None if env.point[last].value <= 0.0 => seconds(sustain, last).max(0.01),
+            None => 100.0,
+        };
+        Self {
+            attack: seconds(0, peak),
+            decay: seconds(peak, sustain),
+            sustain: env.point[sustain].value.clamp(0.0, 1.0),
+            release,
+        }
+    }
+}
+
+/// Instrument vibrato depth in cents and rate in Hz
+pub(crate) fn vibrato(instr: &InstrDefault) -> Option<(f32, f32)> {
+    let v = &instr.vibrato;
+    // XM depth is in 1/64 semitone, the rate in 1/256 cycle per tick
+    let cents = v.depth * 30.0 * 100.0 / 64.0;
+    let hz = v.speed * 63.0 * 4.0 / 256.0 / TICK;
+    (cents > 0.0 && hz > 0.0).then_some((cents, hz))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::envelope::EnvelopePoint;
+    use alloc::string::ToString;
+
+    fn sample(relative_note: i8, flags: LoopType, data: SampleDataType) -> Sample {
+        Sample {
+            name: "".to_string(),
+            loop_start: 1,
+            loop_length: 4,
+            volume: 1.0,
+            finetune: 0.0,
+            flags,
+            panning: 0.5,
+            relative_note,
+            data,
+        }
+    }
+
+    fn depth16() -> SampleDataType {
+        SampleDataType::Depth16(vec![0, 1, 2, 3, 4, 5])
+    }
+
+    #[test]
+    fn zones_follow_relative_note() {
+        let mut instr = InstrDefault::default();
+        instr.sample.push(sample(0, LoopType::No, depth16()));
+        instr.sample.push(sample(-12, LoopType::No, depth16()));
+        for (note, s) in instr.sample_for_note.iter_mut().enumerate() {
+            *s = (note >= 48) as u8;
+        }
+
+        let zones = zones(&instr);
+        // sample 1 sounds one octave lower, its first keys are taken by sample 0
+        let keys: Vec<(usize, u8, u8)> = zones
+            .iter()
+            .map(|z| (z.index, z.low_key, z.high_key))
+            .collect();
+        assert_eq!(keys, vec![(0, 0, 59), (1, 60, 127)]);
+    }
+
+    #[test]
+    fn zones_skip_empty_samples() {
+        let mut instr = InstrDefault::default();
+        instr
+            .sample
+            .push(sample(0, LoopType::No, SampleDataType::Depth16(vec![])));
+        assert!(zones(&instr).is_empty());
+    }
+
+    #[test]
+    fn pcm16_loops() {
+        let (pcm, points) = pcm16(&sample(0, LoopType::No, depth16()));
+        assert_eq!(pcm, vec![0, 1, 2, 3, 4, 5]);
+        assert_eq!(points, None);
+
+        let (pcm, points) = pcm16(&sample(0, LoopType::Forward, depth16()));
+        assert_eq!(pcm, vec![0, 1, 2, 3, 4]);
+        assert_eq!(points, Some((1, 5)));
+
+        // played back without repeating the loop ends
+        let (pcm, points) = pcm16(&sample(0, LoopType::PingPong, depth16()));
+        assert_eq!(pcm, vec![0, 1, 2, 3, 4, 3, 2]);
+        assert_eq!(points, Some((1, 7)));
+    }
+
+    #[test]
+    fn pcm16_widens_8_bit() {
+        let data = SampleDataType::Depth8(vec![1, -1, 127, -128]);
+        let (pcm, _) = pcm16(&sample(0, LoopType::No, data));
+        assert_eq!(pcm, vec![256, -256, 32512, -32768]);
+    }
+
+    fn envelope(points: &[(usize, f32)], sustain: Option<usize>) -> Envelope {
+        Envelope {
+            enabled: true,
+            point: points
+                .iter()
+                .map(|&(frame, value)| EnvelopePoint { frame, value })
+                .collect(),
+            sustain_enabled: sustain.is_some(),
+            sustain_point: sustain.unwrap_or(0),
+            ..Default::default()
+        }
+    }
+
+    fn close(a: f32, b: f32) -> bool {
+        (a - b).abs() < 1e-4
+    }
+
+    #[test]
+    fn adsr_from_volume_envelope() {
+        let mut instr = InstrDefault {
+            volume_fadeout: 0.0,
+            volume_envelope: envelope(&[(0, 0.0), (10, 1.0), (20, 0.5), (40, 0.0)], Some(2)),
+            ..Default::default()
+        };
+        let adsr = Adsr::from_instrument(&instr);
+        assert!(close(adsr.attack, 0.2));
+        assert!(close(adsr.decay, 0.2));
+        assert!(close(adsr.sustain, 0.5));
+        // released along the envelope to its end
+        assert!(close(adsr.release, 0.4));
+
+        // without sustain the decay goes on to the last point
+        instr.volume_envelope = envelope(&[(0, 1.0), (25, 0.25)], None);
+        let adsr = Adsr::from_instrument(&instr);
+        assert!(close(adsr.attack, 0.0));
+        assert!(close(adsr.decay, 0.5));
+        assert!(close(adsr.sustain, 0.25));
+        assert!(close(adsr.release, 100.0));
+    }
+
+    #[test]
+    fn adsr_uses_fadeout() {
+        // the default volume envelope is disabled
+        let mut instr = InstrDefault {
+            volume_fadeout: 0.01,
+            ..Default::default()
+        };
+        let adsr = Adsr::from_instrument(&instr);
+        assert!(close(adsr.attack, 0.0));
+        assert!(close(adsr.sustain, 1.0));
+        assert!(close(adsr.release, 2.0));
+
+        instr.volume_envelope = envelope(&[(0, 1.0), (10, 0.0)], Some(0));
+        assert!(close(Adsr::from_instrument(&instr).release, 2.0));
+    }
+}
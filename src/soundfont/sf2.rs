@@ -0,0 +1,401 @@
+use alloc::{vec, vec::Vec};
+
+#[cfg(feature = "micromath")]
+use micromath::F32Ext;
+#[cfg(feature = "libm")]
+use num_traits::float::Float;
+
+use super::patch::{attenuation, pcm16, tune, vibrato, zones, Adsr, ROOT_KEY, SAMPLE_RATE};
+use crate::instr_default::InstrDefault;
+use crate::instrument::InstrumentType;
+use crate::module::Module;
+
+/// Zero frames after each sample, required by the specification
+const SAMPLE_PADDING: usize = 46;
+
+/// SF2 generators
+const GEN_PAN: u16 = 17;
+const GEN_VIB_LFO_TO_PITCH: u16 = 6;
+const GEN_FREQ_VIB_LFO: u16 = 24;
+const GEN_ATTACK_VOL_ENV: u16 = 34;
+const GEN_DECAY_VOL_ENV: u16 = 36;
+const GEN_SUSTAIN_VOL_ENV: u16 = 37;
+const GEN_RELEASE_VOL_ENV: u16 = 38;
+const GEN_INSTRUMENT: u16 = 41;
+const GEN_KEY_RANGE: u16 = 43;
+const GEN_INITIAL_ATTENUATION: u16 = 48;
+const GEN_SAMPLE_ID: u16 = 53;
+const GEN_SAMPLE_MODES: u16 = 54;
+
+/// Frequency of the absolute cent 0, in Hz
+const CENT_0_HZ: f32 = 8.176;
+
+fn chunk(id: &[u8; 4], data: &[u8]) -> Vec<u8> {
+    let mut chunk = id.to_vec();
+    chunk.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    chunk.extend_from_slice(data);
+    if !data.len().is_multiple_of(2) {
+        chunk.push(0);
+    }
+    chunk
+}
+
+fn list(kind: &[u8; 4], chunks: &[Vec<u8>]) -> Vec<u8> {
+    let mut data = kind.to_vec();
+    for c in chunks {
+        data.extend_from_slice(c);
+    }
+    chunk(b"LIST", &data)
+}
+
+/// Zero terminated string of even length
+fn zstr(text: &str) -> Vec<u8> {
+    let mut data: Vec<u8> = text.bytes().take(255).collect();
+    data.push(0);
+    if !data.len().is_multiple_of(2) {
+        data.push(0);
+    }
+    data
+}
+
+fn name20(name: &str) -> [u8; 20] {
+    let mut data = [0u8; 20];
+    for (d, c) in data.iter_mut().zip(name.bytes().take(19)) {
+        *d = if c.is_ascii() { c } else { b'_' };
+    }
+    data
+}
+
+/// Seconds to timecents
+fn timecents(seconds: f32) -> i16 {
+    if seconds < 0.001 {
+        -12000
+    } else {
+        (1200.0 * seconds.log2()).round().clamp(-12000.0, 8000.0) as i16
+    }
+}
+
+/// dB to centibels
+fn centibels(db: f32) -> i16 {
+    (db * 10.0).round().clamp(0.0, 1440.0) as i16
+}
+
+#[derive(Default)]
+struct Sf2Writer {
+    smpl: Vec<u8>,
+    phdr: Vec<u8>,
+    pbag: Vec<u8>,
+    pgen: Vec<u8>,
+    inst: Vec<u8>,
+    ibag: Vec<u8>,
+    igen: Vec<u8>,
+    shdr: Vec<u8>,
+    /// Counts, used as indexes
+    presets: u16,
+    pbags: u16,
+    pgens: u16,
+    instruments: u16,
+    ibags: u16,
+    igens: u16,
+    samples: u16,
+    frames: u32,
+}
+
+impl Sf2Writer {
+    fn igen(&mut self, generator: u16, amount: i16) {
+        self.igen.extend_from_slice(&generator.to_le_bytes());
+        self.igen.extend_from_slice(&amount.to_le_bytes());
+        self.igens += 1;
+    }
+
+    fn ibag(&mut self) {
+        self.ibag.extend_from_slice(&self.igens.to_le_bytes());
+        self.ibag.extend_from_slice(&0u16.to_le_bytes());
+        self.ibags += 1;
+    }
+
+    fn add_instrument(&mut self, (bank, preset): (u16, u16), name: &str, instr: &InstrDefault) {
+        let zones = zones(instr);
+        if zones.is_empty() {
+            return;
+        }
+
+        // === samples, once each
+        // sample index, sample id, looped
+        let mut sample_ids: Vec<(usize, u16, bool)> = vec![];
+        for zone in &zones {
+            if sample_ids.iter().any(|s| s.0 == zone.index) {
+                continue;
+            }
+            let (pcm, loop_points) = pcm16(zone.sample);
+            let start = self.frames;
+            let end = start + pcm.len() as u32;
+            let (loop_start, loop_end) =
+                loop_points.map_or((start, end), |(s, e)| (start + s, start + e));
+            for frame in pcm {
+                self.smpl.extend_from_slice(&frame.to_le_bytes());
+            }
+            self.smpl.extend_from_slice(&[0; SAMPLE_PADDING * 2]);
+            self.frames = end + SAMPLE_PADDING as u32;
+
+            let sample_name = if zone.sample.name.trim().is_empty() {
+                alloc::format!("{} {}", name, zone.index + 1)
+            } else {
+                zone.sample.name.clone()
+            };
+            self.shdr.extend_from_slice(&name20(&sample_name));
+            for value in [start, end, loop_start, loop_end, SAMPLE_RATE] {
+                self.shdr.extend_from_slice(&value.to_le_bytes());
+            }
+            self.shdr.push(ROOT_KEY);
+            self.shdr.push(tune(zone.sample).clamp(-99, 99) as i8 as u8);
+            // no link, mono sample
+            self.shdr.extend_from_slice(&0u16.to_le_bytes());
+            self.shdr.extend_from_slice(&1u16.to_le_bytes());
+            sample_ids.push((zone.index, self.samples, loop_points.is_some()));
+            self.samples += 1;
+        }
+
+        // === instrument, global zone then one zone per key range
+        self.inst.extend_from_slice(&name20(name));
+        self.inst.extend_from_slice(&self.ibags.to_le_bytes());
+
+        self.ibag();
+        let adsr = Adsr::from_instrument(instr);
+        self.igen(GEN_ATTACK_VOL_ENV, timecents(adsr.attack));
+        self.igen(GEN_DECAY_VOL_ENV, timecents(adsr.decay));
+        self.igen(GEN_SUSTAIN_VOL_ENV, centibels(attenuation(adsr.sustain)));
+        self.igen(GEN_RELEASE_VOL_ENV, timecents(adsr.release));
+        if let Some((cents, hz)) = vibrato(instr) {
+            self.igen(GEN_VIB_LFO_TO_PITCH, cents.round().min(12000.0) as i16);
+            let frequency = (1200.0 * (hz / CENT_0_HZ).log2()).round();
+            self.igen(GEN_FREQ_VIB_LFO, frequency.clamp(-16000.0, 4500.0) as i16);
+        }
+
+        for zone in &zones {
+            self.ibag();
+            let range = zone.low_key as u16 | (zone.high_key as u16) << 8;
+            self.igen(GEN_KEY_RANGE, range as i16);
+            self.igen(
+                GEN_INITIAL_ATTENUATION,
+                centibels(attenuation(zone.sample.volume)),
+            );
+            let pan = ((zone.sample.panning.clamp(0.0, 1.0) - 0.5) * 1000.0).round();
+            self.igen(GEN_PAN, pan as i16);
+            let (_, id, looped) = sample_ids
+                .iter()
+                .find(|s| s.0 == zone.index)
+                .copied()
+                .unwrap_or_default();
+            self.igen(GEN_SAMPLE_MODES, looped as i16);
+            self.igen(GEN_SAMPLE_ID, id as i16);
+        }
+
+        // === preset
+        self.phdr.extend_from_slice(&name20(name));
+        self.phdr.extend_from_slice(&preset.to_le_bytes());
+        self.phdr.extend_from_slice(&bank.to_le_bytes());
+        self.phdr.extend_from_slice(&self.pbags.to_le_bytes());
+        // library, genre, morphology
+        self.phdr.extend_from_slice(&[0; 12]);
+
+        self.pbag.extend_from_slice(&self.pgens.to_le_bytes());
+        self.pbag.extend_from_slice(&0u16.to_le_bytes());
+        self.pbags += 1;
+        self.pgen.extend_from_slice(&GEN_INSTRUMENT.to_le_bytes());
+        self.pgen.extend_from_slice(&self.instruments.to_le_bytes());
+        self.pgens += 1;
+
+        self.instruments += 1;
+        self.presets += 1;
+    }
+
+    fn save(mut self, bank_name: &str) -> Vec<u8> {
+        // terminal records
+        self.phdr.extend_from_slice(&name20("EOP"));
+        self.phdr.extend_from_slice(&[0; 4]);
+        self.phdr.extend_from_slice(&self.pbags.to_le_bytes());
+        self.phdr.extend_from_slice(&[0; 12]);
+        self.pbag.extend_from_slice(&self.pgens.to_le_bytes());
+        self.pbag.extend_from_slice(&0u16.to_le_bytes());
+        self.pgen.extend_from_slice(&[0; 4]);
+        self.inst.extend_from_slice(&name20("EOI"));
+        self.inst.extend_from_slice(&self.ibags.to_le_bytes());
+        self.ibag.extend_from_slice(&self.igens.to_le_bytes());
+        self.ibag.extend_from_slice(&0u16.to_le_bytes());
+        self.igen.extend_from_slice(&[0; 4]);
+        self.shdr.extend_from_slice(&name20("EOS"));
+        self.shdr.extend_from_slice(&[0; 26]);
+
+        let bank_name = if bank_name.trim().is_empty() {
+            "XmRs"
+        } else {
+            bank_name
+        };
+        let mut version = 2u16.to_le_bytes().to_vec();
+        version.extend_from_slice(&1u16.to_le_bytes());
+        let info = list(
+            b"INFO",
+            &[
+                chunk(b"ifil", &version),
+                chunk(b"isng", &zstr("EMU8000")),
+                chunk(b"INAM", &zstr(bank_name)),
+                chunk(b"ISFT", &zstr("XmRs")),
+            ],
+        );
+        let sdta = list(b"sdta", &[chunk(b"smpl", &self.smpl)]);
+        let pdta = list(
+            b"pdta",
+            &[
+                chunk(b"phdr", &self.phdr),
+                chunk(b"pbag", &self.pbag),
+                chunk(b"pmod", &[0; 10]),
+                chunk(b"pgen", &self.pgen),
+                chunk(b"inst", &self.inst),
+                chunk(b"ibag", &self.ibag),
+                chunk(b"imod", &[0; 10]),
+                chunk(b"igen", &self.igen),
+                chunk(b"shdr", &self.shdr),
+            ],
+        );
+
+        let mut data = b"sfbk".to_vec();
+        data.extend_from_slice(&info);
+        data.extend_from_slice(&sdta);
+        data.extend_from_slice(&pdta);
+        chunk(b"RIFF", &data)
+    }
+}
+
+impl Module {
+    /// Bank and preset of an instrument, 0 is the first instrument
+    ///
+    /// The MIDI program of an `InstrDefault` with `midi.on`, else the
+    /// preset `n % 128` of bank `n / 128`. `to_midi()` selects the same.
+    pub(crate) fn sf2_preset(&self, instrument: usize) -> (u16, u16) {
+        match self.instrument.get(instrument).map(|i| &i.instr_type) {
+            Some(InstrumentType::Default(id)) if id.midi.on => (0, id.midi.program.min(127)),
+            _ => ((instrument / 128) as u16, (instrument % 128) as u16),
+        }
+    }
+
+    /// SoundFont 2 bank, with the banks and programs sent by `to_midi()`
+    ///
+    /// Only `InstrDefault` instruments with samples are saved. The instrument
+    /// `n` is the preset `n % 128` of bank `n / 128`, or its MIDI program when
+    /// `midi.on` is set. Keys are the notes of `to_midi()`, stereo samples are
+    /// mixed to mono.
+    pub fn to_sf2(&self) -> Vec<u8> {
+        let mut sf2 = Sf2Writer::default();
+        for (i, instr) in self.instrument.iter().enumerate() {
+            if let InstrumentType::Default(id) = &instr.instr_type {
+                sf2.add_instrument(self.sf2_preset(i), &instr.name, id);
+            }
+        }
+        sf2.save(&self.name)
+    }
+
+    /// SoundFont 2 bank with one instrument as preset 0
+    pub fn instrument_to_sf2(&self, instrument: usize) -> Option<Vec<u8>> {
+        let instr = self.instrument.get(instrument)?;
+        let InstrumentType::Default(id) = &instr.instr_type else {
+            return None;
+        };
+        let mut sf2 = Sf2Writer::default();
+        sf2.add_instrument((0, 0), &instr.name, id);
+        if sf2.presets == 0 {
+            return None;
+        }
+        Some(sf2.save(&instr.name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::instrument::Instrument;
+    use crate::sample::{LoopType, Sample, SampleDataType};
+    use alloc::string::ToString;
+
+    /// Instruments with one sample of 16 frames
+    fn module(count: usize) -> Module {
+        let mut module = Module {
+            name: "bank".to_string(),
+            ..Default::default()
+        };
+        for i in 0..count {
+            let mut id = InstrDefault::default();
+            id.sample.push(Sample {
+                name: "".to_string(),
+                loop_start: 0,
+                loop_length: 0,
+                volume: 1.0,
+                finetune: 0.0,
+                flags: LoopType::No,
+                panning: 0.5,
+                relative_note: 0,
+                data: SampleDataType::Depth8((0..16).map(|v| v * 4).collect()),
+            });
+            module.instrument.push(Instrument {
+                name: alloc::format!("i{}", i),
+                instr_type: InstrumentType::Default(id),
+                ..Default::default()
+            });
+        }
+        module
+    }
+
+    /// Name, preset and bank of each preset header
+    fn presets(sf2: &[u8]) -> Vec<(Vec<u8>, u16, u16)> {
+        let at = sf2.windows(4).position(|w| w == b"phdr").unwrap();
+        let len = u32::from_le_bytes([sf2[at + 4], sf2[at + 5], sf2[at + 6], sf2[at + 7]]);
+        sf2[at + 8..at + 8 + len as usize]
+            .chunks_exact(38)
+            .map(|p| {
+                let name = p[0..20].iter().take_while(|&&c| c != 0).copied().collect();
+                let word = |i: usize| u16::from_le_bytes([p[i], p[i + 1]]);
+                (name, word(20), word(22))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn write_bank() {
+        let mut module = module(130);
+        if let InstrumentType::Default(id) = &mut module.instrument[2].instr_type {
+            id.midi.on = true;
+            id.midi.program = 40;
+        }
+        module.instrument[3].instr_type = InstrumentType::Empty;
+
+        let sf2 = module.to_sf2();
+        assert_eq!(&sf2[0..4], b"RIFF");
+        assert_eq!(&sf2[8..12], b"sfbk");
+        let len = u32::from_le_bytes([sf2[4], sf2[5], sf2[6], sf2[7]]) as usize;
+        assert_eq!(len + 8, sf2.len());
+        assert!(sf2.windows(5).any(|w| w == b"bank\0"));
+
+        let presets = presets(&sf2);
+        assert_eq!(presets.len(), 130);
+        assert_eq!(presets[0], (b"i0".to_vec(), 0, 0));
+        assert_eq!(presets[1], (b"i1".to_vec(), 1, 0));
+        assert_eq!(presets[2], (b"i2".to_vec(), 40, 0));
+        // no instrument 3
+        assert_eq!(presets[3], (b"i4".to_vec(), 4, 0));
+        assert_eq!(presets[127], (b"i128".to_vec(), 0, 1));
+        assert_eq!(presets[128], (b"i129".to_vec(), 1, 1));
+        assert_eq!(presets[129].0, b"EOP");
+        for i in [0, 2, 129] {
+            let (bank, preset) = module.sf2_preset(i);
+            assert!(presets.contains(&(alloc::format!("i{}", i).into_bytes(), preset, bank)));
+        }
+    }
+
+    #[test]
+    fn write_one_instrument() {
+        let module = module(130);
+        let sf2 = module.instrument_to_sf2(129).unwrap();
+        assert_eq!(presets(&sf2)[0], (b"i129".to_vec(), 0, 0));
+        assert!(module.instrument_to_sf2(130).is_none());
+    }
+}
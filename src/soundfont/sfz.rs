@@ -0,0 +1,257 @@
+use alloc::string::String;
+use alloc::{format, vec, vec::Vec};
+
+use super::patch::{attenuation, pcm16, tune, vibrato, zones, Adsr, ROOT_KEY, SAMPLE_RATE};
+use crate::instr_default::InstrDefault;
+use crate::instrument::InstrumentType;
+use crate::module::Module;
+
+/// A file of an SFZ export
+#[derive(Default, Clone, Debug)]
+pub struct SfzFile {
+    /// File name, relative to the `.sfz` file
+    pub name: String,
+    pub data: Vec<u8>,
+}
+
+/// Mono 16-bit PCM WAV file
+fn wav(pcm: &[i16]) -> Vec<u8> {
+    let mut fmt: Vec<u8> = vec![];
+    // PCM, one channel
+    fmt.extend_from_slice(&1u16.to_le_bytes());
+    fmt.extend_from_slice(&1u16.to_le_bytes());
+    fmt.extend_from_slice(&SAMPLE_RATE.to_le_bytes());
+    fmt.extend_from_slice(&(SAMPLE_RATE * 2).to_le_bytes());
+    fmt.extend_from_slice(&2u16.to_le_bytes());
+    fmt.extend_from_slice(&16u16.to_le_bytes());
+
+    let mut data = b"WAVE".to_vec();
+    data.extend_from_slice(b"fmt ");
+    data.extend_from_slice(&(fmt.len() as u32).to_le_bytes());
+    data.extend_from_slice(&fmt);
+    data.extend_from_slice(b"data");
+    data.extend_from_slice(&(pcm.len() as u32 * 2).to_le_bytes());
+    for frame in pcm {
+        data.extend_from_slice(&frame.to_le_bytes());
+    }
+
+    let mut riff = b"RIFF".to_vec();
+    riff.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    riff.append(&mut data);
+    riff
+}
+
+/// File name without path separators and odd characters
+fn file_name(name: &str) -> String {
+    let name: String = name
+        .trim()
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    if name.is_empty() {
+        "instrument".into()
+    } else {
+        name
+    }
+}
+
+/// `.sfz` file then its `.wav` samples
+fn sfz(prefix: &str, title: &str, instr: &InstrDefault) -> Vec<SfzFile> {
+    let zones = zones(instr);
+    let mut files: Vec<SfzFile> = vec![];
+    if zones.is_empty() {
+        return files;
+    }
+
+    let title = match title.trim() {
+        "" => prefix,
+        title => title,
+    };
+    let mut text = format!("// {}\n\n<group>\n", title);
+    let adsr = Adsr::from_instrument(instr);
+    text += &format!(
+        "ampeg_attack={:.3} ampeg_decay={:.3} ampeg_sustain={:.1} ampeg_release={:.3}\n",
+        adsr.attack,
+        adsr.decay,
+        adsr.sustain * 100.0,
+        adsr.release
+    );
+    if let Some((cents, hz)) = vibrato(instr) {
+        text += &format!("pitchlfo_depth={:.0} pitchlfo_freq={:.2}\n", cents, hz);
+    }
+
+    let mut samples: Vec<(usize, String)> = vec![];
+    for zone in &zones {
+        let (pcm, loop_points) = pcm16(zone.sample);
+        let sample_file = match samples.iter().find(|s| s.0 == zone.index) {
+            Some(s) => s.1.clone(),
+            None => {
+                let name = format!("{}_{:02}.wav", prefix, zone.index + 1);
+                files.push(SfzFile {
+                    name: name.clone(),
+                    data: wav(&pcm),
+                });
+                samples.push((zone.index, name.clone()));
+                name
+            }
+        };
+
+        text += &format!(
+            "\n<region>\nsample={}\nlokey={} hikey={} pitch_keycenter={} tune={}\n",
+            sample_file,
+            zone.low_key,
+            zone.high_key,
+            ROOT_KEY,
+            tune(zone.sample)
+        );
+        text += &format!(
+            "volume={:.2} pan={:.0}\n",
+            -attenuation(zone.sample.volume),
+            (zone.sample.panning.clamp(0.0, 1.0) - 0.5) * 200.0
+        );
+        match loop_points {
+            Some((start, end)) => {
+                text += &format!(
+                    "loop_mode=loop_continuous loop_start={} loop_end={}\n",
+                    start,
+                    end - 1
+                )
+            }
+            None => text += "loop_mode=no_loop\n",
+        }
+    }
+
+    files.insert(
+        0,
+        SfzFile {
+            name: format!("{}.sfz", prefix),
+            data: text.into_bytes(),
+        },
+    );
+    files
+}
+
+impl Module {
+    /// SFZ files and WAV samples of each `InstrDefault` instrument
+    ///
+    /// Instrument `n` is saved in `NN_name.sfz`, `NN` being `n + 1`. Keys are
+    /// the notes of `to_midi()`, which selects the programs of `to_sf2()`.
+    /// Stereo samples are mixed to mono.
+    pub fn to_sfz(&self) -> Vec<SfzFile> {
+        (0..self.instrument.len())
+            .flat_map(|i| self.instrument_to_sfz(i))
+            .collect()
+    }
+
+    /// SFZ file and WAV samples of one instrument, empty if it has no sample
+    pub fn instrument_to_sfz(&self, instrument: usize) -> Vec<SfzFile> {
+        match self.instrument.get(instrument) {
+            Some(instr) => match &instr.instr_type {
+                InstrumentType::Default(id) => {
+                    let prefix = format!("{:02}_{}", instrument + 1, file_name(&instr.name));
+                    sfz(&prefix, &instr.name, id)
+                }
+                _ => vec![],
+            },
+            None => vec![],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::instrument::Instrument;
+    use crate::sample::{LoopType, Sample, SampleDataType};
+    use alloc::string::ToString;
+
+    fn u16_at(data: &[u8], at: usize) -> u16 {
+        u16::from_le_bytes([data[at], data[at + 1]])
+    }
+
+    fn u32_at(data: &[u8], at: usize) -> u32 {
+        u32::from_le_bytes([data[at], data[at + 1], data[at + 2], data[at + 3]])
+    }
+
+    /// One instrument with a forward looped 8 frames sample
+    fn module(name: &str) -> Module {
+        let mut id = InstrDefault::default();
+        id.sample.push(Sample {
+            name: "".to_string(),
+            loop_start: 2,
+            loop_length: 6,
+            volume: 1.0,
+            finetune: 0.0,
+            flags: LoopType::Forward,
+            panning: 0.5,
+            relative_note: 0,
+            data: SampleDataType::Depth16((0..8).collect()),
+        });
+        let mut module = Module::default();
+        module.instrument.push(Instrument {
+            name: name.to_string(),
+            instr_type: InstrumentType::Default(id),
+            ..Default::default()
+        });
+        module
+    }
+
+    #[test]
+    fn wav_header() {
+        let data = wav(&[1, -2, 3]);
+        assert_eq!(data.len(), 44 + 6);
+        assert_eq!(&data[0..4], b"RIFF");
+        assert_eq!(u32_at(&data, 4), data.len() as u32 - 8);
+        assert_eq!(&data[8..16], b"WAVEfmt ");
+        assert_eq!(u32_at(&data, 16), 16);
+        assert_eq!(u16_at(&data, 20), 1);
+        assert_eq!(u16_at(&data, 22), 1);
+        assert_eq!(u32_at(&data, 24), SAMPLE_RATE);
+        assert_eq!(u32_at(&data, 28), SAMPLE_RATE * 2);
+        assert_eq!(u16_at(&data, 32), 2);
+        assert_eq!(u16_at(&data, 34), 16);
+        assert_eq!(&data[36..40], b"data");
+        assert_eq!(u32_at(&data, 40), 6);
+        assert_eq!(u16_at(&data, 46) as i16, -2);
+    }
+
+    #[test]
+    fn sfz_text_and_samples() {
+        let files = module(" lead/1 ").to_sfz();
+        let names: Vec<&str> = files.iter().map(|f| f.name.as_str()).collect();
+        assert_eq!(names, vec!["01_lead_1.sfz", "01_lead_1_01.wav"]);
+
+        let text = core::str::from_utf8(&files[0].data).unwrap();
+        assert!(text.starts_with("// lead/1\n\n<group>\n"));
+        assert!(text.contains("\n<region>\nsample=01_lead_1_01.wav\n"));
+        assert!(text.contains("lokey=0 hikey=127 pitch_keycenter=60 tune=0\n"));
+        // SFZ loop end is the last frame of the loop
+        assert!(text.contains("loop_mode=loop_continuous loop_start=2 loop_end=7\n"));
+
+        let wav = &files[1].data;
+        assert_eq!(u32_at(wav, 40), 16);
+        assert_eq!(u16_at(wav, 44 + 7 * 2), 7);
+    }
+
+    #[test]
+    fn sfz_title_falls_back_to_prefix() {
+        let files = module("").to_sfz();
+        assert_eq!(files[0].name, "01_instrument.sfz");
+        let text = core::str::from_utf8(&files[0].data).unwrap();
+        assert!(text.starts_with("// 01_instrument\n"));
+    }
+
+    #[test]
+    fn sfz_without_sample() {
+        let mut module = module("empty");
+        module.instrument[0].instr_type = InstrumentType::Default(InstrDefault::default());
+        assert!(module.to_sfz().is_empty());
+        assert!(module.instrument_to_sfz(1).is_empty());
+    }
+}
@@ -0,0 +1,4 @@
+#![forbid(unsafe_code)]
+
+pub mod serde_helper;
+pub mod stm_module;
@@ -0,0 +1,422 @@
+use super::serde_helper::{deserialize_string_12, deserialize_string_20, deserialize_string_8};
+use bincode::error::DecodeError;
+use serde::Deserialize;
+
+use crate::prelude::*;
+
+use alloc::string::String;
+use alloc::string::ToString;
+use alloc::{vec, vec::Vec};
+
+const STM_HEADER_SIZE: usize = 48;
+const STM_SAMPLE_SIZE: usize = 32;
+const STM_SAMPLES: usize = 31;
+const STM_CHANNELS: usize = 4;
+const STM_ROWS: usize = 64;
+/// End of the order list
+const STM_ORDER_END: u8 = 99;
+
+#[repr(C)]
+#[derive(Default, Deserialize, Debug)]
+struct StmHeader {
+    #[serde(deserialize_with = "deserialize_string_20")]
+    title: String,
+    /// "!Scream!", "BMOD2STM"...
+    #[serde(deserialize_with = "deserialize_string_8")]
+    tracker: String,
+    /// 0x1A
+    sig1: u8,
+    /// 1=song, 2=module
+    file_type: u8,
+    version_major: u8,
+    version_minor: u8,
+    /// speed in high nibble, tempo factor in low nibble
+    tempo: u8,
+    pattern_count: u8,
+    global_volume: u8,
+    reserved: [u8; 13],
+}
+
+impl StmHeader {
+    /// Before 2.21, tempo is written in decimal
+    fn fix_tempo(&self, tempo: u8) -> u8 {
+        if self.version_minor < 21 {
+            ((tempo / 10) << 4) + tempo % 10
+        } else {
+            tempo
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Default, Deserialize, Debug)]
+struct StmSample {
+    #[serde(deserialize_with = "deserialize_string_12")]
+    filename: String,
+    zero: u8,
+    disk: u8,
+    /// offset = ptr_data << 4
+    ptr_data: u16,
+    len: u16,
+    loop_start: u16,
+    /// 0xFFFF: no loop
+    loop_end: u16,
+    /// 0-64
+    volume: u8,
+    reserved1: u8,
+    /// sample rate for middle-c note
+    c2spd: u16,
+    reserved2: [u8; 6],
+}
+
+impl StmSample {
+    fn is_loop(&self) -> bool {
+        self.loop_start < self.len && self.loop_end > self.loop_start && self.loop_end != 0xFFFF
+    }
+
+    fn get_sample_data(&self, data: &[u8]) -> Vec<i8> {
+        let offset = (self.ptr_data as usize) << 4;
+        if self.len == 0 || offset >= data.len() {
+            return vec![];
+        }
+        let end = (offset + self.len as usize).min(data.len());
+        data[offset..end].iter().map(|&x| x as i8).collect()
+    }
+}
+
+#[derive(Default, Debug)]
+pub struct StmModule {
+    header: StmHeader,
+    samples: Vec<StmSample>,
+    audio: Vec<Vec<i8>>,
+    positions: Vec<u8>,
+    patterns: Vec<Pattern>,
+}
+
+impl StmModule {
+    pub fn load(ser_stm_module: &[u8]) -> Result<StmModule, DecodeError> {
+        let mut stm = StmModule {
+            ..Default::default()
+        };
+
+        // === load header
+
+        let s = STM_HEADER_SIZE;
+        if ser_stm_module.len() < s {
+            return Err(DecodeError::Other("Not an STM module?"));
+        }
+        // tracker name must be printable
+        if !ser_stm_module[20..28]
+            .iter()
+            .all(|&c| (0x20..0x7F).contains(&c))
+        {
+            return Err(DecodeError::Other("Not an STM module?"));
+        }
+        stm.header = bincode::serde::decode_from_slice::<StmHeader, _>(
+            &ser_stm_module[0..s],
+            bincode::config::legacy(),
+        )?
+        .0;
+        let data = &ser_stm_module[s..];
+
+        // some broken files use 2 instead of 0x1A
+        if (stm.header.sig1 != 0x1A && stm.header.sig1 != 2)
+            || stm.header.file_type != 2
+            || stm.header.version_major != 2
+            || stm.header.pattern_count > 64
+        {
+            return Err(DecodeError::Other("Not an STM module?"));
+        }
+
+        // === samples
+
+        let s = STM_SAMPLES * STM_SAMPLE_SIZE;
+        if data.len() < s {
+            return Err(DecodeError::Other("Not an STM module?"));
+        }
+        for chunk in data[0..s].chunks_exact(STM_SAMPLE_SIZE) {
+            let sample = bincode::serde::decode_from_slice::<StmSample, _>(
+                chunk,
+                bincode::config::legacy(),
+            )?
+            .0;
+            stm.audio.push(sample.get_sample_data(ser_stm_module));
+            stm.samples.push(sample);
+        }
+        let data = &data[s..];
+
+        // === positions
+
+        let s = if stm.header.version_minor == 0 {
+            64
+        } else {
+            128
+        };
+        if data.len() < s {
+            return Err(DecodeError::Other("Not an STM module?"));
+        }
+        stm.positions = data[0..s]
+            .iter()
+            .copied()
+            .take_while(|&p| p < STM_ORDER_END && p < stm.header.pattern_count)
+            .collect();
+        let mut data = &data[s..];
+
+        // === patterns
+
+        for _ in 0..stm.header.pattern_count {
+            let mut pattern: Pattern = vec![vec![PatternSlot::default(); STM_CHANNELS]; STM_ROWS];
+            for slot in pattern.iter_mut().flatten() {
+                data = stm.decode_pattern_slot(data, slot)?;
+            }
+            stm.patterns.push(pattern);
+        }
+
+        Ok(stm)
+    }
+
+    // return next data
+    fn decode_pattern_slot<'a>(
+        &self,
+        data: &'a [u8],
+        slot: &mut PatternSlot,
+    ) -> Result<&'a [u8], DecodeError> {
+        if data.is_empty() {
+            return Err(DecodeError::Other("STM pattern is truncated"));
+        }
+        let (note, ins_vol, vol_cmd, cmd_inf, next) = match data[0] {
+            // packed slots, only one byte
+            0xFB => (0, 0, 0, 0, &data[1..]),
+            0xFC => return Ok(&data[1..]),
+            0xFD => {
+                slot.note = Note::NoteCut;
+                return Ok(&data[1..]);
+            }
+            _ => {
+                if data.len() < 4 {
+                    return Err(DecodeError::Other("STM pattern is truncated"));
+                }
+                (data[0], data[1], data[2], data[3], &data[4..])
+            }
+        };
+
+        slot.note = match note {
+            0xFE => Note::NoteCut,
+            0..=0x5F if note & 0x0F < 12 => {
+                // ST2 octave 2 is middle c
+                Note::try_from(1 + 24 + (note & 0x0F) + (note >> 4) * 12).unwrap_or(Note::None)
+            }
+            _ => Note::None,
+        };
+
+        slot.instrument = ins_vol >> 3;
+
+        let volume = (ins_vol & 0x07) | ((vol_cmd & 0xF0) >> 1);
+        if volume <= 64 {
+            slot.volume = 0x10 + volume;
+        }
+
+        slot.effect_type = vol_cmd & 0x0F;
+        slot.effect_parameter = cmd_inf;
+        self.efx_correction(slot);
+
+        Ok(next)
+    }
+
+    /// Convert ST2 effects to XM effects
+    fn efx_correction(&self, n: &mut PatternSlot) {
+        match n.effect_type {
+            1 => {
+                // A: ticks per row in high nibble, tempo factor is lost
+                let speed = self.header.fix_tempo(n.effect_parameter) >> 4;
+                n.effect_type = 0x0F;
+                n.effect_parameter = speed;
+            }
+            2 => n.effect_type = 0x0B, // B
+            3 => n.effect_type = 0x0D, // C
+            4 => {
+                // D: lower nibble first, no fine slide
+                n.effect_type = 0x0A;
+                if n.effect_parameter & 0x0F != 0 {
+                    n.effect_parameter &= 0x0F;
+                }
+            }
+            5 => n.effect_type = 0x02,  // E
+            6 => n.effect_type = 0x01,  // F
+            7 => n.effect_type = 0x03,  // G
+            8 => n.effect_type = 0x04,  // H
+            9 => n.effect_type = 0x1D,  // I
+            10 => n.effect_type = 0x00, // J
+            _ => {
+                n.effect_type = 0;
+                n.effect_parameter = 0;
+            }
+        }
+
+        // ST2 has no effect memory
+        if n.effect_parameter == 0 && matches!(n.effect_type, 0x01 | 0x02 | 0x0A | 0x0F | 0x1D) {
+            n.effect_type = 0;
+        }
+    }
+
+    /// Beats per minute of an ST2 tempo, from the highest mixing rate
+    fn bpm(tempo: u8) -> u16 {
+        const TEMPO_FACTOR: [i32; 16] = [140, 50, 25, 15, 10, 7, 6, 4, 3, 3, 2, 2, 2, 2, 1, 1];
+        const MIXING_RATE: i32 = 23863;
+        let divisor = 49 - ((TEMPO_FACTOR[(tempo >> 4) as usize] * (tempo & 0x0F) as i32) >> 4);
+        // ST2 underflows here, ticks depend on the mixing rate
+        let mut samples_per_tick = MIXING_RATE / divisor;
+        if samples_per_tick <= 0 {
+            samples_per_tick += 65536;
+        }
+        (MIXING_RATE * 5 / (samples_per_tick * 2)).clamp(32, 255) as u16
+    }
+
+    pub fn to_module(&self) -> Module {
+        let ph = PeriodHelper::new(FrequencyType::LinearFrequencies, false);
+        let tempo = self.header.fix_tempo(self.header.tempo);
+        let mut module = Module {
+            name: self.header.title.clone(),
+            comment: "XmRs reader".to_string(),
+            frequency_type: FrequencyType::LinearFrequencies,
+            default_tempo: (tempo >> 4).max(1) as u16,
+            default_bpm: Self::bpm(tempo),
+            pattern_order: self.positions.iter().map(|&x| x as usize).collect(),
+            pattern: self.patterns.clone(),
+            ..Default::default()
+        };
+
+        for (stm_sample, audio) in self.samples.iter().zip(&self.audio) {
+            let rn = ph.c4freq_to_relative_note(stm_sample.c2spd as f32);
+            let (flags, loop_start, loop_length) = if stm_sample.is_loop() {
+                (
+                    LoopType::Forward,
+                    stm_sample.loop_start as u32,
+                    (stm_sample.loop_end - stm_sample.loop_start) as u32,
+                )
+            } else {
+                (LoopType::No, 0, 0)
+            };
+            let mut sample = Sample {
+                name: stm_sample.filename.clone(),
+                loop_start,
+                loop_length,
+                volume: stm_sample.volume.min(64) as f32 / 64.0,
+                finetune: rn.1,
+                flags,
+                panning: 0.5,
+                relative_note: rn.0,
+                data: SampleDataType::Depth8(audio.clone()),
+            };
+            sample.fix_loop();
+
+            // Create InstrDefault
+            let mut instr_def = InstrDefault::default();
+            instr_def.sample.push(sample);
+
+            // Create Instrument
+            let instr = Instrument {
+                name: stm_sample.filename.clone(),
+                instr_type: InstrumentType::Default(instr_def),
+                ..Default::default()
+            };
+
+            module.instrument.push(instr);
+        }
+
+        module
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// One pattern module, sample 1 has 16 frames
+    fn stm() -> Vec<u8> {
+        let mut data = b"synthetic".to_vec();
+        data.resize(20, 0);
+        data.extend_from_slice(b"!Scream!");
+        // sig1, file_type, version 2.21, speed 6, one pattern, global volume
+        data.extend_from_slice(&[0x1A, 2, 2, 21, 0x60, 1, 64]);
+        data.resize(STM_HEADER_SIZE, 0);
+
+        let mut sample = b"sample.smp".to_vec();
+        sample.resize(14, 0);
+        sample.extend_from_slice(&(1440u16 >> 4).to_le_bytes());
+        for value in [16u16, 0, 0xFFFF] {
+            sample.extend_from_slice(&value.to_le_bytes());
+        }
+        sample.extend_from_slice(&[64, 0]);
+        sample.extend_from_slice(&8363u16.to_le_bytes());
+        sample.resize(STM_SAMPLE_SIZE, 0);
+        data.extend_from_slice(&sample);
+        data.resize(STM_HEADER_SIZE + STM_SAMPLES * STM_SAMPLE_SIZE, 0);
+
+        let mut orders = [STM_ORDER_END; 128];
+        orders[0] = 0;
+        data.extend_from_slice(&orders);
+
+        // C-2, instrument 1, volume 64, A60
+        data.extend_from_slice(&[0x20, 0x08, 0x81, 0x60]);
+        // note cut
+        data.push(0xFD);
+        // no note, no volume, D23
+        data.extend_from_slice(&[0xFF, 0x00, 0xF4, 0x23]);
+        data.resize(data.len() + STM_ROWS * STM_CHANNELS - 3, 0xFC);
+
+        data.resize(1440, 0);
+        data.extend((0..16).map(|x| x * 8));
+        data
+    }
+
+    #[test]
+    fn load_synthetic_module() {
+        let module = StmModule::load(&stm()).unwrap().to_module();
+        assert_eq!(module.name, "synthetic");
+        assert_eq!(module.default_tempo, 6);
+        assert_eq!(module.pattern_order, vec![0]);
+        assert_eq!(module.pattern.len(), 1);
+
+        let row = &module.pattern[0][0];
+        assert_eq!(row[0].note, Note::C4);
+        assert_eq!(row[0].instrument, 1);
+        assert_eq!(row[0].volume, 0x50);
+        assert_eq!((row[0].effect_type, row[0].effect_parameter), (0x0F, 6));
+        assert_eq!(row[1].note, Note::NoteCut);
+        assert_eq!(row[2].note, Note::None);
+        assert_eq!(row[2].volume, 0);
+        assert_eq!((row[2].effect_type, row[2].effect_parameter), (0x0A, 0x03));
+        assert_eq!(row[3], PatternSlot::default());
+        assert!(module.pattern[0][1..]
+            .iter()
+            .flatten()
+            .all(|s| *s == PatternSlot::default()));
+
+        assert_eq!(module.instrument.len(), STM_SAMPLES);
+        let InstrumentType::Default(instr) = &module.instrument[0].instr_type else {
+            panic!("not a sample instrument");
+        };
+        let sample = &instr.sample[0];
+        assert_eq!(sample.name, "sample.smp");
+        assert!(matches!(sample.flags, LoopType::No));
+        assert_eq!(sample.volume, 1.0);
+        let audio: Vec<i8> = (0..16).map(|x| (x * 8) as i8).collect();
+        assert!(matches!(&sample.data, SampleDataType::Depth8(v) if *v == audio));
+    }
+
+    #[test]
+    fn reject_broken_modules() {
+        let data = stm();
+        assert!(StmModule::load(&data[..STM_HEADER_SIZE - 1]).is_err());
+        // truncated in the middle of the first slot
+        assert!(StmModule::load(&data[..STM_HEADER_SIZE + 992 + 128 + 2]).is_err());
+
+        let mut bad = data.clone();
+        bad[20] = 0;
+        assert!(StmModule::load(&bad).is_err());
+        let mut bad = data;
+        bad[30] = 1;
+        assert!(StmModule::load(&bad).is_err());
+    }
+}
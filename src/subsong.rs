@@ -0,0 +1,294 @@
+use alloc::format;
+use alloc::{vec, vec::Vec};
+use core::ops::Range;
+
+use crate::module::Module;
+use crate::patternslot::PatternSlot;
+
+/// A song found in the order list
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct SubSong {
+    /// Index in `pattern_order` where playback starts
+    pub entry: usize,
+    /// Indexes in `pattern_order` played, sorted
+    pub orders: Vec<usize>,
+    /// Seconds until the end of the song or until it goes back
+    pub duration: f32,
+    /// Index in `pattern_order` where playback goes back, `None` if the song stops
+    pub loop_order: Option<usize>,
+}
+
+/// XM position jump effect
+const EFFECT_POSITION_JUMP: u8 = 0x0B;
+
+impl Module {
+    /// Every song of the order list, the main song first
+    ///
+    /// The main song starts at order 0, each other song starts at the
+    /// first playable order that no previous song reaches.
+    pub fn subsongs(&self) -> Vec<SubSong> {
+        let mut songs: Vec<SubSong> = vec![];
+        let mut reached = vec![false; self.pattern_order.len()];
+
+        let mut entry = 0;
+        loop {
+            while entry < reached.len() && (reached[entry] || !self.is_playable_order(entry)) {
+                entry += 1;
+            }
+            if entry >= reached.len() {
+                break;
+            }
+
+            let timeline = self.timeline_from(entry);
+            let mut orders: Vec<usize> = timeline.rows.iter().map(|e| e.order).collect();
+            orders.sort_unstable();
+            orders.dedup();
+            reached[entry] = true;
+            for &o in &orders {
+                reached[o] = true;
+            }
+
+            songs.push(SubSong {
+                entry,
+                orders,
+                duration: timeline.duration,
+                loop_order: timeline.loop_start.map(|e| e.order),
+            });
+        }
+        songs
+    }
+
+    /// Order ranges never played by the main song
+    pub fn unreachable_orders(&self) -> Vec<Range<usize>> {
+        let mut reached = vec![false; self.pattern_order.len()];
+        for e in self.timeline().rows {
+            reached[e.order] = true;
+        }
+
+        let mut ranges: Vec<Range<usize>> = vec![];
+        for (o, _) in reached.iter().enumerate().filter(|(_, &r)| !r) {
+            match ranges.last_mut() {
+                Some(range) if range.end == o => range.end += 1,
+                _ => ranges.push(o..o + 1),
+            }
+        }
+        ranges
+    }
+
+    /// A copy of the module playing only `song`
+    ///
+    /// Orders are kept in their original sequence, so patterns still fall
+    /// through to the same next order. A song that jumps back before its
+    /// entry starts with a one row jump to it. Unused patterns are removed,
+    /// position jumps and the restart position renumbered.
+    pub fn subsong_to_module(&self, song: &SubSong) -> Module {
+        let lead = usize::from(song.orders.first().is_some_and(|&o| o < song.entry));
+        // new index of the first song order at or after each old order,
+        // orders past the end still end the song
+        let new_order: Vec<usize> = (0..=self.pattern_order.len())
+            .map(|o| lead + song.orders.partition_point(|&x| x < o))
+            .collect();
+        let renumber = |o: usize| new_order[o.min(self.pattern_order.len())];
+
+        let mut new_pattern: Vec<Option<usize>> = vec![None; self.pattern.len()];
+        let mut module = Module {
+            pattern: vec![],
+            pattern_order: vec![],
+            ..self.clone()
+        };
+        for &old in &song.orders {
+            let p = self.pattern_order[old];
+            let index = *new_pattern[p].get_or_insert_with(|| {
+                module.pattern.push(self.pattern[p].clone());
+                module.pattern.len() - 1
+            });
+            module.pattern_order.push(index);
+        }
+
+        for slot in module.pattern.iter_mut().flatten().flatten() {
+            if slot.effect_type == EFFECT_POSITION_JUMP {
+                let new = renumber(slot.effect_parameter as usize);
+                slot.effect_parameter = u8::try_from(new).unwrap_or(u8::MAX);
+            }
+        }
+
+        if lead != 0 {
+            let mut row = vec![PatternSlot::default(); self.max_row_len().max(1)];
+            row[0].effect_type = EFFECT_POSITION_JUMP;
+            row[0].effect_parameter = u8::try_from(renumber(song.entry)).unwrap_or(u8::MAX);
+            module.pattern.push(vec![row]);
+            module.pattern_order.insert(0, module.pattern.len() - 1);
+        }
+
+        module.restart_position = renumber(self.restart_position);
+        module
+    }
+
+    /// One module for each song of the order list, the main song first
+    pub fn to_subsong_modules(&self) -> Vec<Module> {
+        self.subsongs()
+            .iter()
+            .enumerate()
+            .map(|(song_number, song)| {
+                let mut module = self.subsong_to_module(song);
+                module.name = format!("{} {}", self.name, song_number);
+                module
+            })
+            .collect()
+    }
+
+    /// The order points to an existing pattern with rows
+    fn is_playable_order(&self, order: usize) -> bool {
+        self.pattern_order
+            .get(order)
+            .and_then(|&p| self.pattern.get(p))
+            .is_some_and(|p| !p.is_empty())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::module::Pattern;
+
+    /// Two rows told apart by their instrument, `effect` on the last row
+    fn pattern(id: u8, effect: Option<(u8, u8)>) -> Pattern {
+        let mut rows = vec![
+            vec![PatternSlot {
+                instrument: id,
+                ..Default::default()
+            }];
+            2
+        ];
+        if let Some((effect_type, effect_parameter)) = effect {
+            rows[1][0].effect_type = effect_type;
+            rows[1][0].effect_parameter = effect_parameter;
+        }
+        rows
+    }
+
+    /// Instruments played, in playback order
+    fn played(module: &Module) -> Vec<u8> {
+        module
+            .timeline()
+            .rows
+            .iter()
+            .map(|e| module.pattern[module.pattern_order[e.order]][e.row][0].instrument)
+            .collect()
+    }
+
+    fn jump(module: &Module, order: usize) -> Option<u8> {
+        let slot = &module.pattern[module.pattern_order[order]][1][0];
+        (slot.effect_type == EFFECT_POSITION_JUMP).then_some(slot.effect_parameter)
+    }
+
+    /// Song 0: order 0 jumps over order 1 to 2, order 3 stops.
+    /// Song 1: order 1 falls into 2 and 3.
+    /// Song 2: order 4, an empty pattern, then order 6 jumps back to 4.
+    fn module() -> Module {
+        Module {
+            pattern: vec![
+                pattern(1, Some((EFFECT_POSITION_JUMP, 2))),
+                pattern(2, None),
+                pattern(3, None),
+                pattern(4, Some((0x0F, 0))),
+                pattern(5, None),
+                vec![],
+                pattern(7, Some((EFFECT_POSITION_JUMP, 4))),
+            ],
+            pattern_order: vec![0, 1, 2, 3, 4, 5, 6],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn find_songs() {
+        let songs = module().subsongs();
+        let found: Vec<(usize, Vec<usize>, Option<usize>)> = songs
+            .iter()
+            .map(|s| (s.entry, s.orders.clone(), s.loop_order))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                (0, vec![0, 2, 3], None),
+                (1, vec![1, 2, 3], None),
+                (4, vec![4, 6], Some(4)),
+            ]
+        );
+        // 5 rows at speed 6 and 125 BPM, then the stop
+        assert!((songs[0].duration - 5.0 * 0.12).abs() < 1e-4);
+        assert_eq!(module().unreachable_orders(), vec![1..2, 4..7]);
+    }
+
+    #[test]
+    fn songs_keep_their_order_and_jumps() {
+        let module = module();
+        let modules = module.to_subsong_modules();
+        assert_eq!(modules.len(), 3);
+
+        assert_eq!(modules[0].pattern_order, vec![0, 1, 2]);
+        assert_eq!(jump(&modules[0], 0), Some(1));
+        assert_eq!(played(&modules[0]), vec![1, 1, 3, 3, 4, 4]);
+
+        assert_eq!(modules[1].pattern_order, vec![0, 1, 2]);
+        assert_eq!(played(&modules[1]), vec![2, 2, 3, 3, 4, 4]);
+
+        // the empty pattern is gone, the jump back follows
+        assert_eq!(modules[2].pattern_order, vec![0, 1]);
+        assert_eq!(jump(&modules[2], 1), Some(0));
+        assert_eq!(played(&modules[2]), vec![5, 5, 7, 7]);
+        assert!(modules[2].timeline().loops_forever);
+    }
+
+    #[test]
+    fn jump_back_before_the_entry() {
+        // song 1 starts at order 1 and jumps back to order 0, which stops
+        let module = Module {
+            pattern: vec![
+                pattern(1, Some((0x0F, 0))),
+                pattern(2, Some((EFFECT_POSITION_JUMP, 0))),
+            ],
+            pattern_order: vec![0, 1],
+            ..Default::default()
+        };
+        let songs = module.subsongs();
+        assert_eq!(songs.len(), 2);
+        assert_eq!((songs[1].entry, songs[1].orders.clone()), (1, vec![0, 1]));
+
+        let sub = module.subsong_to_module(&songs[1]);
+        // a one row jump to the entry, then orders 0 and 1
+        assert_eq!(sub.pattern_order.len(), 3);
+        let lead = &sub.pattern[sub.pattern_order[0]];
+        assert_eq!(lead.len(), 1);
+        assert_eq!(
+            (lead[0][0].effect_type, lead[0][0].effect_parameter),
+            (EFFECT_POSITION_JUMP, 2)
+        );
+        assert_eq!(jump(&sub, 2), Some(1));
+        assert_eq!(played(&sub), vec![0, 2, 2, 1, 1]);
+    }
+
+    #[test]
+    fn restart_position_is_renumbered() {
+        let mut module = Module {
+            pattern: vec![
+                pattern(1, Some((0x0F, 0))),
+                pattern(2, None),
+                pattern(3, None),
+            ],
+            pattern_order: vec![0, 1, 2],
+            restart_position: 2,
+            ..Default::default()
+        };
+        let songs = module.subsongs();
+        assert_eq!(songs[1].orders, vec![1, 2]);
+        let sub = module.subsong_to_module(&songs[1]);
+        assert_eq!(sub.restart_position, 1);
+        assert_eq!(sub.timeline().loop_start.map(|e| e.order), Some(1));
+
+        module.restart_position = 0;
+        let sub = module.subsong_to_module(&songs[1]);
+        assert_eq!(sub.restart_position, 0);
+    }
+}
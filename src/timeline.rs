@@ -0,0 +1,367 @@
+use alloc::{vec, vec::Vec};
+
+use crate::module::Module;
+
+/// A row played at a given time
+#[derive(Default, Clone, Copy, Debug, PartialEq)]
+pub struct TimelineEntry {
+    /// Seconds from the song start
+    pub time: f32,
+    /// Index in `pattern_order`
+    pub order: usize,
+    pub row: usize,
+    /// Ticks of the row with its delay (EEx), 0 on the row that stops the song
+    pub ticks: usize,
+    /// Seconds of one tick at the row tempo
+    pub tick_seconds: f32,
+}
+
+/// Song playback walk with speed and tempo changes, jumps, loops and delays
+#[derive(Default, Clone, Debug)]
+pub struct Timeline {
+    /// Seconds until the end of the song or until it goes back
+    pub duration: f32,
+    /// Row where playback goes back, `None` if the song stops (F00)
+    pub loop_start: Option<TimelineEntry>,
+    /// A jump effect (Bxx, Dxx) goes back: the song never reaches its end
+    pub loops_forever: bool,
+    /// Every row played, in playback order
+    pub rows: Vec<TimelineEntry>,
+}
+
+impl Timeline {
+    /// Position played at `time` seconds
+    pub fn position_at(&self, time: f32) -> Option<(usize, usize)> {
+        if time < 0.0 || time >= self.duration {
+            return None;
+        }
+        let index = self.rows.partition_point(|e| e.time <= time);
+        self.rows
+            .get(index.checked_sub(1)?)
+            .map(|e| (e.order, e.row))
+    }
+
+    /// First time the row is played
+    pub fn time_of(&self, order: usize, row: usize) -> Option<f32> {
+        self.rows
+            .iter()
+            .find(|e| e.order == order && e.row == row)
+            .map(|e| e.time)
+    }
+}
+
+/// XM effect numbers used by the walk
+const EFFECT_POSITION_JUMP: u8 = 0x0B;
+const EFFECT_PATTERN_BREAK: u8 = 0x0D;
+const EFFECT_EXTENDED: u8 = 0x0E;
+const EFFECT_SPEED_TEMPO: u8 = 0x0F;
+
+/// Safety net for pattern loops inside pattern loops
+const MAX_TIMELINE_ROWS: usize = 1 << 20;
+
+impl Module {
+    /// Duration and position of each row, see `Timeline`
+    pub fn timeline(&self) -> Timeline {
+        self.timeline_from(0)
+    }
+
+    /// Same as `timeline()`, playback starting at `start_order`
+    pub fn timeline_from(&self, start_order: usize) -> Timeline {
+        let mut timeline = Timeline::default();
+
+        let mut tempo = self.default_tempo as f32;
+        let mut bpm = self.default_bpm.max(1) as f32;
+        let mut time: f32 = 0.0;
+
+        let mut visited: Vec<Vec<bool>> = self
+            .pattern_order
+            .iter()
+            .map(|&p| vec![false; self.pattern.get(p).map_or(0, |p| p.len())])
+            .collect();
+
+        let channels = self.max_row_len();
+        let mut loop_row: Vec<usize> = vec![0; channels];
+        let mut loop_count: Vec<usize> = vec![0; channels];
+
+        let mut order: usize = start_order;
+        let mut row: usize = 0;
+
+        loop {
+            // skip empty or missing patterns, a break row goes to the next one,
+            // go back at the end of the song
+            while order < self.pattern_order.len() && visited[order].is_empty() {
+                order += 1;
+            }
+            if order >= self.pattern_order.len() {
+                let restart = self.restart_position;
+                timeline.loop_start = timeline.rows.iter().find(|e| e.order >= restart).copied();
+                break;
+            }
+            if row >= visited[order].len() {
+                row = 0;
+            }
+
+            let in_pattern_loop = loop_count.iter().any(|&c| c != 0);
+            if visited[order][row] && !in_pattern_loop {
+                timeline.loop_start = timeline
+                    .rows
+                    .iter()
+                    .find(|e| e.order == order && e.row == row)
+                    .copied();
+                timeline.loops_forever = true;
+                break;
+            }
+            if timeline.rows.len() >= MAX_TIMELINE_ROWS {
+                break;
+            }
+            visited[order][row] = true;
+            timeline.rows.push(TimelineEntry {
+                time,
+                order,
+                row,
+                ..Default::default()
+            });
+
+            let mut jump_order: Option<usize> = None;
+            let mut break_row: Option<usize> = None;
+            let mut loop_to: Option<usize> = None;
+            let mut row_delay: usize = 0;
+            let mut stop = false;
+
+            let pattern = &self.pattern[self.pattern_order[order]];
+            for (channel, slot) in pattern[row].iter().enumerate() {
+                let param = slot.effect_parameter;
+                match slot.effect_type {
+                    EFFECT_SPEED_TEMPO => {
+                        if param == 0 {
+                            stop = true;
+                        } else if param < 0x20 {
+                            tempo = param as f32;
+                        } else {
+                            bpm = param as f32;
+                        }
+                    }
+                    EFFECT_POSITION_JUMP => {
+                        jump_order = Some(param as usize);
+                        break_row.get_or_insert(0);
+                    }
+                    EFFECT_PATTERN_BREAK => {
+                        break_row = Some((param >> 4) as usize * 10 + (param & 0x0F) as usize);
+                    }
+                    EFFECT_EXTENDED if channel < channels => match param >> 4 {
+                        0x6 => {
+                            let count = (param & 0x0F) as usize;
+                            if count == 0 {
+                                loop_row[channel] = row;
+                            } else if loop_count[channel] == 0 {
+                                loop_count[channel] = count;
+                                loop_to = Some(loop_row[channel]);
+                            } else {
+                                loop_count[channel] -= 1;
+                                if loop_count[channel] != 0 {
+                                    loop_to = Some(loop_row[channel]);
+                                }
+                            }
+                        }
+                        0xE => row_delay = row_delay.max((param & 0x0F) as usize),
+                        _ => {}
+                    },
+                    _ => {}
+                }
+            }
+
+            let entry = timeline.rows.last_mut().unwrap();
+            entry.tick_seconds = 2.5 / bpm;
+            if stop {
+                break;
+            }
+            entry.ticks = tempo as usize * (1 + row_delay);
+            time += tempo * (1 + row_delay) as f32 * 2.5 / bpm;
+
+            if let Some(r) = loop_to {
+                row = r;
+            } else if jump_order.is_some() || break_row.is_some() {
+                order = match jump_order {
+                    Some(o) => o,
+                    None => order + 1,
+                };
+                row = break_row.unwrap_or(0);
+                loop_row.iter_mut().for_each(|r| *r = 0);
+            } else {
+                row += 1;
+                if row >= pattern.len() {
+                    order += 1;
+                    row = 0;
+                    loop_row.iter_mut().for_each(|r| *r = 0);
+                }
+            }
+        }
+
+        timeline.duration = time;
+        timeline
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::module::Pattern;
+    use crate::patternslot::PatternSlot;
+
+    /// `rows` rows of 2 channels, `effects` as (row, channel, type, parameter)
+    fn pattern(rows: usize, effects: &[(usize, usize, u8, u8)]) -> Pattern {
+        let mut pattern = vec![vec![PatternSlot::default(); 2]; rows];
+        for &(row, channel, effect_type, effect_parameter) in effects {
+            pattern[row][channel].effect_type = effect_type;
+            pattern[row][channel].effect_parameter = effect_parameter;
+        }
+        pattern
+    }
+
+    fn module(pattern: Vec<Pattern>, pattern_order: Vec<usize>) -> Module {
+        Module {
+            pattern,
+            pattern_order,
+            ..Default::default()
+        }
+    }
+
+    fn positions(timeline: &Timeline) -> Vec<(usize, usize)> {
+        timeline.rows.iter().map(|e| (e.order, e.row)).collect()
+    }
+
+    fn close(a: f32, b: f32) -> bool {
+        (a - b).abs() < 1e-4
+    }
+
+    #[test]
+    fn speed_and_tempo_changes() {
+        let stop = (3, 0, EFFECT_SPEED_TEMPO, 0);
+        let module = module(
+            vec![pattern(
+                4,
+                &[
+                    (1, 0, EFFECT_SPEED_TEMPO, 3),
+                    (2, 1, EFFECT_SPEED_TEMPO, 250),
+                    stop,
+                ],
+            )],
+            vec![0],
+        );
+        let timeline = module.timeline();
+        let times: Vec<f32> = timeline.rows.iter().map(|e| e.time).collect();
+        let ticks: Vec<usize> = timeline.rows.iter().map(|e| e.ticks).collect();
+        // 6 ticks at 125 BPM, 3 ticks at 125 BPM, 3 ticks at 250 BPM
+        assert!(close(times[1], 0.12) && close(times[2], 0.18) && close(times[3], 0.21));
+        assert_eq!(ticks, vec![6, 3, 3, 0]);
+        assert!(close(timeline.rows[2].tick_seconds, 0.01));
+        assert!(close(timeline.duration, 0.21));
+        assert_eq!(timeline.loop_start, None);
+        assert!(!timeline.loops_forever);
+    }
+
+    #[test]
+    fn pattern_loop() {
+        // rows 1 and 2 played 3 times
+        let module = module(
+            vec![pattern(
+                4,
+                &[(1, 1, EFFECT_EXTENDED, 0x60), (2, 1, EFFECT_EXTENDED, 0x62)],
+            )],
+            vec![0],
+        );
+        let timeline = module.timeline();
+        assert_eq!(
+            positions(&timeline),
+            vec![
+                (0, 0),
+                (0, 1),
+                (0, 2),
+                (0, 1),
+                (0, 2),
+                (0, 1),
+                (0, 2),
+                (0, 3)
+            ]
+        );
+        assert!(close(timeline.duration, 8.0 * 0.12));
+        // the end of the song goes back to order 0
+        assert_eq!(timeline.loop_start.map(|e| (e.order, e.row)), Some((0, 0)));
+        assert!(!timeline.loops_forever);
+    }
+
+    #[test]
+    fn row_delay() {
+        let module = module(vec![pattern(2, &[(0, 0, EFFECT_EXTENDED, 0xE2)])], vec![0]);
+        let timeline = module.timeline();
+        assert_eq!(timeline.rows[0].ticks, 18);
+        assert!(close(timeline.rows[1].time, 3.0 * 0.12));
+        assert!(close(timeline.duration, 4.0 * 0.12));
+    }
+
+    #[test]
+    fn jumps_and_breaks() {
+        let module = module(
+            vec![
+                // break to row 2 of the next order
+                pattern(4, &[(1, 0, EFFECT_PATTERN_BREAK, 0x02)]),
+                // jump to order 4, row 1
+                pattern(
+                    4,
+                    &[
+                        (2, 0, EFFECT_POSITION_JUMP, 4),
+                        (2, 1, EFFECT_PATTERN_BREAK, 0x01),
+                    ],
+                ),
+                pattern(1, &[]),
+                // empty pattern at order 1, skipped
+                vec![],
+            ],
+            vec![0, 3, 1, 2, 1],
+        );
+        let timeline = module.timeline();
+        assert_eq!(
+            positions(&timeline),
+            vec![(0, 0), (0, 1), (2, 2), (4, 1), (4, 2)]
+        );
+        // order 4 row 2 jumps back to order 4 row 1
+        assert!(timeline.loops_forever);
+        assert_eq!(timeline.loop_start.map(|e| (e.order, e.row)), Some((4, 1)));
+
+        assert_eq!(timeline.position_at(0.13), Some((0, 1)));
+        assert_eq!(timeline.position_at(timeline.duration), None);
+        assert_eq!(timeline.time_of(2, 2).map(|t| close(t, 0.24)), Some(true));
+        assert_eq!(timeline.time_of(2, 0), None);
+    }
+
+    #[test]
+    fn loop_detection() {
+        // order 1 jumps back to order 0, row 1
+        let module = module(
+            vec![
+                pattern(2, &[]),
+                pattern(
+                    2,
+                    &[
+                        (1, 0, EFFECT_POSITION_JUMP, 0),
+                        (1, 1, EFFECT_PATTERN_BREAK, 0x01),
+                    ],
+                ),
+            ],
+            vec![0, 1],
+        );
+        let timeline = module.timeline();
+        assert_eq!(positions(&timeline), vec![(0, 0), (0, 1), (1, 0), (1, 1)]);
+        assert!(timeline.loops_forever);
+        let start = timeline.loop_start.unwrap();
+        assert_eq!((start.order, start.row), (0, 1));
+        assert!(close(start.time, 0.12));
+        assert!(close(timeline.duration, 4.0 * 0.12));
+
+        // from order 1, the loop starts in the middle of order 0
+        let timeline = module.timeline_from(1);
+        assert_eq!(positions(&timeline), vec![(1, 0), (1, 1), (0, 1)]);
+        let start = timeline.loop_start.unwrap();
+        assert_eq!((start.order, start.row), (1, 0));
+    }
+}
@@ -0,0 +1,474 @@
+#[cfg(feature = "micromath")]
+use micromath::F32Ext;
+#[cfg(feature = "libm")]
+use num_traits::float::Float;
+
+use bincode::error::DecodeError;
+
+use crate::prelude::*;
+
+use alloc::string::String;
+use alloc::string::ToString;
+use alloc::{vec, vec::Vec};
+
+const ULT_HEADER_SIZE: usize = 48;
+const ULT_ORDERS: usize = 256;
+const ULT_ROWS: usize = 64;
+/// End of the order list
+const ULT_ORDER_END: u8 = 0xFF;
+/// Next event is repeated
+const ULT_REPEAT: u8 = 0xFC;
+
+#[derive(Default, Debug)]
+struct UltSample {
+    name: String,
+    loop_start: u32,
+    loop_end: u32,
+    /// Frames
+    len: u32,
+    /// 0-255
+    volume: u8,
+    /// 4: 16 bits, 8: loop, 16: ping-pong
+    flags: u8,
+    /// Sample rate for middle-c note
+    c2spd: u16,
+    /// 1/32768 of a semitone
+    finetune: i16,
+}
+
+impl UltSample {
+    /// Version 1.4 added the sample rate
+    fn size(version: u8) -> usize {
+        if version >= b'4' {
+            66
+        } else {
+            64
+        }
+    }
+
+    fn load(data: &[u8], version: u8) -> Self {
+        let u32_at =
+            |i: usize| u32::from_le_bytes([data[i], data[i + 1], data[i + 2], data[i + 3]]);
+        let u16_at = |i: usize| u16::from_le_bytes([data[i], data[i + 1]]);
+        let (c2spd, finetune) = if version >= b'4' {
+            (u16_at(62), u16_at(64) as i16)
+        } else {
+            (8363, u16_at(62) as i16)
+        };
+        Self {
+            name: String::from_utf8_lossy(&data[0..32])
+                .trim_matches(char::from(0))
+                .trim()
+                .to_string(),
+            loop_start: u32_at(44),
+            loop_end: u32_at(48),
+            len: u32_at(56).saturating_sub(u32_at(52)),
+            volume: data[60],
+            flags: data[61],
+            c2spd,
+            finetune,
+        }
+    }
+
+    fn is_16bits(&self) -> bool {
+        self.flags & 4 != 0
+    }
+
+    fn loop_type(&self) -> LoopType {
+        match self.flags & 0x18 {
+            0x18 => LoopType::PingPong,
+            0x08 => LoopType::Forward,
+            _ => LoopType::No,
+        }
+    }
+
+    /// Signed data, return the sample and the next data
+    fn get_sample_data<'a>(&self, data: &'a [u8]) -> (SampleDataType, &'a [u8]) {
+        if self.is_16bits() {
+            let len = (self.len as usize * 2).min(data.len());
+            let sdt = SampleDataType::Depth16(
+                data[0..len]
+                    .chunks_exact(2)
+                    .map(|c| i16::from_le_bytes([c[0], c[1]]))
+                    .collect(),
+            );
+            (sdt, &data[len..])
+        } else {
+            let len = (self.len as usize).min(data.len());
+            let sdt = SampleDataType::Depth8(data[0..len].iter().map(|&x| x as i8).collect());
+            (sdt, &data[len..])
+        }
+    }
+}
+
+/// UltraTracker Module
+#[derive(Default, Debug)]
+pub struct UltModule {
+    title: String,
+    comment: String,
+    samples: Vec<UltSample>,
+    audio: Vec<SampleDataType>,
+    positions: Vec<u8>,
+    /// 0-15 for each channel
+    panning: Vec<u8>,
+    patterns: Vec<Pattern>,
+}
+
+impl UltModule {
+    pub fn load(ser_ult_module: &[u8]) -> Result<UltModule, DecodeError> {
+        let mut ult = UltModule {
+            ..Default::default()
+        };
+
+        // === load header
+
+        let s = ULT_HEADER_SIZE;
+        if ser_ult_module.len() < s || &ser_ult_module[0..14] != b"MAS_UTrack_V00" {
+            return Err(DecodeError::Other("Not an ULT module?"));
+        }
+        let version = ser_ult_module[14];
+        if !(b'1'..=b'4').contains(&version) {
+            return Err(DecodeError::Other("Unknown ULT version"));
+        }
+        ult.title = String::from_utf8_lossy(&ser_ult_module[15..47])
+            .trim_matches(char::from(0))
+            .trim()
+            .to_string();
+        let comment_lines = ser_ult_module[47] as usize;
+        let data = &ser_ult_module[s..];
+
+        // === comment, lines of 32 chars
+
+        let s = comment_lines * 32;
+        if data.len() < s + 1 {
+            return Err(DecodeError::Other("Not an ULT module?"));
+        }
+        ult.comment = data[0..s]
+            .chunks(32)
+            .map(|line| {
+                String::from_utf8_lossy(line)
+                    .trim_matches(char::from(0))
+                    .trim_end()
+                    .to_string()
+            })
+            .collect::<Vec<String>>()
+            .join("\n");
+        let data = &data[s..];
+
+        // === samples
+
+        let sample_count = data[0] as usize;
+        let data = &data[1..];
+        let s = sample_count * UltSample::size(version);
+        if data.len() < s {
+            return Err(DecodeError::Other("Not an ULT module?"));
+        }
+        ult.samples = data[0..s]
+            .chunks_exact(UltSample::size(version))
+            .map(|d| UltSample::load(d, version))
+            .collect();
+        let data = &data[s..];
+
+        // === positions
+
+        if data.len() < ULT_ORDERS + 2 {
+            return Err(DecodeError::Other("Not an ULT module?"));
+        }
+        ult.positions = data[0..ULT_ORDERS]
+            .iter()
+            .copied()
+            .take_while(|&p| p != ULT_ORDER_END)
+            .collect();
+        let channel_count = data[ULT_ORDERS] as usize + 1;
+        let pattern_count = data[ULT_ORDERS + 1] as usize + 1;
+        let data = &data[ULT_ORDERS + 2..];
+        if channel_count > 32 {
+            return Err(DecodeError::Other("Not an ULT module?"));
+        }
+        ult.positions.retain(|&p| (p as usize) < pattern_count);
+
+        // === panning
+
+        let mut data = data;
+        if version >= b'3' {
+            if data.len() < channel_count {
+                return Err(DecodeError::Other("Not an ULT module?"));
+            }
+            ult.panning = data[0..channel_count].iter().map(|&p| p & 0x0F).collect();
+            data = &data[channel_count..];
+        } else {
+            ult.panning = (0..channel_count)
+                .map(|c| if c & 1 == 0 { 3 } else { 12 })
+                .collect();
+        }
+
+        // === patterns, channel after channel
+
+        ult.patterns =
+            vec![vec![vec![PatternSlot::default(); channel_count]; ULT_ROWS]; pattern_count];
+        for channel in 0..channel_count {
+            for pattern in ult.patterns.iter_mut() {
+                let mut row = 0;
+                while row < ULT_ROWS {
+                    let (slot, repeat, next) = Self::decode_pattern_slot(data)?;
+                    for _ in 0..repeat {
+                        if row < ULT_ROWS {
+                            pattern[row][channel] = slot;
+                        }
+                        row += 1;
+                    }
+                    data = next;
+                }
+            }
+        }
+
+        // === audio
+
+        for sample in &ult.samples {
+            let (sdt, next) = sample.get_sample_data(data);
+            ult.audio.push(sdt);
+            data = next;
+        }
+
+        Ok(ult)
+    }
+
+    // return PatternSlot, repeat count, next data
+    fn decode_pattern_slot(data: &[u8]) -> Result<(PatternSlot, usize, &[u8]), DecodeError> {
+        let (repeat, data) = match data {
+            [ULT_REPEAT, repeat, rest @ ..] => ((*repeat).max(1) as usize, rest),
+            _ => (1, data),
+        };
+        if data.len() < 5 {
+            return Err(DecodeError::Other("ULT pattern is truncated"));
+        }
+        let mut slot = PatternSlot {
+            note: match data[0] {
+                1..=60 => Note::try_from(24 + data[0]).unwrap_or(Note::None),
+                _ => Note::None,
+            },
+            instrument: data[1],
+            ..Default::default()
+        };
+
+        // two effects, the second one goes in the volume column when possible
+        let first = Self::efx_correction(data[2] & 0x0F, data[3]);
+        let second = Self::efx_correction(data[2] >> 4, data[4]);
+        let (effect, volume) = match (first, second) {
+            (Some(a), Some(b)) => match (Self::to_volume_column(b), Self::to_volume_column(a)) {
+                (Some(v), _) => (Some(a), Some(v)),
+                (None, Some(v)) => (Some(b), Some(v)),
+                // no room for both, keep the first one
+                (None, None) => (Some(a), None),
+            },
+            (Some(a), None) | (None, Some(a)) => (Some(a), None),
+            (None, None) => (None, None),
+        };
+        if let Some((effect_type, effect_parameter)) = effect {
+            slot.effect_type = effect_type;
+            slot.effect_parameter = effect_parameter;
+        }
+        if let Some(volume) = volume {
+            slot.volume = volume;
+        }
+
+        Ok((slot, repeat, &data[5..]))
+    }
+
+    /// Convert an ULT effect to an XM effect
+    fn efx_correction(effect: u8, param: u8) -> Option<(u8, u8)> {
+        match effect {
+            0x0 if param != 0 => Some((0x00, param)), // arpeggio
+            0x1 => Some((0x01, param)),               // slide up
+            0x2 => Some((0x02, param)),               // slide down
+            0x3 => Some((0x03, param)),               // tone portamento
+            0x4 => Some((0x04, param)),               // vibrato
+            0x7 => Some((0x07, param)),               // tremolo
+            // offset in 1024 frames
+            0x9 => Some((0x09, param.saturating_mul(4))),
+            0xA => {
+                // volume slide, up first
+                if param & 0xF0 != 0 {
+                    Some((0x0A, param & 0xF0))
+                } else {
+                    Some((0x0A, param & 0x0F))
+                }
+            }
+            0xB => Some((0x08, (param & 0x0F) * 0x11)), // balance
+            0xC => Some((0x0C, param / 4)),             // volume 0-255
+            0xD => Some((0x0D, param)),                 // pattern break
+            0xE => match param >> 4 {
+                0x1 | 0x2 | 0x9 | 0xA | 0xB | 0xC | 0xD => Some((0x0E, param)),
+                // note delay
+                0x8 => Some((0x0E, 0xD0 | (param & 0x0F))),
+                _ => None,
+            },
+            0xF => match param {
+                0 => Some((0x0F, 6)),
+                // ULT speed goes up to 0x2F
+                0x01..=0x2F => Some((0x0F, param.min(0x1F))),
+                _ => Some((0x0F, param)),
+            },
+            _ => None,
+        }
+    }
+
+    /// XM volume column for some XM effects
+    fn to_volume_column((effect_type, param): (u8, u8)) -> Option<u8> {
+        match effect_type {
+            0x0C => Some(0x10 + param.min(64)),
+            0x0A if param & 0x0F == 0 => Some(0x70 | (param >> 4)),
+            0x0A => Some(0x60 | param),
+            0x08 if param % 0x11 == 0 => Some(0xC0 | (param >> 4)),
+            _ => None,
+        }
+    }
+
+    pub fn to_module(&self) -> Module {
+        let ph = PeriodHelper::new(FrequencyType::LinearFrequencies, false);
+        let mut module = Module {
+            name: self.title.clone(),
+            comment: self.comment.clone(),
+            frequency_type: FrequencyType::LinearFrequencies,
+            pattern_order: self.positions.iter().map(|&x| x as usize).collect(),
+            pattern: self.patterns.clone(),
+            ..Default::default()
+        };
+
+        for (ult_sample, audio) in self.samples.iter().zip(&self.audio) {
+            let bytes = if ult_sample.is_16bits() { 2 } else { 1 };
+            let flags = ult_sample.loop_type();
+            let (loop_start, loop_length) = match flags {
+                LoopType::No => (0, 0),
+                _ => (
+                    ult_sample.loop_start / bytes,
+                    ult_sample.loop_end.saturating_sub(ult_sample.loop_start) / bytes,
+                ),
+            };
+            let rn = ph.c4freq_to_relative_note(ult_sample.c2spd as f32);
+            let note = rn.0 as f32 + rn.1 + ult_sample.finetune as f32 / 32768.0;
+            let relative_note = note.ceil();
+            let mut sample = Sample {
+                name: ult_sample.name.clone(),
+                loop_start,
+                loop_length,
+                volume: ult_sample.volume as f32 / 255.0,
+                finetune: note - relative_note,
+                flags,
+                panning: 0.5,
+                relative_note: relative_note as i8,
+                data: audio.clone(),
+            };
+            sample.fix_loop();
+
+            // Create InstrDefault
+            let mut instr_def = InstrDefault::default();
+            instr_def.sample.push(sample);
+
+            // Create Instrument
+            let instr = Instrument {
+                name: ult_sample.name.clone(),
+                instr_type: InstrumentType::Default(instr_def),
+                ..Default::default()
+            };
+
+            module.instrument.push(instr);
+        }
+
+        let panning: Vec<f32> = self.panning.iter().map(|&p| p as f32 / 15.0).collect();
+        module.set_initial_panning(&panning);
+
+        module
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// One pattern of 2 channels, sample 1 has 8 frames
+    fn ult() -> Vec<u8> {
+        let mut data = b"MAS_UTrack_V004synthetic".to_vec();
+        data.resize(47, 0);
+        data.push(1);
+        let mut comment = b"hello".to_vec();
+        comment.resize(32, 0);
+        data.extend_from_slice(&comment);
+
+        data.push(1);
+        let mut sample = b"sample".to_vec();
+        sample.resize(52, 0);
+        for value in [0u32, 8] {
+            sample.extend_from_slice(&value.to_le_bytes());
+        }
+        // volume, flags
+        sample.extend_from_slice(&[255, 0]);
+        sample.extend_from_slice(&8363u16.to_le_bytes());
+        sample.extend_from_slice(&0u16.to_le_bytes());
+        data.extend_from_slice(&sample);
+
+        let mut orders = [ULT_ORDER_END; ULT_ORDERS];
+        orders[0] = 0;
+        data.extend_from_slice(&orders);
+        // 2 channels, 1 pattern, panning
+        data.extend_from_slice(&[1, 0, 3, 12]);
+
+        // C-4, instrument 1, volume slide A23 and volume C80
+        data.extend_from_slice(&[25, 1, 0xCA, 0x23, 0x80]);
+        data.extend_from_slice(&[ULT_REPEAT, 63, 0, 0, 0, 0, 0]);
+        data.extend_from_slice(&[ULT_REPEAT, 64, 0, 0, 0, 0, 0]);
+
+        data.extend((0..8).map(|x| x * 16));
+        data
+    }
+
+    #[test]
+    fn load_synthetic_module() {
+        let module = UltModule::load(&ult()).unwrap().to_module();
+        assert_eq!(module.name, "synthetic");
+        assert_eq!(module.comment, "hello");
+        assert_eq!(module.pattern_order, vec![0]);
+        assert_eq!(module.pattern.len(), 1);
+
+        let pattern = &module.pattern[0];
+        assert_eq!(pattern.len(), ULT_ROWS);
+        let slot = &pattern[0][0];
+        assert_eq!(slot.note, Note::C4);
+        assert_eq!(slot.instrument, 1);
+        assert_eq!((slot.effect_type, slot.effect_parameter), (0x0A, 0x20));
+        assert_eq!(slot.volume, 0x30);
+        // initial panning on the first row
+        assert_eq!(pattern[0][1].note, Note::None);
+        assert_eq!(pattern[0][1].effect_type, 0x08);
+        assert!(pattern[1..]
+            .iter()
+            .flatten()
+            .all(|s| *s == PatternSlot::default()));
+
+        assert_eq!(module.instrument.len(), 1);
+        let InstrumentType::Default(instr) = &module.instrument[0].instr_type else {
+            panic!("not a sample instrument");
+        };
+        let sample = &instr.sample[0];
+        assert_eq!(sample.name, "sample");
+        assert_eq!(sample.volume, 1.0);
+        assert!(matches!(sample.flags, LoopType::No));
+        let audio: Vec<i8> = (0..8).map(|x| (x * 16) as i8).collect();
+        assert!(matches!(&sample.data, SampleDataType::Depth8(v) if *v == audio));
+    }
+
+    #[test]
+    fn reject_broken_modules() {
+        let data = ult();
+        assert!(UltModule::load(&data[..ULT_HEADER_SIZE - 1]).is_err());
+        // the last repeated event is cut
+        assert!(UltModule::load(&data[..data.len() - 8 - 1]).is_err());
+
+        let mut bad = data.clone();
+        bad[14] = b'5';
+        assert!(UltModule::load(&bad).is_err());
+        let mut bad = data;
+        bad[0] = b'X';
+        assert!(UltModule::load(&bad).is_err());
+    }
+}
@@ -3,6 +3,11 @@ use bincode::error::{DecodeError, EncodeError};
 use serde::{Deserialize, Serialize};
 use serde_big_array::BigArray;
 
+#[cfg(feature = "micromath")]
+use micromath::F32Ext;
+#[cfg(feature = "libm")]
+use num_traits::float::Float;
+
 use alloc::boxed::Box;
 use alloc::string::String;
 use alloc::{vec, vec::Vec};
@@ -158,9 +163,9 @@ impl XmInstrDefault {
                 }
 
                 xmid.vibrato_type = id.vibrato.waveform.try_into().unwrap();
-                xmid.vibrato_sweep = (id.vibrato.sweep * 255.0) as u8;
-                xmid.vibrato_depth = (id.vibrato.depth * 15.0 * 2.0) as u8;
-                xmid.vibrato_rate = (id.vibrato.speed * 63.0 * 4.0) as u8;
+                xmid.vibrato_sweep = (id.vibrato.sweep * 255.0).round() as u8;
+                xmid.vibrato_depth = (id.vibrato.depth * 15.0 * 2.0).round() as u8;
+                xmid.vibrato_rate = (id.vibrato.speed * 63.0 * 4.0).round() as u8;
 
                 xmid.volume_fadeout = (id.volume_fadeout * 4095.0 * 4.0 * 2.0) as u16;
 
@@ -361,11 +366,7 @@ impl XmInstrument {
     }
 
     fn is_envelope_nok(e: &Envelope) -> bool {
-        e.point.len() > 12
-            || e.sustain_point >= e.point.len()
-            || e.loop_start_point >= e.point.len()
-            || e.loop_end_point >= e.point.len()
-            || e.loop_start_point > e.loop_end_point
+        e.point.len() > 12 || !e.has_valid_points()
     }
 
     pub fn to_instrument(&self) -> Instrument {
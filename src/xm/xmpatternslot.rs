@@ -91,10 +91,10 @@ impl XmPatternSlot {
     pub fn save_unpack(&self) -> Vec<u8> {
         let mut bytes: [u8; 5] = [0; 5];
         bytes[0] = {
-            if self.note.is_keyoff() {
-                97
-            } else {
-                self.note.into()
+            match self.note {
+                // no note cut nor note fade in XM
+                Note::KeyOff | Note::NoteCut | Note::NoteFade => 97,
+                note => note.into(),
             }
         };
         bytes[1] = self.instrument;
@@ -107,10 +107,10 @@ impl XmPatternSlot {
     pub fn save(&self) -> Vec<u8> {
         let mut bytes: [u8; 5] = [0; 5];
         bytes[0] = {
-            if self.note.is_keyoff() {
-                97
-            } else {
-                self.note.into()
+            match self.note {
+                // no note cut nor note fade in XM
+                Note::KeyOff | Note::NoteCut | Note::NoteFade => 97,
+                note => note.into(),
             }
         };
         bytes[1] = self.instrument;
@@ -82,13 +82,17 @@ impl XmSample {
     }
 
     pub fn save(&mut self) -> Result<Vec<u8>, EncodeError> {
+        // XM samples are mono
+        if let Some(d) = self.data.as_ref().filter(|d| d.is_stereo()) {
+            self.data = Some(d.to_mono());
+        }
         self.header.length = match &self.data {
             Some(SampleDataType::Depth8(d)) => d.len() as u32,
             Some(SampleDataType::Depth16(d)) => {
                 self.header.flags |= 0b0001_0000;
                 2 * d.len() as u32
             }
-            None => 0,
+            _ => 0,
         };
         let h = bincode::serde::encode_to_vec(&self.header, bincode::config::legacy())?;
         Ok(h)
@@ -102,7 +106,7 @@ impl XmSample {
                 let d = sample16_to_delta(d);
                 vec_u16_to_u8_slice(d)
             }
-            None => vec![],
+            _ => vec![],
         };
         Ok(d)
     }
@@ -160,16 +164,13 @@ impl XmSample {
                 let mut loop_start = s.loop_start;
                 let mut loop_length = s.loop_length;
 
-                if let SampleDataType::Depth16(_) = &s.data {
+                if s.bits() == 16 {
                     loop_start <<= 1;
                     loop_length <<= 1;
                 }
 
                 let mut xms = XmSample::default();
-                xms.header.length = match &s.data {
-                    SampleDataType::Depth8(d) => d.len() as u32,
-                    SampleDataType::Depth16(d) => 2 * d.len() as u32,
-                };
+                xms.header.length = s.len() as u32 * s.bits() as u32 / 8;
                 xms.header.loop_start = loop_start;
                 xms.header.loop_length = loop_length;
                 xms.header.volume = (s.volume * 64.0) as u8;
@@ -178,7 +179,7 @@ impl XmSample {
                 xms.header.panning = (s.panning * 255.0) as u8;
                 xms.header.relative_note = s.relative_note;
                 xms.header.name = s.name.clone();
-                xms.data = Some(s.data.clone());
+                xms.data = Some(s.data.to_mono());
                 output.push(xms);
             }
         }
@@ -189,7 +190,9 @@ impl XmSample {
         match &self.data {
             Some(SampleDataType::Depth8(d)) => d.len() as u32,
             Some(SampleDataType::Depth16(d)) => d.len() as u32,
-            _ => 0,
+            Some(SampleDataType::Stereo8(d)) => d.len() as u32 / 2,
+            Some(SampleDataType::Stereo16(d)) => d.len() as u32 / 2,
+            None => 0,
         }
     }
 }